@@ -5,8 +5,8 @@ use loupe::MemoryUsage;
 use wasmer::{
     imports,
     vm::{self, MemoryError, MemoryStyle, TableStyle, VMMemoryDefinition, VMTableDefinition},
-    wat2wasm, BaseTunables, Instance, Memory, MemoryType, Module, Pages, Store, TableType, Target,
-    Tunables,
+    wat2wasm, BaseTunables, Instance, Memory, MemoryType, Module, ModuleLimits, Pages, Store,
+    TableType, Target, Tunables,
 };
 use wasmer_compiler_cranelift::Cranelift;
 use wasmer_engine_universal::Universal;
@@ -81,6 +81,13 @@ impl<T: Tunables> Tunables for LimitingTunables<T> {
         self.base.table_style(table)
     }
 
+    /// Get the `ModuleLimits` which the modules must satisfy
+    ///
+    /// Delegated to base.
+    fn module_limits(&self) -> ModuleLimits {
+        self.base.module_limits()
+    }
+
     /// Create a memory owned by the host given a [`MemoryType`] and a [`MemoryStyle`].
     ///
     /// The requested memory type is validated, adjusted to the limited and then passed to base.
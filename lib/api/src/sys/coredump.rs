@@ -0,0 +1,54 @@
+use crate::sys::store::Store;
+use crate::sys::RuntimeError;
+use std::convert::TryFrom;
+use wasmer_engine::{CoreDumpFrame, WasmCoreDump};
+use wasmer_vm::{InstanceRef, WeakOrStrongInstanceRef};
+
+/// Attaches a core dump of `instance` to `error` if `store` captures core
+/// dumps on trap.
+///
+/// The native frames don't keep track of the WebAssembly locals and
+/// operand stack, so the frames of the core dump only locate the trap.
+pub(crate) fn capture_coredump(
+    store: &Store,
+    instance: Option<&WeakOrStrongInstanceRef>,
+    error: RuntimeError,
+) -> RuntimeError {
+    if !store.coredump_on_trap() || error.coredump().is_some() {
+        return error;
+    }
+    let instance = match instance.and_then(|instance| InstanceRef::try_from(instance.clone()).ok())
+    {
+        Some(instance) => instance,
+        None => return error,
+    };
+    let module = instance.module();
+
+    let name = std::env::args()
+        .next()
+        .unwrap_or_else(|| "wasmer".to_string());
+    let module_name = module.name.as_deref().unwrap_or("<module>");
+    let mut coredump = WasmCoreDump::new(name, module_name);
+
+    for index in module.memories.keys() {
+        let memory = instance.memory(index);
+        let data = unsafe {
+            let definition = memory.vmmemory().as_ref();
+            std::slice::from_raw_parts(definition.base, definition.current_length as usize).to_vec()
+        };
+        coredump.add_memory(data, memory.ty().maximum);
+    }
+    for index in module.globals.keys() {
+        let global = instance.global(index);
+        let bits = unsafe { global.vmglobal().as_ref().to_u128() };
+        coredump.add_global(*global.ty(), bits);
+    }
+    for frame in error.trace() {
+        coredump.add_frame(CoreDumpFrame::new(
+            frame.func_index(),
+            frame.func_offset() as u32,
+        ));
+    }
+
+    error.with_coredump(coredump)
+}
@@ -1,3 +1,4 @@
+use crate::sys::coredump::capture_coredump;
 use crate::sys::exports::{ExportError, Exportable};
 use crate::sys::externals::Extern;
 use crate::sys::store::Store;
@@ -438,7 +439,11 @@ impl Function {
                 values_vec.as_mut_ptr() as *mut u8,
             )
         } {
-            return Err(RuntimeError::from_trap(error));
+            return Err(capture_coredump(
+                &self.store,
+                self.exported.vm_function.instance_ref.as_ref(),
+                RuntimeError::from_trap(error),
+            ));
         }
 
         // Load the return values out of `values_vec`.
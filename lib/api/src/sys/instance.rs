@@ -113,8 +113,28 @@ impl Instance {
     ///  * Link errors that happen when plugging the imports into the instance
     ///  * Runtime errors that happen when running the module `start` function.
     pub fn new(module: &Module, resolver: &dyn Resolver) -> Result<Self, InstantiationError> {
+        let handle = module.instantiate(resolver, true)?;
+        Self::from_handle(module, handle)
+    }
+
+    /// Creates a new `Instance` like [`Instance::new`], but without
+    /// initializing the module: its memories aren't initialized with the
+    /// data segments of the module, and its `start` function isn't run.
+    ///
+    /// This is meant for the instances importing memories which another
+    /// instance of the module already initialized, and which are still in
+    /// use, such as the ones of the threads of a program. Their tables are
+    /// initialized with the element segments of the module as usual.
+    pub fn new_without_initialization(
+        module: &Module,
+        resolver: &dyn Resolver,
+    ) -> Result<Self, InstantiationError> {
+        let handle = module.instantiate(resolver, false)?;
+        Self::from_handle(module, handle)
+    }
+
+    fn from_handle(module: &Module, handle: InstanceHandle) -> Result<Self, InstantiationError> {
         let store = module.store();
-        let handle = module.instantiate(resolver)?;
         let exports = module
             .exports()
             .map(|export| {
@@ -1,4 +1,5 @@
 mod cell;
+mod coredump;
 mod env;
 mod exports;
 mod externals;
@@ -49,15 +50,18 @@ pub use crate::sys::utils::is_wasm;
 pub use target_lexicon::{Architecture, CallingConvention, OperatingSystem, Triple, HOST};
 #[cfg(feature = "compiler")]
 pub use wasmer_compiler::{
-    wasmparser, CompilerConfig, FunctionMiddleware, MiddlewareError, MiddlewareReaderState,
-    ModuleMiddleware,
+    wasmparser, CompilerConfig, FunctionBodyData, FunctionLocals, FunctionMiddleware,
+    ImportedFunctions, MiddlewareError, MiddlewareReaderState, ModuleMiddleware,
 };
 pub use wasmer_compiler::{
-    CompileError, CpuFeature, Features, ParseCpuFeatureError, Target, WasmError, WasmResult,
+    CancellationToken, CompileError, CompileStats, CpuFeature, Features, FunctionCompileStats,
+    ModuleLimits, ParseCpuFeatureError, Target, WasmError, WasmResult,
 };
 pub use wasmer_engine::{
-    ChainableNamedResolver, DeserializeError, Engine, Export, FrameInfo, LinkError, NamedResolver,
-    NamedResolverChain, Resolver, RuntimeError, SerializeError, Tunables,
+    ChainableNamedResolver, CoreDumpFrame, CoreDumpGlobal, CoreDumpMemory, CoreDumpValue,
+    DeserializeError, Engine, Export, FrameInfo, FrameSymbol, InvalidKeyError, LinkError,
+    NamedResolver, NamedResolverChain, Resolver, RuntimeError, SerializeError, SigningKey,
+    TrustedKeys, Tunables, WasmCoreDump,
 };
 #[cfg(feature = "experimental-reference-types-extern-ref")]
 pub use wasmer_types::ExternRef;
@@ -112,6 +116,9 @@ pub use wasmer_compiler_llvm::{LLVMOptLevel, LLVM};
 #[cfg(feature = "universal")]
 pub use wasmer_engine_universal::{Universal, UniversalArtifact, UniversalEngine};
 
+#[cfg(all(feature = "universal", feature = "compiler"))]
+pub use wasmer_engine_universal::TieredArtifact;
+
 #[cfg(feature = "dylib")]
 pub use wasmer_engine_dylib::{Dylib, DylibArtifact, DylibEngine};
 
@@ -1,16 +1,18 @@
 use crate::sys::store::Store;
 use crate::sys::types::{ExportType, ImportType};
-use crate::sys::InstantiationError;
+use crate::sys::{InstantiationError, RuntimeError};
 use loupe::MemoryUsage;
 use std::fmt;
 use std::io;
 use std::path::Path;
 use std::sync::Arc;
 use thiserror::Error;
-use wasmer_compiler::CompileError;
 #[cfg(feature = "wat")]
 use wasmer_compiler::WasmError;
-use wasmer_engine::{Artifact, DeserializeError, Resolver, SerializeError};
+use wasmer_compiler::{CancellationToken, CompileError, CompileStats};
+use wasmer_engine::{
+    Artifact, DeserializeError, Resolver, SerializeError, SigningKey, TrustedKeys,
+};
 use wasmer_types::{ExportsIterator, ImportsIterator, ModuleInfo};
 use wasmer_vm::InstanceHandle;
 
@@ -145,10 +147,47 @@ impl Module {
         store: &Store,
         binary: &[u8],
     ) -> Result<Self, CompileError> {
-        let module = Self::compile(store, binary)?;
+        let module = Self::compile(store, binary, &CancellationToken::new())?;
         Ok(module)
     }
 
+    /// Creates a new WebAssembly Module like [`Module::new`], failing with
+    /// [`CompileError::Cancelled`] if `cancellation` is cancelled before
+    /// the compilation completes.
+    ///
+    /// The token can be cancelled from another thread, or created with a
+    /// wall-clock budget with [`CancellationToken::with_budget`].
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use wasmer::*;
+    /// use std::time::Duration;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let store = Store::default();
+    /// let cancellation = CancellationToken::with_budget(Duration::from_secs(10));
+    /// let module = Module::new_with_cancellation(&store, "(module)", &cancellation)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(unreachable_code)]
+    pub fn new_with_cancellation(
+        store: &Store,
+        bytes: impl AsRef<[u8]>,
+        cancellation: &CancellationToken,
+    ) -> Result<Self, CompileError> {
+        #[cfg(feature = "wat")]
+        let bytes = wat::parse_bytes(bytes.as_ref()).map_err(|e| {
+            CompileError::Wasm(WasmError::Generic(format!(
+                "Error when converting wat: {}",
+                e
+            )))
+        })?;
+
+        Self::validate(store, bytes.as_ref())?;
+        Self::compile(store, bytes.as_ref(), cancellation)
+    }
+
     /// Validates a new WebAssembly Module given the configuration
     /// in the Store.
     ///
@@ -159,8 +198,15 @@ impl Module {
         store.engine().validate(binary)
     }
 
-    fn compile(store: &Store, binary: &[u8]) -> Result<Self, CompileError> {
-        let artifact = store.engine().compile(binary, store.tunables())?;
+    fn compile(
+        store: &Store,
+        binary: &[u8],
+        cancellation: &CancellationToken,
+    ) -> Result<Self, CompileError> {
+        let artifact =
+            store
+                .engine()
+                .compile_with_cancellation(binary, store.tunables(), cancellation)?;
         Ok(Self::from_artifact(store, artifact))
     }
 
@@ -182,6 +228,28 @@ impl Module {
         self.artifact.serialize()
     }
 
+    /// Serializes a module into a compressed binary representation that the
+    /// `Engine` can later process via [`Module::deserialize`], which
+    /// decompresses it transparently.
+    ///
+    /// Engines that can't compress their artifacts serialize them
+    /// uncompressed.
+    ///
+    /// # Usage
+    ///
+    /// ```ignore
+    /// # use wasmer::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let store = Store::default();
+    /// # let module = Module::from_file(&store, "path/to/foo.wasm")?;
+    /// let serialized = module.serialize_compressed()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn serialize_compressed(&self) -> Result<Vec<u8>, SerializeError> {
+        self.artifact.serialize_compressed()
+    }
+
     /// Serializes a module into a file that the `Engine`
     /// can later process via [`Module::deserialize_from_file`].
     ///
@@ -200,6 +268,38 @@ impl Module {
         self.artifact.serialize_to_file(path.as_ref())
     }
 
+    /// Serializes a module into a binary representation, signed with
+    /// `signing_key`, that the `Engine` can later verify and process via
+    /// [`Module::deserialize_signed`].
+    ///
+    /// # Usage
+    ///
+    /// ```ignore
+    /// # use wasmer::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let store = Store::default();
+    /// # let module = Module::from_file(&store, "path/to/foo.wasm")?;
+    /// let signing_key = SigningKey::from_secret_key(&secret_key)?;
+    /// let serialized = module.serialize_signed(&signing_key)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn serialize_signed(&self, signing_key: &SigningKey) -> Result<Vec<u8>, SerializeError> {
+        self.artifact.serialize_signed(signing_key)
+    }
+
+    /// Serializes a module into a file, signed with `signing_key`, that the
+    /// `Engine` can later verify and process via
+    /// [`Module::deserialize_from_file_signed`].
+    pub fn serialize_to_file_signed(
+        &self,
+        path: impl AsRef<Path>,
+        signing_key: &SigningKey,
+    ) -> Result<(), SerializeError> {
+        self.artifact
+            .serialize_to_file_signed(path.as_ref(), signing_key)
+    }
+
     /// Deserializes a serialized Module binary into a `Module`.
     /// > Note: the module has to be serialized before with the `serialize` method.
     ///
@@ -228,6 +328,34 @@ impl Module {
         Ok(Self::from_artifact(store, artifact))
     }
 
+    /// Deserializes a serialized Module binary into a `Module`, validating
+    /// it first.
+    /// > Note: the module has to be serialized before with the `serialize` method.
+    ///
+    /// The binary is rejected with a [`DeserializeError`] if it was produced
+    /// by another version of the engine or for another target, if its
+    /// checksum doesn't match, or if its contents are malformed. This makes
+    /// it safe to load binaries that may have been corrupted, e.g. from a
+    /// cache on disk. It doesn't protect against binaries crafted by a
+    /// malicious actor, as the checksum can be forged along with the code.
+    ///
+    /// Not every engine supports checked deserialization.
+    ///
+    /// # Usage
+    ///
+    /// ```ignore
+    /// # use wasmer::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let store = Store::default();
+    /// let module = Module::deserialize_checked(&store, serialized_data)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn deserialize_checked(store: &Store, bytes: &[u8]) -> Result<Self, DeserializeError> {
+        let artifact = store.engine().deserialize_checked(bytes)?;
+        Ok(Self::from_artifact(store, artifact))
+    }
+
     /// Deserializes a a serialized Module located in a `Path` into a `Module`.
     /// > Note: the module has to be serialized before with the `serialize` method.
     ///
@@ -253,6 +381,59 @@ impl Module {
         Ok(Self::from_artifact(store, artifact))
     }
 
+    /// Deserializes a serialized Module binary into a `Module`, requiring
+    /// it to be signed by one of the `trusted_keys`.
+    /// > Note: the module has to be serialized before with the
+    /// > `serialize_signed` method.
+    ///
+    /// The binary is rejected with a [`DeserializeError`] if it isn't
+    /// signed, if it's signed by a key that isn't trusted, or if its
+    /// signature doesn't match its contents.
+    ///
+    /// # Safety
+    ///
+    /// The trusted keys must only sign binaries produced by the `Engine`
+    /// of the store, as the binary is then deserialized with
+    /// [`Module::deserialize`].
+    ///
+    /// # Usage
+    ///
+    /// ```ignore
+    /// # use wasmer::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let store = Store::default();
+    /// let mut trusted_keys = TrustedKeys::new();
+    /// trusted_keys.add(&public_key)?;
+    /// let module = unsafe { Module::deserialize_signed(&store, serialized_data, &trusted_keys)? };
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub unsafe fn deserialize_signed(
+        store: &Store,
+        bytes: &[u8],
+        trusted_keys: &TrustedKeys,
+    ) -> Result<Self, DeserializeError> {
+        let artifact = store.engine().deserialize_signed(bytes, trusted_keys)?;
+        Ok(Self::from_artifact(store, artifact))
+    }
+
+    /// Deserializes a serialized Module located in a `Path` into a
+    /// `Module`, requiring it to be signed by one of the `trusted_keys`.
+    ///
+    /// # Safety
+    ///
+    /// Please check [`Module::deserialize_signed`].
+    pub unsafe fn deserialize_from_file_signed(
+        store: &Store,
+        path: impl AsRef<Path>,
+        trusted_keys: &TrustedKeys,
+    ) -> Result<Self, DeserializeError> {
+        let artifact = store
+            .engine()
+            .deserialize_from_file_signed(path.as_ref(), trusted_keys)?;
+        Ok(Self::from_artifact(store, artifact))
+    }
+
     fn from_artifact(store: &Store, artifact: Arc<dyn Artifact>) -> Self {
         Self {
             store: store.clone(),
@@ -260,9 +441,13 @@ impl Module {
         }
     }
 
+    /// Instantiates the module, initializing its memories with its data
+    /// segments and invoking its start function if `initialize_module` is
+    /// true. Its tables are initialized either way.
     pub(crate) fn instantiate(
         &self,
         resolver: &dyn Resolver,
+        initialize_module: bool,
     ) -> Result<InstanceHandle, InstantiationError> {
         unsafe {
             let instance_handle = self.artifact.instantiate(
@@ -276,8 +461,14 @@ impl Module {
             // of this steps traps, we still need to keep the instance alive
             // as some of the Instance elements may have placed in other
             // instance tables.
-            self.artifact
-                .finish_instantiation(&self.store, &instance_handle)?;
+            if initialize_module {
+                self.artifact
+                    .finish_instantiation(&self.store, &instance_handle)?;
+            } else {
+                instance_handle
+                    .initialize_tables()
+                    .map_err(|trap| InstantiationError::Start(RuntimeError::from_trap(trap)))?;
+            }
 
             Ok(instance_handle)
         }
@@ -401,6 +592,30 @@ impl Module {
         self.artifact.module_ref().custom_sections(name)
     }
 
+    /// Returns the statistics about the compilation of the module: the
+    /// compile time, machine code size, relocation count and WebAssembly
+    /// body size of each local function, and their totals.
+    ///
+    /// This is `None` if the module was deserialized, or if its engine
+    /// doesn't collect them.
+    ///
+    /// ```
+    /// # use wasmer::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let store = Store::default();
+    /// let wat = "(module (func (export \"f\") (result i32) i32.const 42))";
+    /// let module = Module::new(&store, wat)?;
+    /// if let Some(stats) = module.compile_stats() {
+    ///     assert_eq!(stats.functions().len(), 1);
+    ///     println!("{} bytes of machine code", stats.total().code_size);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn compile_stats(&self) -> Option<&CompileStats> {
+        self.artifact.compile_stats()
+    }
+
     /// Returns the [`Store`] where the `Instance` belongs.
     pub fn store(&self) -> &Store {
         &self.store
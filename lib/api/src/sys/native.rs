@@ -9,6 +9,7 @@
 //! ```
 use std::marker::PhantomData;
 
+use crate::sys::coredump::capture_coredump;
 use crate::sys::externals::function::{DynamicFunction, VMDynamicFunction};
 use crate::sys::{FromToNativeWasmType, Function, RuntimeError, Store, WasmTypeList};
 use std::panic::{catch_unwind, AssertUnwindSafe};
@@ -153,7 +154,7 @@ macro_rules! impl_native_traits {
                         }
                         rets_list.as_mut()
                     };
-                    unsafe {
+                    if let Err(error) = unsafe {
                         wasmer_vm::wasmer_call_trampoline(
                             &self.store,
                             self.vmctx(),
@@ -161,7 +162,13 @@ macro_rules! impl_native_traits {
                             self.address(),
                             args_rets.as_mut_ptr() as *mut u8,
                         )
-                    }?;
+                    } {
+                        return Err(capture_coredump(
+                            &self.store,
+                            self.exported.vm_function.instance_ref.as_ref(),
+                            RuntimeError::from_trap(error),
+                        ));
+                    }
                     let num_rets = rets_list.len();
                     if !using_rets_array && num_rets > 0 {
                         let src_pointer = params_list.as_ptr();
@@ -2,6 +2,7 @@ use crate::sys::tunables::BaseTunables;
 use loupe::MemoryUsage;
 use std::any::Any;
 use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, RwLock};
 #[cfg(all(feature = "compiler", feature = "engine"))]
 use wasmer_compiler::CompilerConfig;
@@ -24,6 +25,8 @@ pub struct Store {
     tunables: Arc<dyn Tunables + Send + Sync>,
     #[loupe(skip)]
     trap_handler: Arc<RwLock<Option<Box<TrapHandlerFn>>>>,
+    #[loupe(skip)]
+    coredump_on_trap: Arc<AtomicBool>,
 }
 
 impl Store {
@@ -41,6 +44,24 @@ impl Store {
         *m = handler;
     }
 
+    /// Set whether calls into WebAssembly capture a core dump when they
+    /// trap.
+    ///
+    /// The core dump holds the trapped frames along with the memories and
+    /// globals of the instance of the called function, and can be retrieved
+    /// with [`RuntimeError::coredump`].
+    ///
+    /// [`RuntimeError::coredump`]: crate::RuntimeError::coredump
+    pub fn set_coredump_on_trap(&self, enable: bool) {
+        self.coredump_on_trap.store(enable, Ordering::SeqCst);
+    }
+
+    /// Returns whether calls into WebAssembly capture a core dump when
+    /// they trap.
+    pub fn coredump_on_trap(&self) -> bool {
+        self.coredump_on_trap.load(Ordering::SeqCst)
+    }
+
     /// Creates a new `Store` with a specific [`Engine`] and [`Tunables`].
     pub fn new_with_tunables<E>(engine: &E, tunables: impl Tunables + Send + Sync + 'static) -> Self
     where
@@ -54,6 +75,7 @@ impl Store {
             engine: engine.cloned(),
             tunables: Arc::new(tunables),
             trap_handler: Arc::new(RwLock::new(None)),
+            coredump_on_trap: Arc::new(AtomicBool::new(false)),
         }
     }
 
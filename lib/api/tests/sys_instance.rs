@@ -67,4 +67,59 @@ mod sys {
 
         Ok(())
     }
+
+    #[test]
+    fn new_without_initialization_skips_data_and_start() -> Result<()> {
+        let store = Store::default();
+        let module = Module::new(
+            &store,
+            "
+    (module
+      (import \"env\" \"memory\" (memory 1))
+      (type $answer_t (func (result i32)))
+      (table 1 funcref)
+      (elem (i32.const 0) $answer_f)
+      (data (i32.const 0) \"\\01\")
+      (func $answer_f (type $answer_t) (result i32)
+        i32.const 42)
+      (func $start_f
+        i32.const 4
+        i32.const 4
+        i32.load
+        i32.const 1
+        i32.add
+        i32.store)
+      (start $start_f)
+      (func $call_answer_f (result i32)
+        i32.const 0
+        call_indirect (type $answer_t))
+      (export \"call_answer\" (func $call_answer_f)))
+",
+        )?;
+
+        let memory = Memory::new(&store, MemoryType::new(1, None, false))?;
+        let import_object = imports! {
+            "env" => {
+                "memory" => memory.clone(),
+            },
+        };
+
+        Instance::new(&module, &import_object)?;
+        assert_eq!(memory.view::<u8>()[0].get(), 1);
+        assert_eq!(memory.view::<u32>()[1].get(), 1);
+
+        memory.view::<u8>()[0].set(7);
+
+        let instance = Instance::new_without_initialization(&module, &import_object)?;
+
+        // Neither the data segment nor the start function touched the memory.
+        assert_eq!(memory.view::<u8>()[0].get(), 7);
+        assert_eq!(memory.view::<u32>()[1].get(), 1);
+
+        // But the table was still initialized with the element segment.
+        let call_answer = instance.exports.get_function("call_answer")?;
+        assert_eq!(call_answer.call(&[])?.into_vec(), vec![Value::I32(42)]);
+
+        Ok(())
+    }
 }
@@ -26,6 +26,77 @@ mod sys {
         Ok(())
     }
 
+    #[test]
+    fn module_compile_stats() -> Result<()> {
+        let store = Store::default();
+        let wat = r#"(module
+            (func (export "f") (result i32) i32.const 42)
+            (func (export "g") (param i32) (result i32)
+                local.get 0
+                local.get 0
+                i32.mul))"#;
+        let module = Module::new(&store, wat)?;
+        if let Some(stats) = module.compile_stats() {
+            assert_eq!(stats.functions().len(), 2);
+            let total = stats.total();
+            assert_eq!(
+                total.wasm_size,
+                stats
+                    .functions()
+                    .values()
+                    .map(|f| f.wasm_size)
+                    .sum::<usize>()
+            );
+            assert!(total.code_size > 0);
+            assert!(stats.functions().values().all(|f| f.wasm_size > 0));
+        }
+
+        let module = unsafe { Module::deserialize(&store, &module.serialize()?)? };
+        assert!(module.compile_stats().is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn module_new_with_cancellation() -> Result<()> {
+        let store = Store::default();
+        let wat = r#"(module (func (export "f") (result i32) i32.const 42))"#;
+        Module::new_with_cancellation(&store, wat, &CancellationToken::new())?;
+
+        let cancellation = CancellationToken::new();
+        cancellation.cancel();
+        let result = Module::new_with_cancellation(&store, wat, &cancellation);
+        assert!(matches!(result, Err(CompileError::Cancelled)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn module_limits() -> Result<()> {
+        let engine = Store::default().engine().clone();
+        let mut tunables = BaseTunables::for_target(engine.target());
+        tunables.module_limits = ModuleLimits {
+            max_functions: Some(1),
+            max_locals_per_function: Some(2),
+            ..Default::default()
+        };
+        let store = Store::new_with_tunables(&*engine, tunables);
+
+        Module::new(&store, r#"(module (func (local i32 i32)))"#)?;
+        let result = Module::new(&store, r#"(module (func) (func))"#);
+        assert!(matches!(
+            result,
+            Err(CompileError::Wasm(WasmError::ModuleLimitExceeded(_)))
+        ));
+        let result = Module::new(&store, r#"(module (func (local i32 i32 i64)))"#);
+        assert!(matches!(
+            result,
+            Err(CompileError::Wasm(WasmError::ModuleLimitExceeded(_)))
+        ));
+
+        Ok(())
+    }
+
     #[test]
     fn imports() -> Result<()> {
         let store = Store::default();
@@ -50,6 +50,49 @@ pub fn load_cache_universal(c: &mut Criterion) {
     });
 }
 
+pub fn store_compressed_cache_universal(c: &mut Criterion) {
+    let tmp_dir = TempDir::new().unwrap();
+    let mut fs_cache = FileSystemCache::new(tmp_dir.path()).unwrap();
+    fs_cache.set_compression(true);
+    let compiler = Singlepass::default();
+    let store = Store::new(&Universal::new(compiler).engine());
+    let module = Module::new(
+        &store,
+        std::fs::read("../../lib/c-api/examples/assets/qjs.wasm").unwrap(),
+    )
+    .unwrap();
+
+    c.bench_function(
+        "store compressed universal module in filesystem cache",
+        |b| {
+            b.iter(|| {
+                let key = random_key();
+                fs_cache.store(key, &module).unwrap()
+            })
+        },
+    );
+}
+
+pub fn load_compressed_cache_universal(c: &mut Criterion) {
+    let tmp_dir = TempDir::new().unwrap();
+    let mut fs_cache = FileSystemCache::new(tmp_dir.path()).unwrap();
+    fs_cache.set_compression(true);
+    let compiler = Singlepass::default();
+    let store = Store::new(&Universal::new(compiler).engine());
+    let module = Module::new(
+        &store,
+        std::fs::read("../../lib/c-api/examples/assets/qjs.wasm").unwrap(),
+    )
+    .unwrap();
+    let key = Hash::new([0u8; 32]);
+    fs_cache.store(key, &module).unwrap();
+
+    c.bench_function(
+        "load compressed universal module in filesystem cache",
+        |b| b.iter(|| unsafe { fs_cache.load(&store, key.clone()).unwrap() }),
+    );
+}
+
 pub fn store_cache_native(c: &mut Criterion) {
     let tmp_dir = TempDir::new().unwrap();
     let mut fs_cache = FileSystemCache::new(tmp_dir.path()).unwrap();
@@ -90,6 +133,6 @@ pub fn load_cache_native(c: &mut Criterion) {
 criterion_group! {
     name = benches;
     config = Criterion::default().sample_size(300);
-    targets = store_cache_universal, load_cache_universal, store_cache_native, load_cache_native
+    targets = store_cache_universal, load_cache_universal, store_compressed_cache_universal, load_compressed_cache_universal, store_cache_native, load_cache_native
 }
 criterion_main!(benches);
@@ -1,15 +1,28 @@
 use crate::cache::Cache;
 use crate::hash::Hash;
-use std::fs::{create_dir_all, File};
+use filetime::FileTime;
+use fs2::FileExt;
+use std::fs::{self, create_dir_all, File, OpenOptions};
 use std::io::{self, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tempfile::NamedTempFile;
 use wasmer::{DeserializeError, Module, SerializeError, Store};
 
+/// The name of the file locked by the processes sharing a cache directory.
+const LOCK_FILENAME: &str = ".wasmer-cache.lock";
+
 /// Representation of a directory that contains compiled wasm artifacts.
 ///
 /// The `FileSystemCache` type implements the [`Cache`] trait, which allows it to be used
 /// generically when some sort of cache is required.
 ///
+/// The cache can be bounded in size and in number of entries, in which case
+/// the least recently used entries are evicted when storing a new module.
+/// Modules are written to a temporary file that is then renamed, and the
+/// cache directory is protected by a file lock, so several processes can
+/// share the same cache directory.
+///
 /// # Usage
 ///
 /// ```
@@ -33,6 +46,9 @@ use wasmer::{DeserializeError, Module, SerializeError, Store};
 pub struct FileSystemCache {
     path: PathBuf,
     ext: Option<String>,
+    max_size: Option<u64>,
+    max_entries: Option<usize>,
+    compress: bool,
 }
 
 impl FileSystemCache {
@@ -43,7 +59,7 @@ impl FileSystemCache {
             let metadata = path.metadata()?;
             if metadata.is_dir() {
                 if !metadata.permissions().readonly() {
-                    Ok(Self { path, ext: None })
+                    Ok(Self::with_path(path))
                 } else {
                     // This directory is readonly.
                     Err(io::Error::new(
@@ -70,11 +86,21 @@ impl FileSystemCache {
                     format!("failed to create cache directory: {}", path.display()),
                 ))
             } else {
-                Ok(Self { path, ext: None })
+                Ok(Self::with_path(path))
             }
         }
     }
 
+    fn with_path(path: PathBuf) -> Self {
+        Self {
+            path,
+            ext: None,
+            max_size: None,
+            max_entries: None,
+            compress: false,
+        }
+    }
+
     /// Set the extension for this cached file.
     ///
     /// This is needed for loading native files from Windows, as otherwise
@@ -82,6 +108,124 @@ impl FileSystemCache {
     pub fn set_cache_extension(&mut self, ext: Option<impl ToString>) {
         self.ext = ext.map(|ext| ext.to_string());
     }
+
+    /// Set the maximum size in bytes of the cached files.
+    ///
+    /// When storing a module makes the cache exceed this size, the least
+    /// recently used modules are evicted. `None` means no limit.
+    pub fn set_max_size(&mut self, max_size: Option<u64>) {
+        self.max_size = max_size;
+    }
+
+    /// Set the maximum number of cached modules.
+    ///
+    /// When storing a module makes the cache exceed this number of modules,
+    /// the least recently used modules are evicted. `None` means no limit.
+    pub fn set_max_entries(&mut self, max_entries: Option<usize>) {
+        self.max_entries = max_entries;
+    }
+
+    /// Set whether modules are stored compressed.
+    ///
+    /// Compressed modules take less space and are decompressed
+    /// transparently when loaded, at the cost of a slower load. Engines
+    /// that can't compress their artifacts store them uncompressed.
+    pub fn set_compression(&mut self, compress: bool) {
+        self.compress = compress;
+    }
+
+    fn filename(&self, key: Hash) -> String {
+        if let Some(ref ext) = self.ext {
+            format!("{}.{}", key.to_string(), ext)
+        } else {
+            key.to_string()
+        }
+    }
+
+    /// Returns whether `filename` is the name of a module of this cache.
+    fn is_entry(&self, filename: &str) -> bool {
+        let key = match self.ext {
+            Some(ref ext) => match filename.strip_suffix(ext.as_str()) {
+                Some(stem) => match stem.strip_suffix('.') {
+                    Some(key) => key,
+                    None => return false,
+                },
+                None => return false,
+            },
+            None => filename,
+        };
+        Hash::from_str(key).is_ok()
+    }
+
+    /// Opens and locks the lock file of the cache directory, which is
+    /// unlocked when the returned file is dropped.
+    fn lock(&self, exclusive: bool) -> io::Result<File> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(self.path.join(LOCK_FILENAME))?;
+        if exclusive {
+            file.lock_exclusive()?;
+        } else {
+            file.lock_shared()?;
+        }
+        Ok(file)
+    }
+
+    /// Removes the least recently used modules until the cache fits in its
+    /// limits, sparing the module at `keep`.
+    ///
+    /// The cache directory must be locked exclusively.
+    fn evict(&self, keep: &Path) -> io::Result<()> {
+        if self.max_size.is_none() && self.max_entries.is_none() {
+            return Ok(());
+        }
+
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(&self.path)? {
+            let entry = entry?;
+            // Skip the lock file and the temporary files being written.
+            let is_entry = entry
+                .file_name()
+                .to_str()
+                .map_or(false, |f| self.is_entry(f));
+            if !is_entry {
+                continue;
+            }
+            let metadata = entry.metadata()?;
+            if !metadata.is_file() {
+                continue;
+            }
+            let accessed = FileTime::from_last_access_time(&metadata);
+            entries.push((accessed, metadata.len(), entry.path()));
+        }
+        // Most recently used first.
+        entries.sort_by(|a, b| b.0.cmp(&a.0));
+
+        let mut size = entries.iter().map(|(_, len, _)| len).sum::<u64>();
+        let mut count = entries.len();
+        while let Some((_, len, path)) = entries.pop() {
+            let over_size = self.max_size.map_or(false, |max| size > max);
+            let over_count = self.max_entries.map_or(false, |max| count > max);
+            if !over_size && !over_count {
+                break;
+            }
+            if path == keep {
+                continue;
+            }
+            match fs::remove_file(&path) {
+                Ok(()) => {}
+                // On Windows, a loaded dynamic library can't be removed.
+                Err(e) if e.kind() == io::ErrorKind::PermissionDenied => continue,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+            size -= len;
+            count -= 1;
+        }
+        Ok(())
+    }
 }
 
 impl Cache for FileSystemCache {
@@ -89,27 +233,66 @@ impl Cache for FileSystemCache {
     type SerializeError = SerializeError;
 
     unsafe fn load(&self, store: &Store, key: Hash) -> Result<Module, Self::DeserializeError> {
-        let filename = if let Some(ref ext) = self.ext {
-            format!("{}.{}", key.to_string(), ext)
-        } else {
-            key.to_string()
-        };
-        let path = self.path.join(filename);
-        Module::deserialize_from_file(&store, path)
+        let path = self.path.join(self.filename(key));
+        // The shared lock prevents the module from being evicted while it's
+        // being loaded.
+        let _lock = self.lock(false)?;
+        let module = Module::deserialize_from_file(&store, &path)?;
+        // Record the use of the module for the LRU eviction, regardless of
+        // whether the filesystem updates access times itself.
+        filetime::set_file_atime(&path, FileTime::now())?;
+        Ok(module)
     }
 
     fn store(&mut self, key: Hash, module: &Module) -> Result<(), Self::SerializeError> {
-        let filename = if let Some(ref ext) = self.ext {
-            format!("{}.{}", key.to_string(), ext)
+        let path = self.path.join(self.filename(key));
+        let buffer = if self.compress {
+            module.serialize_compressed()?
         } else {
-            key.to_string()
+            module.serialize()?
         };
-        let path = self.path.join(filename);
-        let mut file = File::create(path)?;
 
-        let buffer = module.serialize()?;
+        // Write the module to a temporary file in the cache directory first,
+        // so other processes never see a partially written module.
+        let mut file = NamedTempFile::new_in(&self.path)?;
         file.write_all(&buffer)?;
+        file.as_file().sync_data()?;
+
+        let _lock = self.lock(true)?;
+        file.persist(&path).map_err(|e| e.error)?;
+        self.evict(&path)?;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{empty_module, keys, new_store};
+    use tempfile::TempDir;
+
+    #[test]
+    fn evicts_least_recently_used_modules() {
+        let tmp_dir = TempDir::new().unwrap();
+        let mut fs_cache = FileSystemCache::new(tmp_dir.path()).unwrap();
+        fs_cache.set_max_entries(Some(2));
+        let store = new_store();
+        let module = empty_module(&store);
+
+        let keys = keys();
+        fs_cache.store(keys[0], &module).unwrap();
+        fs_cache.store(keys[1], &module).unwrap();
+        // Make the first module the most recently used one.
+        let past = FileTime::from_unix_time(0, 0);
+        filetime::set_file_atime(tmp_dir.path().join(keys[1].to_string()), past).unwrap();
+        unsafe { fs_cache.load(&store, keys[0]).unwrap() };
+        fs_cache.store(keys[2], &module).unwrap();
+
+        unsafe {
+            assert!(fs_cache.load(&store, keys[0]).is_ok());
+            assert!(fs_cache.load(&store, keys[1]).is_err());
+            assert!(fs_cache.load(&store, keys[2]).is_ok());
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use crate::hash::Hash;
+use wasmer::{Features, Target};
+
+/// A builder of the [`Hash`] identifying a module compiled with a given
+/// configuration.
+///
+/// Hashing only the WebAssembly binary would reuse cached modules that were
+/// compiled for another engine, compiler, set of features or target, so the
+/// key also folds in everything that changes the compiled module, as well
+/// as the version of Wasmer.
+///
+/// # Usage
+///
+/// ```
+/// use wasmer::{Features, Target};
+/// use wasmer_cache::{Hash, KeyBuilder};
+///
+/// fn module_key(bytes: &[u8]) -> Hash {
+///     KeyBuilder::new(bytes)
+///         .engine("universal")
+///         .compiler("cranelift")
+///         .features(&Features::default())
+///         .target(&Target::default())
+///         .build()
+/// }
+/// ```
+pub struct KeyBuilder {
+    hasher: blake3::Hasher,
+}
+
+impl KeyBuilder {
+    /// Starts building the key of the given WebAssembly binary.
+    pub fn new(bytes: &[u8]) -> Self {
+        Self::from_module_hash(Hash::generate(bytes))
+    }
+
+    /// Starts building the key of the WebAssembly binary with the given
+    /// hash, such as a user provided key.
+    pub fn from_module_hash(hash: Hash) -> Self {
+        let mut builder = Self {
+            hasher: blake3::Hasher::new(),
+        };
+        builder.update(b"module", &hash.to_array());
+        builder.update(b"version", wasmer::VERSION.as_bytes());
+        builder
+    }
+
+    /// Folds in the name of the engine.
+    pub fn engine(mut self, name: &str) -> Self {
+        self.update(b"engine", name.as_bytes());
+        self
+    }
+
+    /// Folds in the name of the compiler.
+    pub fn compiler(mut self, name: &str) -> Self {
+        self.update(b"compiler", name.as_bytes());
+        self
+    }
+
+    /// Folds in a named setting of the compiler, such as an optimization
+    /// level or the configuration of a middleware.
+    pub fn compiler_setting(mut self, name: &str, value: &str) -> Self {
+        self.update(b"compiler-setting", name.as_bytes());
+        self.update(b"compiler-setting-value", value.as_bytes());
+        self
+    }
+
+    /// Folds in the enabled WebAssembly features.
+    pub fn features(mut self, features: &Features) -> Self {
+        let Features {
+            threads,
+            reference_types,
+            simd,
+            bulk_memory,
+            multi_value,
+            tail_call,
+            module_linking,
+            multi_memory,
+            memory64,
+            exceptions,
+        } = *features;
+        let flags = [
+            threads,
+            reference_types,
+            simd,
+            bulk_memory,
+            multi_value,
+            tail_call,
+            module_linking,
+            multi_memory,
+            memory64,
+            exceptions,
+        ];
+        let bytes = flags.iter().map(|&flag| flag as u8).collect::<Vec<_>>();
+        self.update(b"features", &bytes);
+        self
+    }
+
+    /// Folds in the target triple and CPU features.
+    pub fn target(mut self, target: &Target) -> Self {
+        self.update(b"triple", target.triple().to_string().as_bytes());
+        self.update(
+            b"cpu-features",
+            &target.cpu_features().as_u64().to_le_bytes(),
+        );
+        self
+    }
+
+    /// Returns the key.
+    pub fn build(self) -> Hash {
+        Hash::new(self.hasher.finalize().into())
+    }
+
+    /// Hashes a tagged and length prefixed field, so that different
+    /// sequences of fields never hash the same bytes.
+    fn update(&mut self, tag: &[u8], bytes: &[u8]) {
+        self.hasher.update(&(tag.len() as u64).to_le_bytes());
+        self.hasher.update(tag);
+        self.hasher.update(&(bytes.len() as u64).to_le_bytes());
+        self.hasher.update(bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_depends_on_configuration() {
+        let bytes = b"\0asm\x01\0\0\0";
+        let key = |features: &Features| {
+            KeyBuilder::new(bytes)
+                .engine("universal")
+                .compiler("cranelift")
+                .features(features)
+                .target(&Target::default())
+                .build()
+        };
+        let mut features = Features::default();
+        assert_eq!(key(&features), key(&features));
+        let default_key = key(&features);
+        features.simd(!features.simd);
+        assert_ne!(key(&features), default_key);
+        assert_ne!(
+            KeyBuilder::new(bytes).engine("dylib").build(),
+            KeyBuilder::new(bytes).engine("universal").build()
+        );
+    }
+}
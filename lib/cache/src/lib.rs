@@ -22,10 +22,20 @@
 mod cache;
 mod filesystem;
 mod hash;
+mod key;
+mod memory;
+mod remote;
+#[cfg(test)]
+mod test_utils;
+mod tiered;
 
 pub use crate::cache::Cache;
 pub use crate::filesystem::FileSystemCache;
 pub use crate::hash::Hash;
+pub use crate::key::KeyBuilder;
+pub use crate::memory::MemoryCache;
+pub use crate::remote::{CacheBackend, DirectoryBackend, RemoteCache};
+pub use crate::tiered::TieredCache;
 
 // We re-export those for convinience of users
 pub use wasmer::{DeserializeError, SerializeError};
@@ -0,0 +1,174 @@
+use crate::cache::Cache;
+use crate::hash::Hash;
+use std::collections::HashMap;
+use std::io;
+use std::sync::Mutex;
+use wasmer::{DeserializeError, Module, SerializeError, Store};
+
+/// A cache keeping compiled modules in memory, within a byte budget.
+///
+/// The size of a module is the memory used by its compiled artifact. When
+/// storing a module makes the cache exceed its budget, the least recently
+/// used modules are evicted.
+///
+/// Modules are only loaded into the store they were compiled for, or a
+/// store sharing its engine.
+///
+/// # Usage
+///
+/// ```
+/// use wasmer::{Module, SerializeError};
+/// use wasmer_cache::{Cache, Hash, MemoryCache};
+///
+/// fn store_module(module: &Module, bytes: &[u8]) -> Result<(), SerializeError> {
+///     // Create a memory cache holding up to 64MiB of modules.
+///     let mut memory_cache = MemoryCache::new(64 << 20);
+///
+///     // Store a module into the cache given a key
+///     memory_cache.store(Hash::generate(bytes), module)?;
+///
+///     Ok(())
+/// }
+/// ```
+pub struct MemoryCache {
+    max_size: u64,
+    inner: Mutex<MemoryCacheInner>,
+}
+
+struct MemoryCacheInner {
+    entries: HashMap<Hash, MemoryCacheEntry>,
+    size: u64,
+    /// Incremented on every use of a module, to order the entries by last
+    /// use.
+    clock: u64,
+}
+
+struct MemoryCacheEntry {
+    module: Module,
+    size: u64,
+    last_use: u64,
+}
+
+impl MemoryCache {
+    /// Creates an empty cache holding up to `max_size` bytes of modules.
+    pub fn new(max_size: u64) -> Self {
+        Self {
+            max_size,
+            inner: Mutex::new(MemoryCacheInner {
+                entries: HashMap::new(),
+                size: 0,
+                clock: 0,
+            }),
+        }
+    }
+
+    /// Returns the number of cached modules.
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().entries.len()
+    }
+
+    /// Returns whether no module is cached.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the size in bytes of the cached modules.
+    pub fn size(&self) -> u64 {
+        self.inner.lock().unwrap().size
+    }
+
+    /// Removes all the cached modules.
+    pub fn clear(&mut self) {
+        let inner = self.inner.get_mut().unwrap();
+        inner.entries.clear();
+        inner.size = 0;
+    }
+}
+
+impl Cache for MemoryCache {
+    type DeserializeError = DeserializeError;
+    type SerializeError = SerializeError;
+
+    unsafe fn load(&self, store: &Store, key: Hash) -> Result<Module, Self::DeserializeError> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.clock += 1;
+        let clock = inner.clock;
+        match inner.entries.get_mut(&key) {
+            Some(entry) if Store::same(entry.module.store(), store) => {
+                entry.last_use = clock;
+                Ok(entry.module.clone())
+            }
+            _ => Err(DeserializeError::Io(io::Error::new(
+                io::ErrorKind::NotFound,
+                "the module is not in the memory cache",
+            ))),
+        }
+    }
+
+    fn store(&mut self, key: Hash, module: &Module) -> Result<(), Self::SerializeError> {
+        let size = loupe::size_of_val(module.artifact()) as u64;
+        let max_size = self.max_size;
+        let inner = self.inner.get_mut().unwrap();
+        if let Some(previous) = inner.entries.remove(&key) {
+            inner.size -= previous.size;
+        }
+        if size > max_size {
+            return Ok(());
+        }
+
+        while inner.size + size > max_size {
+            let oldest = inner
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_use)
+                .map(|(key, _)| *key)
+                .expect("the cache can't be empty while exceeding its budget");
+            let evicted = inner.entries.remove(&oldest).unwrap();
+            inner.size -= evicted.size;
+        }
+
+        inner.clock += 1;
+        let last_use = inner.clock;
+        inner.entries.insert(
+            key,
+            MemoryCacheEntry {
+                module: module.clone(),
+                size,
+                last_use,
+            },
+        );
+        inner.size += size;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{empty_module, keys, new_store};
+
+    #[test]
+    fn evicts_least_recently_used_modules() {
+        let store = new_store();
+        let module = empty_module(&store);
+        let module_size = loupe::size_of_val(module.artifact()) as u64;
+        let mut memory_cache = MemoryCache::new(2 * module_size);
+
+        let keys = keys();
+        memory_cache.store(keys[0], &module).unwrap();
+        memory_cache.store(keys[1], &module).unwrap();
+        unsafe { memory_cache.load(&store, keys[0]).unwrap() };
+        memory_cache.store(keys[2], &module).unwrap();
+
+        assert_eq!(memory_cache.len(), 2);
+        assert_eq!(memory_cache.size(), 2 * module_size);
+        unsafe {
+            assert!(memory_cache.load(&store, keys[0]).is_ok());
+            assert!(memory_cache.load(&store, keys[1]).is_err());
+            assert!(memory_cache.load(&store, keys[2]).is_ok());
+        }
+
+        let other_store = new_store();
+        assert!(unsafe { memory_cache.load(&other_store, keys[0]) }.is_err());
+    }
+}
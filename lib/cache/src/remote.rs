@@ -0,0 +1,109 @@
+use crate::cache::Cache;
+use crate::hash::Hash;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use tempfile::NamedTempFile;
+use wasmer::{DeserializeError, Module, SerializeError, Store};
+
+/// A storage of serialized modules, such as a remote blob storage, backing
+/// a [`RemoteCache`].
+pub trait CacheBackend {
+    /// Returns the serialized module stored with the given key, or `None` if
+    /// there is no such module.
+    fn get(&self, key: Hash) -> io::Result<Option<Vec<u8>>>;
+
+    /// Stores the serialized module with the given key, replacing any
+    /// module previously stored with the same key.
+    fn put(&self, key: Hash, bytes: &[u8]) -> io::Result<()>;
+}
+
+/// A cache storing serialized modules in a [`CacheBackend`].
+///
+/// # Usage
+///
+/// ```
+/// use wasmer::{Module, SerializeError};
+/// use wasmer_cache::{Cache, DirectoryBackend, Hash, RemoteCache};
+///
+/// fn store_module(module: &Module, bytes: &[u8]) -> Result<(), SerializeError> {
+///     let backend = DirectoryBackend::new("some/directory/goes/here")?;
+///     let mut remote_cache = RemoteCache::new(backend);
+///
+///     remote_cache.store(Hash::generate(bytes), module)?;
+///
+///     Ok(())
+/// }
+/// ```
+pub struct RemoteCache<B> {
+    backend: B,
+}
+
+impl<B: CacheBackend> RemoteCache<B> {
+    /// Creates a cache storing modules in `backend`.
+    pub fn new(backend: B) -> Self {
+        Self { backend }
+    }
+
+    /// Returns the backend of the cache.
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+}
+
+impl<B: CacheBackend> Cache for RemoteCache<B> {
+    type DeserializeError = DeserializeError;
+    type SerializeError = SerializeError;
+
+    unsafe fn load(&self, store: &Store, key: Hash) -> Result<Module, Self::DeserializeError> {
+        let bytes = self.backend.get(key)?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "the module is not in the remote cache",
+            )
+        })?;
+        Module::deserialize(store, &bytes)
+    }
+
+    fn store(&mut self, key: Hash, module: &Module) -> Result<(), Self::SerializeError> {
+        let bytes = module.serialize()?;
+        self.backend.put(key, &bytes)?;
+        Ok(())
+    }
+}
+
+/// A [`CacheBackend`] storing the serialized modules in a local directory.
+///
+/// It's a stand-in for remote backends, to test them locally or to share
+/// modules through a network filesystem.
+pub struct DirectoryBackend {
+    path: PathBuf,
+}
+
+impl DirectoryBackend {
+    /// Creates a backend storing the modules in the given directory, which
+    /// is created if it doesn't exist yet.
+    pub fn new<P: Into<PathBuf>>(path: P) -> io::Result<Self> {
+        let path = path.into();
+        fs::create_dir_all(&path)?;
+        Ok(Self { path })
+    }
+}
+
+impl CacheBackend for DirectoryBackend {
+    fn get(&self, key: Hash) -> io::Result<Option<Vec<u8>>> {
+        match fs::read(self.path.join(key.to_string())) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn put(&self, key: Hash, bytes: &[u8]) -> io::Result<()> {
+        let mut file = NamedTempFile::new_in(&self.path)?;
+        file.write_all(bytes)?;
+        file.persist(self.path.join(key.to_string()))
+            .map_err(|e| e.error)?;
+        Ok(())
+    }
+}
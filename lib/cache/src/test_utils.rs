@@ -0,0 +1,24 @@
+//! Fixtures shared by the tests of the caches.
+
+use crate::hash::Hash;
+use wasmer::{Module, Store};
+use wasmer_compiler_singlepass::Singlepass;
+use wasmer_engine_universal::Universal;
+
+/// The bytes of an empty module.
+pub(crate) const EMPTY_MODULE: &[u8] = b"\0asm\x01\0\0\0";
+
+/// A store with an engine of its own.
+pub(crate) fn new_store() -> Store {
+    Store::new(&Universal::new(Singlepass::default()).engine())
+}
+
+/// The empty module, compiled for `store`.
+pub(crate) fn empty_module(store: &Store) -> Module {
+    Module::new(store, EMPTY_MODULE).unwrap()
+}
+
+/// Three distinct keys.
+pub(crate) fn keys() -> [Hash; 3] {
+    [Hash::new([1; 32]), Hash::new([2; 32]), Hash::new([3; 32])]
+}
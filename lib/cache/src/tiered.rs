@@ -0,0 +1,148 @@
+use crate::cache::Cache;
+use crate::hash::Hash;
+use wasmer::{CompileError, Module, Store};
+
+/// A cache layering a fast cache in front of a slower one, such as a
+/// [`MemoryCache`] in front of a [`FileSystemCache`].
+///
+/// Modules are looked up in the fast cache first, then in the slow one.
+/// Stored modules go to both caches, and [`TieredCache::load_and_promote`]
+/// also stores the modules found in the slow cache into the fast one.
+///
+/// # Usage
+///
+/// ```
+/// use std::error::Error;
+/// use wasmer::{Module, Store};
+/// use wasmer_cache::{FileSystemCache, Hash, MemoryCache, TieredCache};
+///
+/// fn get_module(store: &Store, bytes: &[u8]) -> Result<Module, Box<dyn Error>> {
+///     let fs_cache = FileSystemCache::new("some/directory/goes/here")?;
+///     let mut cache = TieredCache::new(MemoryCache::new(64 << 20), fs_cache);
+///
+///     // Looks up the module in memory, then on disk, and compiles it if
+///     // it's in neither cache.
+///     let module = unsafe { cache.load_or_compile(store, Hash::generate(bytes), bytes)? };
+///     Ok(module)
+/// }
+/// ```
+///
+/// [`MemoryCache`]: crate::MemoryCache
+/// [`FileSystemCache`]: crate::FileSystemCache
+pub struct TieredCache<F, S> {
+    fast: F,
+    slow: S,
+}
+
+impl<F, S> TieredCache<F, S>
+where
+    F: Cache,
+    S: Cache<DeserializeError = F::DeserializeError, SerializeError = F::SerializeError>,
+{
+    /// Creates a cache layering `fast` in front of `slow`.
+    pub fn new(fast: F, slow: S) -> Self {
+        Self { fast, slow }
+    }
+
+    /// Returns the fast cache.
+    pub fn fast(&self) -> &F {
+        &self.fast
+    }
+
+    /// Returns the slow cache.
+    pub fn slow(&self) -> &S {
+        &self.slow
+    }
+
+    /// Loads the module from the caches, storing it into the fast cache if it
+    /// was only found in the slow one.
+    ///
+    /// # Safety
+    /// This function is unsafe as the cache store could be tampered with.
+    pub unsafe fn load_and_promote(
+        &mut self,
+        store: &Store,
+        key: Hash,
+    ) -> Result<Module, F::DeserializeError> {
+        if let Ok(module) = self.fast.load(store, key) {
+            return Ok(module);
+        }
+        let module = self.slow.load(store, key)?;
+        // The module is still usable if the fast cache fails to store it.
+        let _ = self.fast.store(key, &module);
+        Ok(module)
+    }
+
+    /// Loads the module from the caches, or compiles `bytes` and stores the
+    /// module in the caches.
+    ///
+    /// Failing to store the compiled module doesn't make this function
+    /// fail, as the module is still usable.
+    ///
+    /// # Safety
+    /// This function is unsafe as the cache store could be tampered with.
+    pub unsafe fn load_or_compile(
+        &mut self,
+        store: &Store,
+        key: Hash,
+        bytes: &[u8],
+    ) -> Result<Module, CompileError> {
+        if let Ok(module) = self.load_and_promote(store, key) {
+            return Ok(module);
+        }
+        let module = Module::new(store, bytes)?;
+        let _ = self.store(key, &module);
+        Ok(module)
+    }
+}
+
+impl<F, S> Cache for TieredCache<F, S>
+where
+    F: Cache,
+    S: Cache<DeserializeError = F::DeserializeError, SerializeError = F::SerializeError>,
+{
+    type DeserializeError = F::DeserializeError;
+    type SerializeError = F::SerializeError;
+
+    unsafe fn load(&self, store: &Store, key: Hash) -> Result<Module, Self::DeserializeError> {
+        self.fast
+            .load(store, key)
+            .or_else(|_| self.slow.load(store, key))
+    }
+
+    fn store(&mut self, key: Hash, module: &Module) -> Result<(), Self::SerializeError> {
+        self.slow.store(key, module)?;
+        self.fast.store(key, module)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::MemoryCache;
+    use crate::remote::{DirectoryBackend, RemoteCache};
+    use crate::test_utils::{new_store, EMPTY_MODULE};
+    use tempfile::TempDir;
+
+    #[test]
+    fn promotes_modules_of_the_slow_cache() {
+        let tmp_dir = TempDir::new().unwrap();
+        let store = new_store();
+        let bytes = EMPTY_MODULE;
+        let key = Hash::generate(bytes);
+
+        let mut remote_cache = RemoteCache::new(DirectoryBackend::new(tmp_dir.path()).unwrap());
+        let module = Module::new(&store, bytes).unwrap();
+        remote_cache.store(key, &module).unwrap();
+
+        let mut cache = TieredCache::new(MemoryCache::new(1 << 20), remote_cache);
+        assert!(cache.fast().is_empty());
+        unsafe { cache.load_and_promote(&store, key).unwrap() };
+        assert_eq!(cache.fast().len(), 1);
+
+        let other_key = Hash::new([0; 32]);
+        unsafe { cache.load_or_compile(&store, other_key, bytes).unwrap() };
+        assert_eq!(cache.fast().len(), 2);
+        assert!(cache.slow().backend().get(other_key).unwrap().is_some());
+    }
+}
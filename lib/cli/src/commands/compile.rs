@@ -29,6 +29,16 @@ pub struct Compile {
 
     #[structopt(short = "m", multiple = true)]
     cpu_features: Vec<CpuFeature>,
+
+    /// Sign the compiled artifact with the ed25519 secret key in this file
+    /// (the raw 32 bytes of the key)
+    #[structopt(long = "sign-key", parse(from_os_str))]
+    sign_key: Option<PathBuf>,
+
+    /// Print the compile time, machine code size, relocation count and
+    /// wasm body size of each function, slowest to compile first
+    #[structopt(long = "stats")]
+    stats: bool,
 }
 
 impl Compile {
@@ -60,6 +70,49 @@ impl Compile {
         })
     }
 
+    fn print_stats(module: &Module) {
+        let stats = match module.compile_stats() {
+            Some(stats) => stats,
+            None => {
+                warning!("the selected engine doesn't collect compile statistics");
+                return;
+            }
+        };
+        let module_info = module.info();
+        let mut functions = stats.functions().iter().collect::<Vec<_>>();
+        functions.sort_by(|(_, a), (_, b)| b.compile_time.cmp(&a.compile_time));
+
+        println!(
+            "{:>12} {:>12} {:>12} {:>12}  function",
+            "time (ms)", "code size", "relocations", "wasm size"
+        );
+        let print_row = |name: &str, function: &FunctionCompileStats| {
+            println!(
+                "{:>12.3} {:>12} {:>12} {:>12}  {}",
+                function.compile_time.as_secs_f64() * 1000.0,
+                function.code_size,
+                function.relocations,
+                function.wasm_size,
+                name
+            );
+        };
+        for (local_index, function) in functions {
+            let index = module_info.func_index(local_index);
+            let name = module_info
+                .function_names
+                .get(&index)
+                .cloned()
+                .unwrap_or_else(|| format!("function[{}]", index.as_u32()));
+            print_row(&name, function);
+        }
+        print_row("total", &stats.total());
+        println!(
+            "Compiled {} functions in {:.3} ms",
+            stats.functions().len(),
+            stats.compile_time().as_secs_f64() * 1000.0
+        );
+    }
+
     fn inner_execute(&self) -> Result<()> {
         let target = self
             .target_triple
@@ -98,8 +151,25 @@ impl Compile {
         println!("Compiler: {}", compiler_type.to_string());
         println!("Target: {}", target.triple());
 
+        let signing_key = self
+            .sign_key
+            .as_ref()
+            .map(|path| -> Result<SigningKey> {
+                let key = std::fs::read(path).with_context(|| {
+                    format!("failed to read the signing key `{}`", path.display())
+                })?;
+                Ok(SigningKey::from_secret_key(&key)?)
+            })
+            .transpose()?;
+
         let module = Module::from_file(&store, &self.path)?;
-        let _ = module.serialize_to_file(&self.output)?;
+        if self.stats {
+            Self::print_stats(&module);
+        }
+        match &signing_key {
+            Some(signing_key) => module.serialize_to_file_signed(&self.output, signing_key)?,
+            None => module.serialize_to_file(&self.output)?,
+        }
         eprintln!(
             "✔ File compiled successfully to `{}`.",
             self.output.display(),
@@ -7,9 +7,13 @@ use crate::warning;
 use anyhow::{anyhow, Context, Result};
 use std::path::PathBuf;
 use std::str::FromStr;
+#[cfg(feature = "compiler")]
+use std::sync::Arc;
 use wasmer::*;
 #[cfg(feature = "cache")]
-use wasmer_cache::{Cache, FileSystemCache, Hash};
+use wasmer_cache::{Cache, FileSystemCache, Hash, KeyBuilder};
+#[cfg(feature = "compiler")]
+use wasmer_middlewares::profiling::{Profile, Profiler, Profiling};
 
 use structopt::StructOpt;
 
@@ -46,6 +50,22 @@ pub struct Run {
     #[structopt(long = "cache-key", hidden = true)]
     cache_key: Option<String>,
 
+    /// Only run precompiled artifacts signed by the ed25519 public key in
+    /// this file (the raw 32 bytes of the key). Can be given multiple times
+    #[structopt(long = "trusted-key", parse(from_os_str), number_of_values = 1)]
+    trusted_keys: Vec<PathBuf>,
+
+    /// Profile the calls of the functions of the module, and write the
+    /// profile to this file in the folded stacks format of flame graphs
+    #[cfg(feature = "compiler")]
+    #[structopt(long = "profile", parse(from_os_str))]
+    profile: Option<PathBuf>,
+
+    /// The profiler of the module, when profiling
+    #[cfg(feature = "compiler")]
+    #[structopt(skip)]
+    profiler: Profiler,
+
     #[structopt(flatten)]
     store: StoreOptions,
 
@@ -54,6 +74,11 @@ pub struct Run {
     #[structopt(flatten)]
     wasi: Wasi,
 
+    /// Allow Emscripten modules to use the network
+    #[cfg(feature = "emscripten")]
+    #[structopt(long = "allow-networking")]
+    allow_networking: bool,
+
     /// Enable non-standard experimental IO devices
     #[cfg(feature = "io-devices")]
     #[structopt(long = "enable-io-devices")]
@@ -93,9 +118,11 @@ impl Run {
         let module = self.get_module()?;
         // Do we want to invoke a function?
         if let Some(ref invoke) = self.invoke {
-            let imports = imports! {};
+            let imports = self.profiler_imports(module.store());
             let instance = Instance::new(&module, &imports)?;
-            let result = self.invoke_function(&instance, &invoke, &self.args)?;
+            let result = self.invoke_function(&instance, &invoke, &self.args);
+            self.write_profile(&instance)?;
+            let result = result?;
             println!(
                 "{}",
                 result
@@ -116,9 +143,15 @@ impl Run {
             if is_emscripten_module(&module) {
                 let mut emscripten_globals = EmscriptenGlobals::new(module.store(), &module)
                     .map_err(|e| anyhow!("{}", e))?;
-                let mut em_env = EmEnv::new(&emscripten_globals.data, Default::default());
+                #[cfg(feature = "wasi")]
+                let mut sandbox = self.wasi.emscripten_sandbox()?;
+                #[cfg(not(feature = "wasi"))]
+                let mut sandbox = wasmer_emscripten::EmSandbox::new();
+                sandbox.allow_networking(self.allow_networking);
+                let mut em_env = EmEnv::new(&emscripten_globals.data, sandbox);
                 let import_object =
-                    generate_emscripten_env(module.store(), &mut emscripten_globals, &mut em_env);
+                    generate_emscripten_env(module.store(), &mut emscripten_globals, &mut em_env)
+                        .chain_back(self.profiler_imports(module.store()));
                 let mut instance = match Instance::new(&module, &import_object) {
                     Ok(instance) => instance,
                     Err(e) => {
@@ -133,7 +166,7 @@ impl Run {
                     }
                 };
 
-                run_emscripten_instance(
+                let result = run_emscripten_instance(
                     &mut instance,
                     &mut em_env,
                     &mut emscripten_globals,
@@ -144,8 +177,9 @@ impl Run {
                     },
                     self.args.iter().map(|arg| arg.as_str()).collect(),
                     None, //run.em_entrypoint.clone(),
-                )?;
-                return Ok(());
+                );
+                self.write_profile(&instance)?;
+                return result.map_err(|e| e.into());
             }
         }
 
@@ -184,9 +218,16 @@ impl Run {
                                 .map(|f| f.to_string_lossy().to_string())
                         })
                         .unwrap_or_default();
+                    let imports = self.profiler_imports(module.store());
                     return self
                         .wasi
-                        .execute(module, program_name, self.args.clone())
+                        .execute(
+                            module,
+                            program_name,
+                            self.args.clone(),
+                            imports,
+                            |instance| self.write_profile(instance),
+                        )
                         .with_context(|| "WASI execution failed");
                 }
                 // not WASI
@@ -195,35 +236,103 @@ impl Run {
         }
 
         // Try to instantiate the wasm file, with no provided imports
-        let imports = imports! {};
+        let imports = self.profiler_imports(module.store());
         let instance = Instance::new(&module, &imports)?;
         let start: Function = self.try_find_function(&instance, "_start", &[])?;
-        start.call(&[])?;
+        let result = start.call(&[]);
+        self.write_profile(&instance)?;
+        result?;
 
         Ok(())
     }
 
+    /// Get the imports of the profiler, if profiling
+    #[allow(unused_variables)]
+    fn profiler_imports(&self, store: &Store) -> ImportObject {
+        #[cfg(feature = "compiler")]
+        if self.profile.is_some() {
+            return self.profiler.imports(store);
+        }
+        ImportObject::new()
+    }
+
+    /// Write the profile of the instance to the `--profile` file, if
+    /// profiling
+    #[allow(unused_variables)]
+    fn write_profile(&self, instance: &Instance) -> Result<()> {
+        #[cfg(feature = "compiler")]
+        if let Some(path) = &self.profile {
+            let profile = Profile::with_timings(instance.module(), instance, &self.profiler);
+            std::fs::write(path, profile.to_folded())
+                .with_context(|| format!("failed to write the profile to `{}`", path.display()))?;
+        }
+        Ok(())
+    }
+
+    /// Check that the options allow running a precompiled artifact
+    #[allow(dead_code)]
+    fn check_compiled(&self) -> Result<()> {
+        #[cfg(feature = "compiler")]
+        if self.profile.is_some() {
+            bail!(
+                "`--profile` needs to compile `{}`, precompiled artifacts can't be profiled",
+                self.path.display()
+            );
+        }
+        Ok(())
+    }
+
+    /// Get the store to compile the module with
+    fn get_store(&self) -> Result<(Store, EngineType, CompilerType)> {
+        #[cfg(feature = "compiler")]
+        if self.profile.is_some() {
+            return self
+                .store
+                .get_store_with_middlewares(vec![Arc::new(Profiling::with_timing())]);
+        }
+        self.store.get_store()
+    }
+
     fn get_module(&self) -> Result<Module> {
         let contents = std::fs::read(self.path.clone())?;
+        let trusted_keys = self.get_trusted_keys()?;
         #[cfg(feature = "dylib")]
         {
             if wasmer_engine_dylib::DylibArtifact::is_deserializable(&contents) {
+                self.check_compiled()?;
                 let engine = wasmer_engine_dylib::Dylib::headless().engine();
                 let store = Store::new(&engine);
-                let module = unsafe { Module::deserialize_from_file(&store, &self.path)? };
+                let module = match &trusted_keys {
+                    Some(trusted_keys) => unsafe {
+                        Module::deserialize_signed(&store, &contents, trusted_keys)?
+                    },
+                    None => unsafe { Module::deserialize_from_file(&store, &self.path)? },
+                };
                 return Ok(module);
             }
         }
         #[cfg(feature = "universal")]
         {
             if wasmer_engine_universal::UniversalArtifact::is_deserializable(&contents) {
+                self.check_compiled()?;
                 let engine = wasmer_engine_universal::Universal::headless().engine();
                 let store = Store::new(&engine);
-                let module = unsafe { Module::deserialize_from_file(&store, &self.path)? };
+                let module = match &trusted_keys {
+                    Some(trusted_keys) => unsafe {
+                        Module::deserialize_signed(&store, &contents, trusted_keys)?
+                    },
+                    None => unsafe { Module::deserialize_from_file(&store, &self.path)? },
+                };
                 return Ok(module);
             }
         }
-        let (store, engine_type, compiler_type) = self.store.get_store()?;
+        if trusted_keys.is_some() {
+            bail!(
+                "`{}` is not a precompiled artifact, but `--trusted-key` only allows running signed artifacts",
+                self.path.display()
+            );
+        }
+        let (store, engine_type, compiler_type) = self.get_store()?;
         #[cfg(feature = "cache")]
         let module_result: Result<Module> = if !self.disable_cache && contents.len() > 0x1000 {
             self.get_module_from_cache(&store, &contents, &engine_type, &compiler_type)
@@ -246,6 +355,22 @@ impl Run {
         Ok(module)
     }
 
+    /// Get the keys trusted to sign precompiled artifacts, if any
+    fn get_trusted_keys(&self) -> Result<Option<TrustedKeys>> {
+        if self.trusted_keys.is_empty() {
+            return Ok(None);
+        }
+        let mut trusted_keys = TrustedKeys::new();
+        for path in &self.trusted_keys {
+            let key = std::fs::read(path)
+                .with_context(|| format!("failed to read the trusted key `{}`", path.display()))?;
+            trusted_keys
+                .add(&key)
+                .with_context(|| format!("invalid trusted key `{}`", path.display()))?;
+        }
+        Ok(Some(trusted_keys))
+    }
+
     #[cfg(feature = "cache")]
     fn get_module_from_cache(
         &self,
@@ -261,11 +386,25 @@ impl Run {
         let mut cache = self.get_cache(engine_type, compiler_type)?;
         // Try to get the hash from the provided `--cache-key`, otherwise
         // generate one from the provided file `.wasm` contents.
-        let hash = self
+        let module_hash = self
             .cache_key
             .as_ref()
             .and_then(|key| Hash::from_str(&key).ok())
             .unwrap_or_else(|| Hash::generate(&contents));
+        // Fold in the configuration, so modules compiled with other
+        // options are never reused.
+        let target = store.engine().target();
+        #[allow(unused_mut)]
+        let mut key = KeyBuilder::from_module_hash(module_hash)
+            .engine(&engine_type.to_string())
+            .compiler(&compiler_type.to_string())
+            .features(&self.store.get_features_for_target(target)?)
+            .target(target);
+        #[cfg(feature = "compiler")]
+        if self.profile.is_some() {
+            key = key.compiler_setting("middleware", "profiling");
+        }
+        let hash = key.build();
         match unsafe { cache.load(&store, hash) } {
             Ok(module) => Ok(module),
             Err(e) => {
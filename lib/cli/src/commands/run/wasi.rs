@@ -1,9 +1,10 @@
-use crate::utils::{parse_envvar, parse_mapdir};
+use crate::utils::{parse_envvar, parse_mapdir, parse_mapdir_archive};
 use anyhow::{Context, Result};
 use std::collections::BTreeSet;
+use std::fs::File;
 use std::path::PathBuf;
-use wasmer::{Instance, Module};
-use wasmer_wasi::{get_wasi_versions, WasiError, WasiState, WasiVersion};
+use wasmer::{ChainableNamedResolver, ImportObject, Instance, Module};
+use wasmer_wasi::{get_wasi_versions, TraceFormat, TraceWriter, WasiError, WasiState, WasiVersion};
 
 use structopt::StructOpt;
 
@@ -18,10 +19,22 @@ pub struct Wasi {
     #[structopt(long = "mapdir", name = "GUEST_DIR:HOST_DIR", multiple = true, parse(try_from_str = parse_mapdir))]
     mapped_dirs: Vec<(String, PathBuf)>,
 
+    /// Mount a tar or zip archive as a read-only directory for the Wasm module
+    #[structopt(long = "mapdir-archive", name = "GUEST_DIR:ARCHIVE", multiple = true, parse(try_from_str = parse_mapdir_archive))]
+    mapped_archives: Vec<(String, PathBuf)>,
+
     /// Pass custom environment variables
     #[structopt(long = "env", name = "KEY=VALUE", multiple = true, parse(try_from_str = parse_envvar))]
     env_vars: Vec<(String, String)>,
 
+    /// Trace every WASI syscall to stderr, or to the given file
+    #[structopt(long = "wasi-trace", name = "TRACE_FILE", require_equals = true)]
+    wasi_trace: Option<Option<PathBuf>>,
+
+    /// The format of the WASI syscall trace: `strace` or `json` (one object per line)
+    #[structopt(long = "wasi-trace-format", default_value = "strace")]
+    wasi_trace_format: TraceFormat,
+
     /// Enable experimental IO devices
     #[cfg(feature = "experimental-io-devices")]
     #[structopt(long = "enable-experimental-io-devices")]
@@ -52,8 +65,30 @@ impl Wasi {
         get_wasi_versions(&module, false).is_some()
     }
 
+    /// Builds the sandbox of an Emscripten module from the directories
+    /// preopened and mapped for WASI.
+    #[cfg(feature = "emscripten")]
+    pub fn emscripten_sandbox(&self) -> Result<wasmer_emscripten::EmSandbox> {
+        let mut sandbox = wasmer_emscripten::EmSandbox::new();
+        sandbox
+            .preopen_dirs(self.pre_opened_directories.clone())?
+            .map_dirs(self.mapped_dirs.clone())?;
+        Ok(sandbox)
+    }
+
     /// Helper function for executing Wasi from the `Run` command.
-    pub fn execute(&self, module: Module, program_name: String, args: Vec<String>) -> Result<()> {
+    ///
+    /// The module is instantiated with the WASI imports and `imports`, and
+    /// `finish` is called with the instance once `_start` returns, even if
+    /// the program exits.
+    pub fn execute(
+        &self,
+        module: Module,
+        program_name: String,
+        args: Vec<String>,
+        imports: ImportObject,
+        finish: impl FnOnce(&Instance) -> Result<()>,
+    ) -> Result<()> {
         let args = args.iter().cloned().map(|arg| arg.into_bytes());
 
         let mut wasi_state_builder = WasiState::new(program_name);
@@ -61,7 +96,8 @@ impl Wasi {
             .args(args)
             .envs(self.env_vars.clone())
             .preopen_dirs(self.pre_opened_directories.clone())?
-            .map_dirs(self.mapped_dirs.clone())?;
+            .map_dirs(self.mapped_dirs.clone())?
+            .map_dir_archives(self.mapped_archives.clone())?;
 
         #[cfg(feature = "experimental-io-devices")]
         {
@@ -72,11 +108,26 @@ impl Wasi {
         }
 
         let mut wasi_env = wasi_state_builder.finalize()?;
-        let resolver = wasi_env.import_object_for_all_wasi_versions(&module)?;
+        match &self.wasi_trace {
+            Some(Some(trace_file)) => {
+                let out = File::create(trace_file).with_context(|| {
+                    format!("failed to create the trace file {}", trace_file.display())
+                })?;
+                wasi_env.set_tracer(TraceWriter::new(out, self.wasi_trace_format));
+            }
+            Some(None) => {
+                wasi_env.set_tracer(TraceWriter::new(std::io::stderr(), self.wasi_trace_format));
+            }
+            None => (),
+        }
+        let resolver = wasi_env
+            .import_object_for_all_wasi_versions(&module)?
+            .chain_back(imports);
         let instance = Instance::new(&module, &resolver)?;
 
         let start = instance.exports.get_function("_start")?;
         let result = start.call(&[]);
+        finish(&instance)?;
 
         match result {
             Ok(_) => Ok(()),
@@ -10,7 +10,7 @@ use std::sync::Arc;
 use structopt::StructOpt;
 use wasmer::*;
 #[cfg(feature = "compiler")]
-use wasmer_compiler::CompilerConfig;
+use wasmer_compiler::{CompilerConfig, ModuleMiddleware};
 
 #[derive(Debug, Clone, StructOpt)]
 /// The compiler and engine options
@@ -385,6 +385,29 @@ impl StoreOptions {
         Ok((store, engine_type, compiler_type))
     }
 
+    /// Gets the store for the host target, compiling the modules with the
+    /// given middlewares
+    pub fn get_store_with_middlewares(
+        &self,
+        middlewares: Vec<Arc<dyn ModuleMiddleware>>,
+    ) -> Result<(Store, EngineType, CompilerType)> {
+        let (mut compiler_config, compiler_type) = self.compiler.get_compiler_config()?;
+        for middleware in middlewares {
+            compiler_config.push_middleware(middleware);
+        }
+        let (engine, engine_type) =
+            self.get_engine_with_compiler(Target::default(), compiler_config)?;
+        let store = Store::new(&*engine);
+        Ok((store, engine_type, compiler_type))
+    }
+
+    /// Gets the WebAssembly features enabled when compiling for a given target
+    pub fn get_features_for_target(&self, target: &Target) -> Result<Features> {
+        let (compiler_config, _) = self.compiler.get_compiler_config()?;
+        self.compiler
+            .get_features(compiler_config.default_features_for_target(target))
+    }
+
     fn get_engine_with_compiler(
         &self,
         target: Target,
@@ -455,6 +478,11 @@ impl StoreOptions {
         Ok((store, engine_type, CompilerType::Headless))
     }
 
+    /// Gets the WebAssembly features enabled when compiling for a given target
+    pub fn get_features_for_target(&self, _target: &Target) -> Result<Features> {
+        Ok(Features::default())
+    }
+
     /// Gets the store for provided host target
     pub fn get_store_for_target(
         &self,
@@ -472,6 +500,21 @@ impl StoreOptions {
         bail!("No engines are enabled");
     }
 
+    /// Gets the store for the host target, compiling the modules with the
+    /// given middlewares
+    #[cfg(feature = "compiler")]
+    pub fn get_store_with_middlewares(
+        &self,
+        _middlewares: Vec<Arc<dyn ModuleMiddleware>>,
+    ) -> Result<(Store, EngineType, CompilerType)> {
+        bail!("No engines are enabled");
+    }
+
+    /// Gets the WebAssembly features enabled when compiling for a given target
+    pub fn get_features_for_target(&self, _target: &Target) -> Result<Features> {
+        bail!("No engines are enabled");
+    }
+
     /// Gets the store for the host target
     pub fn get_store_for_target(
         &self,
@@ -40,6 +40,32 @@ pub fn parse_mapdir(entry: &str) -> Result<(String, PathBuf)> {
     }
 }
 
+/// Parses an archive mapping from a string
+pub fn parse_mapdir_archive(entry: &str) -> Result<(String, PathBuf)> {
+    if let [alias, archive] = entry.split("::").collect::<Vec<&str>>()[..] {
+        retrieve_alias_archive(alias, archive)
+    } else if let [alias, archive] = entry.split(':').collect::<Vec<&str>>()[..] {
+        retrieve_alias_archive(alias, archive)
+    } else {
+        bail!(
+            "Archive mappings must consist of a directory and an archive separate by a `::` or `:`. Found {}",
+            &entry
+        )
+    }
+}
+
+fn retrieve_alias_archive(alias: &str, archive: &str) -> Result<(String, PathBuf)> {
+    let pb = PathBuf::from(&archive);
+    if let Ok(pb_metadata) = pb.metadata() {
+        if !pb_metadata.is_file() {
+            bail!("\"{}\" exists, but it is not a file", &archive);
+        }
+    } else {
+        bail!("Archive \"{}\" does not exist", &archive);
+    }
+    Ok((alias.to_string(), pb))
+}
+
 /// Parses an environment variable.
 pub fn parse_envvar(entry: &str) -> Result<(String, String)> {
     let entry = entry.trim();
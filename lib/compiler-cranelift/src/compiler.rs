@@ -3,6 +3,8 @@
 use crate::address_map::get_function_address_map;
 use crate::config::Cranelift;
 #[cfg(feature = "unwind")]
+use crate::debug::transform_dwarf;
+#[cfg(feature = "unwind")]
 use crate::dwarf::WriterRelocate;
 use crate::func_environ::{get_function_name, FuncEnvironment};
 use crate::sink::{RelocSink, TrapSink};
@@ -21,8 +23,13 @@ use gimli::write::{Address, EhFrame, FrameTable};
 use loupe::MemoryUsage;
 use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
 use std::sync::Arc;
-use wasmer_compiler::CompileError;
+use std::time::Instant;
+#[cfg(feature = "unwind")]
+use tracing::warn;
+#[cfg(feature = "unwind")]
+use wasmer_compiler::DebugSection;
 use wasmer_compiler::{CallingConvention, ModuleTranslationState, Target};
+use wasmer_compiler::{CancellationToken, CompileError};
 use wasmer_compiler::{
     Compilation, CompileModuleInfo, CompiledFunction, CompiledFunctionFrameInfo,
     CompiledFunctionUnwindInfo, Compiler, Dwarf, FunctionBinaryReader, FunctionBody,
@@ -52,6 +59,10 @@ impl CraneliftCompiler {
 }
 
 impl Compiler for CraneliftCompiler {
+    fn name(&self) -> &str {
+        "cranelift"
+    }
+
     /// Get the middlewares for this compiler
     fn get_middlewares(&self) -> &[Arc<dyn ModuleMiddleware>] {
         &self.config.middlewares
@@ -65,6 +76,7 @@ impl Compiler for CraneliftCompiler {
         compile_info: &CompileModuleInfo,
         module_translation_state: &ModuleTranslationState,
         function_body_inputs: PrimaryMap<LocalFunctionIndex, FunctionBodyData<'_>>,
+        cancellation: &CancellationToken,
     ) -> Result<Compilation, CompileError> {
         let isa = self.config().isa(target);
         let frontend_config = isa.frontend_config();
@@ -107,6 +119,8 @@ impl Compiler for CraneliftCompiler {
             .collect::<Vec<(LocalFunctionIndex, &FunctionBodyData<'_>)>>()
             .par_iter()
             .map_init(FuncTranslator::new, |func_translator, (i, input)| {
+                cancellation.check()?;
+                let start = Instant::now();
                 let func_index = module.func_index(*i);
                 let mut context = Context::new();
                 let mut func_env = FuncEnvironment::new(
@@ -118,11 +132,9 @@ impl Compiler for CraneliftCompiler {
                 );
                 context.func.name = get_function_name(func_index);
                 context.func.signature = signatures[module.functions[func_index]].clone();
-                // if generate_debug_info {
-                //     context.func.collect_debug_info();
-                // }
                 let mut reader =
                     MiddlewareBinaryReader::new_with_offset(input.data, input.module_offset);
+                reader.set_function_type(module.signatures[module.functions[func_index]].clone());
                 reader.set_middleware_chain(
                     self.config
                         .middlewares
@@ -163,12 +175,9 @@ impl Compiler for CraneliftCompiler {
                                 .add_fde(
                                     *cie_id,
                                     fde.to_fde(Address::Symbol {
-                                        // The symbol is the kind of relocation.
-                                        // "0" is used for functions
-                                        symbol: WriterRelocate::FUNCTION_SYMBOL,
-                                        // We use the addend as a way to specify the
-                                        // function index
-                                        addend: i.index() as _,
+                                        // The symbol is the function index
+                                        symbol: i.index(),
+                                        addend: 0,
                                     }),
                                 );
                             // The unwind information is inserted into the dwarf section
@@ -186,7 +195,7 @@ impl Compiler for CraneliftCompiler {
                 // We transform the Cranelift JumpTable's into compiler JumpTables
                 let func_jt_offsets = transform_jump_table(context.func.jt_offsets);
 
-                Ok(CompiledFunction {
+                let compiled_function = CompiledFunction {
                     body: FunctionBody {
                         body: code_buf,
                         unwind_info,
@@ -197,9 +206,12 @@ impl Compiler for CraneliftCompiler {
                         address_map,
                         traps: trap_sink.traps,
                     },
-                })
+                };
+                Ok((compiled_function, start.elapsed()))
             })
-            .collect::<Result<Vec<_>, CompileError>>()?
+            .collect::<Result<Vec<_>, CompileError>>()?;
+        let (functions, compile_times): (Vec<_>, Vec<_>) = functions.into_iter().unzip();
+        let functions = functions
             .into_iter()
             .collect::<PrimaryMap<LocalFunctionIndex, _>>();
 
@@ -216,7 +228,35 @@ impl Compiler for CraneliftCompiler {
 
                 let eh_frame_section = eh_frame.0.into_section();
                 custom_sections.push(eh_frame_section);
-                Some(Dwarf::new(SectionIndex::new(0)))
+                let mut dwarf = Dwarf::new(SectionIndex::new(0));
+
+                if self.config.enable_debug_info {
+                    match transform_dwarf(
+                        module,
+                        &functions,
+                        frontend_config.pointer_bytes(),
+                        target.triple().endianness().ok(),
+                    ) {
+                        Ok(debug_sections) => {
+                            dwarf = dwarf.with_debug_sections(
+                                debug_sections
+                                    .into_iter()
+                                    .map(|(name, section)| DebugSection {
+                                        name: name.to_string(),
+                                        section: custom_sections.push(section),
+                                    })
+                                    .collect(),
+                            );
+                        }
+                        // Debug info is best-effort, the module is still
+                        // usable without it
+                        Err(message) => warn!(
+                            "failed to translate the DWARF debug info of the module: {}",
+                            message
+                        ),
+                    }
+                }
+                Some(dwarf)
             } else {
                 None
             };
@@ -258,6 +298,7 @@ impl Compiler for CraneliftCompiler {
             function_call_trampolines,
             dynamic_function_trampolines,
             dwarf,
-        ))
+        )
+        .with_compile_times(compile_times.into_iter().collect()))
     }
 }
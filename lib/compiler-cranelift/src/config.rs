@@ -34,6 +34,8 @@ pub struct Cranelift {
     enable_verifier: bool,
     enable_pic: bool,
     opt_level: CraneliftOptLevel,
+    /// Whether to translate the DWARF debug info of the modules.
+    pub(crate) enable_debug_info: bool,
     /// The middleware chain.
     pub(crate) middlewares: Vec<Arc<dyn ModuleMiddleware>>,
 }
@@ -47,6 +49,7 @@ impl Cranelift {
             enable_verifier: false,
             opt_level: CraneliftOptLevel::Speed,
             enable_pic: false,
+            enable_debug_info: false,
             middlewares: vec![],
         }
     }
@@ -66,6 +69,21 @@ impl Cranelift {
         self
     }
 
+    /// Generate native debug info for the compiled code.
+    ///
+    /// The DWARF debug info embedded in the module (its `.debug_info`,
+    /// `.debug_line`, … custom sections) is translated to describe the
+    /// native code, so that debuggers such as GDB or LLDB can map it back
+    /// to the source lines of the guest program. Modules without debug
+    /// info are compiled as usual.
+    ///
+    /// The Universal engine registers it with debuggers when built with
+    /// its `gdb-jit` feature.
+    pub fn debug_info(&mut self, enable: bool) -> &mut Self {
+        self.enable_debug_info = enable;
+        self
+    }
+
     /// Generates the ISA for the provided target
     pub fn isa(&self, target: &Target) -> Box<dyn TargetIsa> {
         let mut builder =
@@ -1,3 +1,7 @@
 mod address_map;
+#[cfg(feature = "unwind")]
+mod transform;
 
 pub use self::address_map::{ModuleInfoMemoryOffset, ModuleInfoVmctxInfo, ValueLabelsRanges};
+#[cfg(feature = "unwind")]
+pub(crate) use self::transform::transform_dwarf;
@@ -0,0 +1,573 @@
+//! Translation of the DWARF debug info of a WebAssembly module into
+//! native DWARF for the code compiled from it.
+//!
+//! The module's DWARF describes addresses in its code section. Using the
+//! address maps of the compiled functions, we rewrite its line programs
+//! and subprograms to describe the native code instead, with addresses
+//! relocated to the functions once they are allocated.
+//!
+//! Only what debuggers need to map the native code back to the source is
+//! translated: compilation units, line tables and functions. Variable
+//! locations and types are not.
+
+use crate::dwarf::WriterRelocate;
+use crate::HashMap;
+use gimli::read::{self, EndianSlice};
+use gimli::write::{
+    self, Address, AttributeValue, DirectoryId, FileId, LineProgram, LineString, Range, RangeList,
+    Sections, Unit,
+};
+use gimli::{constants, Encoding, Format, LineEncoding, LittleEndian, SectionId};
+use wasmer_compiler::{CompiledFunction, CustomSection, Endianness, FunctionAddressMap};
+use wasmer_types::entity::{EntityRef, PrimaryMap};
+use wasmer_types::{LocalFunctionIndex, ModuleInfo};
+
+/// The maximum size of the LEB128 encoded size preceding a function body,
+/// that the DWARF of some producers includes in the function range.
+const MAX_BODY_SIZE_LEN: u64 = 5;
+
+/// Translates the DWARF debug info embedded in `module`, if any, to
+/// describe the `functions` compiled from it.
+///
+/// Returns the native DWARF sections, named as in an object file.
+pub fn transform_dwarf(
+    module: &ModuleInfo,
+    functions: &PrimaryMap<LocalFunctionIndex, CompiledFunction>,
+    pointer_bytes: u8,
+    endianness: Option<Endianness>,
+) -> Result<Vec<(&'static str, CustomSection)>, String> {
+    if !module.custom_sections.contains_key(".debug_info") {
+        return Ok(vec![]);
+    }
+    let load = |id: SectionId| {
+        let data = module
+            .custom_sections
+            .get(id.name())
+            .map(|index| &*module.custom_sections_data[*index])
+            .unwrap_or(&[]);
+        Ok::<_, read::Error>(EndianSlice::new(data, LittleEndian))
+    };
+    let dwarf = read::Dwarf::load(load).map_err(|e| e.to_string())?;
+
+    let encoding = Encoding {
+        format: Format::Dwarf32,
+        version: 4,
+        address_size: pointer_bytes,
+    };
+    let ranges = FunctionRanges::new(module, functions);
+    let mut out = write::Dwarf::default();
+
+    let mut headers = dwarf.units();
+    while let Some(header) = headers.next().map_err(|e| e.to_string())? {
+        let unit = dwarf.unit(header).map_err(|e| e.to_string())?;
+        if let Some(native_unit) =
+            transform_unit(&dwarf, &unit, &ranges, encoding).map_err(|e| e.to_string())?
+        {
+            out.units.add(native_unit);
+        }
+    }
+
+    let mut sections = Sections::new(WriterRelocate::new(endianness));
+    out.write(&mut sections).map_err(|e| e.to_string())?;
+
+    let mut debug_sections = vec![];
+    sections
+        .for_each(|id, writer| -> Result<(), String> {
+            if writer.len() > 0 {
+                debug_sections.push((id.name(), writer.clone().into_debug_section()));
+            }
+            Ok(())
+        })
+        .map(|()| debug_sections)
+}
+
+/// The range in the code section of each compiled function.
+struct FunctionRanges<'a> {
+    /// The functions by start address.
+    sorted: Vec<(u64, u64, LocalFunctionIndex)>,
+    functions: &'a PrimaryMap<LocalFunctionIndex, CompiledFunction>,
+    code_section_offset: u64,
+}
+
+impl<'a> FunctionRanges<'a> {
+    fn new(
+        module: &ModuleInfo,
+        functions: &'a PrimaryMap<LocalFunctionIndex, CompiledFunction>,
+    ) -> Self {
+        let code_section_offset = module.code_section_offset as u64;
+        // functions without a location in the code section can't be
+        // described by its DWARF
+        let mut sorted = functions
+            .iter()
+            .filter_map(|(index, function)| {
+                let map = &function.frame_info.address_map;
+                Some((
+                    u64::from(map.start_srcloc.bits()).checked_sub(code_section_offset)?,
+                    u64::from(map.end_srcloc.bits()).checked_sub(code_section_offset)?,
+                    index,
+                ))
+            })
+            .collect::<Vec<_>>();
+        sorted.sort_unstable_by_key(|(start, _, _)| *start);
+        Self {
+            sorted,
+            functions,
+            code_section_offset,
+        }
+    }
+
+    /// The function the code section address `address` belongs to.
+    fn function_at(&self, address: u64) -> Option<LocalFunctionIndex> {
+        let position = match self
+            .sorted
+            .binary_search_by_key(&address, |(start, _, _)| *start)
+        {
+            Ok(position) => return Some(self.sorted[position].2),
+            Err(position) => position,
+        };
+        if position > 0 && address < self.sorted[position - 1].1 {
+            return Some(self.sorted[position - 1].2);
+        }
+        // the address may point to the size of the body
+        self.sorted
+            .get(position)
+            .filter(|(start, _, _)| *start - address <= MAX_BODY_SIZE_LEN)
+            .map(|(_, _, index)| *index)
+    }
+
+    /// The functions overlapping the code section range `[start, end)`.
+    fn functions_in(&self, start: u64, end: u64) -> impl Iterator<Item = LocalFunctionIndex> + '_ {
+        self.sorted
+            .iter()
+            .filter(move |(function_start, function_end, _)| {
+                *function_start < end && start < *function_end
+            })
+            .map(|(_, _, index)| *index)
+    }
+
+    fn address_map(&self, index: LocalFunctionIndex) -> &FunctionAddressMap {
+        &self.functions[index].frame_info.address_map
+    }
+}
+
+/// A row of a line program of the module.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct LineRow {
+    file: FileId,
+    line: u64,
+    column: u64,
+    is_statement: bool,
+}
+
+/// A sequence of rows of a line program of the module, sorted by address.
+struct LineSequence {
+    start: u64,
+    end: u64,
+    rows: Vec<(u64, LineRow)>,
+}
+
+impl LineSequence {
+    /// The row describing the code section address `address`.
+    fn row_at(&self, address: u64) -> Option<LineRow> {
+        if address < self.start || address >= self.end {
+            return None;
+        }
+        let position = match self.rows.binary_search_by_key(&address, |(a, _)| *a) {
+            Ok(position) => position,
+            Err(0) => return None,
+            Err(position) => position - 1,
+        };
+        Some(self.rows[position].1)
+    }
+}
+
+type Reader<'a> = EndianSlice<'a, LittleEndian>;
+
+fn transform_unit(
+    dwarf: &read::Dwarf<Reader<'_>>,
+    unit: &read::Unit<Reader<'_>>,
+    ranges: &FunctionRanges<'_>,
+    encoding: Encoding,
+) -> read::Result<Option<Unit>> {
+    let mut entries = unit.entries();
+    let root = match entries.next_dfs()? {
+        Some((_, root)) => root.clone(),
+        None => return Ok(None),
+    };
+    let string = |name: constants::DwAt| -> read::Result<Option<Vec<u8>>> {
+        match root.attr_value(name)? {
+            Some(value) => Ok(Some(dwarf.attr_string(unit, value)?.slice().to_vec())),
+            None => Ok(None),
+        }
+    };
+    let name = string(constants::DW_AT_name)?.unwrap_or_default();
+    let comp_dir = string(constants::DW_AT_comp_dir)?.unwrap_or_default();
+
+    let mut program = LineProgram::new(
+        encoding,
+        LineEncoding::default(),
+        LineString::String(comp_dir.clone()),
+        LineString::String(name.clone()),
+        None,
+    );
+    let sequences = match &unit.line_program {
+        Some(line_program) => read_line_sequences(dwarf, unit, line_program.clone(), &mut program)?,
+        None => vec![],
+    };
+
+    // Emit a native sequence for each function described by the unit
+    let mut covered = vec![];
+    for sequence in &sequences {
+        for index in ranges.functions_in(sequence.start, sequence.end) {
+            if covered.contains(&index) {
+                continue;
+            }
+            covered.push(index);
+            let map = ranges.address_map(index);
+            program.begin_sequence(Some(function_address(index)));
+            let mut previous = None;
+            for instruction in &map.instructions {
+                if instruction.srcloc.is_default() {
+                    continue;
+                }
+                let address = match u64::from(instruction.srcloc.bits())
+                    .checked_sub(ranges.code_section_offset)
+                {
+                    Some(address) => address,
+                    None => continue,
+                };
+                let row = match sequences.iter().find_map(|s| s.row_at(address)) {
+                    Some(row) => row,
+                    None => continue,
+                };
+                if previous == Some(row) {
+                    continue;
+                }
+                previous = Some(row);
+                let native_row = program.row();
+                native_row.address_offset = instruction.code_offset as u64;
+                native_row.file = row.file;
+                native_row.line = row.line;
+                native_row.column = row.column;
+                native_row.is_statement = row.is_statement;
+                program.generate_row();
+            }
+            program.end_sequence(map.body_len as u64);
+        }
+    }
+    if covered.is_empty() {
+        return Ok(None);
+    }
+
+    let mut native_unit = Unit::new(encoding, program);
+    let code_ranges = covered
+        .iter()
+        .map(|index| Range::StartLength {
+            begin: function_address(*index),
+            length: ranges.address_map(*index).body_len as u64,
+        })
+        .collect();
+    let code_ranges = native_unit.ranges.add(RangeList(code_ranges));
+    let native_root_id = native_unit.root();
+    let native_root = native_unit.get_mut(native_root_id);
+    native_root.set(constants::DW_AT_name, AttributeValue::String(name));
+    native_root.set(constants::DW_AT_comp_dir, AttributeValue::String(comp_dir));
+    if let Some(producer) = string(constants::DW_AT_producer)? {
+        native_root.set(constants::DW_AT_producer, AttributeValue::String(producer));
+    }
+    if let Some(read::AttributeValue::Language(language)) =
+        root.attr_value(constants::DW_AT_language)?
+    {
+        native_root.set(
+            constants::DW_AT_language,
+            AttributeValue::Language(language),
+        );
+    }
+    native_root.set(
+        constants::DW_AT_low_pc,
+        AttributeValue::Address(Address::Constant(0)),
+    );
+    native_root.set(
+        constants::DW_AT_ranges,
+        AttributeValue::RangeListRef(code_ranges),
+    );
+    native_root.set(constants::DW_AT_stmt_list, AttributeValue::LineProgramRef);
+
+    // Then the functions, so that debuggers can name the frames
+    while let Some((_, entry)) = entries.next_dfs()? {
+        if entry.tag() != constants::DW_TAG_subprogram {
+            continue;
+        }
+        let low_pc = match entry.attr_value(constants::DW_AT_low_pc)? {
+            Some(read::AttributeValue::Addr(low_pc)) => low_pc,
+            _ => continue,
+        };
+        let index = match ranges.function_at(low_pc) {
+            Some(index) if covered.contains(&index) => index,
+            _ => continue,
+        };
+        let subprogram_id = native_unit.add(native_root_id, constants::DW_TAG_subprogram);
+        let subprogram = native_unit.get_mut(subprogram_id);
+        for attr in &[constants::DW_AT_name, constants::DW_AT_linkage_name] {
+            if let Some(value) = entry.attr_value(*attr)? {
+                let value = dwarf.attr_string(unit, value)?.slice().to_vec();
+                subprogram.set(*attr, AttributeValue::String(value));
+            }
+        }
+        if let Some(read::AttributeValue::Flag(external)) =
+            entry.attr_value(constants::DW_AT_external)?
+        {
+            subprogram.set(constants::DW_AT_external, AttributeValue::Flag(external));
+        }
+        subprogram.set(
+            constants::DW_AT_low_pc,
+            AttributeValue::Address(function_address(index)),
+        );
+        subprogram.set(
+            constants::DW_AT_high_pc,
+            AttributeValue::Udata(ranges.address_map(index).body_len as u64),
+        );
+    }
+
+    Ok(Some(native_unit))
+}
+
+/// Reads the sequences of the line program of `unit`, adding the files
+/// they refer to to the native `program`.
+fn read_line_sequences(
+    dwarf: &read::Dwarf<Reader<'_>>,
+    unit: &read::Unit<Reader<'_>>,
+    line_program: read::IncompleteLineProgram<Reader<'_>>,
+    program: &mut LineProgram,
+) -> read::Result<Vec<LineSequence>> {
+    let mut directories = HashMap::<Vec<u8>, DirectoryId>::new();
+    let mut files = HashMap::<(Vec<u8>, Vec<u8>), FileId>::new();
+    let mut sequences = vec![];
+    let mut rows = vec![];
+
+    let mut line_rows = line_program.rows();
+    while let Some((header, row)) = line_rows.next_row()? {
+        if row.end_sequence() {
+            if let Some((start, _)) = rows.first() {
+                sequences.push(LineSequence {
+                    start: *start,
+                    end: row.address(),
+                    rows: std::mem::take(&mut rows),
+                });
+            }
+            continue;
+        }
+        let file = match row.file(header) {
+            Some(file) => file,
+            None => continue,
+        };
+        let directory = match file.directory(header) {
+            Some(directory) => dwarf.attr_string(unit, directory)?.slice().to_vec(),
+            None => vec![],
+        };
+        let name = dwarf.attr_string(unit, file.path_name())?.slice().to_vec();
+        let file_id = match files.get(&(directory.clone(), name.clone())) {
+            Some(file_id) => *file_id,
+            None => {
+                let directory_id = *directories.entry(directory.clone()).or_insert_with(|| {
+                    program.add_directory(LineString::String(directory.clone()))
+                });
+                let file_id =
+                    program.add_file(LineString::String(name.clone()), directory_id, None);
+                files.insert((directory, name), file_id);
+                file_id
+            }
+        };
+        rows.push((
+            row.address(),
+            LineRow {
+                file: file_id,
+                line: row.line().map(u64::from).unwrap_or(0),
+                column: match row.column() {
+                    read::ColumnType::LeftEdge => 0,
+                    read::ColumnType::Column(column) => u64::from(column),
+                },
+                is_statement: row.is_stmt(),
+            },
+        ));
+    }
+
+    for sequence in &mut sequences {
+        sequence.rows.sort_by_key(|(address, _)| *address);
+    }
+    Ok(sequences)
+}
+
+/// The address of the local function `index`, relocated once the function
+/// is allocated.
+fn function_address(index: LocalFunctionIndex) -> Address {
+    Address::Symbol {
+        symbol: index.index(),
+        addend: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gimli::write::EndianVec;
+    use wasmer_compiler::{
+        CompiledFunctionFrameInfo, FunctionBody, InstructionAddressMap, SourceLoc,
+    };
+    use wasmer_types::SignatureIndex;
+
+    const CODE_SECTION_OFFSET: u32 = 100;
+
+    /// A module whose DWARF describes a function `fib` at `[10, 30)` in its
+    /// code section, with line 1 at 10 and line 2 at 20.
+    fn module_with_dwarf() -> ModuleInfo {
+        let encoding = Encoding {
+            format: Format::Dwarf32,
+            version: 4,
+            address_size: 4,
+        };
+        let mut program = LineProgram::new(
+            encoding,
+            LineEncoding::default(),
+            LineString::String(b"/src".to_vec()),
+            LineString::String(b"fib.c".to_vec()),
+            None,
+        );
+        let directory = program.default_directory();
+        let file = program.add_file(LineString::String(b"fib.c".to_vec()), directory, None);
+        program.begin_sequence(Some(Address::Constant(10)));
+        for (address_offset, line) in &[(0, 1), (10, 2)] {
+            let row = program.row();
+            row.address_offset = *address_offset;
+            row.file = file;
+            row.line = *line;
+            program.generate_row();
+        }
+        program.end_sequence(20);
+
+        let mut dwarf = write::Dwarf::default();
+        let unit_id = dwarf.units.add(Unit::new(encoding, program));
+        let unit = dwarf.units.get_mut(unit_id);
+        let root = unit.root();
+        unit.get_mut(root).set(
+            constants::DW_AT_name,
+            AttributeValue::String(b"fib.c".to_vec()),
+        );
+        unit.get_mut(root)
+            .set(constants::DW_AT_stmt_list, AttributeValue::LineProgramRef);
+        let subprogram = unit.add(root, constants::DW_TAG_subprogram);
+        unit.get_mut(subprogram).set(
+            constants::DW_AT_name,
+            AttributeValue::String(b"fib".to_vec()),
+        );
+        unit.get_mut(subprogram).set(
+            constants::DW_AT_low_pc,
+            AttributeValue::Address(Address::Constant(10)),
+        );
+
+        let mut sections = Sections::new(EndianVec::new(LittleEndian));
+        dwarf.write(&mut sections).unwrap();
+        let mut module = ModuleInfo::new();
+        module.functions.push(SignatureIndex::new(0));
+        module.code_section_offset = CODE_SECTION_OFFSET as usize;
+        sections
+            .for_each(|id, data| {
+                let index = module.custom_sections_data.push(data.slice().into());
+                module.custom_sections.insert(id.name().to_string(), index);
+                Ok::<_, ()>(())
+            })
+            .unwrap();
+        module
+    }
+
+    fn compiled_function(instructions: &[(u32, usize)]) -> CompiledFunction {
+        let srcloc = |address| SourceLoc::new(CODE_SECTION_OFFSET + address);
+        CompiledFunction {
+            body: FunctionBody {
+                body: vec![0; 16],
+                unwind_info: None,
+            },
+            relocations: vec![],
+            jt_offsets: Default::default(),
+            frame_info: CompiledFunctionFrameInfo {
+                traps: vec![],
+                address_map: FunctionAddressMap {
+                    instructions: instructions
+                        .iter()
+                        .map(|(address, code_offset)| InstructionAddressMap {
+                            srcloc: srcloc(*address),
+                            code_offset: *code_offset,
+                            code_len: 4,
+                        })
+                        .collect(),
+                    start_srcloc: srcloc(10),
+                    end_srcloc: srcloc(30),
+                    body_offset: 0,
+                    body_len: 16,
+                },
+            },
+        }
+    }
+
+    #[test]
+    fn test_transform_dwarf() {
+        let module = module_with_dwarf();
+        let mut functions = PrimaryMap::new();
+        functions.push(compiled_function(&[(10, 0), (14, 4), (20, 8)]));
+
+        let sections = transform_dwarf(&module, &functions, 8, Some(Endianness::Little)).unwrap();
+        let load = |id: SectionId| {
+            let data = sections
+                .iter()
+                .find(|(name, _)| *name == id.name())
+                .map(|(_, section)| section.bytes.as_slice())
+                .unwrap_or(&[]);
+            Ok::<_, read::Error>(EndianSlice::new(data, LittleEndian))
+        };
+        let dwarf = read::Dwarf::load(load).unwrap();
+        let header = dwarf.units().next().unwrap().unwrap();
+        let unit = dwarf.unit(header).unwrap();
+
+        // the rows are at offsets in the function, relocated to it
+        let mut rows = unit.line_program.clone().unwrap().rows();
+        let mut lines = vec![];
+        let mut end = None;
+        while let Some((_, row)) = rows.next_row().unwrap() {
+            if row.end_sequence() {
+                end = Some(row.address());
+            } else {
+                lines.push((row.address(), row.line().map(u64::from)));
+            }
+        }
+        assert_eq!(lines, vec![(0, Some(1)), (8, Some(2))]);
+        assert_eq!(end, Some(16));
+
+        let mut entries = unit.entries();
+        let mut subprograms = vec![];
+        while let Some((_, entry)) = entries.next_dfs().unwrap() {
+            if entry.tag() == constants::DW_TAG_subprogram {
+                let name = entry.attr_value(constants::DW_AT_name).unwrap().unwrap();
+                let name = dwarf.attr_string(&unit, name).unwrap();
+                subprograms.push(name.slice().to_vec());
+            }
+        }
+        assert_eq!(subprograms, vec![b"fib".to_vec()]);
+    }
+
+    #[test]
+    fn test_transform_dwarf_ignores_locations_before_the_code_section() {
+        let module = module_with_dwarf();
+        let mut functions = PrimaryMap::new();
+        // an instruction with a location in another section of the module
+        let mut function = compiled_function(&[(10, 0), (20, 8)]);
+        function.frame_info.address_map.instructions[0].srcloc = SourceLoc::new(4);
+        functions.push(function);
+        assert!(transform_dwarf(&module, &functions, 8, Some(Endianness::Little)).is_ok());
+
+        // and a function without a location in the code section at all
+        let mut function = compiled_function(&[]);
+        function.frame_info.address_map.start_srcloc = SourceLoc::new(4);
+        functions.push(function);
+        assert!(transform_dwarf(&module, &functions, 8, Some(Endianness::Little)).is_ok());
+    }
+}
@@ -12,7 +12,6 @@ pub struct WriterRelocate {
 }
 
 impl WriterRelocate {
-    pub const FUNCTION_SYMBOL: usize = 0;
     pub fn new(endianness: Option<Endianness>) -> Self {
         let endianness = match endianness {
             Some(Endianness::Little) => RunTimeEndian::Little,
@@ -26,6 +25,15 @@ impl WriterRelocate {
         }
     }
 
+    /// The section for a `.debug_*` section.
+    pub fn into_debug_section(self) -> CustomSection {
+        CustomSection {
+            protection: CustomSectionProtection::Read,
+            bytes: SectionBody::new_with_vec(self.writer.into_vec()),
+            relocations: self.relocs,
+        }
+    }
+
     pub fn into_section(mut self) -> CustomSection {
         // GCC expects a terminating "empty" length, so write a 0 length at the end of the table.
         self.writer.write_u32(0).unwrap();
@@ -61,42 +69,39 @@ impl Writer for WriterRelocate {
         match address {
             Address::Constant(val) => self.write_udata(val, size),
             Address::Symbol { symbol, addend } => {
-                // Is a function relocation
-                if symbol == Self::FUNCTION_SYMBOL {
-                    // We use the addend to detect the function index
-                    let function_index = LocalFunctionIndex::new(addend as _);
-                    let reloc_target = RelocationTarget::LocalFunc(function_index);
-                    let offset = self.len() as u32;
-                    let kind = match size {
-                        8 => RelocationKind::Abs8,
-                        _ => unimplemented!("dwarf relocation size not yet supported: {}", size),
-                    };
-                    let addend = 0;
-                    self.relocs.push(Relocation {
-                        kind,
-                        reloc_target,
-                        offset,
-                        addend,
-                    });
-                    self.write_udata(addend as u64, size)
-                } else {
-                    unreachable!("Symbol {} in DWARF not recognized", symbol);
-                }
+                // The symbol is the index of the local function the
+                // address points into, and the addend the offset in it
+                let function_index = LocalFunctionIndex::new(symbol);
+                let reloc_target = RelocationTarget::LocalFunc(function_index);
+                let offset = self.len() as u32;
+                let kind = match size {
+                    8 => RelocationKind::Abs8,
+                    _ => unimplemented!("dwarf relocation size not yet supported: {}", size),
+                };
+                self.relocs.push(Relocation {
+                    kind,
+                    reloc_target,
+                    offset,
+                    addend,
+                });
+                self.write_udata(0, size)
             }
         }
     }
 
-    fn write_offset(&mut self, _val: usize, _section: SectionId, _size: u8) -> Result<()> {
-        unimplemented!("write_offset not yet implemented");
+    // Each section is loaded on its own, so offsets into other sections
+    // don't need to be relocated.
+    fn write_offset(&mut self, val: usize, _section: SectionId, size: u8) -> Result<()> {
+        self.write_udata(val as u64, size)
     }
 
     fn write_offset_at(
         &mut self,
-        _offset: usize,
-        _val: usize,
+        offset: usize,
+        val: usize,
         _section: SectionId,
-        _size: u8,
+        size: u8,
     ) -> Result<()> {
-        unimplemented!("write_offset_at not yet implemented");
+        self.write_udata_at(offset, val as u64, size)
     }
 }
@@ -11,10 +11,11 @@ use loupe::MemoryUsage;
 use rayon::iter::ParallelBridge;
 use rayon::prelude::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
 use std::sync::Arc;
+use std::time::Instant;
 use wasmer_compiler::{
-    Compilation, CompileError, CompileModuleInfo, Compiler, CustomSection, CustomSectionProtection,
-    Dwarf, FunctionBodyData, ModuleMiddleware, ModuleTranslationState, RelocationTarget,
-    SectionBody, SectionIndex, Symbol, SymbolRegistry, Target,
+    CancellationToken, Compilation, CompileError, CompileModuleInfo, Compiler, CustomSection,
+    CustomSectionProtection, Dwarf, FunctionBodyData, ModuleMiddleware, ModuleTranslationState,
+    RelocationTarget, SectionBody, SectionIndex, Symbol, SymbolRegistry, Target,
 };
 use wasmer_types::entity::{EntityRef, PrimaryMap};
 use wasmer_types::{FunctionIndex, LocalFunctionIndex, SignatureIndex};
@@ -81,6 +82,7 @@ impl LLVMCompiler {
         function_body_inputs: &PrimaryMap<LocalFunctionIndex, FunctionBodyData<'data>>,
         symbol_registry: &dyn SymbolRegistry,
         wasmer_metadata: &[u8],
+        cancellation: &CancellationToken,
     ) -> Result<Vec<u8>, CompileError> {
         let target_machine = self.config().target_machine(target);
         let ctx = Context::create();
@@ -93,6 +95,7 @@ impl LLVMCompiler {
                 FuncTranslator::new(target_machine)
             },
             |func_translator, (i, input)| {
+                cancellation.check()?;
                 let module = func_translator.translate_to_module(
                     &compile_info.module,
                     module_translation,
@@ -176,6 +179,10 @@ impl LLVMCompiler {
             merged_module.verify().unwrap();
         }
 
+        // The code generation of the whole module can't be interrupted, so
+        // check the token one last time before starting it.
+        cancellation.check()?;
+
         let memory_buffer = target_machine
             .write_to_memory_buffer(&merged_module, FileType::Object)
             .unwrap();
@@ -188,6 +195,10 @@ impl LLVMCompiler {
 }
 
 impl Compiler for LLVMCompiler {
+    fn name(&self) -> &str {
+        "llvm"
+    }
+
     /// Get the middlewares for this compiler
     fn get_middlewares(&self) -> &[Arc<dyn ModuleMiddleware>] {
         &self.config.middlewares
@@ -203,6 +214,7 @@ impl Compiler for LLVMCompiler {
         symbol_registry: &dyn SymbolRegistry,
         // The metadata to inject into the wasmer_metadata section of the object file.
         wasmer_metadata: &[u8],
+        cancellation: &CancellationToken,
     ) -> Option<Result<Vec<u8>, CompileError>> {
         Some(self.compile_native_object(
             target,
@@ -211,6 +223,7 @@ impl Compiler for LLVMCompiler {
             function_body_inputs,
             symbol_registry,
             wasmer_metadata,
+            cancellation,
         ))
     }
 
@@ -222,6 +235,7 @@ impl Compiler for LLVMCompiler {
         compile_info: &'module CompileModuleInfo,
         module_translation: &ModuleTranslationState,
         function_body_inputs: PrimaryMap<LocalFunctionIndex, FunctionBodyData<'data>>,
+        cancellation: &CancellationToken,
     ) -> Result<Compilation, CompileError> {
         //let data = Arc::new(Mutex::new(0));
         let memory_styles = &compile_info.memory_styles;
@@ -246,19 +260,25 @@ impl Compiler for LLVMCompiler {
                 |func_translator, (i, input)| {
                     // TODO: remove (to serialize)
                     //let _data = data.lock().unwrap();
-                    func_translator.translate(
-                        module,
-                        module_translation,
-                        i,
-                        input,
-                        self.config(),
-                        memory_styles,
-                        &table_styles,
-                        &ShortNames {},
-                    )
+                    cancellation.check()?;
+                    let start = Instant::now();
+                    func_translator
+                        .translate(
+                            module,
+                            module_translation,
+                            i,
+                            input,
+                            self.config(),
+                            memory_styles,
+                            &table_styles,
+                            &ShortNames {},
+                        )
+                        .map(|compiled_function| (compiled_function, start.elapsed()))
                 },
             )
-            .collect::<Result<Vec<_>, CompileError>>()?
+            .collect::<Result<Vec<_>, CompileError>>()?;
+        let (functions, compile_times): (Vec<_>, Vec<_>) = functions.into_iter().unzip();
+        let functions = functions
             .into_iter()
             .map(|mut compiled_function| {
                 let first_section = module_custom_sections.len() as u32;
@@ -367,6 +387,7 @@ impl Compiler for LLVMCompiler {
             function_call_trampolines,
             dynamic_function_trampolines,
             dwarf,
-        ))
+        )
+        .with_compile_times(compile_times.into_iter().collect()))
     }
 }
@@ -134,6 +134,7 @@ impl FuncTranslator {
             function_body.data,
             function_body.module_offset,
         );
+        reader.set_function_type(wasm_fn_type.clone());
         reader.set_middleware_chain(
             config
                 .middlewares
@@ -11,11 +11,12 @@ use loupe::MemoryUsage;
 #[cfg(feature = "rayon")]
 use rayon::prelude::{IntoParallelIterator, ParallelIterator};
 use std::sync::Arc;
+use std::time::Instant;
 use wasmer_compiler::{
-    Architecture, Compilation, CompileError, CompileModuleInfo, CompiledFunction, Compiler,
-    CompilerConfig, FunctionBinaryReader, FunctionBody, FunctionBodyData, MiddlewareBinaryReader,
-    ModuleMiddleware, ModuleMiddlewareChain, ModuleTranslationState, OperatingSystem, SectionIndex,
-    Target, TrapInformation,
+    Architecture, CancellationToken, Compilation, CompileError, CompileModuleInfo,
+    CompiledFunction, Compiler, CompilerConfig, FunctionBinaryReader, FunctionBody,
+    FunctionBodyData, MiddlewareBinaryReader, ModuleMiddleware, ModuleMiddlewareChain,
+    ModuleTranslationState, OperatingSystem, SectionIndex, Target, TrapInformation,
 };
 use wasmer_types::entity::{EntityRef, PrimaryMap};
 use wasmer_types::{
@@ -43,6 +44,10 @@ impl SinglepassCompiler {
 }
 
 impl Compiler for SinglepassCompiler {
+    fn name(&self) -> &str {
+        "singlepass"
+    }
+
     /// Get the middlewares for this compiler
     fn get_middlewares(&self) -> &[Arc<dyn ModuleMiddleware>] {
         &self.config.middlewares
@@ -56,6 +61,7 @@ impl Compiler for SinglepassCompiler {
         compile_info: &CompileModuleInfo,
         _module_translation: &ModuleTranslationState,
         function_body_inputs: PrimaryMap<LocalFunctionIndex, FunctionBodyData<'_>>,
+        cancellation: &CancellationToken,
     ) -> Result<Compilation, CompileError> {
         if target.triple().operating_system == OperatingSystem::Windows {
             return Err(CompileError::UnsupportedTarget(
@@ -87,12 +93,17 @@ impl Compiler for SinglepassCompiler {
             .collect::<Vec<(LocalFunctionIndex, &FunctionBodyData<'_>)>>()
             .into_par_iter_if_rayon()
             .map(|(i, input)| {
+                cancellation.check()?;
+                let start = Instant::now();
                 let middleware_chain = self
                     .config
                     .middlewares
                     .generate_function_middleware_chain(i);
                 let mut reader =
                     MiddlewareBinaryReader::new_with_offset(input.data, input.module_offset);
+                reader.set_function_type(
+                    module.signatures[module.functions[module.func_index(i)]].clone(),
+                );
                 reader.set_middleware_chain(middleware_chain);
 
                 // This local list excludes arguments.
@@ -122,9 +133,11 @@ impl Compiler for SinglepassCompiler {
                     generator.feed_operator(op).map_err(to_compile_error)?;
                 }
 
-                Ok(generator.finalize(&input))
+                Ok((generator.finalize(&input), start.elapsed()))
             })
-            .collect::<Result<Vec<CompiledFunction>, CompileError>>()?
+            .collect::<Result<Vec<_>, CompileError>>()?;
+        let (functions, compile_times): (Vec<_>, Vec<_>) = functions.into_iter().unzip();
+        let functions = functions
             .into_iter()
             .collect::<PrimaryMap<LocalFunctionIndex, CompiledFunction>>();
 
@@ -153,7 +166,8 @@ impl Compiler for SinglepassCompiler {
             function_call_trampolines,
             dynamic_function_trampolines,
             None,
-        ))
+        )
+        .with_compile_times(compile_times.into_iter().collect()))
     }
 }
 
@@ -221,7 +235,13 @@ mod tests {
         // Compile for win64
         let win64 = Target::new(triple!("x86_64-pc-windows-msvc"), CpuFeature::for_host());
         let (mut info, translation, inputs) = dummy_compilation_ingredients();
-        let result = compiler.compile_module(&win64, &mut info, &translation, inputs);
+        let result = compiler.compile_module(
+            &win64,
+            &mut info,
+            &translation,
+            inputs,
+            &CancellationToken::new(),
+        );
         match result.unwrap_err() {
             CompileError::UnsupportedTarget(name) => assert_eq!(name, "windows"),
             error => panic!("Unexpected error: {:?}", error),
@@ -230,7 +250,13 @@ mod tests {
         // Compile for 32bit Linux
         let linux32 = Target::new(triple!("i686-unknown-linux-gnu"), CpuFeature::for_host());
         let (mut info, translation, inputs) = dummy_compilation_ingredients();
-        let result = compiler.compile_module(&linux32, &mut info, &translation, inputs);
+        let result = compiler.compile_module(
+            &linux32,
+            &mut info,
+            &translation,
+            inputs,
+            &CancellationToken::new(),
+        );
         match result.unwrap_err() {
             CompileError::UnsupportedTarget(name) => assert_eq!(name, "i686"),
             error => panic!("Unexpected error: {:?}", error),
@@ -239,7 +265,13 @@ mod tests {
         // Compile for win32
         let win32 = Target::new(triple!("i686-pc-windows-gnu"), CpuFeature::for_host());
         let (mut info, translation, inputs) = dummy_compilation_ingredients();
-        let result = compiler.compile_module(&win32, &mut info, &translation, inputs);
+        let result = compiler.compile_module(
+            &win32,
+            &mut info,
+            &translation,
+            inputs,
+            &CancellationToken::new(),
+        );
         match result.unwrap_err() {
             CompileError::UnsupportedTarget(name) => assert_eq!(name, "windows"), // Windows should be checked before architecture
             error => panic!("Unexpected error: {:?}", error),
@@ -8,6 +8,7 @@ use crate::lib::std::sync::Arc;
 use crate::module::CompileModuleInfo;
 use crate::target::Target;
 use crate::translator::ModuleMiddleware;
+use crate::CancellationToken;
 use crate::FunctionBodyData;
 use crate::ModuleTranslationState;
 use crate::SectionIndex;
@@ -79,6 +80,11 @@ where
 
 /// An implementation of a Compiler from parsed WebAssembly module to Compiled native code.
 pub trait Compiler: Send + MemoryUsage {
+    /// The name of the compiler, recorded in the artifacts it produces.
+    fn name(&self) -> &str {
+        "unknown"
+    }
+
     /// Validates a module.
     ///
     /// It returns the a succesful Result in case is valid, `CompileError` in case is not.
@@ -110,6 +116,10 @@ pub trait Compiler: Send + MemoryUsage {
 
     /// Compiles a parsed module.
     ///
+    /// The compiler checks `cancellation` between the functions it
+    /// compiles, and stops with [`CompileError::Cancelled`] once it is
+    /// cancelled.
+    ///
     /// It returns the [`Compilation`] or a [`CompileError`].
     fn compile_module<'data, 'module>(
         &self,
@@ -118,6 +128,7 @@ pub trait Compiler: Send + MemoryUsage {
         module_translation: &ModuleTranslationState,
         // The list of function bodies
         function_body_inputs: PrimaryMap<LocalFunctionIndex, FunctionBodyData<'data>>,
+        cancellation: &CancellationToken,
     ) -> Result<Compilation, CompileError>;
 
     /// Compiles a module into a native object file.
@@ -133,6 +144,7 @@ pub trait Compiler: Send + MemoryUsage {
         _symbol_registry: &dyn SymbolRegistry,
         // The metadata to inject into the wasmer_metadata section of the object file.
         _wasmer_metadata: &[u8],
+        _cancellation: &CancellationToken,
     ) -> Option<Result<Vec<u8>, CompileError>> {
         None
     }
@@ -40,6 +40,11 @@ pub enum CompileError {
     /// Insufficient resources available for execution.
     #[cfg_attr(feature = "std", error("Insufficient resources: {0}"))]
     Resource(String),
+
+    /// The compilation was cancelled with a `CancellationToken`, or ran
+    /// out of its time budget.
+    #[cfg_attr(feature = "std", error("Compilation cancelled"))]
+    Cancelled,
 }
 
 impl From<WasmError> for CompileError {
@@ -101,6 +106,10 @@ pub enum WasmError {
     #[cfg_attr(feature = "std", error("Implementation limit exceeded"))]
     ImplLimitExceeded,
 
+    /// A limit of the `ModuleLimits` of the translation was exceeded.
+    #[cfg_attr(feature = "std", error("Module limit exceeded: {0}"))]
+    ModuleLimitExceeded(String),
+
     /// An error from the middleware error.
     #[cfg_attr(feature = "std", error("{0}"))]
     Middleware(MiddlewareError),
@@ -4,10 +4,14 @@
 //! A `Compilation` contains the compiled function bodies for a WebAssembly
 //! module (`CompiledFunction`).
 
+use crate::lib::std::string::String;
 use crate::lib::std::vec::Vec;
 use crate::section::{CustomSection, SectionIndex};
 use crate::trap::TrapInformation;
 use crate::{CompiledFunctionUnwindInfo, FunctionAddressMap, JumpTableOffsets, Relocation};
+#[cfg(feature = "enable-rkyv")]
+use bytecheck::CheckBytes;
+use core::time::Duration;
 use loupe::MemoryUsage;
 #[cfg(feature = "enable-rkyv")]
 use rkyv::{Archive, Deserialize as RkyvDeserialize, Serialize as RkyvSerialize};
@@ -25,6 +29,7 @@ use wasmer_types::{FunctionIndex, LocalFunctionIndex, SignatureIndex};
     feature = "enable-rkyv",
     derive(RkyvSerialize, RkyvDeserialize, Archive)
 )]
+#[cfg_attr(feature = "enable-rkyv", archive(derive(CheckBytes)))]
 #[derive(Debug, Clone, PartialEq, Eq, Default, MemoryUsage)]
 pub struct CompiledFunctionFrameInfo {
     /// The traps (in the function body).
@@ -42,6 +47,7 @@ pub struct CompiledFunctionFrameInfo {
     feature = "enable-rkyv",
     derive(RkyvSerialize, RkyvDeserialize, Archive)
 )]
+#[cfg_attr(feature = "enable-rkyv", archive(derive(CheckBytes)))]
 #[derive(Debug, Clone, PartialEq, Eq, MemoryUsage)]
 pub struct FunctionBody {
     /// The function body bytes.
@@ -62,6 +68,7 @@ pub struct FunctionBody {
     feature = "enable-rkyv",
     derive(RkyvSerialize, RkyvDeserialize, Archive)
 )]
+#[cfg_attr(feature = "enable-rkyv", archive(derive(CheckBytes)))]
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct CompiledFunction {
     /// The function body.
@@ -86,29 +93,58 @@ pub type CustomSections = PrimaryMap<SectionIndex, CustomSection>;
 /// The DWARF information for this Compilation.
 ///
 /// It is used for retrieving the unwind information once an exception
-/// happens.
-/// In the future this structure may also hold other information useful
-/// for debugging.
+/// happens, and by debuggers when debug info was generated.
 #[cfg_attr(feature = "enable-serde", derive(Deserialize, Serialize))]
 #[cfg_attr(
     feature = "enable-rkyv",
     derive(RkyvSerialize, RkyvDeserialize, Archive)
 )]
+#[cfg_attr(feature = "enable-rkyv", archive(derive(CheckBytes)))]
 #[derive(Debug, PartialEq, Eq, Clone, MemoryUsage)]
 pub struct Dwarf {
     /// The section index in the [`Compilation`] that corresponds to the exception frames.
     /// [Learn
     /// more](https://refspecs.linuxfoundation.org/LSB_3.0.0/LSB-PDA/LSB-PDA/ehframechpt.html).
     pub eh_frame: SectionIndex,
+    /// The sections in the [`Compilation`] holding the debug info of the
+    /// compiled code, if it was generated.
+    pub debug_sections: Vec<DebugSection>,
 }
 
 impl Dwarf {
     /// Creates a `Dwarf` struct with the corresponding indices for its sections
     pub fn new(eh_frame: SectionIndex) -> Self {
-        Self { eh_frame }
+        Self {
+            eh_frame,
+            debug_sections: Vec::new(),
+        }
+    }
+
+    /// Sets the sections holding the debug info of the compiled code.
+    pub fn with_debug_sections(mut self, debug_sections: Vec<DebugSection>) -> Self {
+        self.debug_sections = debug_sections;
+        self
     }
 }
 
+/// A DWARF debug info section of a [`Compilation`], such as `.debug_info`
+/// or `.debug_line`.
+///
+/// The addresses in it are relocated to the compiled functions.
+#[cfg_attr(feature = "enable-serde", derive(Deserialize, Serialize))]
+#[cfg_attr(
+    feature = "enable-rkyv",
+    derive(RkyvSerialize, RkyvDeserialize, Archive)
+)]
+#[cfg_attr(feature = "enable-rkyv", archive(derive(CheckBytes)))]
+#[derive(Debug, PartialEq, Eq, Clone, MemoryUsage)]
+pub struct DebugSection {
+    /// The name of the section, such as `.debug_info`.
+    pub name: String,
+    /// The custom section holding its contents.
+    pub section: SectionIndex,
+}
+
 /// The result of compiling a WebAssembly module's functions.
 #[cfg_attr(feature = "enable-serde", derive(Deserialize, Serialize))]
 #[derive(Debug, PartialEq, Eq)]
@@ -155,6 +191,10 @@ pub struct Compilation {
 
     /// Section ids corresponding to the Dwarf debug info
     debug: Option<Dwarf>,
+
+    /// The time spent compiling each function, if the compiler measured
+    /// it.
+    compile_times: PrimaryMap<LocalFunctionIndex, Duration>,
 }
 
 impl Compilation {
@@ -172,9 +212,19 @@ impl Compilation {
             function_call_trampolines,
             dynamic_function_trampolines,
             debug,
+            compile_times: PrimaryMap::new(),
         }
     }
 
+    /// Sets the time spent compiling each function.
+    pub fn with_compile_times(
+        mut self,
+        compile_times: PrimaryMap<LocalFunctionIndex, Duration>,
+    ) -> Self {
+        self.compile_times = compile_times;
+        self
+    }
+
     /// Gets the bytes of a single function
     pub fn get(&self, func: LocalFunctionIndex) -> &CompiledFunction {
         &self.functions[func]
@@ -249,6 +299,12 @@ impl Compilation {
     pub fn get_debug(&self) -> Option<Dwarf> {
         self.debug.clone()
     }
+
+    /// Gets the time spent compiling each function, which is empty if the
+    /// compiler didn't measure it.
+    pub fn get_compile_times(&self) -> &PrimaryMap<LocalFunctionIndex, Duration> {
+        &self.compile_times
+    }
 }
 
 impl<'a> IntoIterator for &'a Compilation {
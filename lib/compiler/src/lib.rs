@@ -55,6 +55,7 @@ mod compiler;
 mod error;
 mod function;
 mod jump_table;
+mod limits;
 mod module;
 mod relocation;
 mod target;
@@ -65,6 +66,7 @@ mod unwind;
 mod translator;
 mod section;
 mod sourceloc;
+mod stats;
 
 pub use crate::address_map::{FunctionAddressMap, InstructionAddressMap};
 #[cfg(feature = "translator")]
@@ -73,23 +75,25 @@ pub use crate::error::{
     CompileError, MiddlewareError, ParseCpuFeatureError, WasmError, WasmResult,
 };
 pub use crate::function::{
-    Compilation, CompiledFunction, CompiledFunctionFrameInfo, CustomSections, Dwarf, FunctionBody,
-    Functions,
+    Compilation, CompiledFunction, CompiledFunctionFrameInfo, CustomSections, DebugSection, Dwarf,
+    FunctionBody, Functions,
 };
 pub use crate::jump_table::{JumpTable, JumpTableOffsets};
+pub use crate::limits::{CancellationToken, ModuleLimits};
 pub use crate::module::CompileModuleInfo;
 pub use crate::relocation::{Relocation, RelocationKind, RelocationTarget, Relocations};
 pub use crate::section::{CustomSection, CustomSectionProtection, SectionBody, SectionIndex};
 pub use crate::sourceloc::SourceLoc;
+pub use crate::stats::{CompileStats, FunctionCompileStats};
 pub use crate::target::{
     Architecture, BinaryFormat, CallingConvention, CpuFeature, Endianness, OperatingSystem,
     PointerWidth, Target, Triple,
 };
 #[cfg(feature = "translator")]
 pub use crate::translator::{
-    translate_module, wptype_to_type, FunctionBinaryReader, FunctionBodyData, FunctionMiddleware,
-    MiddlewareBinaryReader, MiddlewareReaderState, ModuleEnvironment, ModuleMiddleware,
-    ModuleMiddlewareChain, ModuleTranslationState,
+    translate_module, wptype_to_type, FunctionBinaryReader, FunctionBodyData, FunctionLocals,
+    FunctionMiddleware, ImportedFunctions, MiddlewareBinaryReader, MiddlewareReaderState,
+    ModuleEnvironment, ModuleMiddleware, ModuleMiddlewareChain, ModuleTranslationState,
 };
 pub use crate::trap::TrapInformation;
 pub use crate::unwind::CompiledFunctionUnwindInfo;
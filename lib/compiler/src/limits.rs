@@ -0,0 +1,86 @@
+//! Limits on the work done to compile a module, to bound the time
+//! and memory that compiling an untrusted module can take.
+
+use crate::error::CompileError;
+use crate::lib::std::sync::Arc;
+use core::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+/// Limits on the size of the modules to translate, checked by
+/// `ModuleEnvironment::translate` before any function is compiled.
+///
+/// All the limits are disabled by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ModuleLimits {
+    /// The maximum number of functions defined by the module, not
+    /// counting the imported functions.
+    pub max_functions: Option<u32>,
+    /// The maximum number of locals declared by a function, not counting
+    /// its parameters.
+    pub max_locals_per_function: Option<u32>,
+    /// The maximum size of the body of a function, in bytes.
+    pub max_function_body_size: Option<u32>,
+}
+
+/// A token to cancel a compilation in progress from another thread, or
+/// after a wall-clock budget.
+///
+/// The compilers check the token between the functions they compile,
+/// and the compilation fails with [`CompileError::Cancelled`] once the
+/// token is cancelled. Cloned tokens share their cancellation state.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    #[cfg(feature = "std")]
+    deadline: Option<Instant>,
+}
+
+impl CancellationToken {
+    /// Creates a token which is only cancelled by [`Self::cancel`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a token which is also cancelled once `budget` has elapsed.
+    #[cfg(feature = "std")]
+    pub fn with_budget(budget: Duration) -> Self {
+        Self::with_deadline(Instant::now() + budget)
+    }
+
+    /// Creates a token which is also cancelled at `deadline`.
+    #[cfg(feature = "std")]
+    pub fn with_deadline(deadline: Instant) -> Self {
+        Self {
+            cancelled: Arc::default(),
+            deadline: Some(deadline),
+        }
+    }
+
+    /// Cancels the compilations using this token, or a clone of it.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns whether the token was cancelled, or its deadline passed.
+    pub fn is_cancelled(&self) -> bool {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return true;
+        }
+        #[cfg(feature = "std")]
+        if let Some(deadline) = self.deadline {
+            return Instant::now() >= deadline;
+        }
+        false
+    }
+
+    /// Returns [`CompileError::Cancelled`] if the token was cancelled, or
+    /// its deadline passed.
+    pub fn check(&self) -> Result<(), CompileError> {
+        if self.is_cancelled() {
+            Err(CompileError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! Statistics about the compilation of a WebAssembly module, to spot
+//! the functions which are pathologically slow to compile or which
+//! produce an unexpected amount of code.
+
+use crate::Compilation;
+use core::time::Duration;
+use wasmer_types::entity::PrimaryMap;
+use wasmer_types::LocalFunctionIndex;
+
+/// Statistics about the compilation of a function, or of all the
+/// functions of a module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FunctionCompileStats {
+    /// The time spent compiling the function.
+    pub compile_time: Duration,
+    /// The size of the machine code of the function, in bytes.
+    pub code_size: usize,
+    /// The number of relocations of the machine code of the function.
+    pub relocations: usize,
+    /// The size of the WebAssembly body of the function, in bytes.
+    pub wasm_size: usize,
+}
+
+/// Statistics about the compilation of a module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileStats {
+    functions: PrimaryMap<LocalFunctionIndex, FunctionCompileStats>,
+    compile_time: Duration,
+}
+
+impl CompileStats {
+    /// Creates the statistics of `compilation`, given the size of the
+    /// WebAssembly body of each function and the time spent compiling the
+    /// whole module.
+    ///
+    /// The compile times of the functions are zero if the compiler didn't
+    /// measure them.
+    pub fn new(
+        compilation: &Compilation,
+        wasm_sizes: &PrimaryMap<LocalFunctionIndex, usize>,
+        compile_time: Duration,
+    ) -> Self {
+        let compile_times = compilation.get_compile_times();
+        let functions = wasm_sizes
+            .iter()
+            .map(|(index, wasm_size)| {
+                let function = compilation.get(index);
+                FunctionCompileStats {
+                    compile_time: compile_times.get(index).copied().unwrap_or_default(),
+                    code_size: function.body.body.len(),
+                    relocations: function.relocations.len(),
+                    wasm_size: *wasm_size,
+                }
+            })
+            .collect();
+        Self {
+            functions,
+            compile_time,
+        }
+    }
+
+    /// Returns the statistics of each local function.
+    pub fn functions(&self) -> &PrimaryMap<LocalFunctionIndex, FunctionCompileStats> {
+        &self.functions
+    }
+
+    /// Returns the wall-clock time spent compiling the module.
+    ///
+    /// As the functions may be compiled in parallel, this can be less than
+    /// the sum of their compile times.
+    pub fn compile_time(&self) -> Duration {
+        self.compile_time
+    }
+
+    /// Returns the sums of the statistics of all the functions.
+    pub fn total(&self) -> FunctionCompileStats {
+        self.functions
+            .values()
+            .fold(FunctionCompileStats::default(), |total, function| {
+                FunctionCompileStats {
+                    compile_time: total.compile_time + function.compile_time,
+                    code_size: total.code_size + function.code_size,
+                    relocations: total.relocations + function.relocations,
+                    wasm_size: total.wasm_size + function.wasm_size,
+                }
+            })
+    }
+}
@@ -5,8 +5,8 @@ use crate::lib::std::borrow::ToOwned;
 use crate::lib::std::string::ToString;
 use crate::lib::std::{boxed::Box, string::String, vec::Vec};
 use crate::translate_module;
-use crate::wasmparser::{Operator, Range, Type};
-use crate::{WasmError, WasmResult};
+use crate::wasmparser::{BinaryReader, Operator, Range, Type};
+use crate::{ModuleLimits, WasmError, WasmResult};
 use std::convert::{TryFrom, TryInto};
 use std::sync::Arc;
 use wasmer_types::entity::PrimaryMap;
@@ -71,6 +71,9 @@ pub struct ModuleEnvironment<'data> {
 
     /// The decoded Wasm types for the module.
     pub module_translation_state: Option<ModuleTranslationState>,
+
+    /// The limits enforced during the translation.
+    limits: ModuleLimits,
 }
 
 impl<'data> ModuleEnvironment<'data> {
@@ -81,9 +84,17 @@ impl<'data> ModuleEnvironment<'data> {
             function_body_inputs: PrimaryMap::new(),
             data_initializers: Vec::new(),
             module_translation_state: None,
+            limits: ModuleLimits::default(),
         }
     }
 
+    /// Sets the limits which the translated module must satisfy, or the
+    /// translation fails with `WasmError::ModuleLimitExceeded`.
+    pub fn with_limits(mut self, limits: ModuleLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
     /// Translate a wasm module using this environment. This consumes the
     /// `ModuleEnvironment` and produces a `ModuleInfoTranslation`.
     pub fn translate(mut self, data: &'data [u8]) -> WasmResult<ModuleEnvironment<'data>> {
@@ -221,6 +232,14 @@ impl<'data> ModuleEnvironment<'data> {
     }
 
     pub(crate) fn reserve_func_types(&mut self, num: u32) -> WasmResult<()> {
+        if let Some(max_functions) = self.limits.max_functions {
+            if num > max_functions {
+                return Err(WasmError::ModuleLimitExceeded(format!(
+                    "the module defines {} functions, more than the maximum of {}",
+                    num, max_functions
+                )));
+            }
+        }
         self.module
             .functions
             .reserve_exact(usize::try_from(num).unwrap());
@@ -360,12 +379,18 @@ impl<'data> ModuleEnvironment<'data> {
         Ok(())
     }
 
+    pub(crate) fn declare_code_section(&mut self, offset: usize) -> WasmResult<()> {
+        self.module.code_section_offset = offset;
+        Ok(())
+    }
+
     pub(crate) fn define_function_body(
         &mut self,
         _module_translation_state: &ModuleTranslationState,
         body_bytes: &'data [u8],
         body_offset: usize,
     ) -> WasmResult<()> {
+        self.check_function_body_limits(body_bytes, body_offset)?;
         self.function_body_inputs.push(FunctionBodyData {
             data: body_bytes,
             module_offset: body_offset,
@@ -373,6 +398,35 @@ impl<'data> ModuleEnvironment<'data> {
         Ok(())
     }
 
+    fn check_function_body_limits(&self, body_bytes: &[u8], body_offset: usize) -> WasmResult<()> {
+        let function_index = self.module.num_imported_functions + self.function_body_inputs.len();
+        if let Some(max_function_body_size) = self.limits.max_function_body_size {
+            if body_bytes.len() > max_function_body_size as usize {
+                return Err(WasmError::ModuleLimitExceeded(format!(
+                    "the body of function {} is {} bytes long, more than the maximum of {}",
+                    function_index,
+                    body_bytes.len(),
+                    max_function_body_size
+                )));
+            }
+        }
+        if let Some(max_locals_per_function) = self.limits.max_locals_per_function {
+            let mut reader = BinaryReader::new_with_offset(body_bytes, body_offset);
+            let mut num_locals = 0u64;
+            for _ in 0..reader.read_var_u32()? {
+                num_locals += u64::from(reader.read_var_u32()?);
+                reader.read_type()?;
+            }
+            if num_locals > u64::from(max_locals_per_function) {
+                return Err(WasmError::ModuleLimitExceeded(format!(
+                    "function {} declares {} locals, more than the maximum of {}",
+                    function_index, num_locals, max_locals_per_function
+                )));
+            }
+        }
+        Ok(())
+    }
+
     pub(crate) fn reserve_data_initializers(&mut self, num: u32) -> WasmResult<()> {
         self.data_initializers
             .reserve_exact(usize::try_from(num).unwrap());
@@ -6,11 +6,15 @@ use smallvec::SmallVec;
 use std::collections::VecDeque;
 use std::fmt::Debug;
 use std::ops::Deref;
-use wasmer_types::{LocalFunctionIndex, ModuleInfo};
+use wasmer_types::entity::{EntityRef, PrimaryMap};
+use wasmer_types::{
+    ExportIndex, FunctionIndex, FunctionType, GlobalInit, ImportIndex, LocalFunctionIndex,
+    ModuleInfo,
+};
 use wasmparser::{BinaryReader, Operator, Range, Type};
 
-use crate::error::{MiddlewareError, WasmResult};
-use crate::translator::environ::FunctionBinaryReader;
+use crate::error::{MiddlewareError, WasmError, WasmResult};
+use crate::translator::environ::{FunctionBinaryReader, FunctionBodyData};
 
 /// A shared builder for function middlewares.
 pub trait ModuleMiddleware: Debug + Send + Sync + MemoryUsage {
@@ -24,12 +28,31 @@ pub trait ModuleMiddleware: Debug + Send + Sync + MemoryUsage {
         local_function_index: LocalFunctionIndex,
     ) -> Box<dyn FunctionMiddleware>;
 
+    /// Inspects the bodies of the functions defined by the module. This is called before
+    /// `transform_module_info`, for middlewares whose transformation depends on the code.
+    fn inspect_function_bodies(&self, _: &PrimaryMap<LocalFunctionIndex, FunctionBodyData<'_>>) {}
+
     /// Transforms a `ModuleInfo` struct in-place. This is called before application on functions begins.
     fn transform_module_info(&self, _: &mut ModuleInfo) {}
 }
 
 /// A function middleware specialized for a single function.
 pub trait FunctionMiddleware: Debug {
+    /// Declares the locals used by the middleware, which come after the
+    /// locals of the function and of the previous middlewares.
+    ///
+    /// This is called once, before any operator is fed.
+    fn declare_locals(&mut self, _locals: &mut FunctionLocals<'_>) {}
+
+    /// Returns the functions imported into the module by the module
+    /// middleware.
+    ///
+    /// The function indices of the operators fed to this middleware are
+    /// then remapped to account for these imports.
+    fn imported_functions(&self) -> Option<&ImportedFunctions> {
+        None
+    }
+
     /// Processes the given operator.
     fn feed<'a>(
         &mut self,
@@ -41,6 +64,174 @@ pub trait FunctionMiddleware: Debug {
     }
 }
 
+/// The locals of a function, to which middlewares can add their own.
+#[derive(Debug)]
+pub struct FunctionLocals<'r> {
+    function_type: &'r FunctionType,
+    num_locals: u32,
+    declarations: Vec<(u32, Type)>,
+}
+
+impl<'r> FunctionLocals<'r> {
+    /// Returns the type of the function.
+    pub fn function_type(&self) -> &FunctionType {
+        self.function_type
+    }
+
+    /// Returns the number of locals of the function, including its
+    /// parameters and the locals declared by middlewares so far.
+    pub fn num_locals(&self) -> u32 {
+        self.num_locals
+    }
+
+    /// Declares `count` locals of type `ty`, and returns the index of the
+    /// first one.
+    pub fn declare(&mut self, count: u32, ty: Type) -> u32 {
+        let first = self.num_locals;
+        self.num_locals += count;
+        self.declarations.push((count, ty));
+        first
+    }
+}
+
+/// The functions imported into a module by a module middleware.
+///
+/// Imported functions come before the functions defined by the module, so
+/// importing a function shifts the indices of the functions defined by the
+/// module. [`ImportedFunctions::import`] updates the `ModuleInfo`
+/// accordingly, and the function middlewares returning these imports from
+/// [`FunctionMiddleware::imported_functions`] have the function indices of
+/// the operators they're fed remapped.
+///
+/// The imports are resolved by `Instance::new` like any other import.
+#[derive(Debug, Clone, Default, MemoryUsage)]
+pub struct ImportedFunctions {
+    /// The index of the first imported function, if any.
+    first: Option<FunctionIndex>,
+    /// The number of imported functions.
+    count: u32,
+}
+
+impl ImportedFunctions {
+    /// Creates an empty set of imported functions.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Imports a function of type `function_type` from `module` and
+    /// `field` into `module_info`, and returns its index.
+    ///
+    /// # Panics
+    ///
+    /// All the functions must be imported from the same call to
+    /// `ModuleMiddleware::transform_module_info`.
+    pub fn import(
+        &mut self,
+        module_info: &mut ModuleInfo,
+        module: &str,
+        field: &str,
+        function_type: FunctionType,
+    ) -> FunctionIndex {
+        let index = FunctionIndex::new(module_info.num_imported_functions);
+        let first = *self.first.get_or_insert(index);
+        assert_eq!(
+            first.as_u32() + self.count,
+            index.as_u32(),
+            "the functions must be imported together"
+        );
+
+        let existing_signature = module_info
+            .signatures
+            .iter()
+            .find(|(_, signature)| **signature == function_type)
+            .map(|(signature_index, _)| signature_index);
+        let signature_index = match existing_signature {
+            Some(signature_index) => signature_index,
+            None => module_info.signatures.push(function_type),
+        };
+
+        // Shift the functions defined by the module.
+        let shift = |function_index: &mut FunctionIndex| {
+            if *function_index >= index {
+                *function_index = FunctionIndex::from_u32(function_index.as_u32() + 1);
+            }
+        };
+        let mut functions = module_info.functions.values().cloned().collect::<Vec<_>>();
+        functions.insert(index.index(), signature_index);
+        module_info.functions = functions.into_iter().collect();
+        for export in module_info.exports.values_mut() {
+            if let ExportIndex::Function(function_index) = export {
+                shift(function_index);
+            }
+        }
+        if let Some(start_function) = module_info.start_function.as_mut() {
+            shift(start_function);
+        }
+        for initializer in module_info.table_initializers.iter_mut() {
+            initializer.elements.iter_mut().for_each(shift);
+        }
+        for elements in module_info.passive_elements.values_mut() {
+            elements.iter_mut().for_each(shift);
+        }
+        for initializer in module_info.global_initializers.values_mut() {
+            if let GlobalInit::RefFunc(function_index) = initializer {
+                shift(function_index);
+            }
+        }
+        module_info.function_names = module_info
+            .function_names
+            .drain()
+            .map(|(mut function_index, name)| {
+                shift(&mut function_index);
+                (function_index, name)
+            })
+            .collect();
+
+        let import_index = module_info.imports.len() as u32;
+        module_info.imports.insert(
+            (module.to_string(), field.to_string(), import_index),
+            ImportIndex::Function(index),
+        );
+        module_info.num_imported_functions += 1;
+        self.count += 1;
+        index
+    }
+
+    /// Returns the indices of the imported functions.
+    pub fn indices(&self) -> impl Iterator<Item = FunctionIndex> {
+        let first = self.first.map_or(0, |first| first.as_u32());
+        (first..first + self.count).map(FunctionIndex::from_u32)
+    }
+
+    /// Maps the index of a function of the module before the imports to
+    /// its index after the imports.
+    pub fn remap(&self, function_index: FunctionIndex) -> FunctionIndex {
+        match self.first {
+            Some(first) if function_index >= first => {
+                FunctionIndex::from_u32(function_index.as_u32() + self.count)
+            }
+            _ => function_index,
+        }
+    }
+
+    /// Remaps the function index referenced by `operator`, if any.
+    pub fn remap_operator<'a>(&self, operator: Operator<'a>) -> Operator<'a> {
+        let remap = |function_index| self.remap(FunctionIndex::from_u32(function_index)).as_u32();
+        match operator {
+            Operator::Call { function_index } => Operator::Call {
+                function_index: remap(function_index),
+            },
+            Operator::ReturnCall { function_index } => Operator::ReturnCall {
+                function_index: remap(function_index),
+            },
+            Operator::RefFunc { function_index } => Operator::RefFunc {
+                function_index: remap(function_index),
+            },
+            operator => operator,
+        }
+    }
+}
+
 /// A Middleware binary reader of the WebAssembly structures and types.
 #[derive(Debug)]
 pub struct MiddlewareBinaryReader<'a> {
@@ -49,6 +240,10 @@ pub struct MiddlewareBinaryReader<'a> {
 
     /// The backing middleware chain for this reader.
     chain: Vec<Box<dyn FunctionMiddleware>>,
+
+    /// The local declarations not yet read, including the ones of the
+    /// middlewares.
+    local_declarations: VecDeque<(u32, Type)>,
 }
 
 /// The state of the binary reader. Exposed to middlewares to push their outputs.
@@ -59,6 +254,12 @@ pub struct MiddlewareReaderState<'a> {
 
     /// The pending operations added by the middleware.
     pending_operations: VecDeque<Operator<'a>>,
+
+    /// The type of the function being read.
+    function_type: Option<FunctionType>,
+
+    /// The offset in the module of the operator being fed.
+    original_offset: usize,
 }
 
 /// Trait for generating middleware chains from "prototype" (generator) chains.
@@ -69,6 +270,9 @@ pub trait ModuleMiddlewareChain {
         local_function_index: LocalFunctionIndex,
     ) -> Vec<Box<dyn FunctionMiddleware>>;
 
+    /// Lets the chain inspect the bodies of the functions defined by the module.
+    fn inspect_function_bodies(&self, _: &PrimaryMap<LocalFunctionIndex, FunctionBodyData<'_>>) {}
+
     /// Applies the chain on a `ModuleInfo` struct.
     fn apply_on_module_info(&self, module_info: &mut ModuleInfo);
 }
@@ -84,6 +288,16 @@ impl<T: Deref<Target = dyn ModuleMiddleware>> ModuleMiddlewareChain for [T] {
             .collect()
     }
 
+    /// Lets the chain inspect the bodies of the functions defined by the module.
+    fn inspect_function_bodies(
+        &self,
+        function_bodies: &PrimaryMap<LocalFunctionIndex, FunctionBodyData<'_>>,
+    ) {
+        for item in self {
+            item.inspect_function_bodies(function_bodies);
+        }
+    }
+
     /// Applies the chain on a `ModuleInfo` struct.
     fn apply_on_module_info(&self, module_info: &mut ModuleInfo) {
         for item in self {
@@ -97,6 +311,24 @@ impl<'a> MiddlewareReaderState<'a> {
     pub fn push_operator(&mut self, operator: Operator<'a>) {
         self.pending_operations.push_back(operator);
     }
+
+    /// Returns the type of the function being read.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the compiler didn't set the function type with
+    /// `MiddlewareBinaryReader::set_function_type`.
+    pub fn function_type(&self) -> &FunctionType {
+        self.function_type
+            .as_ref()
+            .expect("the function type of the middleware reader must be set")
+    }
+
+    /// Returns the offset in the module of the original operator from
+    /// which the operators being fed come.
+    pub fn original_offset(&self) -> usize {
+        self.original_offset
+    }
 }
 
 impl<'a> Extend<Operator<'a>> for MiddlewareReaderState<'a> {
@@ -119,8 +351,11 @@ impl<'a> MiddlewareBinaryReader<'a> {
             state: MiddlewareReaderState {
                 inner,
                 pending_operations: VecDeque::new(),
+                function_type: None,
+                original_offset,
             },
             chain: vec![],
+            local_declarations: VecDeque::new(),
         }
     }
 
@@ -128,14 +363,56 @@ impl<'a> MiddlewareBinaryReader<'a> {
     pub fn set_middleware_chain(&mut self, stages: Vec<Box<dyn FunctionMiddleware>>) {
         self.chain = stages;
     }
+
+    /// Sets the type of the function being read, which middlewares need to
+    /// declare locals.
+    pub fn set_function_type(&mut self, function_type: FunctionType) {
+        self.state.function_type = Some(function_type);
+    }
 }
 
 impl<'a> FunctionBinaryReader<'a> for MiddlewareBinaryReader<'a> {
     fn read_local_count(&mut self) -> WasmResult<u32> {
-        Ok(self.state.inner.read_var_u32()?)
+        if self.chain.is_empty() {
+            // We short-circuit in case no chain is used
+            return Ok(self.state.inner.read_var_u32()?);
+        }
+
+        // Read all the local declarations of the function, so the
+        // middlewares know the indices of the locals they declare.
+        let function_type = self
+            .state
+            .function_type
+            .as_ref()
+            .expect("the function type of the middleware reader must be set");
+        let mut locals = FunctionLocals {
+            function_type,
+            num_locals: function_type.params().len() as u32,
+            declarations: vec![],
+        };
+        let local_count = self.state.inner.read_var_u32()?;
+        for _ in 0..local_count {
+            let count = self.state.inner.read_var_u32()?;
+            let ty = self.state.inner.read_type()?;
+            locals.num_locals = locals.num_locals.checked_add(count).ok_or_else(|| {
+                WasmError::InvalidWebAssembly {
+                    message: "too many locals".to_string(),
+                    offset: self.state.inner.original_position(),
+                }
+            })?;
+            self.local_declarations.push_back((count, ty));
+        }
+        for stage in &mut self.chain {
+            stage.declare_locals(&mut locals);
+        }
+        self.local_declarations.extend(locals.declarations);
+        Ok(self.local_declarations.len() as u32)
     }
 
     fn read_local_decl(&mut self) -> WasmResult<(u32, Type)> {
+        if let Some(declaration) = self.local_declarations.pop_front() {
+            return Ok(declaration);
+        }
         let count = self.state.inner.read_var_u32()?;
         let ty = self.state.inner.read_type()?;
         Ok((count, ty))
@@ -149,6 +426,7 @@ impl<'a> FunctionBinaryReader<'a> for MiddlewareBinaryReader<'a> {
 
         // Try to fill the `self.pending_operations` buffer, until it is non-empty.
         while self.state.pending_operations.is_empty() {
+            self.state.original_offset = self.state.inner.original_position();
             let raw_op = self.state.inner.read_operator()?;
 
             // Fill the initial raw operator into pending buffer.
@@ -160,8 +438,13 @@ impl<'a> FunctionBinaryReader<'a> for MiddlewareBinaryReader<'a> {
                 let pending: SmallVec<[Operator<'a>; 2]> =
                     self.state.pending_operations.drain(0..).collect();
 
-                // ...and feed them into the current stage.
+                // ...and feed them into the current stage, with the
+                // function indices it expects.
                 for pending_op in pending {
+                    let pending_op = match stage.imported_functions() {
+                        Some(imported_functions) => imported_functions.remap_operator(pending_op),
+                        None => pending_op,
+                    };
                     stage.feed(pending_op, &mut self.state)?;
                 }
             }
@@ -15,8 +15,8 @@ mod sections;
 
 pub use self::environ::{FunctionBinaryReader, FunctionBodyData, ModuleEnvironment};
 pub use self::middleware::{
-    FunctionMiddleware, MiddlewareBinaryReader, MiddlewareReaderState, ModuleMiddleware,
-    ModuleMiddlewareChain,
+    FunctionLocals, FunctionMiddleware, ImportedFunctions, MiddlewareBinaryReader,
+    MiddlewareReaderState, ModuleMiddleware, ModuleMiddlewareChain,
 };
 pub use self::module::translate_module;
 pub use self::sections::wptype_to_type;
@@ -7,7 +7,7 @@ use std::ffi::CStr;
 use std::mem;
 use std::os::raw::c_char;
 
-use crate::env::{call_malloc, call_malloc_with_cast, EmAddrInfo, EmSockAddr};
+use crate::env::{call_malloc, call_malloc_with_cast, get_emscripten_data, EmAddrInfo, EmSockAddr};
 use crate::ptr::{Array, WasmPtr};
 use crate::utils::{copy_cstr_into_wasm, copy_terminated_array_of_cstrs};
 use crate::EmEnv;
@@ -159,6 +159,9 @@ pub fn _gai_strerror(ctx: &EmEnv, ecode: i32) -> i32 {
     string_on_guest.offset() as _
 }
 
+/// `EAI_FAIL`, as defined by Emscripten.
+const EAI_FAIL: i32 = -4;
+
 pub fn _getaddrinfo(
     ctx: &EmEnv,
     node_ptr: WasmPtr<c_char>,
@@ -168,6 +171,10 @@ pub fn _getaddrinfo(
 ) -> i32 {
     use libc::{addrinfo, freeaddrinfo};
     debug!("emscripten::_getaddrinfo");
+    if !get_emscripten_data(ctx).sandbox.networking_allowed() {
+        debug!("=> networking is not allowed");
+        return EAI_FAIL;
+    }
     let memory = ctx.memory(0);
     debug!(" => node = {}", {
         node_ptr
@@ -1,6 +1,6 @@
 use super::super::env::call_malloc;
 use super::super::utils::copy_cstr_into_wasm;
-use libc::{chroot as _chroot, getpwuid as _getpwuid, printf as _printf};
+use libc::{getpwuid as _getpwuid, printf as _printf};
 use std::mem;
 
 use crate::EmEnv;
@@ -20,10 +20,12 @@ pub fn printf(ctx: &EmEnv, memory_offset: i32, extra: i32) -> i32 {
 }
 
 /// chroot
-pub fn chroot(ctx: &EmEnv, name_ptr: i32) -> i32 {
+///
+/// The module can't change the root of the host process: its view of the
+/// filesystem is set by its sandbox.
+pub fn chroot(_ctx: &EmEnv, _name_ptr: i32) -> i32 {
     debug!("emscripten::chroot");
-    let name = emscripten_memory_pointer!(ctx.memory(0), name_ptr) as *const i8;
-    unsafe { _chroot(name as *const _) }
+    -1
 }
 
 /// getpwuid
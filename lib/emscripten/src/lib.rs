@@ -16,7 +16,6 @@ extern crate log;
 use lazy_static::lazy_static;
 use std::collections::HashMap;
 use std::f64;
-use std::path::PathBuf;
 use std::sync::{Arc, Mutex, RwLock};
 use wasmer::{
     imports, namespace, Exports, Function, FunctionType, Global, ImportObject, Instance, LazyInit,
@@ -53,6 +52,7 @@ mod memory;
 mod process;
 mod pthread;
 mod ptr;
+mod sandbox;
 mod signal;
 mod storage;
 mod syscalls;
@@ -62,6 +62,7 @@ mod unistd;
 mod utils;
 mod varargs;
 
+pub use self::sandbox::{EmSandbox, EmSandboxError, PreopenDirBuilder};
 pub use self::storage::{align_memory, static_alloc};
 pub use self::utils::{
     allocate_cstr_on_stack, allocate_on_stack, get_emscripten_memory_size, get_emscripten_metadata,
@@ -84,13 +85,24 @@ impl WasmerEnv for EmEnv {
 }
 
 impl EmEnv {
-    pub fn new(data: &EmscriptenGlobalsData, mapped_dirs: HashMap<String, PathBuf>) -> Self {
+    /// Creates the environment of a module, which is only granted the
+    /// capabilities of `sandbox`.
+    pub fn new(data: &EmscriptenGlobalsData, sandbox: EmSandbox) -> Self {
         Self {
             memory: Arc::new(RwLock::new(None)),
-            data: Arc::new(Mutex::new(EmscriptenData::new(data.clone(), mapped_dirs))),
+            data: Arc::new(Mutex::new(EmscriptenData::new(data.clone(), sandbox))),
         }
     }
 
+    /// Allows the module to create threads, each of them running in a new
+    /// instance of `module` sharing the memory of `globals`.
+    ///
+    /// [`run_emscripten_instance`] does it before running the module.
+    pub fn enable_pthreads(&self, module: &Module, globals: &EmscriptenGlobals) {
+        let pthreads = self.data.lock().unwrap().pthreads.clone();
+        pthreads.enable(module, globals);
+    }
+
     pub fn set_memory(&mut self, memory: Memory) {
         let mut w = self.memory.write().unwrap();
         *w = Some(memory);
@@ -272,18 +284,24 @@ pub struct EmscriptenData {
     pub stack_restore: LazyInit<NativeFunc<i32>>,
     #[wasmer(export(name = "setThrew", alias = "_setThrew", optional = true))]
     pub set_threw: LazyInit<NativeFunc<(i32, i32)>>,
-    pub mapped_dirs: HashMap<String, PathBuf>,
+    pub sandbox: EmSandbox,
+    /// The files opened by the module, shared by all its threads.
+    pub(crate) fds: Arc<Mutex<sandbox::FdTable>>,
+
+    pub(crate) pthreads: Arc<pthread::Pthreads>,
+    /// The `pthread_t` of the thread the instance runs on.
+    pub(crate) thread_id: u32,
+    /// The thread-specific data of the thread, by key.
+    pub(crate) thread_specific: HashMap<u32, u32>,
 }
 
 impl EmscriptenData {
-    pub fn new(
-        globals: EmscriptenGlobalsData,
-        mapped_dirs: HashMap<String, PathBuf>,
-    ) -> EmscriptenData {
+    pub fn new(globals: EmscriptenGlobalsData, sandbox: EmSandbox) -> EmscriptenData {
         EmscriptenData {
             globals,
             temp_ret_0: 0,
-            mapped_dirs,
+            sandbox,
+            thread_id: pthread::MAIN_THREAD_ID,
             ..Default::default()
         }
     }
@@ -368,6 +386,7 @@ pub fn run_emscripten_instance(
     entrypoint: Option<String>,
 ) -> Result<(), RuntimeError> {
     env.set_memory(globals.memory.clone());
+    env.enable_pthreads(instance.module(), globals);
     set_up_emscripten(instance)?;
 
     // println!("running emscripten instance");
@@ -437,6 +456,7 @@ pub struct EmscriptenGlobalsData {
     use_old_abort_on_cannot_grow_memory: bool,
 }
 
+#[derive(Clone)]
 pub struct EmscriptenGlobals {
     // The emscripten data
     pub data: EmscriptenGlobalsData,
@@ -902,9 +922,11 @@ pub fn generate_emscripten_env(
         "_pthread_attr_destroy" => Function::new_native_with_env(store, env.clone(), crate::pthread::_pthread_attr_destroy),
         "_pthread_attr_getstack" => Function::new_native_with_env(store, env.clone(), crate::pthread::_pthread_attr_getstack),
         "_pthread_attr_init" => Function::new_native_with_env(store, env.clone(), crate::pthread::_pthread_attr_init),
+        "_pthread_attr_setdetachstate" => Function::new_native_with_env(store, env.clone(), crate::pthread::_pthread_attr_setdetachstate),
         "_pthread_attr_setstacksize" => Function::new_native_with_env(store, env.clone(), crate::pthread::_pthread_attr_setstacksize),
         "_pthread_cleanup_pop" => Function::new_native_with_env(store, env.clone(), crate::pthread::_pthread_cleanup_pop),
         "_pthread_cleanup_push" => Function::new_native_with_env(store, env.clone(), crate::pthread::_pthread_cleanup_push),
+        "_pthread_cond_broadcast" => Function::new_native_with_env(store, env.clone(), crate::pthread::_pthread_cond_broadcast),
         "_pthread_cond_destroy" => Function::new_native_with_env(store, env.clone(), crate::pthread::_pthread_cond_destroy),
         "_pthread_cond_init" => Function::new_native_with_env(store, env.clone(), crate::pthread::_pthread_cond_init),
         "_pthread_cond_signal" => Function::new_native_with_env(store, env.clone(), crate::pthread::_pthread_cond_signal),
@@ -924,6 +946,9 @@ pub fn generate_emscripten_env(
         "_pthread_key_create" => Function::new_native_with_env(store, env.clone(), crate::pthread::_pthread_key_create),
         "_pthread_mutex_destroy" => Function::new_native_with_env(store, env.clone(), crate::pthread::_pthread_mutex_destroy),
         "_pthread_mutex_init" => Function::new_native_with_env(store, env.clone(), crate::pthread::_pthread_mutex_init),
+        "_pthread_mutex_lock" => Function::new_native_with_env(store, env.clone(), crate::pthread::_pthread_mutex_lock),
+        "_pthread_mutex_trylock" => Function::new_native_with_env(store, env.clone(), crate::pthread::_pthread_mutex_trylock),
+        "_pthread_mutex_unlock" => Function::new_native_with_env(store, env.clone(), crate::pthread::_pthread_mutex_unlock),
         "_pthread_mutexattr_destroy" => Function::new_native_with_env(store, env.clone(), crate::pthread::_pthread_mutexattr_destroy),
         "_pthread_mutexattr_init" => Function::new_native_with_env(store, env.clone(), crate::pthread::_pthread_mutexattr_init),
         "_pthread_mutexattr_settype" => Function::new_native_with_env(store, env.clone(), crate::pthread::_pthread_mutexattr_settype),
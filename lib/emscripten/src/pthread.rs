@@ -1,30 +1,543 @@
-use crate::EmEnv;
-
-pub fn _pthread_attr_destroy(_ctx: &EmEnv, _a: i32) -> i32 {
+//! POSIX threads for Emscripten modules.
+//!
+//! Every thread created by the module runs on a host thread of its own, in a
+//! new `Instance` of the module. The instances share the linear memory of
+//! the main one, which is only initialized with the data segments and the
+//! start function of the module by the main instance, and each of them gets its own stack,
+//! allocated in the shared memory and handed to it through the `STACKTOP` and
+//! `STACK_MAX` globals of its `EmscriptenGlobals`. Every instance gets its own
+//! table too, filled by its element segments, so that the function pointers
+//! of the module call into the instance of the thread calling them, the way
+//! they do in the workers of Emscripten.
+//!
+//! Mutexes, condition variables and read-write locks are backed by host
+//! primitives, looked up by the address of the guest object.
+
+use crate::env::{call_memalign, get_emscripten_data};
+use crate::sandbox::FdTable;
+use crate::storage::align_memory;
+use crate::{generate_emscripten_env, EmEnv, EmSandbox, EmscriptenGlobals};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use wasmer::{Instance, Module, RuntimeError, Table, Val};
+
+// Error codes, as defined by Emscripten.
+const EPERM: i32 = 1;
+const ESRCH: i32 = 3;
+const EAGAIN: i32 = 11;
+const EBUSY: i32 = 16;
+const EINVAL: i32 = 22;
+const EDEADLK: i32 = 35;
+const ETIMEDOUT: i32 = 110;
+
+// Mutex types, as defined by Emscripten.
+const PTHREAD_MUTEX_NORMAL: i32 = 0;
+const PTHREAD_MUTEX_RECURSIVE: i32 = 1;
+const PTHREAD_MUTEX_ERRORCHECK: i32 = 2;
+
+const PTHREAD_CREATE_DETACHED: i32 = 1;
+
+/// The `pthread_t` of the thread running `main`.
+pub(crate) const MAIN_THREAD_ID: u32 = 1;
+/// The smallest stack a thread can be given.
+const PTHREAD_STACK_MIN: u32 = 16 * 1024;
+/// The size of the stack of the threads created without one.
+const DEFAULT_STACK_SIZE: u32 = 2 * 1024 * 1024;
+/// The size of the host stack the wasm code of a thread runs on.
+const HOST_STACK_SIZE: usize = 8 * 1024 * 1024;
+
+/// Raised by `pthread_exit` to unwind the stack of the thread.
+#[derive(Copy, Clone, Debug)]
+pub struct PthreadExit(pub i32);
+
+impl fmt::Display for PthreadExit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "pthread_exit({})", self.0)
+    }
+}
+
+impl Error for PthreadExit {}
+
+/// What's needed to instantiate the module again for a new thread.
+#[derive(Clone)]
+struct Spawner {
+    module: Module,
+    globals: EmscriptenGlobals,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ThreadAttr {
+    stack_size: u32,
+    detached: bool,
+    /// The stack of the thread the attributes were read from, by
+    /// `pthread_getattr_np`.
+    stack: Option<(u32, u32)>,
+}
+
+impl Default for ThreadAttr {
+    fn default() -> Self {
+        Self {
+            stack_size: DEFAULT_STACK_SIZE,
+            detached: false,
+            stack: None,
+        }
+    }
+}
+
+struct Thread {
+    join_handle: Option<JoinHandle<i32>>,
+    /// The base and size of the stack of the thread.
+    stack: (u32, u32),
+    finished: bool,
+    detached: bool,
+}
+
+#[derive(Debug, Default)]
+struct MutexState {
+    owner: u32,
+    count: u32,
+}
+
+#[derive(Debug, Default)]
+struct HostMutex {
+    kind: i32,
+    state: Mutex<MutexState>,
+    unlocked: Condvar,
+}
+
+impl HostMutex {
+    fn new(kind: i32) -> Self {
+        Self {
+            kind,
+            ..Default::default()
+        }
+    }
+
+    fn lock(&self, thread_id: u32) -> i32 {
+        let mut state = self.state.lock().unwrap();
+        if state.owner == thread_id {
+            match self.kind {
+                PTHREAD_MUTEX_RECURSIVE => {
+                    state.count += 1;
+                    return 0;
+                }
+                PTHREAD_MUTEX_ERRORCHECK => return EDEADLK,
+                _ => (),
+            }
+        }
+        while state.owner != 0 {
+            state = self.unlocked.wait(state).unwrap();
+        }
+        state.owner = thread_id;
+        state.count = 1;
+        0
+    }
+
+    fn try_lock(&self, thread_id: u32) -> i32 {
+        let mut state = self.state.lock().unwrap();
+        if state.owner == 0 {
+            state.owner = thread_id;
+            state.count = 1;
+            0
+        } else if state.owner == thread_id && self.kind == PTHREAD_MUTEX_RECURSIVE {
+            state.count += 1;
+            0
+        } else {
+            EBUSY
+        }
+    }
+
+    fn unlock(&self, thread_id: u32) -> i32 {
+        let mut state = self.state.lock().unwrap();
+        if state.owner != thread_id {
+            return EPERM;
+        }
+        state.count -= 1;
+        if state.count == 0 {
+            state.owner = 0;
+            self.unlocked.notify_one();
+        }
+        0
+    }
+
+    /// Releases the mutex however many times it's locked by `thread_id`,
+    /// returning that count to `relock` it with.
+    fn unlock_all(&self, thread_id: u32) -> Result<u32, i32> {
+        let mut state = self.state.lock().unwrap();
+        if state.owner != thread_id {
+            return Err(EPERM);
+        }
+        let count = std::mem::take(&mut state.count);
+        state.owner = 0;
+        self.unlocked.notify_one();
+        Ok(count)
+    }
+
+    /// Locks the mutex again after `unlock_all`, as many times as it was.
+    fn relock(&self, thread_id: u32, count: u32) {
+        self.lock(thread_id);
+        self.state.lock().unwrap().count = count;
+    }
+}
+
+#[derive(Debug, Default)]
+struct HostCond {
+    /// Bumped on every signal, so that waiters can tell they were woken up.
+    generation: Mutex<u64>,
+    cond: Condvar,
+}
+
+impl HostCond {
+    fn wait(&self, mutex: &HostMutex, thread_id: u32, deadline: Option<Instant>) -> i32 {
+        // the generation is locked before the mutex is released so that no
+        // signal can be missed in between
+        let mut generation = self.generation.lock().unwrap();
+        let start = *generation;
+        // a recursive mutex is released entirely while waiting
+        let count = match mutex.unlock_all(thread_id) {
+            Ok(count) => count,
+            Err(errno) => return errno,
+        };
+        let mut timed_out = false;
+        while *generation == start {
+            match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        timed_out = true;
+                        break;
+                    }
+                    generation = self
+                        .cond
+                        .wait_timeout(generation, deadline - now)
+                        .unwrap()
+                        .0;
+                }
+                None => generation = self.cond.wait(generation).unwrap(),
+            }
+        }
+        drop(generation);
+        mutex.relock(thread_id, count);
+        if timed_out {
+            ETIMEDOUT
+        } else {
+            0
+        }
+    }
+
+    fn signal(&self, all: bool) {
+        *self.generation.lock().unwrap() += 1;
+        if all {
+            self.cond.notify_all();
+        } else {
+            self.cond.notify_one();
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct RwLockState {
+    readers: u32,
+    writer: u32,
+}
+
+#[derive(Debug, Default)]
+struct HostRwLock {
+    state: Mutex<RwLockState>,
+    released: Condvar,
+}
+
+impl HostRwLock {
+    fn read(&self) -> i32 {
+        let mut state = self.state.lock().unwrap();
+        while state.writer != 0 {
+            state = self.released.wait(state).unwrap();
+        }
+        state.readers += 1;
+        0
+    }
+
+    fn write(&self, thread_id: u32) -> i32 {
+        let mut state = self.state.lock().unwrap();
+        if state.writer == thread_id {
+            return EDEADLK;
+        }
+        while state.writer != 0 || state.readers != 0 {
+            state = self.released.wait(state).unwrap();
+        }
+        state.writer = thread_id;
+        0
+    }
+
+    fn unlock(&self, thread_id: u32) -> i32 {
+        let mut state = self.state.lock().unwrap();
+        if state.writer == thread_id {
+            state.writer = 0;
+        } else if state.readers > 0 {
+            state.readers -= 1;
+        } else {
+            return EPERM;
+        }
+        self.released.notify_all();
+        0
+    }
+}
+
+/// The threads of a module and their synchronization primitives, shared by
+/// the environments of all of its instances.
+#[derive(Default)]
+pub(crate) struct Pthreads {
+    spawner: Mutex<Option<Spawner>>,
+    next_id: AtomicU32,
+    threads: Mutex<HashMap<u32, Thread>>,
+    thread_attrs: Mutex<HashMap<u32, ThreadAttr>>,
+    mutexes: Mutex<HashMap<u32, Arc<HostMutex>>>,
+    mutex_attrs: Mutex<HashMap<u32, i32>>,
+    conds: Mutex<HashMap<u32, Arc<HostCond>>>,
+    rwlocks: Mutex<HashMap<u32, Arc<HostRwLock>>>,
+    onces: Mutex<HashMap<u32, Arc<Mutex<()>>>>,
+    /// The destructors of the thread-specific data keys, by key.
+    key_destructors: Mutex<Vec<u32>>,
+}
+
+impl Pthreads {
+    /// Allows the module to create threads.
+    pub(crate) fn enable(&self, module: &Module, globals: &EmscriptenGlobals) {
+        *self.spawner.lock().unwrap() = Some(Spawner {
+            module: module.clone(),
+            globals: globals.clone(),
+        });
+    }
+
+    fn mutex(&self, addr: u32) -> Arc<HostMutex> {
+        let mut mutexes = self.mutexes.lock().unwrap();
+        // statically initialized mutexes are never passed to
+        // `pthread_mutex_init`
+        mutexes
+            .entry(addr)
+            .or_insert_with(|| Arc::new(HostMutex::new(PTHREAD_MUTEX_NORMAL)))
+            .clone()
+    }
+
+    fn cond(&self, addr: u32) -> Arc<HostCond> {
+        self.conds.lock().unwrap().entry(addr).or_default().clone()
+    }
+
+    fn rwlock(&self, addr: u32) -> Arc<HostRwLock> {
+        self.rwlocks
+            .lock()
+            .unwrap()
+            .entry(addr)
+            .or_default()
+            .clone()
+    }
+}
+
+fn pthreads(ctx: &EmEnv) -> Arc<Pthreads> {
+    get_emscripten_data(ctx).pthreads.clone()
+}
+
+fn thread_id(ctx: &EmEnv) -> u32 {
+    get_emscripten_data(ctx).thread_id
+}
+
+fn read_u32(ctx: &EmEnv, ptr: i32) -> u32 {
+    ctx.memory(0).view::<u32>()[(ptr as u32 / 4) as usize].get()
+}
+
+fn write_u32(ctx: &EmEnv, ptr: i32, value: u32) {
+    ctx.memory(0).view::<u32>()[(ptr as u32 / 4) as usize].set(value);
+}
+
+fn free(ctx: &EmEnv, ptr: u32) {
+    let free = get_emscripten_data(ctx).free_ref().cloned();
+    if let Some(free) = free {
+        let _ = free.call(ptr);
+    }
+}
+
+/// Runs the start routine of the thread `thread_id` in a new instance of
+/// the module, returning the value the thread exited with.
+#[allow(clippy::too_many_arguments)]
+fn run_thread(
+    spawner: Spawner,
+    pthreads: Arc<Pthreads>,
+    sandbox: EmSandbox,
+    fds: Arc<Mutex<FdTable>>,
+    thread_id: u32,
+    (stack_base, stack_size): (u32, u32),
+    start_routine: i32,
+    arg: i32,
+) -> i32 {
+    let mut globals = spawner.globals;
+    globals.data.stacktop = align_memory(stack_base);
+    globals.data.stack_max = stack_base + stack_size;
+    // the entries of the table point into the instance that filled it: the
+    // table of the main instance can't be handed to the ones of the threads,
+    // which would overwrite them with functions that go away with the thread
+    globals.table = match Table::new(
+        spawner.module.store(),
+        *globals.table.ty(),
+        Val::FuncRef(None),
+    ) {
+        Ok(table) => table,
+        Err(e) => {
+            error!(
+                "emscripten: can't create the table of thread {}: {}",
+                thread_id, e
+            );
+            return 0;
+        }
+    };
+
+    let mut env = EmEnv::new(&globals.data, sandbox);
+    {
+        let mut data = get_emscripten_data(&env);
+        data.pthreads = pthreads.clone();
+        data.fds = fds;
+        data.thread_id = thread_id;
+    }
+    let import_object = generate_emscripten_env(spawner.module.store(), &mut globals, &env);
+    // the memory is already initialized, and in use by the other threads.
+    // The instance has to outlive every call made through the table of the
+    // thread, that is the rest of this function.
+    let instance = Instance::new_without_initialization(&spawner.module, &import_object);
+    let instance = match instance {
+        Ok(instance) => instance,
+        Err(e) => {
+            error!("emscripten: can't instantiate thread {}: {}", thread_id, e);
+            return 0;
+        }
+    };
+    env.set_memory(globals.memory.clone());
+
+    let start = get_emscripten_data(&env).dyn_call_ii_ref().cloned();
+    let ret = match start.map(|start| start.call(start_routine, arg)) {
+        Some(Ok(ret)) => ret,
+        Some(Err(e)) => match e.downcast::<PthreadExit>() {
+            Ok(PthreadExit(ret)) => ret,
+            Err(e) => {
+                error!("emscripten: thread {} trapped: {}", thread_id, e);
+                0
+            }
+        },
+        None => {
+            error!("emscripten: the module doesn't export `dynCall_ii`");
+            0
+        }
+    };
+
+    // run the destructors of the thread-specific data
+    let specific = std::mem::take(&mut get_emscripten_data(&env).thread_specific);
+    let destructors = pthreads.key_destructors.lock().unwrap().clone();
+    let call_destructor = get_emscripten_data(&env).dyn_call_vi_ref().cloned();
+    if let Some(call_destructor) = call_destructor {
+        for (key, value) in specific {
+            match destructors.get(key as usize - 1) {
+                Some(&destructor) if destructor != 0 && value != 0 => {
+                    let _ = call_destructor.call(destructor as i32, value as i32);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    let mut threads = pthreads.threads.lock().unwrap();
+    let detached = match threads.get_mut(&thread_id) {
+        Some(thread) if thread.detached => true,
+        Some(thread) => {
+            thread.finished = true;
+            false
+        }
+        None => false,
+    };
+    if detached {
+        // nobody will join the thread: its stack can go now that nothing
+        // runs on it anymore
+        threads.remove(&thread_id);
+        drop(threads);
+        free(&env, stack_base);
+    }
+    drop(env);
+    drop(instance);
+    ret
+}
+
+pub fn _pthread_attr_destroy(ctx: &EmEnv, attr: i32) -> i32 {
     trace!("emscripten::_pthread_attr_destroy");
+    pthreads(ctx)
+        .thread_attrs
+        .lock()
+        .unwrap()
+        .remove(&(attr as u32));
     0
 }
 
-pub fn _pthread_attr_getstack(_ctx: &EmEnv, _stackaddr: i32, _stacksize: i32, _other: i32) -> i32 {
+pub fn _pthread_attr_getstack(ctx: &EmEnv, attr: i32, stackaddr: i32, stacksize: i32) -> i32 {
     trace!(
         "emscripten::_pthread_attr_getstack({}, {}, {})",
-        _stackaddr,
-        _stacksize,
-        _other
+        attr,
+        stackaddr,
+        stacksize
     );
-    // TODO: Translate from Emscripten
-    // HEAP32[stackaddr >> 2] = STACK_BASE;
-    // HEAP32[stacksize >> 2] = TOTAL_STACK;
+    let stack = pthreads(ctx)
+        .thread_attrs
+        .lock()
+        .unwrap()
+        .get(&(attr as u32))
+        .and_then(|attr| attr.stack);
+    let (base, size) = stack.unwrap_or_else(|| {
+        let globals = &get_emscripten_data(ctx).globals;
+        (globals.stacktop, globals.stack_max - globals.stacktop)
+    });
+    write_u32(ctx, stackaddr, base);
+    write_u32(ctx, stacksize, size);
+    0
+}
+
+pub fn _pthread_attr_init(ctx: &EmEnv, attr: i32) -> i32 {
+    trace!("emscripten::_pthread_attr_init({})", attr);
+    pthreads(ctx)
+        .thread_attrs
+        .lock()
+        .unwrap()
+        .insert(attr as u32, ThreadAttr::default());
     0
 }
 
-pub fn _pthread_attr_init(_ctx: &EmEnv, _a: i32) -> i32 {
-    trace!("emscripten::_pthread_attr_init({})", _a);
+pub fn _pthread_attr_setdetachstate(ctx: &EmEnv, attr: i32, state: i32) -> i32 {
+    trace!(
+        "emscripten::_pthread_attr_setdetachstate({}, {})",
+        attr,
+        state
+    );
+    pthreads(ctx)
+        .thread_attrs
+        .lock()
+        .unwrap()
+        .entry(attr as u32)
+        .or_default()
+        .detached = state == PTHREAD_CREATE_DETACHED;
     0
 }
 
-pub fn _pthread_attr_setstacksize(_ctx: &EmEnv, _a: i32, _b: i32) -> i32 {
+pub fn _pthread_attr_setstacksize(ctx: &EmEnv, attr: i32, stacksize: i32) -> i32 {
     trace!("emscripten::_pthread_attr_setstacksize");
+    if (stacksize as u32) < PTHREAD_STACK_MIN {
+        return EINVAL;
+    }
+    pthreads(ctx)
+        .thread_attrs
+        .lock()
+        .unwrap()
+        .entry(attr as u32)
+        .or_default()
+        .stack_size = stacksize as u32;
     0
 }
 
@@ -36,29 +549,58 @@ pub fn _pthread_cleanup_push(_ctx: &EmEnv, _a: i32, _b: i32) {
     trace!("emscripten::_pthread_cleanup_push");
 }
 
-pub fn _pthread_cond_destroy(_ctx: &EmEnv, _a: i32) -> i32 {
+pub fn _pthread_cond_broadcast(ctx: &EmEnv, cond: i32) -> i32 {
+    trace!("emscripten::_pthread_cond_broadcast");
+    pthreads(ctx).cond(cond as u32).signal(true);
+    0
+}
+
+pub fn _pthread_cond_destroy(ctx: &EmEnv, cond: i32) -> i32 {
     trace!("emscripten::_pthread_cond_destroy");
+    pthreads(ctx).conds.lock().unwrap().remove(&(cond as u32));
     0
 }
 
-pub fn _pthread_cond_init(_ctx: &EmEnv, _a: i32, _b: i32) -> i32 {
+pub fn _pthread_cond_init(ctx: &EmEnv, cond: i32, _attr: i32) -> i32 {
     trace!("emscripten::_pthread_cond_init");
+    pthreads(ctx)
+        .conds
+        .lock()
+        .unwrap()
+        .insert(cond as u32, Default::default());
     0
 }
 
-pub fn _pthread_cond_signal(_ctx: &EmEnv, _a: i32) -> i32 {
+pub fn _pthread_cond_signal(ctx: &EmEnv, cond: i32) -> i32 {
     trace!("emscripten::_pthread_cond_signal");
+    pthreads(ctx).cond(cond as u32).signal(false);
     0
 }
 
-pub fn _pthread_cond_timedwait(_ctx: &EmEnv, _a: i32, _b: i32, _c: i32) -> i32 {
+pub fn _pthread_cond_timedwait(ctx: &EmEnv, cond: i32, mutex: i32, abstime: i32) -> i32 {
     trace!("emscripten::_pthread_cond_timedwait");
-    0
-}
-
-pub fn _pthread_cond_wait(_ctx: &EmEnv, _a: i32, _b: i32) -> i32 {
+    // `abstime` is a `struct timespec` on the realtime clock
+    let seconds = read_u32(ctx, abstime) as i32;
+    let nanos = read_u32(ctx, abstime + 4) as i32;
+    if !(0..1_000_000_000).contains(&nanos) {
+        return EINVAL;
+    }
+    let target = Duration::new(seconds.max(0) as u64, nanos as u32);
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let deadline = Instant::now() + target.checked_sub(now).unwrap_or_default();
+
+    let pthreads = pthreads(ctx);
+    let (cond, mutex) = (pthreads.cond(cond as u32), pthreads.mutex(mutex as u32));
+    cond.wait(&mutex, thread_id(ctx), Some(deadline))
+}
+
+pub fn _pthread_cond_wait(ctx: &EmEnv, cond: i32, mutex: i32) -> i32 {
     trace!("emscripten::_pthread_cond_wait");
-    0
+    let pthreads = pthreads(ctx);
+    let (cond, mutex) = (pthreads.cond(cond as u32), pthreads.mutex(mutex as u32));
+    cond.wait(&mutex, thread_id(ctx), None)
 }
 
 pub fn _pthread_condattr_destroy(_ctx: &EmEnv, _a: i32) -> i32 {
@@ -76,104 +618,319 @@ pub fn _pthread_condattr_setclock(_ctx: &EmEnv, _a: i32, _b: i32) -> i32 {
     0
 }
 
-pub fn _pthread_create(_ctx: &EmEnv, _a: i32, _b: i32, _c: i32, _d: i32) -> i32 {
+pub fn _pthread_create(ctx: &EmEnv, thread: i32, attr: i32, start_routine: i32, arg: i32) -> i32 {
     trace!("emscripten::_pthread_create");
-    // 11 seems to mean "no"
-    11
-}
-
-pub fn _pthread_detach(_ctx: &EmEnv, _a: i32) -> i32 {
+    let pthreads = pthreads(ctx);
+    let spawner = match pthreads.spawner.lock().unwrap().clone() {
+        Some(spawner) => spawner,
+        None => {
+            debug!("=> threads aren't enabled for this module");
+            return EAGAIN;
+        }
+    };
+    let attr = if attr == 0 {
+        ThreadAttr::default()
+    } else {
+        pthreads
+            .thread_attrs
+            .lock()
+            .unwrap()
+            .get(&(attr as u32))
+            .copied()
+            .unwrap_or_default()
+    };
+
+    let stack_base = call_memalign(ctx, 16, attr.stack_size);
+    if stack_base == 0 {
+        return EAGAIN;
+    }
+    let stack = (stack_base, attr.stack_size);
+    let thread_id = pthreads.next_id.fetch_add(1, Ordering::SeqCst) + MAIN_THREAD_ID + 1;
+    write_u32(ctx, thread, thread_id);
+
+    let sandbox = get_emscripten_data(ctx).sandbox.clone();
+    let fds = get_emscripten_data(ctx).fds.clone();
+    // the thread is registered before it starts so that it finds itself
+    // when it exits
+    let mut threads = pthreads.threads.lock().unwrap();
+    let child_pthreads = pthreads.clone();
+    let spawned = thread::Builder::new()
+        .name(format!("emscripten-pthread-{}", thread_id))
+        .stack_size(HOST_STACK_SIZE)
+        .spawn(move || {
+            run_thread(
+                spawner,
+                child_pthreads,
+                sandbox,
+                fds,
+                thread_id,
+                stack,
+                start_routine,
+                arg,
+            )
+        });
+    match spawned {
+        Ok(join_handle) => {
+            threads.insert(
+                thread_id,
+                Thread {
+                    join_handle: Some(join_handle),
+                    stack,
+                    finished: false,
+                    detached: attr.detached,
+                },
+            );
+            0
+        }
+        Err(e) => {
+            drop(threads);
+            debug!("=> can't spawn a host thread: {}", e);
+            free(ctx, stack_base);
+            EAGAIN
+        }
+    }
+}
+
+pub fn _pthread_detach(ctx: &EmEnv, thread: i32) -> i32 {
     trace!("emscripten::_pthread_detach");
-    0
-}
-
-pub fn _pthread_equal(_ctx: &EmEnv, _a: i32, _b: i32) -> i32 {
+    let pthreads = pthreads(ctx);
+    let mut threads = pthreads.threads.lock().unwrap();
+    let finished = match threads.get_mut(&(thread as u32)) {
+        Some(thread) if thread.detached => return EINVAL,
+        Some(thread) => {
+            thread.detached = true;
+            thread.join_handle = None;
+            thread.finished
+        }
+        None => return ESRCH,
+    };
+    if finished {
+        if let Some(thread) = threads.remove(&(thread as u32)) {
+            drop(threads);
+            free(ctx, thread.stack.0);
+        }
+    }
+    0
+}
+
+pub fn _pthread_equal(_ctx: &EmEnv, a: i32, b: i32) -> i32 {
     trace!("emscripten::_pthread_equal");
-    0
+    (a == b) as i32
 }
 
-pub fn _pthread_exit(_ctx: &EmEnv, _a: i32) {
+pub fn _pthread_exit(_ctx: &EmEnv, value: i32) {
     trace!("emscripten::_pthread_exit");
-}
-
-pub fn _pthread_getattr_np(_ctx: &EmEnv, _thread: i32, _attr: i32) -> i32 {
-    trace!("emscripten::_pthread_getattr_np({}, {})", _thread, _attr);
+    RuntimeError::raise(Box::new(PthreadExit(value)));
+}
+
+pub fn _pthread_getattr_np(ctx: &EmEnv, thread: i32, attr: i32) -> i32 {
+    trace!("emscripten::_pthread_getattr_np({}, {})", thread, attr);
+    let pthreads = pthreads(ctx);
+    let stack = if thread as u32 == thread_id(ctx) {
+        let globals = &get_emscripten_data(ctx).globals;
+        (globals.stacktop, globals.stack_max - globals.stacktop)
+    } else {
+        match pthreads.threads.lock().unwrap().get(&(thread as u32)) {
+            Some(thread) => thread.stack,
+            None => return ESRCH,
+        }
+    };
+    pthreads.thread_attrs.lock().unwrap().insert(
+        attr as u32,
+        ThreadAttr {
+            stack_size: stack.1,
+            stack: Some(stack),
+            ..Default::default()
+        },
+    );
     0
 }
 
-pub fn _pthread_getspecific(_ctx: &EmEnv, _a: i32) -> i32 {
+pub fn _pthread_getspecific(ctx: &EmEnv, key: i32) -> i32 {
     trace!("emscripten::_pthread_getspecific");
-    0
+    get_emscripten_data(ctx)
+        .thread_specific
+        .get(&(key as u32))
+        .copied()
+        .unwrap_or(0) as i32
 }
 
-pub fn _pthread_join(_ctx: &EmEnv, _a: i32, _b: i32) -> i32 {
+pub fn _pthread_join(ctx: &EmEnv, thread: i32, retval: i32) -> i32 {
     trace!("emscripten::_pthread_join");
-    0
-}
-
-pub fn _pthread_self(_ctx: &EmEnv) -> i32 {
+    if thread as u32 == thread_id(ctx) {
+        return EDEADLK;
+    }
+    let pthreads = pthreads(ctx);
+    let join_handle = match pthreads.threads.lock().unwrap().get_mut(&(thread as u32)) {
+        Some(thread) => match thread.join_handle.take() {
+            Some(join_handle) => join_handle,
+            None => return EINVAL,
+        },
+        None => return ESRCH,
+    };
+    let ret = join_handle.join().unwrap_or(0);
+    if let Some(thread) = pthreads.threads.lock().unwrap().remove(&(thread as u32)) {
+        free(ctx, thread.stack.0);
+    }
+    if retval != 0 {
+        write_u32(ctx, retval, ret as u32);
+    }
+    0
+}
+
+pub fn _pthread_self(ctx: &EmEnv) -> i32 {
     trace!("emscripten::_pthread_self");
-    0
+    thread_id(ctx) as i32
 }
 
-pub fn _pthread_key_create(_ctx: &EmEnv, _a: i32, _b: i32) -> i32 {
+pub fn _pthread_key_create(ctx: &EmEnv, key: i32, destructor: i32) -> i32 {
     trace!("emscripten::_pthread_key_create");
+    let pthreads = pthreads(ctx);
+    let mut destructors = pthreads.key_destructors.lock().unwrap();
+    destructors.push(destructor as u32);
+    write_u32(ctx, key, destructors.len() as u32);
     0
 }
 
-pub fn _pthread_mutex_destroy(_ctx: &EmEnv, _a: i32) -> i32 {
+pub fn _pthread_mutex_destroy(ctx: &EmEnv, mutex: i32) -> i32 {
     trace!("emscripten::_pthread_mutex_destroy");
+    pthreads(ctx)
+        .mutexes
+        .lock()
+        .unwrap()
+        .remove(&(mutex as u32));
     0
 }
 
-pub fn _pthread_mutex_init(_ctx: &EmEnv, _a: i32, _b: i32) -> i32 {
+pub fn _pthread_mutex_init(ctx: &EmEnv, mutex: i32, attr: i32) -> i32 {
     trace!("emscripten::_pthread_mutex_init");
+    let pthreads = pthreads(ctx);
+    let kind = pthreads
+        .mutex_attrs
+        .lock()
+        .unwrap()
+        .get(&(attr as u32))
+        .copied()
+        .unwrap_or(PTHREAD_MUTEX_NORMAL);
+    pthreads
+        .mutexes
+        .lock()
+        .unwrap()
+        .insert(mutex as u32, Arc::new(HostMutex::new(kind)));
     0
 }
 
-pub fn _pthread_mutexattr_destroy(_ctx: &EmEnv, _a: i32) -> i32 {
-    trace!("emscripten::_pthread_mutexattr_destroy");
-    0
+pub fn _pthread_mutex_lock(ctx: &EmEnv, mutex: i32) -> i32 {
+    trace!("emscripten::_pthread_mutex_lock");
+    pthreads(ctx).mutex(mutex as u32).lock(thread_id(ctx))
 }
 
-pub fn _pthread_mutexattr_init(_ctx: &EmEnv, _a: i32) -> i32 {
-    trace!("emscripten::_pthread_mutexattr_init");
-    0
+pub fn _pthread_mutex_trylock(ctx: &EmEnv, mutex: i32) -> i32 {
+    trace!("emscripten::_pthread_mutex_trylock");
+    pthreads(ctx).mutex(mutex as u32).try_lock(thread_id(ctx))
 }
 
-pub fn _pthread_mutexattr_settype(_ctx: &EmEnv, _a: i32, _b: i32) -> i32 {
-    trace!("emscripten::_pthread_mutexattr_settype");
-    0
+pub fn _pthread_mutex_unlock(ctx: &EmEnv, mutex: i32) -> i32 {
+    trace!("emscripten::_pthread_mutex_unlock");
+    pthreads(ctx).mutex(mutex as u32).unlock(thread_id(ctx))
 }
 
-pub fn _pthread_once(_ctx: &EmEnv, _a: i32, _b: i32) -> i32 {
-    trace!("emscripten::_pthread_once");
+pub fn _pthread_mutexattr_destroy(ctx: &EmEnv, attr: i32) -> i32 {
+    trace!("emscripten::_pthread_mutexattr_destroy");
+    pthreads(ctx)
+        .mutex_attrs
+        .lock()
+        .unwrap()
+        .remove(&(attr as u32));
     0
 }
 
-pub fn _pthread_rwlock_destroy(_ctx: &EmEnv, _rwlock: i32) -> i32 {
-    trace!("emscripten::_pthread_rwlock_destroy({})", _rwlock);
+pub fn _pthread_mutexattr_init(ctx: &EmEnv, attr: i32) -> i32 {
+    trace!("emscripten::_pthread_mutexattr_init");
+    pthreads(ctx)
+        .mutex_attrs
+        .lock()
+        .unwrap()
+        .insert(attr as u32, PTHREAD_MUTEX_NORMAL);
     0
 }
 
-pub fn _pthread_rwlock_init(_ctx: &EmEnv, _rwlock: i32, _attr: i32) -> i32 {
-    trace!("emscripten::_pthread_rwlock_init({}, {})", _rwlock, _attr);
+pub fn _pthread_mutexattr_settype(ctx: &EmEnv, attr: i32, kind: i32) -> i32 {
+    trace!("emscripten::_pthread_mutexattr_settype");
+    if !(PTHREAD_MUTEX_NORMAL..=PTHREAD_MUTEX_ERRORCHECK).contains(&kind) {
+        return EINVAL;
+    }
+    pthreads(ctx)
+        .mutex_attrs
+        .lock()
+        .unwrap()
+        .insert(attr as u32, kind);
     0
 }
 
-pub fn _pthread_rwlock_rdlock(_ctx: &EmEnv, _a: i32) -> i32 {
+pub fn _pthread_once(ctx: &EmEnv, once_control: i32, init_routine: i32) -> i32 {
+    trace!("emscripten::_pthread_once");
+    let once = pthreads(ctx)
+        .onces
+        .lock()
+        .unwrap()
+        .entry(once_control as u32)
+        .or_default()
+        .clone();
+    let result = {
+        // concurrent callers wait for the routine to be done
+        let _guard = once.lock().unwrap();
+        if read_u32(ctx, once_control) != 0 {
+            return 0;
+        }
+        let init = get_emscripten_data(ctx).dyn_call_v_ref().cloned();
+        let result = init.map_or(Ok(()), |init| init.call(init_routine));
+        if result.is_ok() {
+            write_u32(ctx, once_control, 1);
+        }
+        result
+    };
+    // traps skip the destructors of the host frames: the guard must be
+    // released before propagating one
+    if let Err(e) = result {
+        RuntimeError::raise(Box::new(e));
+    }
+    0
+}
+
+pub fn _pthread_rwlock_destroy(ctx: &EmEnv, rwlock: i32) -> i32 {
+    trace!("emscripten::_pthread_rwlock_destroy({})", rwlock);
+    pthreads(ctx)
+        .rwlocks
+        .lock()
+        .unwrap()
+        .remove(&(rwlock as u32));
+    0
+}
+
+pub fn _pthread_rwlock_init(ctx: &EmEnv, rwlock: i32, _attr: i32) -> i32 {
+    trace!("emscripten::_pthread_rwlock_init({}, {})", rwlock, _attr);
+    pthreads(ctx)
+        .rwlocks
+        .lock()
+        .unwrap()
+        .insert(rwlock as u32, Default::default());
+    0
+}
+
+pub fn _pthread_rwlock_rdlock(ctx: &EmEnv, rwlock: i32) -> i32 {
     trace!("emscripten::_pthread_rwlock_rdlock");
-    0
+    pthreads(ctx).rwlock(rwlock as u32).read()
 }
 
-pub fn _pthread_rwlock_unlock(_ctx: &EmEnv, _a: i32) -> i32 {
+pub fn _pthread_rwlock_unlock(ctx: &EmEnv, rwlock: i32) -> i32 {
     trace!("emscripten::_pthread_rwlock_unlock");
-    0
+    pthreads(ctx).rwlock(rwlock as u32).unlock(thread_id(ctx))
 }
 
-pub fn _pthread_rwlock_wrlock(_ctx: &EmEnv, _rwlock: i32) -> i32 {
-    trace!("emscripten::_pthread_rwlock_wrlock({})", _rwlock);
-    0
+pub fn _pthread_rwlock_wrlock(ctx: &EmEnv, rwlock: i32) -> i32 {
+    trace!("emscripten::_pthread_rwlock_wrlock({})", rwlock);
+    pthreads(ctx).rwlock(rwlock as u32).write(thread_id(ctx))
 }
 
 pub fn _pthread_setcancelstate(_ctx: &EmEnv, _a: i32, _b: i32) -> i32 {
@@ -181,8 +938,15 @@ pub fn _pthread_setcancelstate(_ctx: &EmEnv, _a: i32, _b: i32) -> i32 {
     0
 }
 
-pub fn _pthread_setspecific(_ctx: &EmEnv, _a: i32, _b: i32) -> i32 {
+pub fn _pthread_setspecific(ctx: &EmEnv, key: i32, value: i32) -> i32 {
     trace!("emscripten::_pthread_setspecific");
+    let known = key > 0 && key as usize <= pthreads(ctx).key_destructors.lock().unwrap().len();
+    if !known {
+        return EINVAL;
+    }
+    get_emscripten_data(ctx)
+        .thread_specific
+        .insert(key as u32, value as u32);
     0
 }
 
@@ -0,0 +1,841 @@
+//! The capabilities granted to an Emscripten module.
+//!
+//! Emscripten syscalls used to run directly against the host. They now go
+//! through an [`EmSandbox`], which follows the same model as WASI: the
+//! module only sees the host directories that were preopened for it, each
+//! with its own read, write and create permissions, and it can't use the
+//! network unless it was explicitly allowed to.
+//!
+//! Guest paths are resolved against a virtual working directory. They are
+//! normalized lexically, so `..` can never climb above `/`. On Unix, the
+//! syscalls then reach the entry from the preopened directory, one
+//! component at a time and without following any symlink, and act on it
+//! with the `*at` syscalls relative to its parent, so nothing can swap a
+//! component for a symlink between the check and the use. Symlinks keep
+//! the target the module gave them: as they are never followed, they
+//! can't lead it out of its directories.
+//!
+//! The module never sees host file descriptors: it uses the ones of its
+//! [`FdTable`], which only holds the standard streams and the files it
+//! opened through the sandbox.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+#[cfg(unix)]
+use std::{
+    ffi::{CStr, CString},
+    os::unix::ffi::OsStrExt,
+    sync::Arc,
+};
+
+// `open` flags, as defined by Emscripten.
+const O_WRONLY: i32 = 0o1;
+const O_RDWR: i32 = 0o2;
+const O_CREAT: i32 = 0o100;
+const O_TRUNC: i32 = 0o1000;
+
+/// Device files a module may always open, whatever was preopened.
+const DEVICES: &[&str] = &["/dev/null", "/dev/zero", "/dev/random", "/dev/urandom"];
+
+/// The number of file descriptors a module can have open at once.
+const MAX_FDS: i32 = 1024;
+
+/// Error type returned when configuring an [`EmSandbox`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmSandboxError {
+    /// The host directory to preopen doesn't exist.
+    PreopenedDirectoryNotFound(PathBuf),
+    /// The preopened directory is misconfigured.
+    PreopenedDirectoryError(String),
+}
+
+impl fmt::Display for EmSandboxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PreopenedDirectoryNotFound(path) => {
+                write!(f, "preopened directory not found: `{}`", path.display())
+            }
+            Self::PreopenedDirectoryError(message) => {
+                write!(f, "preopened directory error: `{}`", message)
+            }
+        }
+    }
+}
+
+impl Error for EmSandboxError {}
+
+/// The kind of access a syscall needs on a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Access {
+    /// Reading the entry or its metadata.
+    Read,
+    /// Modifying an existing entry.
+    Write,
+    /// Creating, renaming or removing entries.
+    Create,
+}
+
+impl Access {
+    /// The access needed to `open` a file with the given `flags`.
+    pub(crate) fn for_open_flags(flags: i32) -> Self {
+        if flags & O_CREAT != 0 {
+            Access::Create
+        } else if flags & (O_WRONLY | O_RDWR | O_TRUNC) != 0 {
+            Access::Write
+        } else {
+            Access::Read
+        }
+    }
+}
+
+/// Convenient builder API for configuring preopened directories, mirroring
+/// the one of WASI.
+#[derive(Debug, Clone, Default)]
+pub struct PreopenDirBuilder {
+    path: Option<PathBuf>,
+    alias: Option<String>,
+    read: bool,
+    write: bool,
+    create: bool,
+}
+
+/// The built version of `PreopenDirBuilder`
+#[derive(Debug, Clone)]
+pub(crate) struct PreopenedDir {
+    /// The absolute path the module sees the directory at.
+    guest_path: PathBuf,
+    /// The canonical host directory.
+    host_path: PathBuf,
+    /// The host directory, opened once and for all.
+    #[cfg(unix)]
+    dir: Arc<DirFd>,
+    read: bool,
+    write: bool,
+    create: bool,
+}
+
+impl PreopenDirBuilder {
+    /// Point the preopened directory to the path given by `po_dir`
+    pub fn directory<FilePath>(&mut self, po_dir: FilePath) -> &mut Self
+    where
+        FilePath: AsRef<Path>,
+    {
+        self.path = Some(po_dir.as_ref().to_path_buf());
+
+        self
+    }
+
+    /// Make this preopened directory appear to the Emscripten program as
+    /// `alias`.
+    ///
+    /// Aliases are relative to `/`, so `"data"` and `"/data"` are the same
+    /// and `"."` maps the directory at the root.
+    pub fn alias(&mut self, alias: &str) -> &mut Self {
+        self.alias = Some(alias.to_string());
+
+        self
+    }
+
+    /// Set read permissions affecting files in the directory
+    pub fn read(&mut self, toggle: bool) -> &mut Self {
+        self.read = toggle;
+
+        self
+    }
+
+    /// Set write permissions affecting files in the directory
+    pub fn write(&mut self, toggle: bool) -> &mut Self {
+        self.write = toggle;
+
+        self
+    }
+
+    /// Set create permissions affecting files in the directory
+    ///
+    /// Create implies `write` permissions
+    pub fn create(&mut self, toggle: bool) -> &mut Self {
+        self.create = toggle;
+        if toggle {
+            self.write = true;
+        }
+
+        self
+    }
+
+    pub(crate) fn build(&self) -> Result<PreopenedDir, EmSandboxError> {
+        // ensure at least one is set
+        if !(self.read || self.write || self.create) {
+            return Err(EmSandboxError::PreopenedDirectoryError("Preopened directories must have at least one of read, write, create permissions set".to_string()));
+        }
+
+        let path = self.path.clone().ok_or_else(|| {
+            EmSandboxError::PreopenedDirectoryError(
+                "Preopened directories must point to a host directory".to_string(),
+            )
+        })?;
+        if !path.is_dir() {
+            return Err(EmSandboxError::PreopenedDirectoryNotFound(path));
+        }
+        let host_path = path
+            .canonicalize()
+            .map_err(|_| EmSandboxError::PreopenedDirectoryNotFound(path.clone()))?;
+        #[cfg(unix)]
+        let dir = DirFd::open(&host_path)
+            .map_err(|_| EmSandboxError::PreopenedDirectoryNotFound(path.clone()))?;
+
+        let alias = match &self.alias {
+            Some(alias) => alias.clone(),
+            None => path.to_string_lossy().into_owned(),
+        };
+        if alias.bytes().any(|b| b == b'\0') {
+            return Err(EmSandboxError::PreopenedDirectoryError(format!(
+                "Alias \"{}\" contains a nul byte",
+                alias
+            )));
+        }
+
+        Ok(PreopenedDir {
+            guest_path: normalize(Path::new("/"), Path::new(&alias)),
+            host_path,
+            #[cfg(unix)]
+            dir: Arc::new(dir),
+            read: self.read,
+            write: self.write,
+            create: self.create,
+        })
+    }
+}
+
+/// The filesystem and network capabilities of an Emscripten module.
+///
+/// Nothing is granted by default.
+///
+/// Usage:
+///
+/// ```no_run
+/// # use wasmer_emscripten::{EmSandbox, EmSandboxError};
+/// # fn main() -> Result<(), EmSandboxError> {
+/// let mut sandbox = EmSandbox::new();
+/// sandbox
+///     .preopen_dir("src")?
+///     .preopen(|p| p.directory("/var/data").alias("data").read(true))?
+///     .allow_networking(true);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct EmSandbox {
+    preopens: Vec<PreopenedDir>,
+    networking: bool,
+    /// The virtual working directory of the module.
+    cwd: PathBuf,
+}
+
+impl Default for EmSandbox {
+    fn default() -> Self {
+        Self {
+            preopens: vec![],
+            networking: false,
+            cwd: PathBuf::from("/"),
+        }
+    }
+}
+
+impl EmSandbox {
+    /// Creates a sandbox granting nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Preopen a directory.
+    ///
+    /// This makes the given directory visible to the module at the same
+    /// path, relative to `/`, and allows the module to read and write to it.
+    pub fn preopen_dir<FilePath>(&mut self, po_dir: FilePath) -> Result<&mut Self, EmSandboxError>
+    where
+        FilePath: AsRef<Path>,
+    {
+        self.preopen(|p| p.directory(&po_dir).read(true).write(true).create(true))
+    }
+
+    /// Preopen directories.
+    pub fn preopen_dirs<I, FilePath>(&mut self, po_dirs: I) -> Result<&mut Self, EmSandboxError>
+    where
+        I: IntoIterator<Item = FilePath>,
+        FilePath: AsRef<Path>,
+    {
+        for po_dir in po_dirs {
+            self.preopen_dir(po_dir)?;
+        }
+
+        Ok(self)
+    }
+
+    /// Preopen a directory and configure it.
+    pub fn preopen<F>(&mut self, inner: F) -> Result<&mut Self, EmSandboxError>
+    where
+        F: Fn(&mut PreopenDirBuilder) -> &mut PreopenDirBuilder,
+    {
+        let mut pdb = PreopenDirBuilder::default();
+        let po_dir = inner(&mut pdb).build()?;
+
+        self.preopens.push(po_dir);
+
+        Ok(self)
+    }
+
+    /// Preopen the host directory `po_dir` at `alias`, with read and write
+    /// permissions.
+    pub fn map_dir<FilePath>(
+        &mut self,
+        alias: &str,
+        po_dir: FilePath,
+    ) -> Result<&mut Self, EmSandboxError>
+    where
+        FilePath: AsRef<Path>,
+    {
+        self.preopen(|p| {
+            p.directory(&po_dir)
+                .alias(alias)
+                .read(true)
+                .write(true)
+                .create(true)
+        })
+    }
+
+    /// Preopen a series of host directories at the given aliases.
+    pub fn map_dirs<I, FilePath>(&mut self, mapped_dirs: I) -> Result<&mut Self, EmSandboxError>
+    where
+        I: IntoIterator<Item = (String, FilePath)>,
+        FilePath: AsRef<Path>,
+    {
+        for (alias, po_dir) in mapped_dirs {
+            self.map_dir(&alias, po_dir)?;
+        }
+
+        Ok(self)
+    }
+
+    /// Allow or deny the module to use sockets and to resolve host names.
+    pub fn allow_networking(&mut self, toggle: bool) -> &mut Self {
+        self.networking = toggle;
+
+        self
+    }
+
+    pub(crate) fn networking_allowed(&self) -> bool {
+        self.networking
+    }
+
+    /// The virtual working directory of the module.
+    pub(crate) fn cwd(&self) -> &Path {
+        &self.cwd
+    }
+
+    /// Changes the virtual working directory to `path`.
+    ///
+    /// Errors are `errno` values.
+    pub(crate) fn chdir(&mut self, path: &Path) -> Result<(), i32> {
+        let guest_path = normalize(&self.cwd, path);
+        let host_path = self.resolve(path, Access::Read)?;
+        if !host_path.exists() {
+            return Err(libc::ENOENT);
+        }
+        if !host_path.is_dir() {
+            return Err(libc::ENOTDIR);
+        }
+        self.cwd = guest_path;
+        Ok(())
+    }
+
+    /// Resolves the guest `path` to the host path it designates, making
+    /// sure the module was granted `access` to it.
+    ///
+    /// Errors are `errno` values.
+    pub(crate) fn resolve(&self, path: &Path, access: Access) -> Result<PathBuf, i32> {
+        let (preopen, rest) = match self.locate(path, access)? {
+            Location::Device(device) => return Ok(PathBuf::from(device)),
+            Location::Preopen(preopen, rest) => (preopen, rest),
+        };
+        let host_path = preopen.host_path.join(rest);
+        if !stays_within(&host_path, &preopen.host_path, access) {
+            return Err(libc::EACCES);
+        }
+        Ok(host_path)
+    }
+
+    /// Opens the guest `path` with the `open` `flags` and `mode`, and
+    /// returns the host file descriptor.
+    ///
+    /// The path is opened one component at a time from the preopened
+    /// directory, and none of them may be a symlink.
+    ///
+    /// Errors are `errno` values.
+    #[cfg(unix)]
+    pub(crate) fn open(&self, path: &Path, flags: i32, mode: u32) -> Result<i32, i32> {
+        let at = self.open_parent(path, Access::for_open_flags(flags))?;
+        check(unsafe {
+            libc::openat(
+                at.dir(),
+                at.name().as_ptr(),
+                flags | libc::O_NOFOLLOW | libc::O_CLOEXEC,
+                mode as libc::c_uint,
+            )
+        })
+    }
+
+    /// Opens the directory holding the guest `path`, making sure the
+    /// module was granted `access` to it, for the `*at` syscalls.
+    ///
+    /// The directory is opened one component at a time from the preopened
+    /// directory, and none of them may be a symlink.
+    ///
+    /// Errors are `errno` values.
+    #[cfg(unix)]
+    pub(crate) fn open_parent(&self, path: &Path, access: Access) -> Result<AtPath, i32> {
+        let (preopen, rest) = match self.locate(path, access)? {
+            Location::Device(device) => {
+                let device = Path::new(device);
+                let name = device.file_name().unwrap_or_default().as_bytes();
+                return Ok(AtPath {
+                    dir: DirFd::open(device.parent().unwrap_or_else(|| Path::new("/")))?,
+                    name: CString::new(name).map_err(|_| libc::EINVAL)?,
+                });
+            }
+            Location::Preopen(preopen, rest) => (preopen, rest),
+        };
+
+        let names = rest
+            .iter()
+            .map(|name| CString::new(name.as_bytes()).map_err(|_| libc::EINVAL))
+            .collect::<Result<Vec<_>, _>>()?;
+        let (name, parents) = match names.split_last() {
+            Some((name, parents)) => (name.clone(), parents),
+            None => (CString::new(".").unwrap(), &[][..]),
+        };
+        let mut dir = preopen.dir.try_clone()?;
+        for dir_name in parents {
+            let fd = check(unsafe {
+                libc::openat(
+                    dir.0,
+                    dir_name.as_ptr(),
+                    libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW | libc::O_CLOEXEC,
+                )
+            })?;
+            dir = DirFd(fd);
+        }
+        Ok(AtPath { dir, name })
+    }
+
+    /// Finds what the guest `path` designates, making sure the module was
+    /// granted `access` to it.
+    ///
+    /// Errors are `errno` values.
+    fn locate(&self, path: &Path, access: Access) -> Result<Location<'_>, i32> {
+        if path.as_os_str().is_empty() {
+            return Err(libc::ENOENT);
+        }
+        let guest_path = normalize(&self.cwd, path);
+        if let Some(device) = DEVICES
+            .iter()
+            .find(|device| guest_path == Path::new(device))
+        {
+            return Ok(Location::Device(*device));
+        }
+
+        let preopen = self
+            .preopens
+            .iter()
+            .filter(|preopen| guest_path.starts_with(&preopen.guest_path))
+            .max_by_key(|preopen| preopen.guest_path.components().count())
+            .ok_or(libc::EACCES)?;
+        let granted = match access {
+            Access::Read => preopen.read,
+            Access::Write => preopen.write,
+            Access::Create => preopen.create,
+        };
+        if !granted {
+            return Err(libc::EACCES);
+        }
+
+        let rest = guest_path
+            .strip_prefix(&preopen.guest_path)
+            .map_err(|_| libc::EACCES)?;
+        Ok(Location::Preopen(preopen, rest.to_path_buf()))
+    }
+}
+
+/// What a guest path designates.
+enum Location<'a> {
+    /// One of the [`DEVICES`].
+    Device(&'static str),
+    /// The path relative to a preopened directory.
+    Preopen(&'a PreopenedDir, PathBuf),
+}
+
+/// A guest path, as the host directory holding it and its name in that
+/// directory, for the `*at` syscalls.
+#[cfg(unix)]
+#[derive(Debug)]
+pub(crate) struct AtPath {
+    dir: DirFd,
+    name: CString,
+}
+
+#[cfg(unix)]
+impl AtPath {
+    /// The host file descriptor of the directory.
+    pub(crate) fn dir(&self) -> i32 {
+        self.dir.0
+    }
+
+    /// The name of the entry in the directory.
+    pub(crate) fn name(&self) -> &CStr {
+        &self.name
+    }
+}
+
+/// A host directory file descriptor, closed on drop.
+#[cfg(unix)]
+#[derive(Debug)]
+struct DirFd(i32);
+
+#[cfg(unix)]
+impl DirFd {
+    fn open(path: &Path) -> Result<Self, i32> {
+        let path = CString::new(path.as_os_str().as_bytes()).map_err(|_| libc::EINVAL)?;
+        let fd = check(unsafe {
+            libc::open(
+                path.as_ptr(),
+                libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC,
+            )
+        })?;
+        Ok(Self(fd))
+    }
+
+    fn try_clone(&self) -> Result<Self, i32> {
+        let fd = check(unsafe { libc::fcntl(self.0, libc::F_DUPFD_CLOEXEC, 0) })?;
+        Ok(Self(fd))
+    }
+}
+
+#[cfg(unix)]
+impl Drop for DirFd {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.0);
+        }
+    }
+}
+
+/// Turns the result of a libc call returning a file descriptor into the
+/// file descriptor or the `errno` value.
+#[cfg(unix)]
+fn check(fd: i32) -> Result<i32, i32> {
+    if fd < 0 {
+        Err(std::io::Error::last_os_error()
+            .raw_os_error()
+            .unwrap_or(libc::EIO))
+    } else {
+        Ok(fd)
+    }
+}
+
+/// The files opened by a module, by the file descriptors it knows them by.
+///
+/// Syscalls look up the file descriptors the module passes them here, so
+/// it can't use a host file it didn't open itself, whatever number it
+/// makes up. The table is shared by all the threads of the module.
+#[derive(Debug)]
+pub(crate) struct FdTable {
+    /// The host file descriptors, by guest file descriptor.
+    fds: BTreeMap<i32, i32>,
+}
+
+impl Default for FdTable {
+    /// The table of a new module, which only holds the standard streams.
+    fn default() -> Self {
+        Self {
+            fds: (0..3).map(|fd| (fd, fd)).collect(),
+        }
+    }
+}
+
+impl FdTable {
+    /// Returns the host file descriptor of the guest one `fd`.
+    ///
+    /// Errors are `errno` values.
+    pub(crate) fn get(&self, fd: i32) -> Result<i32, i32> {
+        self.fds.get(&fd).copied().ok_or(libc::EBADF)
+    }
+
+    /// Registers `host_fd` under the lowest free guest file descriptor
+    /// not below `min`, and returns it.
+    ///
+    /// The table owns `host_fd` from then on: it's closed if there's no
+    /// free file descriptor. Errors are `errno` values.
+    pub(crate) fn insert(&mut self, host_fd: i32, min: i32) -> Result<i32, i32> {
+        match (min.max(0)..MAX_FDS).find(|fd| !self.fds.contains_key(fd)) {
+            Some(fd) => {
+                self.fds.insert(fd, host_fd);
+                Ok(fd)
+            }
+            None => {
+                unsafe {
+                    libc::close(host_fd);
+                }
+                Err(libc::EMFILE)
+            }
+        }
+    }
+
+    /// Registers `host_fd` under the guest file descriptor `fd`, and
+    /// returns the host file descriptor it replaces, which the caller has
+    /// to close.
+    ///
+    /// Errors are `errno` values.
+    pub(crate) fn insert_at(&mut self, fd: i32, host_fd: i32) -> Result<Option<i32>, i32> {
+        if !(0..MAX_FDS).contains(&fd) {
+            return Err(libc::EBADF);
+        }
+        Ok(self.fds.insert(fd, host_fd))
+    }
+
+    /// Unregisters the guest file descriptor `fd`, and returns its host
+    /// file descriptor, which the caller has to close.
+    ///
+    /// Errors are `errno` values.
+    pub(crate) fn remove(&mut self, fd: i32) -> Result<i32, i32> {
+        self.fds.remove(&fd).ok_or(libc::EBADF)
+    }
+}
+
+/// Makes `path` absolute against `cwd` and resolves its `.` and `..`
+/// components without touching the filesystem.
+fn normalize(cwd: &Path, path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::from("/");
+    for component in cwd.join(path).components() {
+        match component {
+            Component::Prefix(_) | Component::RootDir => normalized = PathBuf::from("/"),
+            Component::CurDir => (),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::Normal(name) => normalized.push(name),
+        }
+    }
+    normalized
+}
+
+/// Checks that `host_path` is still inside `root` once the symlinks in it
+/// are followed.
+fn stays_within(host_path: &Path, root: &Path, access: Access) -> bool {
+    if let Ok(canonical) = host_path.canonicalize() {
+        return canonical.starts_with(root);
+    }
+    // a dangling symlink could be used to create a file anywhere on the host
+    if access != Access::Read && host_path.symlink_metadata().is_ok() {
+        return false;
+    }
+    // the entry doesn't exist (yet): check its closest existing ancestor
+    host_path
+        .ancestors()
+        .skip(1)
+        .find_map(|ancestor| ancestor.canonicalize().ok())
+        .map(|canonical| canonical.starts_with(root))
+        .unwrap_or(false)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::symlink;
+
+    fn sandbox() -> (tempfile::TempDir, EmSandbox) {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir(root.path().join("sandbox")).unwrap();
+        fs::create_dir(root.path().join("secret")).unwrap();
+        fs::write(root.path().join("sandbox/file.txt"), "file").unwrap();
+        fs::write(root.path().join("secret/secret.txt"), "secret").unwrap();
+        symlink("../secret", root.path().join("sandbox/link")).unwrap();
+        symlink(
+            "../secret/secret.txt",
+            root.path().join("sandbox/file_link"),
+        )
+        .unwrap();
+
+        let mut sandbox = EmSandbox::new();
+        sandbox
+            .preopen(|p| {
+                p.directory(root.path().join("sandbox"))
+                    .alias(".")
+                    .read(true)
+                    .create(true)
+            })
+            .unwrap();
+        (root, sandbox)
+    }
+
+    fn open(sandbox: &EmSandbox, path: &str, flags: i32) -> Result<(), i32> {
+        let fd = sandbox.open(Path::new(path), flags, 0o644)?;
+        unsafe {
+            libc::close(fd);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn open_preopened_files() {
+        let (_root, sandbox) = sandbox();
+        assert_eq!(open(&sandbox, "/file.txt", libc::O_RDONLY), Ok(()));
+        assert_eq!(open(&sandbox, "file.txt", libc::O_RDWR), Ok(()));
+        assert_eq!(open(&sandbox, "/", libc::O_RDONLY), Ok(()));
+        assert_eq!(
+            open(&sandbox, "new.txt", libc::O_WRONLY | libc::O_CREAT),
+            Ok(())
+        );
+        assert_eq!(open(&sandbox, "/dev/null", libc::O_RDONLY), Ok(()));
+    }
+
+    #[test]
+    fn open_cannot_escape_with_dot_dot() {
+        let (root, sandbox) = sandbox();
+        assert_eq!(
+            open(&sandbox, "../secret/secret.txt", libc::O_RDONLY),
+            Err(libc::ENOENT)
+        );
+        assert_eq!(
+            open(&sandbox, "/../../secret/secret.txt", libc::O_RDONLY),
+            Err(libc::ENOENT)
+        );
+        assert!(open(
+            &sandbox,
+            "../secret/new.txt",
+            libc::O_WRONLY | libc::O_CREAT
+        )
+        .is_err());
+        assert!(!root.path().join("secret/new.txt").exists());
+    }
+
+    #[test]
+    fn open_cannot_escape_with_symlinks() {
+        let (root, sandbox) = sandbox();
+        assert_eq!(
+            open(&sandbox, "file_link", libc::O_RDONLY),
+            Err(libc::ELOOP)
+        );
+        assert!(open(&sandbox, "link/secret.txt", libc::O_RDONLY).is_err());
+        assert!(open(&sandbox, "link/new.txt", libc::O_WRONLY | libc::O_CREAT).is_err());
+        assert!(!root.path().join("secret/new.txt").exists());
+    }
+
+    #[test]
+    fn open_cannot_escape_with_absolute_paths() {
+        let (root, sandbox) = sandbox();
+        let secret = root.path().join("secret/secret.txt");
+        assert!(open(&sandbox, secret.to_str().unwrap(), libc::O_RDONLY).is_err());
+        assert_eq!(
+            open(&sandbox, "/etc/passwd", libc::O_RDONLY),
+            Err(libc::ENOENT)
+        );
+    }
+
+    #[test]
+    fn open_checks_permissions() {
+        let (root, _) = sandbox();
+        let mut sandbox = EmSandbox::new();
+        sandbox
+            .preopen(|p| {
+                p.directory(root.path().join("sandbox"))
+                    .alias("data")
+                    .read(true)
+            })
+            .unwrap();
+        assert_eq!(open(&sandbox, "/data/file.txt", libc::O_RDONLY), Ok(()));
+        assert_eq!(
+            open(&sandbox, "/data/file.txt", libc::O_RDWR),
+            Err(libc::EACCES)
+        );
+        assert_eq!(
+            open(&sandbox, "/data/new.txt", libc::O_WRONLY | libc::O_CREAT),
+            Err(libc::EACCES)
+        );
+        assert_eq!(
+            open(&sandbox, "/file.txt", libc::O_RDONLY),
+            Err(libc::EACCES)
+        );
+    }
+
+    #[test]
+    fn open_parent_cannot_escape_with_symlinks() {
+        let (root, sandbox) = sandbox();
+        let at = sandbox
+            .open_parent(Path::new("/file.txt"), Access::Read)
+            .unwrap();
+        assert_eq!(at.name().to_bytes(), b"file.txt");
+        assert!(sandbox
+            .open_parent(Path::new("link/new"), Access::Create)
+            .is_err());
+        assert!(!root.path().join("secret/new").exists());
+    }
+
+    #[test]
+    fn symlinks_keep_the_guest_target() {
+        let (root, sandbox) = sandbox();
+        let target = CString::new("/file.txt").unwrap();
+        let at = sandbox
+            .open_parent(Path::new("guest_link"), Access::Create)
+            .unwrap();
+        let result = unsafe { libc::symlinkat(target.as_ptr(), at.dir(), at.name().as_ptr()) };
+        assert_eq!(result, 0);
+        assert_eq!(
+            fs::read_link(root.path().join("sandbox/guest_link")).unwrap(),
+            Path::new("/file.txt")
+        );
+
+        let mut buf = [0u8; 64];
+        let len = unsafe {
+            libc::readlinkat(
+                at.dir(),
+                at.name().as_ptr(),
+                buf.as_mut_ptr() as *mut _,
+                buf.len(),
+            )
+        };
+        assert_eq!(&buf[..len as usize], b"/file.txt");
+        // the link isn't followed, so it can't reach the host's `/file.txt`
+        assert_eq!(
+            open(&sandbox, "guest_link", libc::O_RDONLY),
+            Err(libc::ELOOP)
+        );
+    }
+
+    #[test]
+    fn fd_table_rejects_forged_fds() {
+        let mut fds = FdTable::default();
+        assert_eq!(fds.get(1), Ok(1));
+        // a host file the module didn't open through the sandbox
+        let host_file = tempfile::tempfile().unwrap();
+        let host_fd = std::os::unix::io::AsRawFd::as_raw_fd(&host_file);
+        assert_eq!(fds.get(host_fd), Err(libc::EBADF));
+        assert_eq!(fds.get(-1), Err(libc::EBADF));
+
+        let dup = unsafe { libc::dup(host_fd) };
+        let fd = fds.insert(dup, 0).unwrap();
+        assert_eq!(fd, 3);
+        assert_eq!(fds.get(fd), Ok(dup));
+        assert_eq!(fds.remove(fd), Ok(dup));
+        assert_eq!(fds.get(fd), Err(libc::EBADF));
+        assert_eq!(fds.remove(fd), Err(libc::EBADF));
+        unsafe {
+            libc::close(dup);
+        }
+
+        assert_eq!(fds.insert_at(MAX_FDS, 1), Err(libc::EBADF));
+        assert_eq!(fds.insert_at(10, 1), Ok(None));
+        assert_eq!(fds.insert_at(10, 2), Ok(Some(1)));
+    }
+}
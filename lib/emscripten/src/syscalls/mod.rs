@@ -11,8 +11,12 @@ pub use self::unix::*;
 pub use self::windows::*;
 
 use crate::{
+    env::get_emscripten_data,
     ptr::{Array, WasmPtr},
-    utils::{copy_stat_into_wasm, get_cstr_path, get_current_directory},
+    utils::{
+        close_fd, close_host_fd, copy_stat_into_wasm, get_current_directory, get_host_fd,
+        register_host_fd,
+    },
     EmEnv,
 };
 
@@ -23,10 +27,8 @@ use byteorder::{ByteOrder, LittleEndian};
 use libc::{
     c_int,
     c_void,
-    chdir,
     // setsockopt, getppid
-    close,
-    dup2,
+    dup,
     exit,
     fstat,
     getpid,
@@ -35,18 +37,14 @@ use libc::{
     lseek,
     //    open,
     read,
-    rename,
     // sockaddr_in,
     // readv,
-    rmdir,
     // writev,
-    stat,
     write,
     // ENOTTY,
 };
 
 use super::env;
-use std::cell::Cell;
 #[allow(unused_imports)]
 use std::io::Error;
 use std::slice;
@@ -68,8 +66,12 @@ pub fn ___syscall3(ctx: &EmEnv, _which: i32, mut varargs: VarArgs) -> i32 {
     let buf: u32 = varargs.get(ctx);
     let count: i32 = varargs.get(ctx);
     debug!("=> fd: {}, buf_offset: {}, count: {}", fd, buf, count);
+    let host_fd = match get_host_fd(ctx, fd) {
+        Ok(host_fd) => host_fd,
+        Err(errno) => return errno,
+    };
     let buf_addr = emscripten_memory_pointer!(ctx.memory(0), buf) as *mut c_void;
-    let ret = unsafe { read(fd, buf_addr, count as _) };
+    let ret = unsafe { read(host_fd, buf_addr, count as _) };
     debug!("=> ret: {}", ret);
     ret as _
 }
@@ -81,8 +83,12 @@ pub fn ___syscall4(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
     let buf: i32 = varargs.get(ctx);
     let count: i32 = varargs.get(ctx);
     debug!("=> fd: {}, buf: {}, count: {}", fd, buf, count);
+    let host_fd = match get_host_fd(ctx, fd) {
+        Ok(host_fd) => host_fd,
+        Err(errno) => return errno,
+    };
     let buf_addr = emscripten_memory_pointer!(ctx.memory(0), buf) as *const c_void;
-    unsafe { write(fd, buf_addr, count as _) as i32 }
+    unsafe { write(host_fd, buf_addr, count as _) as i32 }
 }
 
 /// close
@@ -90,25 +96,27 @@ pub fn ___syscall6(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall6 (close) {}", _which);
     let fd: i32 = varargs.get(ctx);
     debug!("fd: {}", fd);
-    unsafe { close(fd) }
+    close_fd(ctx, fd)
 }
 
 // chdir
 pub fn ___syscall12(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall12 (chdir) {}", _which);
     let path_ptr = varargs.get_str(ctx);
-    let real_path_owned = get_cstr_path(ctx, path_ptr as *const _);
-    let real_path = if let Some(ref rp) = real_path_owned {
-        rp.as_c_str().as_ptr()
-    } else {
-        path_ptr
+    let path = match unsafe { std::ffi::CStr::from_ptr(path_ptr) }.to_str() {
+        Ok(path) => path,
+        Err(_) => return -libc::EINVAL,
     };
-    let ret = unsafe { chdir(real_path) };
-    debug!(
-        "=> path: {:?}, ret: {}",
-        unsafe { std::ffi::CStr::from_ptr(real_path) },
-        ret
-    );
+    // only the virtual working directory changes, the host process stays
+    // where it is
+    let ret = match get_emscripten_data(ctx)
+        .sandbox
+        .chdir(std::path::Path::new(path))
+    {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    };
+    debug!("=> path: {:?}, ret: {}", path, ret);
     ret
 }
 
@@ -163,46 +171,6 @@ pub fn ___syscall36(_ctx: &EmEnv, _one: i32, _two: i32) -> i32 {
     -1
 }
 
-// rename
-pub fn ___syscall38(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> i32 {
-    debug!("emscripten::___syscall38 (rename)");
-    let old_path = varargs.get_str(ctx);
-    let new_path = varargs.get_str(ctx);
-    let real_old_path_owned = get_cstr_path(ctx, old_path as *const _);
-    let real_old_path = if let Some(ref rp) = real_old_path_owned {
-        rp.as_c_str().as_ptr()
-    } else {
-        old_path
-    };
-    let real_new_path_owned = get_cstr_path(ctx, new_path as *const _);
-    let real_new_path = if let Some(ref rp) = real_new_path_owned {
-        rp.as_c_str().as_ptr()
-    } else {
-        new_path
-    };
-    let result = unsafe { rename(real_old_path, real_new_path) };
-    debug!(
-        "=> old_path: {}, new_path: {}, result: {}",
-        unsafe { std::ffi::CStr::from_ptr(real_old_path).to_str().unwrap() },
-        unsafe { std::ffi::CStr::from_ptr(real_new_path).to_str().unwrap() },
-        result
-    );
-    result
-}
-
-// rmdir
-pub fn ___syscall40(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
-    debug!("emscripten::___syscall40 (rmdir)");
-    let pathname_addr = varargs.get_str(ctx);
-    let real_path_owned = get_cstr_path(ctx, pathname_addr as *const _);
-    let real_path = if let Some(ref rp) = real_path_owned {
-        rp.as_c_str().as_ptr()
-    } else {
-        pathname_addr
-    };
-    unsafe { rmdir(real_path) }
-}
-
 // pipe
 pub fn ___syscall42(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall42 (pipe)");
@@ -211,24 +179,33 @@ pub fn ___syscall42(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
 
     let emscripten_memory = ctx.memory(0);
 
-    // convert the file descriptor into a vec with two slots
-    let mut fd_vec: Vec<c_int> = emscripten_memory.view()[((fd_offset / 4) as usize)..]
-        .iter()
-        .map(|pipe_end: &Cell<c_int>| pipe_end.get())
-        .take(2)
-        .collect();
+    let mut host_fds: [c_int; 2] = [0; 2];
 
-    // get it as a mutable pointer
-    let fd_ptr = fd_vec.as_mut_ptr();
-
-    // call pipe and store the pointers in this array
+    // call pipe and store the host file descriptors in this array
     #[cfg(target_os = "windows")]
-    let result: c_int = unsafe { libc::pipe(fd_ptr, 2048, 0) };
+    let result: c_int = unsafe { libc::pipe(host_fds.as_mut_ptr(), 2048, 0) };
     #[cfg(not(target_os = "windows"))]
-    let result: c_int = unsafe { libc::pipe(fd_ptr) };
+    let result: c_int = unsafe { libc::pipe(host_fds.as_mut_ptr()) };
     if result == -1 {
         debug!("=> os error: {}", Error::last_os_error());
+        return result;
+    }
+
+    // the module gets the ends under its own file descriptors
+    let read_end = register_host_fd(ctx, host_fds[0]);
+    if read_end < 0 {
+        unsafe { libc::close(host_fds[1]) };
+        return read_end;
     }
+    let write_end = register_host_fd(ctx, host_fds[1]);
+    if write_end < 0 {
+        close_fd(ctx, read_end);
+        return write_end;
+    }
+    let fd_index = (fd_offset / 4) as usize;
+    let view = emscripten_memory.view::<c_int>();
+    view[fd_index].set(read_end);
+    view[fd_index + 1].set(write_end);
     result
 }
 
@@ -259,7 +236,37 @@ pub fn ___syscall63(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
     let src: i32 = varargs.get(ctx);
     let dst: i32 = varargs.get(ctx);
 
-    unsafe { dup2(src, dst) }
+    dup_fd(ctx, src, dst)
+}
+
+/// Makes the guest file descriptor `dst` a duplicate of `src`, closing the
+/// file `dst` was before.
+pub(crate) fn dup_fd(ctx: &EmEnv, src: c_int, dst: c_int) -> c_int {
+    let host_src = match get_host_fd(ctx, src) {
+        Ok(host_fd) => host_fd,
+        Err(errno) => return errno,
+    };
+    if src == dst {
+        return dst;
+    }
+    let host_dst = unsafe { dup(host_src) };
+    if host_dst == -1 {
+        debug!("=> os error: {}", Error::last_os_error());
+        return -1;
+    }
+    let fds = get_emscripten_data(ctx).fds.clone();
+    let replaced = fds.lock().unwrap().insert_at(dst, host_dst);
+    match replaced {
+        Ok(Some(replaced)) => {
+            close_host_fd(ctx, dst, replaced);
+        }
+        Ok(None) => (),
+        Err(errno) => {
+            unsafe { libc::close(host_dst) };
+            return -errno;
+        }
+    }
+    dst
 }
 
 // getppid
@@ -354,7 +361,7 @@ pub fn ___syscall183(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> i32 {
     let buf_offset: WasmPtr<libc::c_char, Array> = varargs.get(ctx);
     let _size: c_int = varargs.get(ctx);
     let path = get_current_directory(ctx);
-    let path_string = path.unwrap().display().to_string();
+    let path_string = path.display().to_string();
     let len = path_string.len();
     let memory = ctx.memory(0);
 
@@ -411,7 +418,11 @@ pub fn ___syscall140(ctx: &EmEnv, _which: i32, mut varargs: VarArgs) -> i32 {
     let result_ptr_value: WasmPtr<i64> = varargs.get(ctx);
     let whence: i32 = varargs.get(ctx);
     let offset = offset_low;
-    let ret = unsafe { lseek(fd, offset as _, whence) as i64 };
+    let host_fd = match get_host_fd(ctx, fd) {
+        Ok(host_fd) => host_fd,
+        Err(errno) => return errno,
+    };
+    let ret = unsafe { lseek(host_fd, offset as _, whence) as i64 };
     let memory = ctx.memory(0);
 
     let result_ptr = result_ptr_value.deref(&memory).unwrap();
@@ -446,6 +457,10 @@ pub fn ___syscall145(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> i32 {
     }
 
     debug!("=> fd: {}, iov: {}, iovcnt = {}", fd, iov, iovcnt);
+    let host_fd = match get_host_fd(ctx, fd) {
+        Ok(host_fd) => host_fd,
+        Err(errno) => return errno,
+    };
     let mut ret = 0;
     unsafe {
         for i in 0..iovcnt {
@@ -455,7 +470,7 @@ pub fn ___syscall145(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> i32 {
                 as *mut c_void;
             let iov_len = (*guest_iov_addr).iov_len as _;
             // debug!("=> iov_addr: {:?}, {:?}", iov_base, iov_len);
-            let curr = read(fd, iov_base, iov_len);
+            let curr = read(host_fd, iov_base, iov_len);
             if curr < 0 {
                 return -1;
             }
@@ -482,6 +497,10 @@ pub fn ___syscall146(ctx: &EmEnv, _which: i32, mut varargs: VarArgs) -> i32 {
     }
 
     debug!("=> fd: {}, iov: {}, iovcnt = {}", fd, iov, iovcnt);
+    let host_fd = match get_host_fd(ctx, fd) {
+        Ok(host_fd) => host_fd,
+        Err(errno) => return errno,
+    };
     let mut ret = 0;
     for i in 0..iovcnt {
         unsafe {
@@ -491,7 +510,7 @@ pub fn ___syscall146(ctx: &EmEnv, _which: i32, mut varargs: VarArgs) -> i32 {
                 as *const c_void;
             let iov_len = (*guest_iov_addr).iov_len as _;
             // debug!("=> iov_addr: {:?}, {:?}", iov_base, iov_len);
-            let curr = write(fd, iov_base, iov_len);
+            let curr = write(host_fd, iov_base, iov_len);
             debug!(
                 "=> iov_base: {}, iov_len: {}, curr = {}",
                 (*guest_iov_addr).iov_base,
@@ -531,47 +550,20 @@ pub fn ___syscall193(_ctx: &EmEnv, _one: i32, _two: i32) -> i32 {
     -1
 }
 
-// stat64
-pub fn ___syscall195(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
-    debug!("emscripten::___syscall195 (stat64) {}", _which);
-    let pathname_addr = varargs.get_str(ctx);
-    let buf: u32 = varargs.get(ctx);
-
-    let real_path_owned = get_cstr_path(ctx, pathname_addr as *const _);
-    let real_path = if let Some(ref rp) = real_path_owned {
-        rp.as_c_str().as_ptr()
-    } else {
-        pathname_addr
-    };
-
-    unsafe {
-        let mut _stat: stat = std::mem::zeroed();
-        let ret = stat(real_path, &mut _stat);
-        debug!(
-            "=> pathname: {}, buf: {} = {}",
-            std::ffi::CStr::from_ptr(real_path).to_str().unwrap(),
-            buf,
-            ret
-        );
-        if ret != 0 {
-            debug!("=> os error: {}", Error::last_os_error());
-            return ret;
-        }
-        copy_stat_into_wasm(ctx, buf, &_stat);
-    }
-    0
-}
-
 // fstat64
 pub fn ___syscall197(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall197 (fstat64) {}", _which);
 
     let fd: c_int = varargs.get(ctx);
     let buf: u32 = varargs.get(ctx);
+    let host_fd = match get_host_fd(ctx, fd) {
+        Ok(host_fd) => host_fd,
+        Err(errno) => return errno,
+    };
 
     unsafe {
         let mut stat = std::mem::zeroed();
-        let ret = fstat(fd, &mut stat);
+        let ret = fstat(host_fd, &mut stat);
         debug!("=> fd: {}, buf: {} = {}", fd, buf, ret);
         if ret != 0 {
             debug!("=> os error: {}", Error::last_os_error());
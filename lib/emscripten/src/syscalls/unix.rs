@@ -1,24 +1,28 @@
-use crate::{ptr::WasmPtr, varargs::VarArgs, LibcDirWrapper};
+use crate::{
+    ptr::{Array, WasmPtr},
+    varargs::VarArgs,
+    LibcDirWrapper,
+};
 #[cfg(target_os = "macos")]
 use libc::size_t;
 /// NOTE: TODO: These syscalls only support wasm_32 for now because they assume offsets are u32
 /// Syscall list: https://www.cs.utexas.edu/~bismith/test/syscalls/syscalls32.html
 use libc::{
     accept,
-    access,
     bind,
     c_char,
     c_int,
     c_ulong,
     c_void,
-    chown,
     // fcntl, setsockopt, getppid
     connect,
     dup,
-    dup2,
+    faccessat,
     fchmod,
     fchown,
+    fchownat,
     fcntl,
+    fstatat,
     // ENOTTY,
     fsync,
     getegid,
@@ -35,23 +39,23 @@ use libc::{
     in_addr_t,
     in_port_t,
     ioctl,
-    lchown,
-    link,
+    linkat,
     // iovec,
     listen,
-    mkdir,
+    mkdirat,
     mode_t,
     msghdr,
     nice,
     off_t,
-    open,
     pid_t,
     pread,
     pwrite,
     readdir,
+    readlinkat,
     // readv,
     recvfrom,
     recvmsg,
+    renameat,
     // ENOTTY,
     rusage,
     sa_family_t,
@@ -65,16 +69,21 @@ use libc::{
     socket,
     socklen_t,
     stat,
-    symlink,
+    symlinkat,
     uid_t,
     uname,
+    unlinkat,
     utsname,
+    AT_REMOVEDIR,
+    AT_SYMLINK_NOFOLLOW,
+    EACCES,
+    EBADF,
+    EFAULT,
     EINVAL,
     // sockaddr_in,
     FIOCLEX,
     FIONBIO,
-    F_GETFD,
-    F_SETFD,
+    F_DUPFD,
     SOL_SOCKET,
     TIOCGWINSZ,
     TIOCSPGRP,
@@ -93,6 +102,8 @@ const WASM_TIOCSPGRP: u32 = 0x5410;
 const WASM_TIOCGWINSZ: u32 = 0x5413;
 const WASM_TCGETS: u32 = 0x5401;
 const WASM_TCSETSW: u32 = 0x5403;
+const WASM_F_DUPFD: i32 = 0;
+const WASM_F_DUPFD_CLOEXEC: i32 = 1030;
 
 // Based on @syrusakbary sugerence at
 // https://github.com/wasmerio/wasmer/pull/532#discussion_r300837800
@@ -112,9 +123,11 @@ fn translate_ioctl(wasm_ioctl: u32) -> c_ulong {
 
 #[allow(unused_imports)]
 use std::ffi::CStr;
+use std::path::Path;
 
-use crate::env::EmSockAddr;
-use crate::utils::{self, get_cstr_path};
+use crate::env::{get_emscripten_data, EmSockAddr};
+use crate::sandbox::Access;
+use crate::utils::{self, get_at_path};
 use crate::EmEnv;
 #[allow(unused_imports)]
 use std::io::Error;
@@ -127,7 +140,6 @@ extern "C" {
     pub fn wait4(pid: pid_t, status: *mut c_int, options: c_int, rusage: *mut rusage) -> pid_t;
     pub fn madvise(addr: *mut c_void, len: size_t, advice: c_int) -> c_int;
     pub fn fdatasync(fd: c_int) -> c_int;
-    pub fn lstat64(path: *const libc::c_char, buf: *mut c_void) -> c_int;
 }
 
 // Linking to functions that are not provided by rust libc
@@ -137,7 +149,6 @@ extern "C" {
     pub fn wait4(pid: pid_t, status: *mut c_int, options: c_int, rusage: *mut rusage) -> pid_t;
     pub fn fdatasync(fd: c_int) -> c_int;
     pub fn ftruncate(fd: c_int, length: i64) -> c_int;
-    pub fn lstat(path: *const libc::c_char, buf: *mut stat) -> c_int;
 }
 
 #[cfg(not(any(target_os = "freebsd", target_os = "macos", target_os = "android")))]
@@ -145,7 +156,7 @@ use libc::fallocate;
 #[cfg(target_os = "freebsd")]
 use libc::madvise;
 #[cfg(not(any(target_os = "freebsd", target_os = "macos")))]
-use libc::{fdatasync, ftruncate64, lstat, madvise, wait4};
+use libc::{fdatasync, ftruncate64, madvise, wait4};
 
 // Another conditional constant for name resolution: Macos et iOS use
 // SO_NOSIGPIPE as a setsockopt flag to disable SIGPIPE emission on socket.
@@ -161,21 +172,21 @@ pub fn ___syscall5(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
     let pathname_addr = varargs.get_str(ctx);
     let flags: i32 = varargs.get(ctx);
     let mode: u32 = varargs.get(ctx);
-    let real_path_owned = utils::get_cstr_path(ctx, pathname_addr as *const _);
-    let real_path = if let Some(ref rp) = real_path_owned {
-        rp.as_c_str().as_ptr()
-    } else {
-        pathname_addr
+    let path = match unsafe { CStr::from_ptr(pathname_addr) }.to_str() {
+        Ok(path) => path,
+        Err(_) => return -EINVAL,
+    };
+    let opened = get_emscripten_data(ctx)
+        .sandbox
+        .open(Path::new(path), flags, mode);
+    let fd = match opened {
+        Ok(host_fd) => utils::register_host_fd(ctx, host_fd),
+        Err(errno) => -errno,
     };
-    let _path_str = unsafe { std::ffi::CStr::from_ptr(real_path).to_str().unwrap() };
-    let fd = unsafe { open(real_path, flags, mode) };
     debug!(
         "=> path: {}, flags: {}, mode: {} = fd: {}",
-        _path_str, flags, mode, fd,
+        path, flags, mode, fd,
     );
-    if fd == -1 {
-        debug!("=> last os error: {}", Error::last_os_error(),);
-    }
     fd
 }
 
@@ -185,11 +196,27 @@ pub fn ___syscall9(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
 
     let oldname_ptr = varargs.get_str(ctx);
     let newname_ptr = varargs.get_str(ctx);
-    let result = unsafe { link(oldname_ptr, newname_ptr) };
+    let oldname = match get_at_path(ctx, oldname_ptr as *const _, Access::Write) {
+        Ok(path) => path,
+        Err(errno) => return errno,
+    };
+    let newname = match get_at_path(ctx, newname_ptr as *const _, Access::Create) {
+        Ok(path) => path,
+        Err(errno) => return errno,
+    };
+    let result = unsafe {
+        linkat(
+            oldname.dir(),
+            oldname.name().as_ptr(),
+            newname.dir(),
+            newname.name().as_ptr(),
+            0,
+        )
+    };
     debug!(
-        "=> oldname: {}, newname: {}, result: {}",
-        unsafe { std::ffi::CStr::from_ptr(oldname_ptr).to_str().unwrap() },
-        unsafe { std::ffi::CStr::from_ptr(newname_ptr).to_str().unwrap() },
+        "=> oldname: {:?}, newname: {:?}, result: {}",
+        oldname.name(),
+        newname.name(),
         result,
     );
     result
@@ -213,23 +240,17 @@ pub fn ___syscall83(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
 
     let path1 = varargs.get_str(ctx);
     let path2 = varargs.get_str(ctx);
-    let real_path1_owned = utils::get_cstr_path(ctx, path1 as *const _);
-    let real_path1 = if let Some(ref rp) = real_path1_owned {
-        rp.as_c_str().as_ptr()
-    } else {
-        path1
-    };
-    let real_path2_owned = utils::get_cstr_path(ctx, path2 as *const _);
-    let real_path2 = if let Some(ref rp) = real_path2_owned {
-        rp.as_c_str().as_ptr()
-    } else {
-        path2
+    // the target is stored as the module gave it, the sandbox never follows it
+    let target = unsafe { CStr::from_ptr(path1) };
+    let link = match get_at_path(ctx, path2 as *const _, Access::Create) {
+        Ok(path) => path,
+        Err(errno) => return errno,
     };
-    let result = unsafe { symlink(real_path1, real_path2) };
+    let result = unsafe { symlinkat(target.as_ptr(), link.dir(), link.name().as_ptr()) };
     debug!(
-        "=> path1: {}, path2: {}, result: {}",
-        unsafe { std::ffi::CStr::from_ptr(real_path1).to_str().unwrap() },
-        unsafe { std::ffi::CStr::from_ptr(real_path2).to_str().unwrap() },
+        "=> path1: {:?}, path2: {:?}, result: {}",
+        target,
+        link.name(),
         result,
     );
     result
@@ -242,21 +263,20 @@ pub fn ___syscall85(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> i32 {
     let buf = varargs.get_str(ctx);
     // let buf_addr: i32 = varargs.get(ctx);
     let buf_size: i32 = varargs.get(ctx);
-    let real_path_owned = get_cstr_path(ctx, pathname_addr as *const _);
-    let real_path = if let Some(ref rp) = real_path_owned {
-        rp.as_c_str().as_ptr()
-    } else {
-        pathname_addr
+    let path = match get_at_path(ctx, pathname_addr as *const _, Access::Read) {
+        Ok(path) => path,
+        Err(errno) => return errno,
     };
 
-    let ret = unsafe { libc::readlink(real_path, buf as _, buf_size as _) as i32 };
+    let ret =
+        unsafe { readlinkat(path.dir(), path.name().as_ptr(), buf as _, buf_size as _) as i32 };
     if ret == -1 {
         debug!("readlink failed");
         return ret;
     }
     debug!(
-        "=> path: {}, buf: {}, buf_size: {}, return: {} ",
-        unsafe { std::ffi::CStr::from_ptr(real_path).to_str().unwrap() },
+        "=> path: {:?}, buf: {}, buf_size: {}, return: {} ",
+        path.name(),
         unsafe { std::ffi::CStr::from_ptr(buf as _).to_str().unwrap() },
         buf_size,
         ret
@@ -267,15 +287,19 @@ pub fn ___syscall85(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> i32 {
 /// ftruncate64
 pub fn ___syscall194(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall194 (ftruncate64) {}", _which);
-    let _fd: c_int = varargs.get(ctx);
+    let fd: c_int = varargs.get(ctx);
     let _length: i64 = varargs.get(ctx);
+    let _host_fd = match utils::get_host_fd(ctx, fd) {
+        Ok(host_fd) => host_fd,
+        Err(errno) => return errno,
+    };
     #[cfg(not(any(target_os = "freebsd", target_os = "macos")))]
     unsafe {
-        ftruncate64(_fd, _length)
+        ftruncate64(_host_fd, _length)
     }
     #[cfg(target_os = "freebsd")]
     unsafe {
-        ftruncate(_fd, _length)
+        ftruncate(_host_fd, _length)
     }
     #[cfg(target_os = "macos")]
     unimplemented!("emscripten::___syscall194 (ftruncate64) {}", _which)
@@ -285,18 +309,24 @@ pub fn ___syscall194(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int
 pub fn ___syscall198(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall198 (lchown) {}", _which);
     let path_ptr = varargs.get_str(ctx);
-    let real_path_owned = utils::get_cstr_path(ctx, path_ptr as *const _);
-    let real_path = if let Some(ref rp) = real_path_owned {
-        rp.as_c_str().as_ptr()
-    } else {
-        path_ptr
+    let path = match get_at_path(ctx, path_ptr as *const _, Access::Write) {
+        Ok(path) => path,
+        Err(errno) => return errno,
     };
     let uid: uid_t = varargs.get(ctx);
     let gid: gid_t = varargs.get(ctx);
-    let result = unsafe { lchown(real_path, uid, gid) };
+    let result = unsafe {
+        fchownat(
+            path.dir(),
+            path.name().as_ptr(),
+            uid,
+            gid,
+            AT_SYMLINK_NOFOLLOW,
+        )
+    };
     debug!(
-        "=> path: {}, uid: {}, gid: {}, result: {}",
-        unsafe { std::ffi::CStr::from_ptr(real_path).to_str().unwrap() },
+        "=> path: {:?}, uid: {}, gid: {}, result: {}",
+        path.name(),
         uid,
         gid,
         result,
@@ -326,16 +356,22 @@ pub fn ___syscall212(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int
     debug!("emscripten::___syscall212 (chown) {}", _which);
 
     let pathname_addr = varargs.get_str(ctx);
-    let real_path_owned = utils::get_cstr_path(ctx, pathname_addr as *const _);
-    let real_path = if let Some(ref rp) = real_path_owned {
-        rp.as_c_str().as_ptr()
-    } else {
-        pathname_addr
+    let path = match get_at_path(ctx, pathname_addr as *const _, Access::Write) {
+        Ok(path) => path,
+        Err(errno) => return errno,
     };
     let owner: u32 = varargs.get(ctx);
     let group: u32 = varargs.get(ctx);
 
-    unsafe { chown(real_path, owner, group) }
+    unsafe {
+        fchownat(
+            path.dir(),
+            path.name().as_ptr(),
+            owner,
+            group,
+            AT_SYMLINK_NOFOLLOW,
+        )
+    }
 }
 
 /// madvise
@@ -354,18 +390,16 @@ pub fn ___syscall219(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int
 /// access
 pub fn ___syscall33(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall33 (access) {}", _which);
-    let path = varargs.get_str(ctx);
-    let real_path_owned = utils::get_cstr_path(ctx, path as *const _);
-    let real_path = if let Some(ref rp) = real_path_owned {
-        rp.as_c_str().as_ptr()
-    } else {
-        path
+    let path_ptr = varargs.get_str(ctx);
+    let path = match get_at_path(ctx, path_ptr as *const _, Access::Read) {
+        Ok(path) => path,
+        Err(errno) => return errno,
     };
     let amode: c_int = varargs.get(ctx);
-    let result = unsafe { access(real_path, amode) };
+    let result = unsafe { faccessat(path.dir(), path.name().as_ptr(), amode, AT_SYMLINK_NOFOLLOW) };
     debug!(
-        "=> path: {}, amode: {}, result: {}",
-        unsafe { std::ffi::CStr::from_ptr(real_path).to_str().unwrap() },
+        "=> path: {:?}, amode: {}, result: {}",
+        path.name(),
         amode,
         result
     );
@@ -383,21 +417,64 @@ pub fn ___syscall34(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
 pub fn ___syscall39(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall39 (mkdir) {}", _which);
     let pathname_addr = varargs.get_str(ctx);
-    let real_path_owned = utils::get_cstr_path(ctx, pathname_addr as *const _);
-    let real_path = if let Some(ref rp) = real_path_owned {
-        rp.as_c_str().as_ptr()
-    } else {
-        pathname_addr
+    let path = match get_at_path(ctx, pathname_addr as *const _, Access::Create) {
+        Ok(path) => path,
+        Err(errno) => return errno,
     };
     let mode: u32 = varargs.get(ctx);
-    unsafe { mkdir(real_path, mode as _) }
+    unsafe { mkdirat(path.dir(), path.name().as_ptr(), mode as _) }
+}
+
+// rename
+pub fn ___syscall38(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> i32 {
+    debug!("emscripten::___syscall38 (rename)");
+    let old_path_ptr = varargs.get_str(ctx);
+    let new_path_ptr = varargs.get_str(ctx);
+    let old_path = match get_at_path(ctx, old_path_ptr as *const _, Access::Create) {
+        Ok(path) => path,
+        Err(errno) => return errno,
+    };
+    let new_path = match get_at_path(ctx, new_path_ptr as *const _, Access::Create) {
+        Ok(path) => path,
+        Err(errno) => return errno,
+    };
+    let result = unsafe {
+        renameat(
+            old_path.dir(),
+            old_path.name().as_ptr(),
+            new_path.dir(),
+            new_path.name().as_ptr(),
+        )
+    };
+    debug!(
+        "=> old_path: {:?}, new_path: {:?}, result: {}",
+        old_path.name(),
+        new_path.name(),
+        result
+    );
+    result
+}
+
+// rmdir
+pub fn ___syscall40(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
+    debug!("emscripten::___syscall40 (rmdir)");
+    let pathname_addr = varargs.get_str(ctx);
+    let path = match get_at_path(ctx, pathname_addr as *const _, Access::Create) {
+        Ok(path) => path,
+        Err(errno) => return errno,
+    };
+    unsafe { unlinkat(path.dir(), path.name().as_ptr(), AT_REMOVEDIR) }
 }
 
 /// dup
 pub fn ___syscall41(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall41 (dup) {}", _which);
     let fd: c_int = varargs.get(ctx);
-    unsafe { dup(fd) }
+    let host_fd = match utils::get_host_fd(ctx, fd) {
+        Ok(host_fd) => host_fd,
+        Err(errno) => return errno,
+    };
+    utils::register_host_fd(ctx, unsafe { dup(host_fd) })
 }
 
 /// getgid32
@@ -431,7 +508,11 @@ pub fn ___syscall207(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int
     let fd: c_int = varargs.get(ctx);
     let owner: uid_t = varargs.get(ctx);
     let group: gid_t = varargs.get(ctx);
-    unsafe { fchown(fd, owner, group) }
+    let host_fd = match utils::get_host_fd(ctx, fd) {
+        Ok(host_fd) => host_fd,
+        Err(errno) => return errno,
+    };
+    unsafe { fchown(host_fd, owner, group) }
 }
 
 /// dup3
@@ -443,23 +524,12 @@ pub fn ___syscall330(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> pid_t
     let flags: c_int = varargs.get(ctx);
 
     if oldfd == newfd {
-        return EINVAL;
-    }
-
-    let res = unsafe { dup2(oldfd, newfd) };
-
-    // Set flags on newfd (https://www.gnu.org/software/libc/manual/html_node/Descriptor-Flags.html)
-    let mut old_flags = unsafe { fcntl(newfd, F_GETFD, 0) };
-
-    if old_flags > 0 {
-        old_flags |= flags;
-    } else if old_flags == 0 {
-        old_flags &= !flags;
+        return -EINVAL;
     }
 
-    unsafe {
-        fcntl(newfd, F_SETFD, old_flags);
-    }
+    // the only flag is `O_CLOEXEC`, which makes no difference since the
+    // module can't `exec`
+    let res = super::dup_fd(ctx, oldfd, newfd);
 
     debug!(
         "=> oldfd: {}, newfd: {}, flags: {} = pid: {}",
@@ -475,6 +545,10 @@ pub fn ___syscall54(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
     let fd: i32 = varargs.get(ctx);
     let request: u32 = varargs.get(ctx);
     debug!("=> fd: {}, op: {}", fd, request);
+    let host_fd = match utils::get_host_fd(ctx, fd) {
+        Ok(host_fd) => host_fd,
+        Err(errno) => return errno,
+    };
 
     // Got the equivalents here: https://code.woboq.org/linux/linux/include/uapi/asm-generic/ioctls.h.html
     match request {
@@ -483,7 +557,7 @@ pub fn ___syscall54(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
             let argp: u32 = varargs.get(ctx);
             let argp_ptr = emscripten_memory_pointer!(ctx.memory(0), argp) as *mut c_void;
             let translated_request = translate_ioctl(request);
-            let ret = unsafe { ioctl(fd, translated_request as _, argp_ptr) };
+            let ret = unsafe { ioctl(host_fd, translated_request as _, argp_ptr) };
             debug!(
                 " => request: {}, translated: {}, return: {}",
                 request, translated_request, ret
@@ -513,6 +587,10 @@ const SOCK_CLOEXC: i32 = 0x80000;
 #[allow(clippy::cast_ptr_alignment)]
 pub fn ___syscall102(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall102 (socketcall) {}", _which);
+    if !get_emscripten_data(ctx).sandbox.networking_allowed() {
+        debug!("=> networking is not allowed");
+        return -EACCES;
+    }
     let call: u32 = varargs.get(ctx);
     let mut socket_varargs: VarArgs = varargs.get(ctx);
     let memory = ctx.memory(0);
@@ -571,13 +649,17 @@ pub fn ___syscall102(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int
                 "=> domain: {}, type: {}, protocol: {} = fd: {}",
                 domain, ty, protocol, fd
             );
-            fd as _
+            utils::register_host_fd(ctx, fd)
         }
         2 => {
             debug!("socket: bind");
             // bind (socket: c_int, address: *const sockaddr, address_len: socklen_t) -> c_int
             // TODO: Emscripten has a different signature.
             let socket = socket_varargs.get(ctx);
+            let socket = match utils::get_host_fd(ctx, socket) {
+                Ok(host_fd) => host_fd,
+                Err(errno) => return errno,
+            };
             let address: u32 = socket_varargs.get(ctx);
             let address_len = socket_varargs.get(ctx);
             let address = emscripten_memory_pointer!(&memory, address) as *mut sockaddr;
@@ -603,6 +685,10 @@ pub fn ___syscall102(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int
             // connect (socket: c_int, address: *const sockaddr, len: socklen_t) -> c_int
             // TODO: Emscripten has a different signature.
             let socket = socket_varargs.get(ctx);
+            let socket = match utils::get_host_fd(ctx, socket) {
+                Ok(host_fd) => host_fd,
+                Err(errno) => return errno,
+            };
             let address: u32 = socket_varargs.get(ctx);
             let address_len = socket_varargs.get(ctx);
             let address = emscripten_memory_pointer!(&memory, address) as *mut sockaddr;
@@ -612,6 +698,10 @@ pub fn ___syscall102(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int
             debug!("socket: listen");
             // listen (socket: c_int, backlog: c_int) -> c_int
             let socket = socket_varargs.get(ctx);
+            let socket = match utils::get_host_fd(ctx, socket) {
+                Ok(host_fd) => host_fd,
+                Err(errno) => return errno,
+            };
             let backlog: i32 = socket_varargs.get(ctx);
             let status = unsafe { listen(socket, backlog) };
             debug!(
@@ -624,6 +714,10 @@ pub fn ___syscall102(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int
             debug!("socket: accept");
             // accept (socket: c_int, address: *mut sockaddr, address_len: *mut socklen_t) -> c_int
             let socket: i32 = socket_varargs.get(ctx);
+            let socket = match utils::get_host_fd(ctx, socket) {
+                Ok(host_fd) => host_fd,
+                Err(errno) => return errno,
+            };
             let address: WasmPtr<EmSockAddr> = socket_varargs.get(ctx);
             let address_len: WasmPtr<u32> = socket_varargs.get(ctx);
 
@@ -659,12 +753,16 @@ pub fn ___syscall102(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int
                 address_addr, address_len_addr, fd
             );
 
-            fd as _
+            utils::register_host_fd(ctx, fd)
         }
         6 => {
             debug!("socket: getsockname");
             // getsockname (socket: c_int, address: *mut sockaddr, address_len: *mut socklen_t) -> c_int
             let socket: i32 = socket_varargs.get(ctx);
+            let socket = match utils::get_host_fd(ctx, socket) {
+                Ok(host_fd) => host_fd,
+                Err(errno) => return errno,
+            };
             let address: WasmPtr<EmSockAddr> = socket_varargs.get(ctx);
             let address_len: WasmPtr<u32> = socket_varargs.get(ctx);
             let address_len_addr = unsafe { address_len.deref(&memory).unwrap().get_mut() };
@@ -698,6 +796,10 @@ pub fn ___syscall102(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int
             debug!("socket: getpeername");
             // getpeername (socket: c_int, address: *mut sockaddr, address_len: *mut socklen_t) -> c_int
             let socket = socket_varargs.get(ctx);
+            let socket = match utils::get_host_fd(ctx, socket) {
+                Ok(host_fd) => host_fd,
+                Err(errno) => return errno,
+            };
             let address: u32 = socket_varargs.get(ctx);
             let address_len: u32 = socket_varargs.get(ctx);
             let address = emscripten_memory_pointer!(memory, address) as *mut sockaddr;
@@ -709,6 +811,10 @@ pub fn ___syscall102(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int
             debug!("socket: sendto");
             // sendto (socket: c_int, buf: *const c_void, len: size_t, flags: c_int, addr: *const sockaddr, addrlen: socklen_t) -> ssize_t
             let socket = socket_varargs.get(ctx);
+            let socket = match utils::get_host_fd(ctx, socket) {
+                Ok(host_fd) => host_fd,
+                Err(errno) => return errno,
+            };
             let buf: u32 = socket_varargs.get(ctx);
             let flags = socket_varargs.get(ctx);
             let len: i32 = socket_varargs.get(ctx);
@@ -722,6 +828,10 @@ pub fn ___syscall102(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int
             debug!("socket: recvfrom");
             // recvfrom (socket: c_int, buf: *const c_void, len: size_t, flags: c_int, addr: *const sockaddr, addrlen: socklen_t) -> ssize_t
             let socket = socket_varargs.get(ctx);
+            let socket = match utils::get_host_fd(ctx, socket) {
+                Ok(host_fd) => host_fd,
+                Err(errno) => return errno,
+            };
             let buf: u32 = socket_varargs.get(ctx);
             let len: i32 = socket_varargs.get(ctx);
             let flags: i32 = socket_varargs.get(ctx);
@@ -749,6 +859,10 @@ pub fn ___syscall102(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int
             // setsockopt (socket: c_int, level: c_int, name: c_int, value: *const c_void, option_len: socklen_t) -> c_int
 
             let socket = socket_varargs.get(ctx);
+            let socket = match utils::get_host_fd(ctx, socket) {
+                Ok(host_fd) => host_fd,
+                Err(errno) => return errno,
+            };
             let level: i32 = socket_varargs.get(ctx);
             let level = if level == 1 { SOL_SOCKET } else { level };
             let untranslated_name: i32 = socket_varargs.get(ctx);
@@ -766,6 +880,10 @@ pub fn ___syscall102(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int
             debug!("socket: getsockopt");
             // getsockopt (sockfd: c_int, level: c_int, optname: c_int, optval: *mut c_void, optlen: *mut socklen_t) -> c_int
             let socket = socket_varargs.get(ctx);
+            let socket = match utils::get_host_fd(ctx, socket) {
+                Ok(host_fd) => host_fd,
+                Err(errno) => return errno,
+            };
             let level: i32 = socket_varargs.get(ctx);
             let level = if level == 1 { SOL_SOCKET } else { level };
             let untranslated_name: i32 = socket_varargs.get(ctx);
@@ -780,6 +898,10 @@ pub fn ___syscall102(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int
             debug!("socket: sendmsg");
             // sendmsg (fd: c_int, msg: *const msghdr, flags: c_int) -> ssize_t
             let socket: i32 = socket_varargs.get(ctx);
+            let socket = match utils::get_host_fd(ctx, socket) {
+                Ok(host_fd) => host_fd,
+                Err(errno) => return errno,
+            };
             let msg: u32 = socket_varargs.get(ctx);
             let flags: i32 = socket_varargs.get(ctx);
             let msg_addr = emscripten_memory_pointer!(memory, msg) as *const msghdr;
@@ -789,6 +911,10 @@ pub fn ___syscall102(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int
             debug!("socket: recvmsg");
             // recvmsg (fd: c_int, msg: *mut msghdr, flags: c_int) -> ssize_t
             let socket: i32 = socket_varargs.get(ctx);
+            let socket = match utils::get_host_fd(ctx, socket) {
+                Ok(host_fd) => host_fd,
+                Err(errno) => return errno,
+            };
             let msg: u32 = socket_varargs.get(ctx);
             let flags: i32 = socket_varargs.get(ctx);
             let msg_addr = emscripten_memory_pointer!(memory, msg) as *mut msghdr;
@@ -852,22 +978,58 @@ unsafe impl wasmer::ValueType for EmPollFd {}
 /// poll
 pub fn ___syscall168(ctx: &EmEnv, _which: i32, mut varargs: VarArgs) -> i32 {
     debug!("emscripten::___syscall168(poll)");
-    let fds: WasmPtr<EmPollFd> = varargs.get(ctx);
+    let fds: WasmPtr<EmPollFd, Array> = varargs.get(ctx);
     let nfds: u32 = varargs.get(ctx);
     let timeout: i32 = varargs.get(ctx);
     let memory = ctx.memory(0);
 
-    let fds_mut = unsafe { fds.deref(&memory).unwrap().get_mut() };
-
-    let ret = unsafe {
-        libc::poll(
-            fds_mut as *mut EmPollFd as *mut libc::pollfd,
-            nfds as _,
-            timeout,
-        )
+    let guest_fds = if nfds == 0 {
+        vec![]
+    } else {
+        match fds.deref(&memory, 0, nfds) {
+            Some(guest_fds) => guest_fds,
+            None => return -EFAULT,
+        }
     };
-
-    ret
+    // the file descriptors the module doesn't have are reported as invalid,
+    // and ignored by the host
+    let mut host_fds = guest_fds
+        .iter()
+        .map(|guest_fd| {
+            let guest_fd = guest_fd.get();
+            let fd = if guest_fd.fd < 0 {
+                guest_fd.fd
+            } else {
+                utils::get_host_fd(ctx, guest_fd.fd).unwrap_or(-1)
+            };
+            libc::pollfd {
+                fd,
+                events: guest_fd.events,
+                revents: 0,
+            }
+        })
+        .collect::<Vec<_>>();
+    let invalid = guest_fds
+        .iter()
+        .zip(&host_fds)
+        .filter(|(guest_fd, host_fd)| guest_fd.get().fd >= 0 && host_fd.fd < 0)
+        .count() as i32;
+    let timeout = if invalid > 0 { 0 } else { timeout };
+
+    let ret = unsafe { libc::poll(host_fds.as_mut_ptr(), nfds as _, timeout) };
+    if ret < 0 {
+        return ret;
+    }
+    for (guest_fd, host_fd) in guest_fds.iter().zip(&host_fds) {
+        let mut poll_fd = guest_fd.get();
+        poll_fd.revents = if poll_fd.fd >= 0 && host_fd.fd < 0 {
+            libc::POLLNVAL
+        } else {
+            host_fd.revents
+        };
+        guest_fd.set(poll_fd);
+    }
+    ret + invalid
 }
 
 // pread
@@ -883,8 +1045,12 @@ pub fn ___syscall180(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int
     let offset: i64 = varargs.get(ctx);
 
     let buf_ptr = emscripten_memory_pointer!(ctx.memory(0), buf) as _;
+    let host_fd = match utils::get_host_fd(ctx, fd) {
+        Ok(host_fd) => host_fd,
+        Err(errno) => return errno,
+    };
 
-    unsafe { pread(fd, buf_ptr, count as _, offset) as _ }
+    unsafe { pread(host_fd, buf_ptr, count as _, offset) as _ }
 }
 
 // pwrite
@@ -900,7 +1066,11 @@ pub fn ___syscall181(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int
     let offset: i64 = varargs.get(ctx);
 
     let buf_ptr = emscripten_memory_pointer!(ctx.memory(0), buf) as _;
-    let status = unsafe { pwrite(fd, buf_ptr, count as _, offset) as _ };
+    let host_fd = match utils::get_host_fd(ctx, fd) {
+        Ok(host_fd) => host_fd,
+        Err(errno) => return errno,
+    };
+    let status = unsafe { pwrite(host_fd, buf_ptr, count as _, offset) as _ };
     debug!(
         "=> fd: {}, buf: {}, count: {}, offset: {} = status:{}",
         fd, buf, count, offset, status
@@ -913,7 +1083,11 @@ pub fn ___syscall94(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall118 (fchmod) {}", _which);
     let fd: c_int = varargs.get(ctx);
     let mode: mode_t = varargs.get(ctx);
-    unsafe { fchmod(fd, mode) }
+    let host_fd = match utils::get_host_fd(ctx, fd) {
+        Ok(host_fd) => host_fd,
+        Err(errno) => return errno,
+    };
+    unsafe { fchmod(host_fd, mode) }
 }
 
 /// wait4
@@ -939,7 +1113,11 @@ pub fn ___syscall114(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> pid_t
 pub fn ___syscall118(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall118 (fsync) {}", _which);
     let fd: c_int = varargs.get(ctx);
-    unsafe { fsync(fd) }
+    let host_fd = match utils::get_host_fd(ctx, fd) {
+        Ok(host_fd) => host_fd,
+        Err(errno) => return errno,
+    };
+    unsafe { fsync(host_fd) }
 }
 
 // select
@@ -959,10 +1137,67 @@ pub fn ___syscall142(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int
     }
     assert!(exceptfds == 0, "`exceptfds` is not supporrted");
 
-    let readfds_ptr = emscripten_memory_pointer!(ctx.memory(0), readfds) as _;
-    let writefds_ptr = emscripten_memory_pointer!(ctx.memory(0), writefds) as _;
+    let memory = ctx.memory(0);
+    let view = memory.view::<u32>();
+    let word = |set: u32, fd: i32| &view[(set / 4) as usize + fd as usize / 32];
+    let is_set = |set: u32, fd: i32| set != 0 && word(set, fd).get() & (1 << (fd % 32)) != 0;
+
+    // the sets are rebuilt with the host file descriptors
+    let mut host_readfds: libc::fd_set = unsafe { mem::zeroed() };
+    let mut host_writefds: libc::fd_set = unsafe { mem::zeroed() };
+    let mut host_nfds = 0;
+    let mut fds = vec![];
+    for fd in 0..nfds {
+        let (read, write) = (is_set(readfds, fd), is_set(writefds, fd));
+        if !(read || write) {
+            continue;
+        }
+        let host_fd = match utils::get_host_fd(ctx, fd) {
+            Ok(host_fd) => host_fd,
+            Err(errno) => return errno,
+        };
+        if host_fd >= libc::FD_SETSIZE as i32 {
+            return -EBADF;
+        }
+        unsafe {
+            if read {
+                libc::FD_SET(host_fd, &mut host_readfds);
+            }
+            if write {
+                libc::FD_SET(host_fd, &mut host_writefds);
+            }
+        }
+        host_nfds = host_nfds.max(host_fd + 1);
+        fds.push((fd, host_fd));
+    }
 
-    unsafe { select(nfds, readfds_ptr, writefds_ptr, 0 as _, 0 as _) }
+    let ret = unsafe {
+        select(
+            host_nfds,
+            &mut host_readfds,
+            &mut host_writefds,
+            0 as _,
+            0 as _,
+        )
+    };
+    if ret < 0 {
+        return ret;
+    }
+    for &(set, host_set) in &[(readfds, &host_readfds), (writefds, &host_writefds)] {
+        if set == 0 {
+            continue;
+        }
+        for fd in (0..nfds).step_by(32) {
+            word(set, fd).set(0);
+        }
+        for &(fd, host_fd) in &fds {
+            if unsafe { libc::FD_ISSET(host_fd, host_set) } {
+                let cell = word(set, fd);
+                cell.set(cell.get() | 1 << (fd % 32));
+            }
+        }
+    }
+    ret
 }
 
 /// fdatasync
@@ -970,8 +1205,12 @@ pub fn ___syscall148(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int
     debug!("emscripten::___syscall148 (fdatasync) {}", _which);
 
     let fd: i32 = varargs.get(ctx);
+    let host_fd = match utils::get_host_fd(ctx, fd) {
+        Ok(host_fd) => host_fd,
+        Err(errno) => return errno,
+    };
 
-    unsafe { fdatasync(fd) }
+    unsafe { fdatasync(host_fd) }
 }
 
 // setpgid
@@ -999,30 +1238,53 @@ pub fn ___syscall122(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int
     unsafe { uname(buf_addr) }
 }
 
+// stat64
+pub fn ___syscall195(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
+    debug!("emscripten::___syscall195 (stat64) {}", _which);
+    let pathname_addr = varargs.get_str(ctx);
+    let buf: u32 = varargs.get(ctx);
+
+    let path = match get_at_path(ctx, pathname_addr as *const _, Access::Read) {
+        Ok(path) => path,
+        Err(errno) => return errno,
+    };
+
+    unsafe {
+        let mut _stat: stat = std::mem::zeroed();
+        // symlinks are never followed in the sandbox
+        let ret = fstatat(
+            path.dir(),
+            path.name().as_ptr(),
+            &mut _stat,
+            AT_SYMLINK_NOFOLLOW,
+        );
+        debug!("=> pathname: {:?}, buf: {} = {}", path.name(), buf, ret);
+        if ret != 0 {
+            debug!("=> os error: {}", Error::last_os_error());
+            return ret;
+        }
+        utils::copy_stat_into_wasm(ctx, buf, &_stat);
+    }
+    0
+}
+
 /// lstat64
 pub fn ___syscall196(ctx: &EmEnv, _which: i32, mut varargs: VarArgs) -> i32 {
     debug!("emscripten::___syscall196 (lstat64) {}", _which);
-    let path = varargs.get_str(ctx);
-    let real_path_owned = utils::get_cstr_path(ctx, path as *const _);
-    let real_path = if let Some(ref rp) = real_path_owned {
-        rp.as_c_str().as_ptr()
-    } else {
-        path
+    let path_ptr = varargs.get_str(ctx);
+    let path = match get_at_path(ctx, path_ptr as *const _, Access::Read) {
+        Ok(path) => path,
+        Err(errno) => return errno,
     };
     let buf_ptr: u32 = varargs.get(ctx);
     unsafe {
         let mut stat: stat = std::mem::zeroed();
-
-        #[cfg(target_os = "macos")]
-        let stat_ptr = &mut stat as *mut stat as *mut c_void;
-        #[cfg(not(target_os = "macos"))]
-        let stat_ptr = &mut stat as *mut stat;
-
-        #[cfg(target_os = "macos")]
-        let ret = lstat64(real_path, stat_ptr);
-        #[cfg(not(target_os = "macos"))]
-        let ret = lstat(real_path, stat_ptr);
-
+        let ret = fstatat(
+            path.dir(),
+            path.name().as_ptr(),
+            &mut stat,
+            AT_SYMLINK_NOFOLLOW,
+        );
         debug!("ret: {}", ret);
         if ret != 0 {
             return ret;
@@ -1044,8 +1306,6 @@ pub fn ___syscall199(_ctx: &EmEnv, _one: i32, _two: i32) -> i32 {
 // dirent structure is
 // i64, i64, u16 (280), i8, [i8; 256]
 pub fn ___syscall220(ctx: &EmEnv, _which: i32, mut varargs: VarArgs) -> i32 {
-    use super::super::env::get_emscripten_data;
-
     let fd: i32 = varargs.get(ctx);
     let dirp_addr: i32 = varargs.get(ctx);
     let count: u32 = varargs.get(ctx);
@@ -1055,6 +1315,10 @@ pub fn ___syscall220(ctx: &EmEnv, _which: i32, mut varargs: VarArgs) -> i32 {
     );
 
     let dirp = emscripten_memory_pointer!(ctx.memory(0), dirp_addr) as *mut u8;
+    let host_fd = match utils::get_host_fd(ctx, fd) {
+        Ok(host_fd) => host_fd,
+        Err(errno) => return errno,
+    };
 
     let opened_dirs = &mut get_emscripten_data(ctx).opened_dirs;
 
@@ -1062,7 +1326,7 @@ pub fn ___syscall220(ctx: &EmEnv, _which: i32, mut varargs: VarArgs) -> i32 {
     // let dir: *mut libc::DIR = unsafe { libc::fdopendir(fd) };
     let dir = &*opened_dirs
         .entry(fd)
-        .or_insert_with(|| unsafe { Box::new(LibcDirWrapper(libc::fdopendir(fd))) });
+        .or_insert_with(|| unsafe { Box::new(LibcDirWrapper(libc::fdopendir(host_fd))) });
 
     let mut pos = 0;
     let offset = 256 + 12;
@@ -1116,7 +1380,24 @@ pub fn ___syscall221(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int
     // |FASYNC    - 0x40
     // |FFSYNC    - 0x80
     // |FNONBLOCK - 0x04
-    let ret = unsafe { fcntl(fd, cmd, arg) };
+    let host_fd = match utils::get_host_fd(ctx, fd) {
+        Ok(host_fd) => host_fd,
+        Err(errno) => return errno,
+    };
+    let ret = match cmd {
+        // the new file descriptor is the lowest free one not below `arg`
+        WASM_F_DUPFD | WASM_F_DUPFD_CLOEXEC => {
+            let host_dup = unsafe { fcntl(host_fd, F_DUPFD, 0) };
+            if host_dup == -1 {
+                -1
+            } else {
+                let fds = get_emscripten_data(ctx).fds.clone();
+                let dup = fds.lock().unwrap().insert(host_dup, arg);
+                dup.unwrap_or_else(|errno| -errno)
+            }
+        }
+        _ => unsafe { fcntl(host_fd, cmd, arg) },
+    };
     debug!("=> fd: {}, cmd: {} = {}", fd, cmd, ret);
     if ret == -1 {
         debug!("=> last os error: {}", Error::last_os_error(),);
@@ -1127,13 +1408,17 @@ pub fn ___syscall221(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int
 /// fallocate
 pub fn ___syscall324(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall324 (fallocate) {}", _which);
-    let _fd: c_int = varargs.get(ctx);
+    let fd: c_int = varargs.get(ctx);
     let _mode: c_int = varargs.get(ctx);
     let _offset: off_t = varargs.get(ctx);
     let _len: off_t = varargs.get(ctx);
+    let _host_fd = match utils::get_host_fd(ctx, fd) {
+        Ok(host_fd) => host_fd,
+        Err(errno) => return errno,
+    };
     #[cfg(not(any(target_os = "freebsd", target_os = "macos", target_os = "android")))]
     unsafe {
-        fallocate(_fd, _mode, _offset, _len)
+        fallocate(_host_fd, _mode, _offset, _len)
     }
     #[cfg(any(target_os = "freebsd", target_os = "macos", target_os = "android"))]
     {
@@ -1,11 +1,15 @@
-use crate::utils::{copy_cstr_into_wasm, get_cstr_path};
+use crate::sandbox::Access;
+use crate::utils::{copy_cstr_into_wasm, copy_stat_into_wasm, get_cstr_path, register_host_fd};
 use crate::varargs::VarArgs;
 use crate::EmEnv;
 use libc::mkdir;
 use libc::open;
+use libc::{rename, rmdir, stat};
 use std::env;
 use std::ffi::CString;
 use std::fs::File;
+#[allow(unused_imports)]
+use std::io::Error;
 use std::io::Write;
 use std::os::raw::c_int;
 
@@ -18,14 +22,13 @@ pub fn ___syscall5(ctx: &EmEnv, which: c_int, mut varargs: VarArgs) -> c_int {
     #[cfg(not(feature = "debug"))]
     let _ = which;
     let pathname_addr = varargs.get_str(ctx);
-    let real_path_owned = get_cstr_path(ctx, pathname_addr);
-    let real_path = if let Some(ref rp) = real_path_owned {
-        rp.as_c_str().as_ptr()
-    } else {
-        pathname_addr
-    };
     let flags: i32 = varargs.get(ctx);
     let mode: u32 = varargs.get(ctx);
+    let real_path_owned = match get_cstr_path(ctx, pathname_addr, Access::for_open_flags(flags)) {
+        Ok(path) => path,
+        Err(errno) => return errno,
+    };
+    let real_path = real_path_owned.as_ptr();
     let path_str = unsafe { std::ffi::CStr::from_ptr(real_path).to_str().unwrap() };
     let memory = ctx.memory(0);
 
@@ -52,7 +55,7 @@ pub fn ___syscall5(ctx: &EmEnv, which: c_int, mut varargs: VarArgs) -> c_int {
                 "=> pathname: {}, flags: {}, mode: {} = fd: {}",
                 path_str, flags, mode, fd
             );
-            fd
+            register_host_fd(ctx, fd)
         }
         _ => {
             let fd = unsafe { open(real_path, flags, mode) };
@@ -60,7 +63,7 @@ pub fn ___syscall5(ctx: &EmEnv, which: c_int, mut varargs: VarArgs) -> c_int {
                 "=> pathname: {}, flags: {}, mode: {} = fd: {}\npath: {}",
                 path_str, flags, mode, fd, path_str
             );
-            fd
+            register_host_fd(ctx, fd)
         }
     }
 }
@@ -103,12 +106,11 @@ pub fn ___syscall39(ctx: &EmEnv, which: c_int, mut varargs: VarArgs) -> c_int {
     #[cfg(not(feature = "debug"))]
     let _ = which;
     let pathname_addr = varargs.get_str(ctx);
-    let real_path_owned = get_cstr_path(ctx, pathname_addr);
-    let real_path = if let Some(ref rp) = real_path_owned {
-        rp.as_c_str().as_ptr()
-    } else {
-        pathname_addr
+    let real_path_owned = match get_cstr_path(ctx, pathname_addr, Access::Create) {
+        Ok(path) => path,
+        Err(errno) => return errno,
     };
+    let real_path = real_path_owned.as_ptr();
     unsafe { mkdir(real_path) }
 }
 
@@ -310,3 +312,70 @@ pub fn ___syscall324(_ctx: &EmEnv, _which: c_int, _varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall324 (fallocate) {}", _which);
     unimplemented!("emscripten::___syscall324 (fallocate) {}", _which)
 }
+
+// rename
+pub fn ___syscall38(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> i32 {
+    debug!("emscripten::___syscall38 (rename)");
+    let old_path = varargs.get_str(ctx);
+    let new_path = varargs.get_str(ctx);
+    let real_old_path_owned = match get_cstr_path(ctx, old_path as *const _, Access::Create) {
+        Ok(path) => path,
+        Err(errno) => return errno,
+    };
+    let real_old_path = real_old_path_owned.as_ptr();
+    let real_new_path_owned = match get_cstr_path(ctx, new_path as *const _, Access::Create) {
+        Ok(path) => path,
+        Err(errno) => return errno,
+    };
+    let real_new_path = real_new_path_owned.as_ptr();
+    let result = unsafe { rename(real_old_path, real_new_path) };
+    debug!(
+        "=> old_path: {}, new_path: {}, result: {}",
+        unsafe { std::ffi::CStr::from_ptr(real_old_path).to_str().unwrap() },
+        unsafe { std::ffi::CStr::from_ptr(real_new_path).to_str().unwrap() },
+        result
+    );
+    result
+}
+
+// rmdir
+pub fn ___syscall40(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
+    debug!("emscripten::___syscall40 (rmdir)");
+    let pathname_addr = varargs.get_str(ctx);
+    let real_path_owned = match get_cstr_path(ctx, pathname_addr as *const _, Access::Create) {
+        Ok(path) => path,
+        Err(errno) => return errno,
+    };
+    let real_path = real_path_owned.as_ptr();
+    unsafe { rmdir(real_path) }
+}
+
+// stat64
+pub fn ___syscall195(ctx: &EmEnv, _which: c_int, mut varargs: VarArgs) -> c_int {
+    debug!("emscripten::___syscall195 (stat64) {}", _which);
+    let pathname_addr = varargs.get_str(ctx);
+    let buf: u32 = varargs.get(ctx);
+
+    let real_path_owned = match get_cstr_path(ctx, pathname_addr as *const _, Access::Read) {
+        Ok(path) => path,
+        Err(errno) => return errno,
+    };
+    let real_path = real_path_owned.as_ptr();
+
+    unsafe {
+        let mut _stat: stat = std::mem::zeroed();
+        let ret = stat(real_path, &mut _stat);
+        debug!(
+            "=> pathname: {}, buf: {} = {}",
+            std::ffi::CStr::from_ptr(real_path).to_str().unwrap(),
+            buf,
+            ret
+        );
+        if ret != 0 {
+            debug!("=> os error: {}", Error::last_os_error());
+            return ret;
+        }
+        copy_stat_into_wasm(ctx, buf, &_stat);
+    }
+    0
+}
@@ -1,12 +1,15 @@
 use super::env;
 use super::env::get_emscripten_data;
+use crate::sandbox::Access;
+#[cfg(unix)]
+use crate::sandbox::AtPath;
 use crate::storage::align_memory;
 use crate::EmEnv;
 use libc::stat;
 use std::ffi::CStr;
 use std::mem::size_of;
-use std::os::raw::c_char;
-use std::path::PathBuf;
+use std::os::raw::{c_char, c_int};
+use std::path::{Path, PathBuf};
 use std::slice;
 use wasmer::{GlobalInit, Memory, Module, Pages};
 
@@ -222,58 +225,89 @@ pub fn read_string_from_wasm(memory: &Memory, offset: u32) -> String {
     String::from_utf8_lossy(&v).to_owned().to_string()
 }
 
-/// This function trys to find an entry in mapdir
-/// translating paths into their correct value
-pub fn get_cstr_path(ctx: &EmEnv, path: *const i8) -> Option<std::ffi::CString> {
-    use std::collections::VecDeque;
+/// Resolves the guest path at `path` through the sandbox, making sure the
+/// module was granted `access` to it.
+///
+/// Errors are negative `errno` values, ready to be returned by a syscall.
+#[cfg(not(unix))]
+pub(crate) fn get_cstr_path(
+    ctx: &EmEnv,
+    path: *const i8,
+    access: Access,
+) -> Result<std::ffi::CString, c_int> {
+    let path_str = unsafe { CStr::from_ptr(path as *const _) }
+        .to_str()
+        .map_err(|_| -libc::EINVAL)?;
+    let host_path = get_emscripten_data(ctx)
+        .sandbox
+        .resolve(Path::new(path_str), access)
+        .map_err(|errno| -errno)?;
+    std::ffi::CString::new(host_path.to_string_lossy().as_bytes()).map_err(|_| -libc::EINVAL)
+}
+
+/// Opens the directory holding the guest path at `path` through the
+/// sandbox, making sure the module was granted `access` to it, for the
+/// `*at` syscalls.
+///
+/// Errors are negative `errno` values, ready to be returned by a syscall.
+#[cfg(unix)]
+pub(crate) fn get_at_path(ctx: &EmEnv, path: *const i8, access: Access) -> Result<AtPath, c_int> {
+    let path_str = unsafe { CStr::from_ptr(path as *const _) }
+        .to_str()
+        .map_err(|_| -libc::EINVAL)?;
+    get_emscripten_data(ctx)
+        .sandbox
+        .open_parent(Path::new(path_str), access)
+        .map_err(|errno| -errno)
+}
 
-    let path_str =
-        unsafe { std::ffi::CStr::from_ptr(path as *const _).to_str().unwrap() }.to_string();
-    let data = get_emscripten_data(ctx);
-    let path = PathBuf::from(path_str);
-    let mut prefix_added = false;
-    let mut components = path.components().collect::<VecDeque<_>>();
-    // TODO(mark): handle absolute/non-canonical/non-relative paths too (this
-    // functionality should be shared among the abis)
-    if components.len() == 1 {
-        components.push_front(std::path::Component::CurDir);
-        prefix_added = true;
+/// Looks up the host file descriptor of the guest one `fd`.
+///
+/// Errors are negative `errno` values, ready to be returned by a syscall.
+pub(crate) fn get_host_fd(ctx: &EmEnv, fd: c_int) -> Result<c_int, c_int> {
+    let fds = get_emscripten_data(ctx).fds.clone();
+    let host_fd = fds.lock().unwrap().get(fd).map_err(|errno| -errno)?;
+    Ok(host_fd)
+}
+
+/// Registers the host file descriptor returned by a syscall, and returns
+/// the guest file descriptor the module knows it by.
+///
+/// Failures of the syscall, `-1`, are passed through.
+pub(crate) fn register_host_fd(ctx: &EmEnv, host_fd: c_int) -> c_int {
+    if host_fd < 0 {
+        return host_fd;
     }
-    let mut cumulative_path = PathBuf::new();
-    for c in components.into_iter() {
-        cumulative_path.push(c);
-        if let Some(val) = data
-            .mapped_dirs
-            .get(&cumulative_path.to_string_lossy().to_string())
-        {
-            let rest_of_path = if !prefix_added {
-                path.strip_prefix(cumulative_path).ok()?
-            } else {
-                &path
-            };
-            let rebased_path = val.join(rest_of_path);
-            return std::ffi::CString::new(rebased_path.to_string_lossy().as_bytes()).ok();
+    let fds = get_emscripten_data(ctx).fds.clone();
+    let fd = fds.lock().unwrap().insert(host_fd, 0);
+    fd.unwrap_or_else(|errno| -errno)
+}
+
+/// Closes the guest file descriptor `fd`.
+pub(crate) fn close_fd(ctx: &EmEnv, fd: c_int) -> c_int {
+    let fds = get_emscripten_data(ctx).fds.clone();
+    let host_fd = match fds.lock().unwrap().remove(fd) {
+        Ok(host_fd) => host_fd,
+        Err(errno) => return -errno,
+    };
+    close_host_fd(ctx, fd, host_fd)
+}
+
+/// Closes `host_fd`, which the module knew as `fd`.
+pub(crate) fn close_host_fd(ctx: &EmEnv, fd: c_int, host_fd: c_int) -> c_int {
+    // a directory read by `getdents` is owned by its stream
+    #[cfg(unix)]
+    {
+        if let Some(dir) = get_emscripten_data(ctx).opened_dirs.remove(&fd) {
+            return unsafe { libc::closedir(**dir) };
         }
     }
-    None
+    #[cfg(not(unix))]
+    let _ = (ctx, fd);
+    unsafe { libc::close(host_fd) }
 }
 
-/// gets the current directory
-/// handles mapdir logic
-pub fn get_current_directory(ctx: &EmEnv) -> Option<PathBuf> {
-    if let Some(val) = get_emscripten_data(ctx).mapped_dirs.get(".") {
-        return Some(val.clone());
-    }
-    std::env::current_dir()
-        .map(|cwd| {
-            if let Some(val) = get_emscripten_data(ctx)
-                .mapped_dirs
-                .get(&cwd.to_string_lossy().to_string())
-            {
-                val.clone()
-            } else {
-                cwd
-            }
-        })
-        .ok()
+/// gets the virtual current directory of the module
+pub fn get_current_directory(ctx: &EmEnv) -> PathBuf {
+    get_emscripten_data(ctx).sandbox.cwd().to_path_buf()
 }
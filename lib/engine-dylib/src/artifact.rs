@@ -12,17 +12,19 @@ use std::path::{Path, PathBuf};
 #[cfg(feature = "compiler")]
 use std::process::Command;
 use std::sync::{Arc, Mutex};
+#[cfg(feature = "compiler")]
+use std::time::Instant;
 use tempfile::NamedTempFile;
 #[cfg(feature = "compiler")]
 use tracing::trace;
+#[cfg(feature = "compiler")]
 use wasmer_compiler::{
-    CompileError, CompiledFunctionFrameInfo, Features, FunctionAddressMap, OperatingSystem, Symbol,
-    SymbolRegistry, Triple,
+    CancellationToken, CompileModuleInfo, Compiler, FunctionBodyData, ModuleEnvironment,
+    ModuleMiddlewareChain, ModuleTranslationState,
 };
-#[cfg(feature = "compiler")]
 use wasmer_compiler::{
-    CompileModuleInfo, Compiler, FunctionBodyData, ModuleEnvironment, ModuleMiddlewareChain,
-    ModuleTranslationState,
+    CompileError, CompileStats, CompiledFunctionFrameInfo, Features, FunctionAddressMap,
+    OperatingSystem, Symbol, SymbolRegistry, Triple,
 };
 use wasmer_engine::{
     register_frame_info, Artifact, DeserializeError, FunctionExtent, GlobalFrameInfoRegistration,
@@ -56,6 +58,10 @@ pub struct DylibArtifact {
     func_data_registry: Arc<FuncDataRegistry>,
     signatures: BoxedSlice<SignatureIndex, VMSharedSignatureIndex>,
     frame_info_registration: Mutex<Option<GlobalFrameInfoRegistration>>,
+    /// The statistics about the compilation, if the artifact was compiled
+    /// rather than deserialized.
+    #[loupe(skip)]
+    compile_stats: Option<CompileStats>,
 }
 
 fn to_compile_error(err: impl Error) -> CompileError {
@@ -121,12 +127,13 @@ impl DylibArtifact {
         ),
         CompileError,
     > {
-        let environ = ModuleEnvironment::new();
+        let environ = ModuleEnvironment::new().with_limits(tunables.module_limits());
         let translation = environ.translate(data).map_err(CompileError::Wasm)?;
 
         // We try to apply the middleware first
         let mut module = translation.module;
         let middlewares = compiler.get_middlewares();
+        middlewares.inspect_function_bodies(&translation.function_body_inputs);
         middlewares.apply_on_module_info(&mut module);
 
         let memory_styles: PrimaryMap<MemoryIndex, MemoryStyle> = module
@@ -161,6 +168,7 @@ impl DylibArtifact {
         engine: &DylibEngine,
         data: &[u8],
         tunables: &dyn Tunables,
+        cancellation: &CancellationToken,
     ) -> Result<Self, CompileError> {
         let mut engine_inner = engine.inner_mut();
         let target = engine.target();
@@ -218,8 +226,10 @@ impl DylibArtifact {
             &function_body_inputs,
             &symbol_registry,
             &metadata_binary,
+            cancellation,
         );
 
+        let mut compile_stats = None;
         let filepath = match maybe_obj_bytes {
             Some(obj_bytes) => {
                 let obj_bytes = obj_bytes?;
@@ -235,12 +245,23 @@ impl DylibArtifact {
                 filepath
             }
             None => {
+                let wasm_sizes = function_body_inputs
+                    .values()
+                    .map(|function_body| function_body.data.len())
+                    .collect::<PrimaryMap<LocalFunctionIndex, usize>>();
+                let start = Instant::now();
                 let compilation = compiler.compile_module(
                     &target,
                     &compile_info,
                     module_translation.as_ref().unwrap(),
                     function_body_inputs,
+                    cancellation,
                 )?;
+                compile_stats = Some(CompileStats::new(
+                    &compilation,
+                    &wasm_sizes,
+                    start.elapsed(),
+                ));
                 let mut obj = get_object_for_target(&target_triple).map_err(to_compile_error)?;
                 emit_data(
                     &mut obj,
@@ -339,12 +360,14 @@ impl DylibArtifact {
             )));
         }
         trace!("gcc command result {:?}", output);
-        if is_cross_compiling {
-            Self::from_parts_crosscompiled(metadata, shared_filepath)
+        let mut artifact = if is_cross_compiling {
+            Self::from_parts_crosscompiled(metadata, shared_filepath)?
         } else {
             let lib = unsafe { Library::new(&shared_filepath).map_err(to_compile_error)? };
-            Self::from_parts(&mut engine_inner, metadata, shared_filepath, lib)
-        }
+            Self::from_parts(&mut engine_inner, metadata, shared_filepath, lib)?
+        };
+        artifact.compile_stats = compile_stats;
+        Ok(artifact)
     }
 
     /// Get the default extension when serializing this artifact
@@ -380,6 +403,7 @@ impl DylibArtifact {
             func_data_registry: Arc::new(FuncDataRegistry::new()),
             signatures: signatures.into_boxed_slice(),
             frame_info_registration: Mutex::new(None),
+            compile_stats: None,
         })
     }
 
@@ -485,6 +509,7 @@ impl DylibArtifact {
             func_data_registry: engine_inner.func_data().clone(),
             signatures: signatures.into_boxed_slice(),
             frame_info_registration: Mutex::new(None),
+            compile_stats: None,
         })
     }
 
@@ -765,6 +790,10 @@ impl Artifact for DylibArtifact {
         &self.func_data_registry
     }
 
+    fn compile_stats(&self) -> Option<&CompileStats> {
+        self.compile_stats.as_ref()
+    }
+
     fn preinstantiate(&self) -> Result<(), InstantiationError> {
         Ok(())
     }
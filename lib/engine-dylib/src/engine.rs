@@ -6,9 +6,9 @@ use loupe::MemoryUsage;
 use std::path::Path;
 use std::sync::Arc;
 use std::sync::Mutex;
-use wasmer_compiler::{CompileError, Target};
 #[cfg(feature = "compiler")]
-use wasmer_compiler::{Compiler, Triple};
+use wasmer_compiler::{CancellationToken, Compiler, Triple};
+use wasmer_compiler::{CompileError, Target};
 use wasmer_engine::{Artifact, DeserializeError, Engine, EngineId, Tunables};
 #[cfg(feature = "compiler")]
 use wasmer_types::Features;
@@ -143,7 +143,24 @@ impl Engine for DylibEngine {
         binary: &[u8],
         tunables: &dyn Tunables,
     ) -> Result<Arc<dyn Artifact>, CompileError> {
-        Ok(Arc::new(DylibArtifact::new(&self, binary, tunables)?))
+        self.compile_with_cancellation(binary, tunables, &CancellationToken::new())
+    }
+
+    /// Compile a WebAssembly binary, checking `cancellation` between the
+    /// functions
+    #[cfg(feature = "compiler")]
+    fn compile_with_cancellation(
+        &self,
+        binary: &[u8],
+        tunables: &dyn Tunables,
+        cancellation: &CancellationToken,
+    ) -> Result<Arc<dyn Artifact>, CompileError> {
+        Ok(Arc::new(DylibArtifact::new(
+            &self,
+            binary,
+            tunables,
+            cancellation,
+        )?))
     }
 
     /// Compile a WebAssembly binary (it will fail because the `compiler` flag is disabled).
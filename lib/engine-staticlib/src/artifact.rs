@@ -8,12 +8,12 @@ use std::collections::BTreeMap;
 use std::error::Error;
 use std::mem;
 use std::sync::Arc;
-use wasmer_compiler::{CompileError, Features, OperatingSystem, SymbolRegistry, Triple};
 #[cfg(feature = "compiler")]
 use wasmer_compiler::{
-    CompileModuleInfo, Compiler, FunctionBodyData, ModuleEnvironment, ModuleMiddlewareChain,
-    ModuleTranslationState,
+    CancellationToken, CompileModuleInfo, Compiler, FunctionBodyData, ModuleEnvironment,
+    ModuleMiddlewareChain, ModuleTranslationState,
 };
+use wasmer_compiler::{CompileError, Features, OperatingSystem, SymbolRegistry, Triple};
 use wasmer_engine::{Artifact, DeserializeError, InstantiationError, SerializeError};
 #[cfg(feature = "compiler")]
 use wasmer_engine::{Engine, Tunables};
@@ -113,12 +113,13 @@ impl StaticlibArtifact {
         ),
         CompileError,
     > {
-        let environ = ModuleEnvironment::new();
+        let environ = ModuleEnvironment::new().with_limits(tunables.module_limits());
         let translation = environ.translate(data).map_err(CompileError::Wasm)?;
 
         // We try to apply the middleware first
         let mut module = translation.module;
         let middlewares = compiler.get_middlewares();
+        middlewares.inspect_function_bodies(&translation.function_body_inputs);
         middlewares.apply_on_module_info(&mut module);
 
         let memory_styles: PrimaryMap<MemoryIndex, MemoryStyle> = module
@@ -153,6 +154,7 @@ impl StaticlibArtifact {
         engine: &StaticlibEngine,
         data: &[u8],
         tunables: &dyn Tunables,
+        cancellation: &CancellationToken,
     ) -> Result<Self, CompileError> {
         let mut engine_inner = engine.inner_mut();
         let target = engine.target();
@@ -222,6 +224,7 @@ impl StaticlibArtifact {
             &function_body_inputs,
             &symbol_registry,
             &metadata_binary,
+            cancellation,
         );
 
         let obj_bytes = if let Some(obj_bytes) = maybe_obj_bytes {
@@ -232,6 +235,7 @@ impl StaticlibArtifact {
                 &metadata.compile_info,
                 module_translation.as_ref().unwrap(),
                 function_body_inputs,
+                cancellation,
             )?;
             // there's an ordering issue, but we can update function_body_lengths here.
             /*
@@ -4,7 +4,7 @@ use std::io::Read;
 use std::path::Path;
 use std::sync::{Arc, Mutex};
 #[cfg(feature = "compiler")]
-use wasmer_compiler::Compiler;
+use wasmer_compiler::{CancellationToken, Compiler};
 use wasmer_compiler::{CompileError, Target};
 use wasmer_engine::{Artifact, DeserializeError, Engine, EngineId, Tunables};
 #[cfg(feature = "compiler")]
@@ -132,7 +132,24 @@ impl Engine for StaticlibEngine {
         binary: &[u8],
         tunables: &dyn Tunables,
     ) -> Result<Arc<dyn Artifact>, CompileError> {
-        Ok(Arc::new(StaticlibArtifact::new(&self, binary, tunables)?))
+        self.compile_with_cancellation(binary, tunables, &CancellationToken::new())
+    }
+
+    /// Compile a WebAssembly binary, checking `cancellation` between the
+    /// functions
+    #[cfg(feature = "compiler")]
+    fn compile_with_cancellation(
+        &self,
+        binary: &[u8],
+        tunables: &dyn Tunables,
+        cancellation: &CancellationToken,
+    ) -> Result<Arc<dyn Artifact>, CompileError> {
+        Ok(Arc::new(StaticlibArtifact::new(
+            &self,
+            binary,
+            tunables,
+            cancellation,
+        )?))
     }
 
     /// Compile a WebAssembly binary (it will fail because the `compiler` flag is disabled).
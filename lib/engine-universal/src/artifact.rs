@@ -2,21 +2,29 @@
 //! done as separate steps.
 
 use crate::engine::{UniversalEngine, UniversalEngineInner};
+#[cfg(feature = "gdb-jit")]
+use crate::gdb_jit::{build_elf_image, ElfFunction, GdbJitImageRegistration};
 use crate::link::link_module;
 #[cfg(feature = "compiler")]
 use crate::serialize::SerializableCompilation;
-use crate::serialize::SerializableModule;
+use crate::serialize::{ArtifactHeader, ArtifactProvenance, Compression, SerializableModule};
 use loupe::MemoryUsage;
+use std::convert::TryFrom;
 use std::sync::{Arc, Mutex};
-use wasmer_compiler::{CompileError, Features, Triple};
 #[cfg(feature = "compiler")]
-use wasmer_compiler::{CompileModuleInfo, ModuleEnvironment, ModuleMiddlewareChain};
-use wasmer_engine::{
-    register_frame_info, Artifact, DeserializeError, FunctionExtent, GlobalFrameInfoRegistration,
-    SerializeError,
+use std::time::Instant;
+#[cfg(feature = "compiler")]
+use wasmer_compiler::{
+    CancellationToken, Compilation, CompileModuleInfo, Compiler, ModuleEnvironment,
+    ModuleMiddlewareChain,
 };
+use wasmer_compiler::{CompileError, CompileStats, Features, Triple};
 #[cfg(feature = "compiler")]
-use wasmer_engine::{Engine, Tunables};
+use wasmer_engine::Tunables;
+use wasmer_engine::{
+    register_frame_info, Artifact, DeserializeError, Engine, FunctionExtent,
+    GlobalFrameInfoRegistration, SerializeError,
+};
 use wasmer_types::entity::{BoxedSlice, PrimaryMap};
 use wasmer_types::{
     FunctionIndex, LocalFunctionIndex, MemoryIndex, ModuleInfo, OwnedDataInitializer,
@@ -27,13 +35,29 @@ use wasmer_vm::{
     VMTrampoline,
 };
 
-const SERIALIZED_METADATA_LENGTH_OFFSET: usize = 22;
-const SERIALIZED_METADATA_CONTENT_OFFSET: usize = 32;
+const SERIALIZED_HEADER_OFFSET: usize = 22;
+
+/// Gathers the parts of `compilation` which are serialized.
+#[cfg(feature = "compiler")]
+fn serializable_compilation(compilation: &Compilation) -> SerializableCompilation {
+    SerializableCompilation {
+        function_bodies: compilation.get_function_bodies(),
+        function_relocations: compilation.get_relocations(),
+        function_jt_offsets: compilation.get_jt_offsets(),
+        function_frame_info: compilation.get_frame_info(),
+        function_call_trampolines: compilation.get_function_call_trampolines(),
+        dynamic_function_trampolines: compilation.get_dynamic_function_trampolines(),
+        custom_sections: compilation.get_custom_sections(),
+        custom_section_relocations: compilation.get_custom_section_relocations(),
+        debug: compilation.get_debug(),
+    }
+}
 
 /// A compiled wasm module, ready to be instantiated.
 #[derive(MemoryUsage)]
 pub struct UniversalArtifact {
     serializable: SerializableModule,
+    provenance: ArtifactProvenance,
     finished_functions: BoxedSlice<LocalFunctionIndex, FunctionBodyPtr>,
     #[loupe(skip)]
     finished_function_call_trampolines: BoxedSlice<SignatureIndex, VMTrampoline>,
@@ -42,6 +66,14 @@ pub struct UniversalArtifact {
     func_data_registry: Arc<FuncDataRegistry>,
     frame_info_registration: Mutex<Option<GlobalFrameInfoRegistration>>,
     finished_function_lengths: BoxedSlice<LocalFunctionIndex, usize>,
+    /// The registration of the debug info with debuggers, if it was generated.
+    #[cfg(feature = "gdb-jit")]
+    #[loupe(skip)]
+    debug_registration: Option<GdbJitImageRegistration>,
+    /// The statistics about the compilation, if the artifact was compiled
+    /// rather than deserialized.
+    #[loupe(skip)]
+    compile_stats: Option<CompileStats>,
 }
 
 impl UniversalArtifact {
@@ -58,8 +90,9 @@ impl UniversalArtifact {
         engine: &UniversalEngine,
         data: &[u8],
         tunables: &dyn Tunables,
+        cancellation: &CancellationToken,
     ) -> Result<Self, CompileError> {
-        let environ = ModuleEnvironment::new();
+        let environ = ModuleEnvironment::new().with_limits(tunables.module_limits());
         let mut inner_engine = engine.inner_mut();
         let features = inner_engine.features();
 
@@ -70,6 +103,7 @@ impl UniversalArtifact {
         // We try to apply the middleware first
         let mut module = translation.module;
         let middlewares = compiler.get_middlewares();
+        middlewares.inspect_function_bodies(&translation.function_body_inputs);
         middlewares.apply_on_module_info(&mut module);
 
         let memory_styles: PrimaryMap<MemoryIndex, MemoryStyle> = module
@@ -90,7 +124,16 @@ impl UniversalArtifact {
             table_styles,
         };
 
+        let provenance = ArtifactProvenance::new(compiler.name(), engine.target());
+
+        let wasm_sizes = translation
+            .function_body_inputs
+            .values()
+            .map(|function_body| function_body.data.len())
+            .collect::<PrimaryMap<LocalFunctionIndex, usize>>();
+
         // Compile the Module
+        let start = Instant::now();
         let compilation = compiler.compile_module(
             &engine.target(),
             &compile_info,
@@ -99,9 +142,9 @@ impl UniversalArtifact {
             // `module_translation_state`.
             translation.module_translation_state.as_ref().unwrap(),
             translation.function_body_inputs,
+            cancellation,
         )?;
-        let function_call_trampolines = compilation.get_function_call_trampolines();
-        let dynamic_function_trampolines = compilation.get_dynamic_function_trampolines();
+        let compile_stats = CompileStats::new(&compilation, &wasm_sizes, start.elapsed());
 
         let data_initializers = translation
             .data_initializers
@@ -110,25 +153,68 @@ impl UniversalArtifact {
             .collect::<Vec<_>>()
             .into_boxed_slice();
 
-        let frame_infos = compilation.get_frame_info();
-
-        let serializable_compilation = SerializableCompilation {
-            function_bodies: compilation.get_function_bodies(),
-            function_relocations: compilation.get_relocations(),
-            function_jt_offsets: compilation.get_jt_offsets(),
-            function_frame_info: frame_infos,
-            function_call_trampolines,
-            dynamic_function_trampolines,
-            custom_sections: compilation.get_custom_sections(),
-            custom_section_relocations: compilation.get_custom_section_relocations(),
-            debug: compilation.get_debug(),
-        };
         let serializable = SerializableModule {
-            compilation: serializable_compilation,
+            compilation: serializable_compilation(&compilation),
             compile_info,
             data_initializers,
         };
-        Self::from_parts(&mut inner_engine, serializable)
+        let mut artifact = Self::from_parts(&mut inner_engine, serializable, provenance)?;
+        artifact.compile_stats = Some(compile_stats);
+        Ok(artifact)
+    }
+
+    /// Compile the data buffer this artifact was compiled from again with
+    /// `compiler`, into a `UniversalArtifact` which can replace this one.
+    ///
+    /// The module info, memory styles and table styles of this artifact
+    /// are reused as is, so the functions of both artifacts can be called
+    /// with the `VMContext` of the instances of either one. Unlike
+    /// [`UniversalArtifact::new`], the engine is not locked while the
+    /// functions are compiled.
+    #[cfg(feature = "compiler")]
+    pub(crate) fn recompile(
+        &self,
+        engine: &UniversalEngine,
+        compiler: &dyn Compiler,
+        data: &[u8],
+        cancellation: &CancellationToken,
+    ) -> Result<Self, CompileError> {
+        let translation = ModuleEnvironment::new()
+            .translate(data)
+            .map_err(CompileError::Wasm)?;
+        let compile_info = &self.serializable.compile_info;
+        let compile_info = CompileModuleInfo {
+            features: compile_info.features.clone(),
+            module: compile_info.module.clone(),
+            memory_styles: compile_info.memory_styles.clone(),
+            table_styles: compile_info.table_styles.clone(),
+        };
+        let provenance = ArtifactProvenance::new(compiler.name(), engine.target());
+
+        let wasm_sizes = translation
+            .function_body_inputs
+            .values()
+            .map(|function_body| function_body.data.len())
+            .collect::<PrimaryMap<LocalFunctionIndex, usize>>();
+
+        let start = Instant::now();
+        let compilation = compiler.compile_module(
+            &engine.target(),
+            &compile_info,
+            translation.module_translation_state.as_ref().unwrap(),
+            translation.function_body_inputs,
+            cancellation,
+        )?;
+        let compile_stats = CompileStats::new(&compilation, &wasm_sizes, start.elapsed());
+
+        let serializable = SerializableModule {
+            compilation: serializable_compilation(&compilation),
+            compile_info,
+            data_initializers: self.serializable.data_initializers.clone(),
+        };
+        let mut artifact = Self::from_parts(&mut engine.inner_mut(), serializable, provenance)?;
+        artifact.compile_stats = Some(compile_stats);
+        Ok(artifact)
     }
 
     /// Compile a data buffer into a `UniversalArtifact`, which may then be instantiated.
@@ -148,31 +234,63 @@ impl UniversalArtifact {
         universal: &UniversalEngine,
         bytes: &[u8],
     ) -> Result<Self, DeserializeError> {
+        let (header, stored_metadata) = Self::split(bytes)?;
+        let decompressed = header.decompress(stored_metadata)?;
+        let metadata_slice = decompressed
+            .as_ref()
+            .map_or(stored_metadata, |buffer| buffer.as_slice());
+        let serializable = SerializableModule::deserialize(metadata_slice)?;
+        Self::from_parts(&mut universal.inner_mut(), serializable, header.provenance)
+            .map_err(DeserializeError::Compiler)
+    }
+
+    /// Deserialize a UniversalArtifact, validating it first.
+    ///
+    /// The artifact must have been produced by this version of the engine
+    /// for the target of `universal`, and its contents must match its
+    /// checksum and be well-formed.
+    pub fn deserialize_checked(
+        universal: &UniversalEngine,
+        bytes: &[u8],
+    ) -> Result<Self, DeserializeError> {
+        let (header, stored_metadata) = Self::split(bytes)?;
+        header.provenance.check(universal.target())?;
+        header.check_metadata(stored_metadata)?;
+        let decompressed = header.decompress(stored_metadata)?;
+        let metadata_slice = decompressed
+            .as_ref()
+            .map_or(stored_metadata, |buffer| buffer.as_slice());
+        let serializable = SerializableModule::deserialize_checked(metadata_slice)?;
+        Self::from_parts(&mut universal.inner_mut(), serializable, header.provenance)
+            .map_err(DeserializeError::Compiler)
+    }
+
+    /// Splits a serialized artifact into its header and its stored, possibly
+    /// compressed, `SerializableModule`.
+    fn split(bytes: &[u8]) -> Result<(ArtifactHeader, &[u8]), DeserializeError> {
         if !Self::is_deserializable(bytes) {
             return Err(DeserializeError::Incompatible(
                 "The provided bytes are not wasmer-universal".to_string(),
             ));
         }
 
-        let mut inner_bytes = &bytes[SERIALIZED_METADATA_LENGTH_OFFSET..];
-
-        let metadata_len = leb128::read::unsigned(&mut inner_bytes).map_err(|_e| {
-            DeserializeError::CorruptedBinary("Can't read metadata size".to_string())
-        })?;
-        let metadata_slice: &[u8] = std::slice::from_raw_parts(
-            &bytes[SERIALIZED_METADATA_CONTENT_OFFSET] as *const u8,
-            metadata_len as usize,
-        );
-
-        let serializable = SerializableModule::deserialize(metadata_slice)?;
-        Self::from_parts(&mut universal.inner_mut(), serializable)
-            .map_err(DeserializeError::Compiler)
+        let (header, header_length) =
+            ArtifactHeader::deserialize(&bytes[SERIALIZED_HEADER_OFFSET..])?;
+        let metadata_offset = metadata_offset(SERIALIZED_HEADER_OFFSET + header_length);
+        let metadata_slice = usize::try_from(header.metadata_length)
+            .ok()
+            .and_then(|length| bytes.get(metadata_offset..metadata_offset.checked_add(length)?))
+            .ok_or_else(|| {
+                DeserializeError::CorruptedBinary("truncated artifact metadata".to_string())
+            })?;
+        Ok((header, metadata_slice))
     }
 
     /// Construct a `UniversalArtifact` from component parts.
     pub fn from_parts(
         inner_engine: &mut UniversalEngineInner,
         serializable: SerializableModule,
+        provenance: ArtifactProvenance,
     ) -> Result<Self, CompileError> {
         let (
             finished_functions,
@@ -226,6 +344,43 @@ impl UniversalArtifact {
 
         inner_engine.publish_eh_frame(eh_frame)?;
 
+        #[cfg(feature = "gdb-jit")]
+        let debug_registration = match &serializable.compilation.debug {
+            Some(debug) if !debug.debug_sections.is_empty() => {
+                let module = &serializable.compile_info.module;
+                let functions = finished_functions
+                    .iter()
+                    .map(|(index, extent)| ElfFunction {
+                        name: crate::profiling::function_name(module, index),
+                        address: *extent.ptr as usize,
+                        length: extent.length,
+                    })
+                    .collect::<Vec<_>>();
+                let debug_sections = debug
+                    .debug_sections
+                    .iter()
+                    .map(|debug_section| {
+                        let length = serializable.compilation.custom_sections
+                            [debug_section.section]
+                            .bytes
+                            .len();
+                        let data = unsafe {
+                            std::slice::from_raw_parts(
+                                *custom_sections[debug_section.section],
+                                length,
+                            )
+                        };
+                        (debug_section.name.as_str(), data)
+                    })
+                    .collect::<Vec<_>>();
+                Some(GdbJitImageRegistration::register(build_elf_image(
+                    &functions,
+                    &debug_sections,
+                )))
+            }
+            _ => None,
+        };
+
         let finished_function_lengths = finished_functions
             .values()
             .map(|extent| extent.length)
@@ -245,6 +400,7 @@ impl UniversalArtifact {
 
         Ok(Self {
             serializable,
+            provenance,
             finished_functions,
             finished_function_call_trampolines,
             finished_dynamic_function_trampolines,
@@ -252,6 +408,9 @@ impl UniversalArtifact {
             frame_info_registration: Mutex::new(None),
             finished_function_lengths,
             func_data_registry,
+            #[cfg(feature = "gdb-jit")]
+            debug_registration,
+            compile_stats: None,
         })
     }
 
@@ -335,23 +494,52 @@ impl Artifact for UniversalArtifact {
     fn func_data_registry(&self) -> &FuncDataRegistry {
         &self.func_data_registry
     }
+
+    fn compile_stats(&self) -> Option<&CompileStats> {
+        self.compile_stats.as_ref()
+    }
+
     fn serialize(&self) -> Result<Vec<u8>, SerializeError> {
+        self.serialize_with_compression(Compression::None)
+    }
+
+    fn serialize_compressed(&self) -> Result<Vec<u8>, SerializeError> {
+        self.serialize_with_compression(Compression::Zstd)
+    }
+}
+
+impl UniversalArtifact {
+    /// Serializes the artifact, storing its `SerializableModule` with the
+    /// given compression.
+    pub fn serialize_with_compression(
+        &self,
+        compression: Compression,
+    ) -> Result<Vec<u8>, SerializeError> {
+        let (header, serialized_data) = ArtifactHeader::store(
+            self.provenance.clone(),
+            self.serializable.serialize()?,
+            compression,
+        )?;
+
         // Prepend the header.
         let mut serialized = Self::MAGIC_HEADER.to_vec();
-
-        serialized.resize(SERIALIZED_METADATA_CONTENT_OFFSET, 0);
-        let mut writable_leb = &mut serialized[SERIALIZED_METADATA_LENGTH_OFFSET..];
-        let serialized_data = self.serializable.serialize()?;
-        let length = serialized_data.len();
-        leb128::write::unsigned(&mut writable_leb, length as u64).expect("Should write number");
+        serialized.extend_from_slice(&header.serialize());
+        let header_end = serialized.len();
 
         let offset = pad_and_extend::<SerializableModule>(&mut serialized, &serialized_data);
-        assert_eq!(offset, SERIALIZED_METADATA_CONTENT_OFFSET);
+        assert_eq!(offset, metadata_offset(header_end));
 
         Ok(serialized)
     }
 }
 
+/// The offset of the serialized `SerializableModule` in an artifact whose
+/// header ends at `header_end`.
+fn metadata_offset(header_end: usize) -> usize {
+    let align = std::mem::align_of::<SerializableModule>();
+    (header_end + align - 1) & !(align - 1)
+}
+
 /// It pads the data with the desired alignment
 pub fn pad_and_extend<T>(prev_data: &mut Vec<u8>, data: &[u8]) -> usize {
     let align = std::mem::align_of::<T>();
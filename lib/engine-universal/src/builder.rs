@@ -1,12 +1,15 @@
-use crate::UniversalEngine;
+use crate::{ProfilingStrategy, UniversalEngine};
 use wasmer_compiler::{CompilerConfig, Features, Target};
 
 /// The Universal builder
 pub struct Universal {
     #[allow(dead_code)]
     compiler_config: Option<Box<dyn CompilerConfig>>,
+    #[allow(dead_code)]
+    tier_up_compiler_config: Option<Box<dyn CompilerConfig>>,
     target: Option<Target>,
     features: Option<Features>,
+    profiling: Option<ProfilingStrategy>,
 }
 
 impl Universal {
@@ -17,8 +20,10 @@ impl Universal {
     {
         Self {
             compiler_config: Some(compiler_config.into()),
+            tier_up_compiler_config: None,
             target: None,
             features: None,
+            profiling: None,
         }
     }
 
@@ -26,8 +31,10 @@ impl Universal {
     pub fn headless() -> Self {
         Self {
             compiler_config: None,
+            tier_up_compiler_config: None,
             target: None,
             features: None,
+            profiling: None,
         }
     }
 
@@ -43,24 +50,66 @@ impl Universal {
         self
     }
 
+    /// Report the compiled code to external profilers, such as `perf`
+    pub fn profiling(mut self, strategy: ProfilingStrategy) -> Self {
+        self.profiling = Some(strategy);
+        self
+    }
+
+    /// Recompile the modules with an optimizing compiler in the
+    /// background once they are compiled, and switch them to the
+    /// optimized code when it's ready. See [`TieredArtifact`].
+    ///
+    /// The modules are compiled by the compiler of this builder first, so
+    /// it should be a fast one, such as Singlepass. The middlewares of that
+    /// compiler are also used by the optimizing one, and must not be
+    /// pushed to `compiler_config`.
+    ///
+    /// [`TieredArtifact`]: crate::TieredArtifact
+    pub fn tier_up<T>(mut self, compiler_config: T) -> Self
+    where
+        T: Into<Box<dyn CompilerConfig>>,
+    {
+        self.tier_up_compiler_config = Some(compiler_config.into());
+        self
+    }
+
     /// Build the `UniversalEngine` for this configuration
     #[cfg(feature = "compiler")]
     pub fn engine(self) -> UniversalEngine {
         let target = self.target.unwrap_or_default();
-        if let Some(compiler_config) = self.compiler_config {
+        let engine = if let Some(compiler_config) = self.compiler_config {
             let features = self
                 .features
                 .unwrap_or_else(|| compiler_config.default_features_for_target(&target));
             let compiler = compiler_config.compiler();
-            UniversalEngine::new(compiler, target, features)
+            let tier_up_compiler = self.tier_up_compiler_config.map(|mut tier_up_config| {
+                for middleware in compiler.get_middlewares() {
+                    tier_up_config.push_middleware(middleware.clone());
+                }
+                tier_up_config.compiler()
+            });
+            let engine = UniversalEngine::new(compiler, target, features);
+            if let Some(tier_up_compiler) = tier_up_compiler {
+                engine.inner_mut().set_tier_up_compiler(tier_up_compiler);
+            }
+            engine
         } else {
             UniversalEngine::headless()
+        };
+        if let Some(strategy) = self.profiling {
+            engine.inner_mut().set_profiling_strategy(strategy);
         }
+        engine
     }
 
     /// Build the `UniversalEngine` for this configuration
     #[cfg(not(feature = "compiler"))]
     pub fn engine(self) -> UniversalEngine {
-        UniversalEngine::headless()
+        let engine = UniversalEngine::headless();
+        if let Some(strategy) = self.profiling {
+            engine.inner_mut().set_profiling_strategy(strategy);
+        }
+        engine
     }
 }
@@ -1,10 +1,14 @@
 //! Universal compilation.
 
+use crate::profiling::{self, Profiler, ProfilingStrategy};
+#[cfg(feature = "compiler")]
+use crate::TieredArtifact;
 use crate::{CodeMemory, UniversalArtifact};
 use loupe::MemoryUsage;
 use std::sync::{Arc, Mutex};
+use tracing::warn;
 #[cfg(feature = "compiler")]
-use wasmer_compiler::Compiler;
+use wasmer_compiler::{CancellationToken, Compiler};
 use wasmer_compiler::{
     CompileError, CustomSection, CustomSectionProtection, FunctionBody, SectionIndex, Target,
 };
@@ -34,10 +38,13 @@ impl UniversalEngine {
         Self {
             inner: Arc::new(Mutex::new(UniversalEngineInner {
                 compiler: Some(compiler),
+                tier_up_compiler: None,
                 code_memory: vec![],
                 signatures: SignatureRegistry::new(),
                 func_data: Arc::new(FuncDataRegistry::new()),
                 features,
+                profiler: None,
+                profiled_code: vec![],
             })),
             target: Arc::new(target),
             engine_id: EngineId::default(),
@@ -62,10 +69,14 @@ impl UniversalEngine {
             inner: Arc::new(Mutex::new(UniversalEngineInner {
                 #[cfg(feature = "compiler")]
                 compiler: None,
+                #[cfg(feature = "compiler")]
+                tier_up_compiler: None,
                 code_memory: vec![],
                 signatures: SignatureRegistry::new(),
                 func_data: Arc::new(FuncDataRegistry::new()),
                 features: Features::default(),
+                profiler: None,
+                profiled_code: vec![],
             })),
             target: Arc::new(Target::default()),
             engine_id: EngineId::default(),
@@ -116,7 +127,24 @@ impl Engine for UniversalEngine {
         binary: &[u8],
         tunables: &dyn Tunables,
     ) -> Result<Arc<dyn Artifact>, CompileError> {
-        Ok(Arc::new(UniversalArtifact::new(&self, binary, tunables)?))
+        self.compile_with_cancellation(binary, tunables, &CancellationToken::new())
+    }
+
+    /// Compile a WebAssembly binary, checking `cancellation` between the
+    /// functions
+    #[cfg(feature = "compiler")]
+    fn compile_with_cancellation(
+        &self,
+        binary: &[u8],
+        tunables: &dyn Tunables,
+        cancellation: &CancellationToken,
+    ) -> Result<Arc<dyn Artifact>, CompileError> {
+        let artifact = UniversalArtifact::new(&self, binary, tunables, cancellation)?;
+        let tier_up_compiler = self.inner().tier_up_compiler.clone();
+        match tier_up_compiler {
+            Some(compiler) => Ok(TieredArtifact::new(self, artifact, binary, compiler)),
+            None => Ok(Arc::new(artifact)),
+        }
     }
 
     /// Compile a WebAssembly binary
@@ -137,6 +165,13 @@ impl Engine for UniversalEngine {
         Ok(Arc::new(UniversalArtifact::deserialize(&self, &bytes)?))
     }
 
+    /// Deserializes a WebAssembly module, validating it first
+    fn deserialize_checked(&self, bytes: &[u8]) -> Result<Arc<dyn Artifact>, DeserializeError> {
+        Ok(Arc::new(UniversalArtifact::deserialize_checked(
+            &self, bytes,
+        )?))
+    }
+
     fn id(&self) -> &EngineId {
         &self.engine_id
     }
@@ -152,6 +187,10 @@ pub struct UniversalEngineInner {
     /// The compiler
     #[cfg(feature = "compiler")]
     compiler: Option<Box<dyn Compiler>>,
+    /// The compiler recompiling the modules in the background once they
+    /// are compiled by `compiler`, if tiered compilation is enabled.
+    #[cfg(feature = "compiler")]
+    tier_up_compiler: Option<Arc<Mutex<Box<dyn Compiler>>>>,
     /// The features to compile the Wasm module with
     features: Features,
     /// The code memory is responsible of publishing the compiled
@@ -164,6 +203,12 @@ pub struct UniversalEngineInner {
     /// functions with the same `VMCallerCheckedAnyfunc` will have the same `VMFuncRef`.
     /// It also guarantees that the `VMFuncRef`s stay valid until the engine is dropped.
     func_data: Arc<FuncDataRegistry>,
+    /// The external profiler the published code is reported to, if any.
+    #[loupe(skip)]
+    profiler: Option<Profiler>,
+    /// The code allocated since the last publication, as its name, address
+    /// and length, to be reported to the profiler once published.
+    profiled_code: Vec<(String, usize, usize)>,
 }
 
 impl UniversalEngineInner {
@@ -196,11 +241,33 @@ impl UniversalEngineInner {
         &self.features
     }
 
+    /// Report the code published from now on to an external profiler.
+    ///
+    /// Profiling is best-effort: if the profiler output can't be opened
+    /// or written to, a warning is logged and the code is not reported.
+    pub(crate) fn set_profiling_strategy(&mut self, strategy: ProfilingStrategy) {
+        self.profiler = match Profiler::new(strategy) {
+            Ok(profiler) => Some(profiler),
+            Err(e) => {
+                warn!("can't open the {:?} profiler output: {}", strategy, e);
+                None
+            }
+        };
+    }
+
+    /// Recompile the modules with `compiler` in the background once they
+    /// are compiled, and switch them to the recompiled code when it's
+    /// ready.
+    #[cfg(feature = "compiler")]
+    pub(crate) fn set_tier_up_compiler(&mut self, compiler: Box<dyn Compiler>) {
+        self.tier_up_compiler = Some(Arc::new(Mutex::new(compiler)));
+    }
+
     /// Allocate compiled functions into memory
     #[allow(clippy::type_complexity)]
     pub(crate) fn allocate(
         &mut self,
-        _module: &ModuleInfo,
+        module: &ModuleInfo,
         functions: &PrimaryMap<LocalFunctionIndex, FunctionBody>,
         function_call_trampolines: &PrimaryMap<SignatureIndex, FunctionBody>,
         dynamic_function_trampolines: &PrimaryMap<FunctionIndex, FunctionBody>,
@@ -240,6 +307,27 @@ impl UniversalEngineInner {
                     ))
                 })?;
 
+        if self.profiler.is_some() {
+            let names = functions
+                .keys()
+                .map(|index| profiling::function_name(module, index))
+                .chain(
+                    function_call_trampolines
+                        .keys()
+                        .map(|index| profiling::function_call_trampoline_name(module, index)),
+                )
+                .chain(
+                    dynamic_function_trampolines
+                        .keys()
+                        .map(|index| profiling::dynamic_function_trampoline_name(module, index)),
+                );
+            self.profiled_code.extend(
+                names
+                    .zip(allocated_functions.iter())
+                    .map(|(name, slice)| (name, slice.as_ptr() as usize, slice.len())),
+            );
+        }
+
         let allocated_functions_result = allocated_functions
             .drain(0..functions.len())
             .map(|slice| FunctionExtent {
@@ -292,6 +380,17 @@ impl UniversalEngineInner {
     /// Make memory containing compiled code executable.
     pub(crate) fn publish_compiled_code(&mut self) {
         self.code_memory.last_mut().unwrap().publish();
+
+        if let Some(profiler) = &mut self.profiler {
+            for (name, address, length) in self.profiled_code.drain(..) {
+                let code = unsafe { std::slice::from_raw_parts(address as *const u8, length) };
+                if let Err(e) = profiler.register(&name, code) {
+                    warn!("can't report `{}` to the profiler: {}", name, e);
+                    break;
+                }
+            }
+        }
+        self.profiled_code.clear();
     }
 
     /// Register DWARF-type exception handling information associated with the code.
@@ -0,0 +1,417 @@
+//! Registration of the debug info of compiled code with debuggers, through
+//! the GDB JIT interface.
+//!
+//! The interface is described in the GDB manual, under "JIT Compilation
+//! Interface", and is also implemented by LLDB. Debuggers set a breakpoint
+//! in `__jit_debug_register_code` and read the in-memory object files
+//! linked from `__jit_debug_descriptor` each time it is called.
+//!
+//! The object files we register are minimal ELF images: a `.text` section
+//! spanning the compiled functions, their symbols, and the DWARF sections
+//! generated by the compiler, whose addresses are already relocated.
+
+use std::ptr;
+use std::sync::Mutex;
+
+const JIT_NOACTION: u32 = 0;
+const JIT_REGISTER_FN: u32 = 1;
+const JIT_UNREGISTER_FN: u32 = 2;
+
+#[repr(C)]
+struct JitCodeEntry {
+    next_entry: *mut JitCodeEntry,
+    prev_entry: *mut JitCodeEntry,
+    symfile_addr: *const u8,
+    symfile_size: u64,
+}
+
+#[repr(C)]
+struct JitDescriptor {
+    version: u32,
+    action_flag: u32,
+    relevant_entry: *mut JitCodeEntry,
+    first_entry: *mut JitCodeEntry,
+}
+
+#[no_mangle]
+#[allow(non_upper_case_globals)]
+static mut __jit_debug_descriptor: JitDescriptor = JitDescriptor {
+    version: 1,
+    action_flag: JIT_NOACTION,
+    relevant_entry: ptr::null_mut(),
+    first_entry: ptr::null_mut(),
+};
+
+/// Debuggers set a breakpoint in this function, so it must not be inlined
+/// or optimized away.
+#[no_mangle]
+#[inline(never)]
+extern "C" fn __jit_debug_register_code() {
+    std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::SeqCst);
+}
+
+lazy_static::lazy_static! {
+    /// Serializes the updates of `__jit_debug_descriptor`.
+    static ref GDB_REGISTRATION: Mutex<()> = Mutex::new(());
+}
+
+/// An object file registered with the GDB JIT interface, unregistered when
+/// dropped.
+pub(crate) struct GdbJitImageRegistration {
+    entry: Box<JitCodeEntry>,
+    /// The object file the entry points to.
+    _image: Box<[u8]>,
+}
+
+impl GdbJitImageRegistration {
+    /// Registers the object file `image`.
+    pub(crate) fn register(image: Vec<u8>) -> Self {
+        let image = image.into_boxed_slice();
+        let mut entry = Box::new(JitCodeEntry {
+            next_entry: ptr::null_mut(),
+            prev_entry: ptr::null_mut(),
+            symfile_addr: image.as_ptr(),
+            symfile_size: image.len() as u64,
+        });
+
+        let _guard = GDB_REGISTRATION.lock().unwrap();
+        unsafe {
+            let entry: *mut JitCodeEntry = &mut *entry;
+            (*entry).next_entry = __jit_debug_descriptor.first_entry;
+            if !(*entry).next_entry.is_null() {
+                (*(*entry).next_entry).prev_entry = entry;
+            }
+            __jit_debug_descriptor.first_entry = entry;
+            __jit_debug_descriptor.relevant_entry = entry;
+            __jit_debug_descriptor.action_flag = JIT_REGISTER_FN;
+            __jit_debug_register_code();
+            __jit_debug_descriptor.action_flag = JIT_NOACTION;
+            __jit_debug_descriptor.relevant_entry = ptr::null_mut();
+        }
+
+        Self {
+            entry,
+            _image: image,
+        }
+    }
+}
+
+impl Drop for GdbJitImageRegistration {
+    fn drop(&mut self) {
+        let _guard = GDB_REGISTRATION.lock().unwrap();
+        unsafe {
+            let entry: *mut JitCodeEntry = &mut *self.entry;
+            if (*entry).prev_entry.is_null() {
+                __jit_debug_descriptor.first_entry = (*entry).next_entry;
+            } else {
+                (*(*entry).prev_entry).next_entry = (*entry).next_entry;
+            }
+            if !(*entry).next_entry.is_null() {
+                (*(*entry).next_entry).prev_entry = (*entry).prev_entry;
+            }
+            __jit_debug_descriptor.relevant_entry = entry;
+            __jit_debug_descriptor.action_flag = JIT_UNREGISTER_FN;
+            __jit_debug_register_code();
+            __jit_debug_descriptor.action_flag = JIT_NOACTION;
+            __jit_debug_descriptor.relevant_entry = ptr::null_mut();
+        }
+    }
+}
+
+// The entry is only accessed under `GDB_REGISTRATION`, and by debuggers
+// while the process is stopped.
+unsafe impl Send for GdbJitImageRegistration {}
+unsafe impl Sync for GdbJitImageRegistration {}
+
+#[cfg(target_arch = "x86_64")]
+const ELF_MACHINE: u16 = 62; // EM_X86_64
+#[cfg(target_arch = "aarch64")]
+const ELF_MACHINE: u16 = 183; // EM_AARCH64
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const ELF_MACHINE: u16 = 0; // EM_NONE
+
+#[cfg(target_endian = "little")]
+const ELF_DATA: u8 = 1; // ELFDATA2LSB
+#[cfg(target_endian = "big")]
+const ELF_DATA: u8 = 2; // ELFDATA2MSB
+
+const ELF_HEADER_SIZE: usize = 64;
+const PROGRAM_HEADER_SIZE: usize = 56;
+const SECTION_HEADER_SIZE: usize = 64;
+const SYMBOL_SIZE: usize = 24;
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_NOBITS: u32 = 8;
+const SHF_ALLOC: u64 = 0x2;
+const SHF_EXECINSTR: u64 = 0x4;
+
+/// A function of an object file, by name, address and length.
+pub(crate) struct ElfFunction {
+    pub(crate) name: String,
+    pub(crate) address: usize,
+    pub(crate) length: usize,
+}
+
+struct SectionHeader {
+    name: u32,
+    kind: u32,
+    flags: u64,
+    address: u64,
+    offset: u64,
+    size: u64,
+    link: u32,
+    info: u32,
+    align: u64,
+    entry_size: u64,
+}
+
+/// Builds an ELF image describing the `functions`, with the given DWARF
+/// `debug_sections`.
+///
+/// The image is loaded at the address of the functions: its `.text` section
+/// spans them, without holding their code.
+pub(crate) fn build_elf_image(
+    functions: &[ElfFunction],
+    debug_sections: &[(&str, &[u8])],
+) -> Vec<u8> {
+    let text_start = functions.iter().map(|f| f.address).min().unwrap_or(0) as u64;
+    let text_end = functions
+        .iter()
+        .map(|f| f.address + f.length)
+        .max()
+        .unwrap_or(0) as u64;
+
+    let mut shstrtab = vec![0];
+    let mut section_name = |name: &str| {
+        let offset = shstrtab.len() as u32;
+        shstrtab.extend_from_slice(name.as_bytes());
+        shstrtab.push(0);
+        offset
+    };
+    let text_name = section_name(".text");
+    let debug_names = debug_sections
+        .iter()
+        .map(|(name, _)| section_name(name))
+        .collect::<Vec<_>>();
+    let symtab_name = section_name(".symtab");
+    let strtab_name = section_name(".strtab");
+    let shstrtab_name = section_name(".shstrtab");
+
+    let mut strtab = vec![0];
+    let mut symtab = vec![0; SYMBOL_SIZE];
+    for function in functions {
+        let name = strtab.len() as u32;
+        strtab.extend_from_slice(function.name.replace('\0', " ").as_bytes());
+        strtab.push(0);
+        symtab.extend_from_slice(&name.to_ne_bytes());
+        symtab.push(0x12); // STB_GLOBAL, STT_FUNC
+        symtab.push(0); // STV_DEFAULT
+        symtab.extend_from_slice(&1u16.to_ne_bytes()); // .text
+        symtab.extend_from_slice(&(function.address as u64).to_ne_bytes());
+        symtab.extend_from_slice(&(function.length as u64).to_ne_bytes());
+    }
+
+    // The contents of the sections follow the headers
+    let mut contents = vec![];
+    let mut offset = (ELF_HEADER_SIZE + PROGRAM_HEADER_SIZE) as u64;
+    let mut section = |data: &[u8], align: usize| -> u64 {
+        let padding = (align - (offset as usize) % align) % align;
+        contents.extend(std::iter::repeat(0).take(padding));
+        offset += padding as u64;
+        let start = offset;
+        contents.extend_from_slice(data);
+        offset += data.len() as u64;
+        start
+    };
+    let debug_offsets = debug_sections
+        .iter()
+        .map(|(_, data)| section(*data, 1))
+        .collect::<Vec<_>>();
+    let symtab_offset = section(&symtab[..], 8);
+    let strtab_offset = section(&strtab[..], 1);
+    let shstrtab_offset = section(&shstrtab[..], 1);
+    let section_headers_offset = section(&[][..], 8);
+
+    let symtab_index = 2 + debug_sections.len() as u32;
+    let mut headers = vec![
+        SectionHeader {
+            name: 0,
+            kind: 0,
+            flags: 0,
+            address: 0,
+            offset: 0,
+            size: 0,
+            link: 0,
+            info: 0,
+            align: 0,
+            entry_size: 0,
+        },
+        SectionHeader {
+            name: text_name,
+            kind: SHT_NOBITS,
+            flags: SHF_ALLOC | SHF_EXECINSTR,
+            address: text_start,
+            offset: ELF_HEADER_SIZE as u64,
+            size: text_end - text_start,
+            link: 0,
+            info: 0,
+            align: 16,
+            entry_size: 0,
+        },
+    ];
+    for (((_, data), name), offset) in debug_sections.iter().zip(debug_names).zip(debug_offsets) {
+        headers.push(SectionHeader {
+            name,
+            kind: SHT_PROGBITS,
+            flags: 0,
+            address: 0,
+            offset,
+            size: data.len() as u64,
+            link: 0,
+            info: 0,
+            align: 1,
+            entry_size: 0,
+        });
+    }
+    headers.push(SectionHeader {
+        name: symtab_name,
+        kind: SHT_SYMTAB,
+        flags: 0,
+        address: 0,
+        offset: symtab_offset,
+        size: symtab.len() as u64,
+        link: symtab_index + 1,
+        // the index of the first global symbol
+        info: 1,
+        align: 8,
+        entry_size: SYMBOL_SIZE as u64,
+    });
+    headers.push(SectionHeader {
+        name: strtab_name,
+        kind: SHT_STRTAB,
+        flags: 0,
+        address: 0,
+        offset: strtab_offset,
+        size: strtab.len() as u64,
+        link: 0,
+        info: 0,
+        align: 1,
+        entry_size: 0,
+    });
+    headers.push(SectionHeader {
+        name: shstrtab_name,
+        kind: SHT_STRTAB,
+        flags: 0,
+        address: 0,
+        offset: shstrtab_offset,
+        size: shstrtab.len() as u64,
+        link: 0,
+        info: 0,
+        align: 1,
+        entry_size: 0,
+    });
+
+    let mut image = Vec::with_capacity(
+        ELF_HEADER_SIZE
+            + PROGRAM_HEADER_SIZE
+            + contents.len()
+            + headers.len() * SECTION_HEADER_SIZE,
+    );
+    // ELF header
+    image.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, ELF_DATA, 1, 0]);
+    image.extend_from_slice(&[0; 8]);
+    image.extend_from_slice(&3u16.to_ne_bytes()); // ET_DYN
+    image.extend_from_slice(&ELF_MACHINE.to_ne_bytes());
+    image.extend_from_slice(&1u32.to_ne_bytes());
+    image.extend_from_slice(&0u64.to_ne_bytes());
+    image.extend_from_slice(&(ELF_HEADER_SIZE as u64).to_ne_bytes());
+    image.extend_from_slice(&section_headers_offset.to_ne_bytes());
+    image.extend_from_slice(&0u32.to_ne_bytes());
+    image.extend_from_slice(&(ELF_HEADER_SIZE as u16).to_ne_bytes());
+    image.extend_from_slice(&(PROGRAM_HEADER_SIZE as u16).to_ne_bytes());
+    image.extend_from_slice(&1u16.to_ne_bytes());
+    image.extend_from_slice(&(SECTION_HEADER_SIZE as u16).to_ne_bytes());
+    image.extend_from_slice(&(headers.len() as u16).to_ne_bytes());
+    image.extend_from_slice(&((headers.len() - 1) as u16).to_ne_bytes());
+    // A loadable segment for `.text`
+    image.extend_from_slice(&1u32.to_ne_bytes()); // PT_LOAD
+    image.extend_from_slice(&5u32.to_ne_bytes()); // PF_R | PF_X
+    image.extend_from_slice(&(ELF_HEADER_SIZE as u64).to_ne_bytes());
+    image.extend_from_slice(&text_start.to_ne_bytes());
+    image.extend_from_slice(&text_start.to_ne_bytes());
+    image.extend_from_slice(&0u64.to_ne_bytes());
+    image.extend_from_slice(&(text_end - text_start).to_ne_bytes());
+    image.extend_from_slice(&16u64.to_ne_bytes());
+
+    image.extend_from_slice(&contents);
+
+    for header in headers {
+        image.extend_from_slice(&header.name.to_ne_bytes());
+        image.extend_from_slice(&header.kind.to_ne_bytes());
+        image.extend_from_slice(&header.flags.to_ne_bytes());
+        image.extend_from_slice(&header.address.to_ne_bytes());
+        image.extend_from_slice(&header.offset.to_ne_bytes());
+        image.extend_from_slice(&header.size.to_ne_bytes());
+        image.extend_from_slice(&header.link.to_ne_bytes());
+        image.extend_from_slice(&header.info.to_ne_bytes());
+        image.extend_from_slice(&header.align.to_ne_bytes());
+        image.extend_from_slice(&header.entry_size.to_ne_bytes());
+    }
+
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_u16(image: &[u8], offset: usize) -> u16 {
+        let mut bytes = [0; 2];
+        bytes.copy_from_slice(&image[offset..offset + 2]);
+        u16::from_ne_bytes(bytes)
+    }
+
+    fn read_u64(image: &[u8], offset: usize) -> u64 {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&image[offset..offset + 8]);
+        u64::from_ne_bytes(bytes)
+    }
+
+    #[test]
+    fn test_build_elf_image() {
+        let functions = [
+            ElfFunction {
+                name: "wasm::fib".to_string(),
+                address: 0x1000,
+                length: 0x40,
+            },
+            ElfFunction {
+                name: "wasm::main".to_string(),
+                address: 0x1040,
+                length: 0x20,
+            },
+        ];
+        let debug_line: &[u8] = &[1, 2, 3];
+        let image = build_elf_image(&functions, &[(".debug_line", debug_line)]);
+
+        assert_eq!(&image[..4], b"\x7fELF");
+        let section_headers = read_u64(&image, 0x28) as usize;
+        let section_count = read_u16(&image, 0x3c) as usize;
+        // null, .text, .debug_line, .symtab, .strtab, .shstrtab
+        assert_eq!(section_count, 6);
+        assert_eq!(
+            image.len(),
+            section_headers + section_count * SECTION_HEADER_SIZE
+        );
+
+        let text = section_headers + SECTION_HEADER_SIZE;
+        assert_eq!(read_u64(&image, text + 0x10), 0x1000);
+        assert_eq!(read_u64(&image, text + 0x20), 0x60);
+
+        let debug_line_header = text + SECTION_HEADER_SIZE;
+        let offset = read_u64(&image, debug_line_header + 0x18) as usize;
+        assert_eq!(&image[offset..offset + 3], debug_line);
+    }
+}
@@ -28,8 +28,13 @@ mod artifact;
 mod builder;
 mod code_memory;
 mod engine;
+#[cfg(feature = "gdb-jit")]
+mod gdb_jit;
 mod link;
+mod profiling;
 mod serialize;
+#[cfg(feature = "compiler")]
+mod tiered;
 mod unwind;
 
 pub use crate::artifact::UniversalArtifact;
@@ -37,6 +42,10 @@ pub use crate::builder::Universal;
 pub use crate::code_memory::CodeMemory;
 pub use crate::engine::UniversalEngine;
 pub use crate::link::link_module;
+pub use crate::profiling::ProfilingStrategy;
+pub use crate::serialize::Compression;
+#[cfg(feature = "compiler")]
+pub use crate::tiered::TieredArtifact;
 
 /// Version number of this crate.
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
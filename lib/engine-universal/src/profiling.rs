@@ -0,0 +1,292 @@
+//! Reporting JIT-compiled code to external profilers.
+//!
+//! Code published by the `UniversalEngine` lives in anonymous memory, so
+//! profilers such as Linux `perf` can't symbolize it on their own. When a
+//! [`ProfilingStrategy`] is set, the engine describes every function and
+//! trampoline it publishes in one of the formats `perf` understands:
+//!
+//! * a perf map, `/tmp/perf-<pid>.map`, which `perf report` reads
+//!   directly;
+//! * a jitdump file, `/tmp/jit-<pid>.dump`, which also carries the machine
+//!   code and must be merged into the recording with
+//!   `perf inject --jit` (record with `perf record -k mono`). Opening it
+//!   truncates it, so it's opened once and shared by all the engines of the
+//!   process, which keeps it open until it exits.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+#[cfg(target_os = "linux")]
+use std::sync::Mutex;
+use wasmer_types::entity::EntityRef;
+use wasmer_types::{FunctionIndex, LocalFunctionIndex, ModuleInfo, SignatureIndex};
+
+/// How to report compiled code to external profilers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfilingStrategy {
+    /// Append `<start> <size> <name>` entries to `/tmp/perf-<pid>.map`.
+    PerfMap,
+    /// Write a jitdump file to `/tmp/jit-<pid>.dump`.
+    ///
+    /// Only supported on Linux.
+    JitDump,
+}
+
+/// An opened profiling output, receiving the code published by the engine.
+pub(crate) struct Profiler {
+    output: ProfilerOutput,
+}
+
+enum ProfilerOutput {
+    PerfMap(File),
+    /// Writes to [`JIT_DUMP`].
+    #[cfg(target_os = "linux")]
+    JitDump,
+}
+
+#[cfg(target_os = "linux")]
+lazy_static::lazy_static! {
+    /// The jitdump file of the process, once an engine opened it.
+    static ref JIT_DUMP: Mutex<Option<jitdump::JitDumpFile>> = Mutex::new(None);
+}
+
+impl Profiler {
+    /// Opens the output of the given strategy for the current process.
+    pub(crate) fn new(strategy: ProfilingStrategy) -> io::Result<Self> {
+        let pid = std::process::id();
+        let output = match strategy {
+            ProfilingStrategy::PerfMap => ProfilerOutput::PerfMap(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(format!("/tmp/perf-{}.map", pid))?,
+            ),
+            #[cfg(target_os = "linux")]
+            ProfilingStrategy::JitDump => {
+                let mut jit_dump = JIT_DUMP.lock().unwrap();
+                if jit_dump.is_none() {
+                    *jit_dump = Some(jitdump::JitDumpFile::new(format!("/tmp/jit-{}.dump", pid))?);
+                }
+                ProfilerOutput::JitDump
+            }
+            #[cfg(not(target_os = "linux"))]
+            ProfilingStrategy::JitDump => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "jitdump profiling is only supported on Linux",
+                ))
+            }
+        };
+        Ok(Self { output })
+    }
+
+    /// Reports the published `code` under `name`.
+    pub(crate) fn register(&mut self, name: &str, code: &[u8]) -> io::Result<()> {
+        match &mut self.output {
+            ProfilerOutput::PerfMap(file) => {
+                // one `write` per entry, so that concurrent engines don't
+                // interleave their lines
+                let entry = perf_map_entry(name, code.as_ptr() as usize, code.len());
+                file.write_all(entry.as_bytes())
+            }
+            #[cfg(target_os = "linux")]
+            ProfilerOutput::JitDump => JIT_DUMP
+                .lock()
+                .unwrap()
+                .as_mut()
+                .expect("the jitdump file is opened with the profiler")
+                .code_load(name, code),
+        }
+    }
+}
+
+fn perf_map_entry(name: &str, address: usize, size: usize) -> String {
+    // names can't contain newlines, as they delimit entries
+    format!("{:x} {:x} {}\n", address, size, name.replace('\n', " "))
+}
+
+/// The name to report for a local function: its name from the name
+/// section if any, or its index otherwise.
+pub(crate) fn function_name(module: &ModuleInfo, local_index: LocalFunctionIndex) -> String {
+    let index = module.func_index(local_index);
+    let function = match module.function_names.get(&index) {
+        Some(name) => name.clone(),
+        None => format!("function[{}]", index.index()),
+    };
+    match &module.name {
+        Some(module_name) => format!("wasm[{}]::{}", module_name, function),
+        None => format!("wasm::{}", function),
+    }
+}
+
+/// The name to report for the trampoline calling functions of `signature`.
+pub(crate) fn function_call_trampoline_name(
+    module: &ModuleInfo,
+    signature: SignatureIndex,
+) -> String {
+    format!(
+        "wasm::trampoline[{}]",
+        module.signatures[signature].to_string().replace(' ', "")
+    )
+}
+
+/// The name to report for the trampoline of the imported function `index`.
+pub(crate) fn dynamic_function_trampoline_name(
+    module: &ModuleInfo,
+    index: FunctionIndex,
+) -> String {
+    let function = match module.function_names.get(&index) {
+        Some(name) => name.clone(),
+        None => format!("function[{}]", index.index()),
+    };
+    format!("wasm::dynamic_trampoline[{}]", function)
+}
+
+#[cfg(target_os = "linux")]
+mod jitdump {
+    //! The jitdump format, as specified in the Linux sources under
+    //! `tools/perf/Documentation/jitdump-specification.txt`.
+
+    use std::fs::{File, OpenOptions};
+    use std::io::{self, Write};
+    use std::os::unix::io::AsRawFd;
+    use std::path::Path;
+    use std::ptr;
+
+    const MAGIC: u32 = 0x4A69_5444;
+    const VERSION: u32 = 1;
+    const HEADER_SIZE: u32 = 40;
+    const JIT_CODE_LOAD: u32 = 0;
+
+    #[cfg(target_arch = "x86_64")]
+    const ELF_MACHINE: u32 = 62; // EM_X86_64
+    #[cfg(target_arch = "aarch64")]
+    const ELF_MACHINE: u32 = 183; // EM_AARCH64
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    const ELF_MACHINE: u32 = 0; // EM_NONE
+
+    /// A jitdump file, never closed: the code it describes can be
+    /// sampled until the process exits.
+    pub(super) struct JitDumpFile {
+        file: File,
+        pid: u32,
+        code_index: u64,
+    }
+
+    impl JitDumpFile {
+        pub(super) fn new(path: impl AsRef<Path>) -> io::Result<Self> {
+            let mut file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(path)?;
+
+            // `perf` only picks up a jitdump file that was mapped
+            // executable by the process that writes it. Like the file, the
+            // mapping is kept until the process exits.
+            let marker_size = region::page::size();
+            let marker = unsafe {
+                libc::mmap(
+                    ptr::null_mut(),
+                    marker_size,
+                    libc::PROT_READ | libc::PROT_EXEC,
+                    libc::MAP_PRIVATE,
+                    file.as_raw_fd(),
+                    0,
+                )
+            };
+            if marker == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+
+            let pid = std::process::id();
+            let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+            header.extend_from_slice(&MAGIC.to_ne_bytes());
+            header.extend_from_slice(&VERSION.to_ne_bytes());
+            header.extend_from_slice(&HEADER_SIZE.to_ne_bytes());
+            header.extend_from_slice(&ELF_MACHINE.to_ne_bytes());
+            header.extend_from_slice(&0u32.to_ne_bytes());
+            header.extend_from_slice(&pid.to_ne_bytes());
+            header.extend_from_slice(&timestamp().to_ne_bytes());
+            header.extend_from_slice(&0u64.to_ne_bytes());
+            file.write_all(&header)?;
+
+            Ok(Self {
+                file,
+                pid,
+                code_index: 0,
+            })
+        }
+
+        /// Writes a `JIT_CODE_LOAD` record for `code`.
+        pub(super) fn code_load(&mut self, name: &str, code: &[u8]) -> io::Result<()> {
+            let address = code.as_ptr() as u64;
+            let name = name.replace('\0', " ");
+            let total_size = 16 + 40 + name.len() + 1 + code.len();
+
+            let mut record = Vec::with_capacity(total_size);
+            record.extend_from_slice(&JIT_CODE_LOAD.to_ne_bytes());
+            record.extend_from_slice(&(total_size as u32).to_ne_bytes());
+            record.extend_from_slice(&timestamp().to_ne_bytes());
+            record.extend_from_slice(&self.pid.to_ne_bytes());
+            record.extend_from_slice(&thread_id().to_ne_bytes());
+            record.extend_from_slice(&address.to_ne_bytes());
+            record.extend_from_slice(&address.to_ne_bytes());
+            record.extend_from_slice(&(code.len() as u64).to_ne_bytes());
+            record.extend_from_slice(&self.code_index.to_ne_bytes());
+            record.extend_from_slice(name.as_bytes());
+            record.push(0);
+            record.extend_from_slice(code);
+            self.file.write_all(&record)?;
+
+            self.code_index += 1;
+            Ok(())
+        }
+    }
+
+    /// `perf record -k mono` timestamps samples with `CLOCK_MONOTONIC`.
+    fn timestamp() -> u64 {
+        let mut ts = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        unsafe {
+            libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts);
+        }
+        ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+    }
+
+    fn thread_id() -> u32 {
+        unsafe { libc::syscall(libc::SYS_gettid) as u32 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_perf_map_entry() {
+        assert_eq!(
+            perf_map_entry("wasm::function[3]", 0x7f00_1000, 0x40),
+            "7f001000 40 wasm::function[3]\n"
+        );
+        assert_eq!(perf_map_entry("a\nb", 0x10, 0x2), "10 2 a b\n");
+    }
+
+    #[test]
+    fn test_function_name() {
+        let mut module = ModuleInfo::new();
+        let first = module.functions.push(SignatureIndex::new(0));
+        let second = module.functions.push(SignatureIndex::new(0));
+        module.function_names.insert(first, "fib".to_string());
+
+        let first = module.local_func_index(first).unwrap();
+        let second = module.local_func_index(second).unwrap();
+        assert_eq!(function_name(&module, first), "wasm::fib");
+        assert_eq!(function_name(&module, second), "wasm::function[1]");
+
+        module.name = Some("math".to_string());
+        assert_eq!(function_name(&module, first), "wasm[math]::fib");
+    }
+}
@@ -1,14 +1,17 @@
+use bytecheck::CheckBytes;
+use enumset::EnumSet;
 use loupe::MemoryUsage;
 use rkyv::{
-    archived_value,
+    archived_value, check_archived_value,
     de::{adapters::SharedDeserializerAdapter, deserializers::AllocDeserializer},
     ser::adapters::SharedSerializerAdapter,
     ser::{serializers::WriteSerializer, Serializer as RkyvSerializer},
     Archive, Deserialize as RkyvDeserialize, Serialize as RkyvSerialize,
 };
+use std::convert::{TryFrom, TryInto};
 use wasmer_compiler::{
-    CompileModuleInfo, CompiledFunctionFrameInfo, CustomSection, Dwarf, FunctionBody,
-    JumpTableOffsets, Relocation, SectionIndex,
+    CompileModuleInfo, CompiledFunctionFrameInfo, CpuFeature, CustomSection, Dwarf, FunctionBody,
+    JumpTableOffsets, Relocation, SectionIndex, Target,
 };
 use wasmer_engine::{DeserializeError, SerializeError};
 use wasmer_types::entity::PrimaryMap;
@@ -16,6 +19,7 @@ use wasmer_types::{FunctionIndex, LocalFunctionIndex, OwnedDataInitializer, Sign
 
 /// The compilation related data for a serialized modules
 #[derive(MemoryUsage, Archive, RkyvDeserialize, RkyvSerialize)]
+#[archive(derive(CheckBytes))]
 pub struct SerializableCompilation {
     pub function_bodies: PrimaryMap<LocalFunctionIndex, FunctionBody>,
     pub function_relocations: PrimaryMap<LocalFunctionIndex, Vec<Relocation>>,
@@ -32,6 +36,7 @@ pub struct SerializableCompilation {
 /// Serializable struct that is able to serialize from and to
 /// a `UniversalArtifactInfo`.
 #[derive(MemoryUsage, Archive, RkyvDeserialize, RkyvSerialize)]
+#[archive(derive(CheckBytes))]
 pub struct SerializableModule {
     pub compilation: SerializableCompilation,
     pub compile_info: CompileModuleInfo,
@@ -63,15 +68,33 @@ impl SerializableModule {
     /// # Safety
     ///
     /// This method is unsafe since it deserializes data directly
-    /// from memory.
-    /// Right now we are not doing any extra work for validation, but
-    /// `rkyv` has an option to do bytecheck on the serialized data before
-    /// serializing (via `rkyv::check_archived_value`).
+    /// from memory, without validating it.
+    /// Use `SerializableModule::deserialize_checked` for data that may
+    /// be malformed.
     pub unsafe fn deserialize(metadata_slice: &[u8]) -> Result<Self, DeserializeError> {
         let archived = Self::archive_from_slice(metadata_slice)?;
         Self::deserialize_from_archive(archived)
     }
 
+    /// Deserialize a Module from a slice, validating the archived data
+    /// first.
+    /// The slice has the same format as for `SerializableModule::deserialize`.
+    pub fn deserialize_checked(metadata_slice: &[u8]) -> Result<Self, DeserializeError> {
+        if metadata_slice.len() < 8 {
+            return Err(DeserializeError::Incompatible(
+                "invalid serialized data".into(),
+            ));
+        }
+        let (data, pos) = metadata_slice.split_at(metadata_slice.len() - 8);
+        let pos = u64::from_le_bytes(pos.try_into().unwrap());
+        // The validation rejects misaligned archives, and the slice may
+        // start anywhere in the serialized artifact.
+        let aligned = AlignedBuffer::new(data);
+        let archived = check_archived_value::<SerializableModule>(aligned.as_slice(), pos as usize)
+            .map_err(|e| DeserializeError::CorruptedBinary(format!("{:?}", e)))?;
+        Self::deserialize_from_archive(archived)
+    }
+
     /// # Safety
     ///
     /// This method is unsafe.
@@ -101,3 +124,336 @@ impl SerializableModule {
             .map_err(|e| DeserializeError::CorruptedBinary(format!("{:?}", e)))
     }
 }
+
+/// A copy of some bytes, aligned for any archived value.
+pub(crate) struct AlignedBuffer {
+    words: Vec<u128>,
+    len: usize,
+}
+
+impl AlignedBuffer {
+    fn zeroed(len: usize) -> Self {
+        let word_size = std::mem::size_of::<u128>();
+        Self {
+            words: vec![0u128; (len + word_size - 1) / word_size],
+            len,
+        }
+    }
+
+    fn new(bytes: &[u8]) -> Self {
+        let word_size = std::mem::size_of::<u128>();
+        let mut words = vec![0u128; (bytes.len() + word_size - 1) / word_size];
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                bytes.as_ptr(),
+                words.as_mut_ptr() as *mut u8,
+                bytes.len(),
+            );
+        }
+        Self {
+            words,
+            len: bytes.len(),
+        }
+    }
+
+    pub(crate) fn as_slice(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.words.as_ptr() as *const u8, self.len) }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.words.as_mut_ptr() as *mut u8, self.len) }
+    }
+}
+
+/// How much larger than its compressed form the serialized
+/// `SerializableModule` can be: a zstd block decompresses to at most 128KiB
+/// and takes at least 4 bytes.
+const MAX_COMPRESSION_RATIO: usize = 32 * 1024;
+
+/// The version of the layout of serialized artifacts, to be bumped on any
+/// change to `ArtifactHeader` or `SerializableModule`.
+pub const ARTIFACT_FORMAT_VERSION: u32 = 3;
+
+/// How the serialized `SerializableModule` is stored in an artifact.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// The serialized `SerializableModule` is stored as is.
+    None,
+    /// The serialized `SerializableModule` is compressed with zstd.
+    Zstd,
+}
+
+impl Compression {
+    fn to_u8(self) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::Zstd => 1,
+        }
+    }
+
+    fn from_u8(value: u8) -> Result<Self, DeserializeError> {
+        match value {
+            0 => Ok(Self::None),
+            1 => Ok(Self::Zstd),
+            _ => Err(DeserializeError::Incompatible(format!(
+                "unknown artifact compression {}",
+                value
+            ))),
+        }
+    }
+}
+
+/// What produced a serialized artifact.
+#[derive(Debug, Clone, PartialEq, Eq, MemoryUsage)]
+pub struct ArtifactProvenance {
+    /// The version of the engine that produced the artifact.
+    pub engine_version: String,
+    /// The name of the compiler that compiled the artifact.
+    pub compiler: String,
+    /// The target triple the artifact was compiled for.
+    pub triple: String,
+    /// The CPU features the artifact was compiled for.
+    pub cpu_features: u64,
+}
+
+impl ArtifactProvenance {
+    /// The provenance of an artifact compiled by `compiler` for `target`
+    /// with this engine.
+    pub fn new(compiler: &str, target: &Target) -> Self {
+        Self {
+            engine_version: format!("wasmer-engine-universal {}", env!("CARGO_PKG_VERSION")),
+            compiler: compiler.to_string(),
+            triple: target.triple().to_string(),
+            cpu_features: target.cpu_features().as_u64(),
+        }
+    }
+
+    /// Checks that an engine for `target` can run the artifact.
+    pub fn check(&self, target: &Target) -> Result<(), DeserializeError> {
+        let expected = Self::new(&self.compiler, target);
+        if self.engine_version != expected.engine_version {
+            return Err(DeserializeError::EngineMismatch {
+                expected: expected.engine_version,
+                found: self.engine_version.clone(),
+            });
+        }
+        if self.triple != expected.triple {
+            return Err(DeserializeError::TargetMismatch {
+                expected: expected.triple,
+                found: self.triple.clone(),
+            });
+        }
+        let missing = self.cpu_features & !expected.cpu_features;
+        if missing != 0 {
+            let missing = EnumSet::<CpuFeature>::from_u64_truncated(missing)
+                .iter()
+                .map(|feature| feature.to_string())
+                .collect::<Vec<_>>();
+            return Err(DeserializeError::TargetMismatch {
+                expected: format!("{} without {}", expected.triple, missing.join(", ")),
+                found: format!("{} with {}", self.triple, missing.join(", ")),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// The header of a serialized artifact, following the magic bytes.
+///
+/// It's laid out as:
+/// format version (4 bytes) + header length (4 bytes) +
+/// engine version + compiler + triple (each a 4 bytes length and UTF-8) +
+/// CPU features (8 bytes) + metadata length (8 bytes) + compression (1 byte) +
+/// uncompressed metadata length (8 bytes) + checksum (4 bytes),
+/// all little-endian.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArtifactHeader {
+    /// What produced the artifact.
+    pub provenance: ArtifactProvenance,
+    /// The length of the stored, possibly compressed, `SerializableModule`.
+    pub metadata_length: u64,
+    /// How the serialized `SerializableModule` is stored.
+    pub compression: Compression,
+    /// The length of the serialized `SerializableModule` once uncompressed.
+    pub uncompressed_length: u64,
+    /// The CRC32 checksum of the other fields of the header, followed by
+    /// the stored `SerializableModule`.
+    pub checksum: u32,
+}
+
+impl ArtifactHeader {
+    /// Stores the serialized `metadata` with the given compression,
+    /// returning the header of the artifact along with the stored metadata.
+    pub fn store(
+        provenance: ArtifactProvenance,
+        metadata: Vec<u8>,
+        compression: Compression,
+    ) -> Result<(Self, Vec<u8>), SerializeError> {
+        let uncompressed_length = metadata.len() as u64;
+        let stored = match compression {
+            Compression::None => metadata,
+            Compression::Zstd => zstd::bulk::compress(&metadata, 0)?,
+        };
+        let mut header = Self {
+            provenance,
+            metadata_length: stored.len() as u64,
+            compression,
+            uncompressed_length,
+            checksum: 0,
+        };
+        header.checksum = header.compute_checksum(&stored);
+        Ok((header, stored))
+    }
+
+    /// Decompresses the stored metadata, or returns `None` if it isn't
+    /// compressed.
+    pub(crate) fn decompress(
+        &self,
+        stored: &[u8],
+    ) -> Result<Option<AlignedBuffer>, DeserializeError> {
+        match self.compression {
+            Compression::None => Ok(None),
+            Compression::Zstd => {
+                // the length isn't covered by the checksum when the artifact
+                // isn't checked, so it's bounded before anything is allocated
+                let length = usize::try_from(self.uncompressed_length)
+                    .ok()
+                    .filter(|length| *length <= stored.len().saturating_mul(MAX_COMPRESSION_RATIO))
+                    .ok_or_else(|| {
+                        DeserializeError::CorruptedBinary("invalid metadata length".to_string())
+                    })?;
+                let mut buffer = AlignedBuffer::zeroed(length);
+                let decompressed = zstd::bulk::decompress_to_buffer(stored, buffer.as_mut_slice())
+                    .map_err(|e| {
+                        DeserializeError::CorruptedBinary(format!(
+                            "invalid compressed metadata: {}",
+                            e
+                        ))
+                    })?;
+                if decompressed != length {
+                    return Err(DeserializeError::CorruptedBinary(
+                        "truncated compressed metadata".to_string(),
+                    ));
+                }
+                Ok(Some(buffer))
+            }
+        }
+    }
+
+    /// Serializes the fields of the header covered by its checksum.
+    fn checksummed_fields(&self) -> Vec<u8> {
+        let mut header = Vec::new();
+        for string in &[
+            &self.provenance.engine_version,
+            &self.provenance.compiler,
+            &self.provenance.triple,
+        ] {
+            header.extend_from_slice(&(string.len() as u32).to_le_bytes());
+            header.extend_from_slice(string.as_bytes());
+        }
+        header.extend_from_slice(&self.provenance.cpu_features.to_le_bytes());
+        header.extend_from_slice(&self.metadata_length.to_le_bytes());
+        header.push(self.compression.to_u8());
+        header.extend_from_slice(&self.uncompressed_length.to_le_bytes());
+        header
+    }
+
+    fn compute_checksum(&self, metadata: &[u8]) -> u32 {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&self.checksummed_fields());
+        hasher.update(metadata);
+        hasher.finalize()
+    }
+
+    /// Serializes the header.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut header = self.checksummed_fields();
+        header.extend_from_slice(&self.checksum.to_le_bytes());
+
+        let mut serialized = Vec::with_capacity(8 + header.len());
+        serialized.extend_from_slice(&ARTIFACT_FORMAT_VERSION.to_le_bytes());
+        serialized.extend_from_slice(&(header.len() as u32).to_le_bytes());
+        serialized.extend_from_slice(&header);
+        serialized
+    }
+
+    /// Deserializes the header at the start of `bytes`, returning it along
+    /// with its serialized length.
+    pub fn deserialize(bytes: &[u8]) -> Result<(Self, usize), DeserializeError> {
+        let mut reader = HeaderReader { bytes };
+        let version = reader.u32()?;
+        if version != ARTIFACT_FORMAT_VERSION {
+            return Err(DeserializeError::EngineMismatch {
+                expected: format!("artifact format version {}", ARTIFACT_FORMAT_VERSION),
+                found: format!("artifact format version {}", version),
+            });
+        }
+        let length = reader.u32()? as usize;
+        if reader.bytes.len() < length {
+            return Err(truncated_header());
+        }
+        let mut reader = HeaderReader {
+            bytes: &reader.bytes[..length],
+        };
+        let provenance = ArtifactProvenance {
+            engine_version: reader.string()?,
+            compiler: reader.string()?,
+            triple: reader.string()?,
+            cpu_features: reader.u64()?,
+        };
+        let header = Self {
+            provenance,
+            metadata_length: reader.u64()?,
+            compression: Compression::from_u8(reader.u8()?)?,
+            uncompressed_length: reader.u64()?,
+            checksum: reader.u32()?,
+        };
+        Ok((header, 8 + length))
+    }
+
+    /// Checks that the header and the stored `metadata` match the checksum
+    /// of the header.
+    pub fn check_metadata(&self, metadata: &[u8]) -> Result<(), DeserializeError> {
+        if self.compute_checksum(metadata) != self.checksum {
+            return Err(DeserializeError::ChecksumMismatch);
+        }
+        Ok(())
+    }
+}
+
+struct HeaderReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> HeaderReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DeserializeError> {
+        if self.bytes.len() < len {
+            return Err(truncated_header());
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, DeserializeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, DeserializeError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, DeserializeError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<String, DeserializeError> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|_| DeserializeError::CorruptedBinary("invalid artifact header".to_string()))
+    }
+}
+
+fn truncated_header() -> DeserializeError {
+    DeserializeError::CorruptedBinary("truncated artifact header".to_string())
+}
@@ -0,0 +1,300 @@
+//! Define `TieredArtifact`, an artifact compiled first by a fast compiler
+//! and then recompiled in the background by an optimizing one.
+
+use crate::{UniversalArtifact, UniversalEngine};
+use loupe::MemoryUsage;
+use std::any::Any;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::{Arc, Condvar, Mutex, PoisonError, Weak};
+use std::thread;
+use wasmer_compiler::{CancellationToken, CompileStats, Compiler, Features};
+use wasmer_engine::{Artifact, InstantiationError, Resolver, SerializeError, SigningKey, Tunables};
+use wasmer_types::entity::{BoxedSlice, EntityRef, PrimaryMap};
+use wasmer_types::{
+    FunctionIndex, LocalFunctionIndex, MemoryIndex, ModuleInfo, OwnedDataInitializer,
+    SignatureIndex, TableIndex,
+};
+use wasmer_vm::{
+    FuncDataRegistry, FunctionBodyPtr, InstanceHandle, MemoryStyle, TableStyle, VMFuncRef,
+    VMSharedSignatureIndex, VMTrampoline,
+};
+
+/// The progress of the recompilation of a `TieredArtifact`.
+#[derive(MemoryUsage)]
+enum TierUp {
+    /// The optimized artifact is being compiled. This holds the function
+    /// references of the local functions of the instances created from
+    /// the baseline artifact meanwhile, to switch them to the optimized
+    /// code once it's ready.
+    Pending(Vec<Box<[VMFuncRef]>>),
+    /// The optimized artifact is ready, and used by the new instances.
+    Done,
+    /// The optimized artifact couldn't be compiled, so the baseline one is
+    /// kept.
+    Failed,
+}
+
+/// A compiled wasm module which is first compiled by a fast, baseline,
+/// compiler such as Singlepass, and then recompiled in the background by
+/// an optimizing compiler such as Cranelift or LLVM.
+///
+/// Until the optimized code is ready, the module runs the baseline code.
+/// Once it is, the instances created afterwards use the optimized code
+/// directly, and the instances created before run it for the calls made
+/// through their tables or `ref.func` from then on. The calls made
+/// directly from the baseline code, and through the exported functions of
+/// the instances created before, keep running the baseline code, even
+/// when the exports are obtained after the switch.
+///
+/// `TieredArtifact`s are created by the `UniversalEngine` when built with
+/// [`Universal::tier_up`](crate::Universal::tier_up).
+#[derive(MemoryUsage)]
+pub struct TieredArtifact {
+    baseline: Arc<UniversalArtifact>,
+    /// The optimized artifact, null until it's ready. It's set once, and
+    /// then lives as long as `self`.
+    #[loupe(skip)]
+    optimized: AtomicPtr<UniversalArtifact>,
+    state: Mutex<TierUp>,
+    #[loupe(skip)]
+    state_changed: Condvar,
+    /// Cancels the recompilation if the artifact is dropped first.
+    #[loupe(skip)]
+    cancellation: CancellationToken,
+}
+
+impl TieredArtifact {
+    /// Wraps `baseline`, compiled from `data`, and starts recompiling
+    /// `data` with `compiler` in a background thread.
+    pub(crate) fn new(
+        engine: &UniversalEngine,
+        baseline: UniversalArtifact,
+        data: &[u8],
+        compiler: Arc<Mutex<Box<dyn Compiler>>>,
+    ) -> Arc<Self> {
+        let artifact = Arc::new(Self {
+            baseline: Arc::new(baseline),
+            optimized: AtomicPtr::new(ptr::null_mut()),
+            state: Mutex::new(TierUp::Pending(Vec::new())),
+            state_changed: Condvar::new(),
+            cancellation: CancellationToken::new(),
+        });
+
+        let weak_artifact = Arc::downgrade(&artifact);
+        let baseline = artifact.baseline.clone();
+        let cancellation = artifact.cancellation.clone();
+        let engine = engine.clone();
+        let data = data.to_vec();
+        let spawned = thread::Builder::new()
+            .name("wasmer-tier-up".to_string())
+            .spawn(move || {
+                let _fail_on_panic = FailOnPanic(weak_artifact.clone());
+                // The compiler is poisoned by a recompilation which panicked,
+                // and can't be trusted to compile anymore.
+                let optimized = match compiler.lock() {
+                    Ok(compiler) => baseline
+                        .recompile(&engine, &**compiler, &data, &cancellation)
+                        .ok(),
+                    Err(_) => None,
+                };
+                if let Some(artifact) = weak_artifact.upgrade() {
+                    artifact.finish(optimized);
+                }
+            });
+        if spawned.is_err() {
+            artifact.finish(None);
+        }
+        artifact
+    }
+
+    /// Returns whether the optimized code is ready.
+    pub fn is_optimized(&self) -> bool {
+        !self.optimized.load(Ordering::Acquire).is_null()
+    }
+
+    /// Blocks until the recompilation completes, and returns whether it
+    /// succeeded.
+    pub fn wait_optimized(&self) -> bool {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        while let TierUp::Pending(_) = *state {
+            state = self
+                .state_changed
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        self.is_optimized()
+    }
+
+    /// Returns the artifact which the new instances are created from.
+    fn current(&self) -> &UniversalArtifact {
+        let optimized = self.optimized.load(Ordering::Acquire);
+        if optimized.is_null() {
+            &self.baseline
+        } else {
+            // SAFETY: `optimized` is set once to a leaked `Box`, which is
+            // only dropped with `self`.
+            unsafe { &*optimized }
+        }
+    }
+
+    /// Switches to the `optimized` artifact, or keeps the baseline one if
+    /// the recompilation failed.
+    fn finish(&self, optimized: Option<UniversalArtifact>) {
+        // This may run while unwinding from a panic poisoning the state,
+        // whose value is still consistent.
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let instances = match std::mem::replace(&mut *state, TierUp::Failed) {
+            TierUp::Pending(instances) => instances,
+            _ => return,
+        };
+        if let Some(optimized) = optimized {
+            optimized.register_frame_info();
+            let registry = self.baseline.func_data_registry();
+            for func_refs in instances.iter() {
+                for (local_index, func_ref) in func_refs.iter().enumerate() {
+                    let func_ptr =
+                        optimized.finished_functions()[LocalFunctionIndex::new(local_index)].0;
+                    // SAFETY: both artifacts are compiled from the same
+                    // module info, memory styles and table styles, so their
+                    // functions have the same signatures and `VMContext`.
+                    unsafe {
+                        registry.set_func_ptr(*func_ref, func_ptr);
+                    }
+                }
+            }
+            self.optimized
+                .store(Box::into_raw(Box::new(optimized)), Ordering::Release);
+            *state = TierUp::Done;
+        }
+        self.state_changed.notify_all();
+    }
+}
+
+/// Fails the recompilation of a `TieredArtifact` when dropped by a panic,
+/// so that it doesn't stay pending forever.
+struct FailOnPanic(Weak<TieredArtifact>);
+
+impl Drop for FailOnPanic {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            if let Some(artifact) = self.0.upgrade() {
+                artifact.finish(None);
+                // `finish` itself may have panicked after leaving the
+                // pending state, before waking up the waiters.
+                artifact.state_changed.notify_all();
+            }
+        }
+    }
+}
+
+impl Drop for TieredArtifact {
+    fn drop(&mut self) {
+        self.cancellation.cancel();
+        let optimized = *self.optimized.get_mut();
+        if !optimized.is_null() {
+            // SAFETY: `optimized` was leaked by `finish`, and nothing borrows
+            // it anymore.
+            drop(unsafe { Box::from_raw(optimized) });
+        }
+    }
+}
+
+impl Artifact for TieredArtifact {
+    fn module(&self) -> Arc<ModuleInfo> {
+        self.baseline.module()
+    }
+
+    fn module_ref(&self) -> &ModuleInfo {
+        self.baseline.module_ref()
+    }
+
+    /// The module info is shared with the recompilation, so it can't be
+    /// modified.
+    fn module_mut(&mut self) -> Option<&mut ModuleInfo> {
+        None
+    }
+
+    fn register_frame_info(&self) {
+        self.current().register_frame_info()
+    }
+
+    fn features(&self) -> &Features {
+        self.baseline.features()
+    }
+
+    fn memory_styles(&self) -> &PrimaryMap<MemoryIndex, MemoryStyle> {
+        self.baseline.memory_styles()
+    }
+
+    fn table_styles(&self) -> &PrimaryMap<TableIndex, TableStyle> {
+        self.baseline.table_styles()
+    }
+
+    fn data_initializers(&self) -> &[OwnedDataInitializer] {
+        self.baseline.data_initializers()
+    }
+
+    fn finished_functions(&self) -> &BoxedSlice<LocalFunctionIndex, FunctionBodyPtr> {
+        self.current().finished_functions()
+    }
+
+    fn finished_function_call_trampolines(&self) -> &BoxedSlice<SignatureIndex, VMTrampoline> {
+        self.current().finished_function_call_trampolines()
+    }
+
+    fn finished_dynamic_function_trampolines(&self) -> &BoxedSlice<FunctionIndex, FunctionBodyPtr> {
+        self.current().finished_dynamic_function_trampolines()
+    }
+
+    fn signatures(&self) -> &BoxedSlice<SignatureIndex, VMSharedSignatureIndex> {
+        self.baseline.signatures()
+    }
+
+    fn func_data_registry(&self) -> &FuncDataRegistry {
+        self.baseline.func_data_registry()
+    }
+
+    fn compile_stats(&self) -> Option<&CompileStats> {
+        self.current().compile_stats()
+    }
+
+    fn serialize(&self) -> Result<Vec<u8>, SerializeError> {
+        self.current().serialize()
+    }
+
+    fn serialize_compressed(&self) -> Result<Vec<u8>, SerializeError> {
+        self.current().serialize_compressed()
+    }
+
+    fn serialize_signed(&self, signing_key: &SigningKey) -> Result<Vec<u8>, SerializeError> {
+        self.current().serialize_signed(signing_key)
+    }
+
+    fn preinstantiate(&self) -> Result<(), InstantiationError> {
+        self.current().preinstantiate()
+    }
+
+    unsafe fn instantiate(
+        &self,
+        tunables: &dyn Tunables,
+        resolver: &dyn Resolver,
+        host_state: Box<dyn Any>,
+    ) -> Result<InstanceHandle, InstantiationError> {
+        // Hold the state while instantiating, so the instance is either
+        // created from the optimized artifact or switched by `finish`.
+        let mut state = self.state.lock().unwrap();
+        let handle = self.current().instantiate(tunables, resolver, host_state)?;
+        if let TierUp::Pending(instances) = &mut *state {
+            let module = handle.module_ref();
+            let func_refs = (0..module.functions.len() - module.num_imported_functions)
+                .map(|local_index| {
+                    let index = module.func_index(LocalFunctionIndex::new(local_index));
+                    handle.func_ref(index).unwrap()
+                })
+                .collect();
+            instances.push(func_refs);
+        }
+        Ok(handle)
+    }
+}
@@ -1,12 +1,13 @@
 use crate::{
-    resolve_imports, InstantiationError, Resolver, RuntimeError, SerializeError, Tunables,
+    resolve_imports, InstantiationError, Resolver, RuntimeError, SerializeError, SigningKey,
+    Tunables,
 };
 use loupe::MemoryUsage;
 use std::any::Any;
 use std::fs;
 use std::path::Path;
 use std::sync::Arc;
-use wasmer_compiler::Features;
+use wasmer_compiler::{CompileStats, Features};
 use wasmer_types::entity::{BoxedSlice, PrimaryMap};
 use wasmer_types::{
     DataInitializer, FunctionIndex, LocalFunctionIndex, MemoryIndex, ModuleInfo,
@@ -70,9 +71,26 @@ pub trait Artifact: Send + Sync + Upcastable + MemoryUsage {
     /// Get the func data registry
     fn func_data_registry(&self) -> &FuncDataRegistry;
 
+    /// Returns the statistics about the compilation of this `Artifact`.
+    ///
+    /// This is `None` if the `Artifact` was deserialized, or if the
+    /// engine doesn't collect them.
+    fn compile_stats(&self) -> Option<&CompileStats> {
+        None
+    }
+
     /// Serializes an artifact into bytes
     fn serialize(&self) -> Result<Vec<u8>, SerializeError>;
 
+    /// Serializes an artifact into compressed bytes, which the engine
+    /// decompresses transparently when deserializing them.
+    ///
+    /// Engines that can't compress their artifacts serialize them
+    /// uncompressed.
+    fn serialize_compressed(&self) -> Result<Vec<u8>, SerializeError> {
+        self.serialize()
+    }
+
     /// Serializes an artifact into a file path
     fn serialize_to_file(&self, path: &Path) -> Result<(), SerializeError> {
         let serialized = self.serialize()?;
@@ -80,6 +98,22 @@ pub trait Artifact: Send + Sync + Upcastable + MemoryUsage {
         Ok(())
     }
 
+    /// Serializes an artifact into bytes, signed with `signing_key`.
+    fn serialize_signed(&self, signing_key: &SigningKey) -> Result<Vec<u8>, SerializeError> {
+        Ok(signing_key.sign(self.serialize()?))
+    }
+
+    /// Serializes an artifact into a file path, signed with `signing_key`.
+    fn serialize_to_file_signed(
+        &self,
+        path: &Path,
+        signing_key: &SigningKey,
+    ) -> Result<(), SerializeError> {
+        let serialized = self.serialize_signed(signing_key)?;
+        fs::write(&path, serialized)?;
+        Ok(())
+    }
+
     /// Do preinstantiation logic that is executed before instantiating
     fn preinstantiate(&self) -> Result<(), InstantiationError> {
         Ok(())
@@ -1,13 +1,13 @@
 //! Engine trait and associated types.
 
 use crate::tunables::Tunables;
-use crate::{Artifact, DeserializeError};
+use crate::{Artifact, DeserializeError, TrustedKeys};
 use loupe::MemoryUsage;
 use memmap2::Mmap;
 use std::path::Path;
 use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
 use std::sync::Arc;
-use wasmer_compiler::{CompileError, Target};
+use wasmer_compiler::{CancellationToken, CompileError, Target};
 use wasmer_types::FunctionType;
 use wasmer_vm::{VMCallerCheckedAnyfunc, VMFuncRef, VMSharedSignatureIndex};
 
@@ -40,6 +40,21 @@ pub trait Engine: MemoryUsage {
         tunables: &dyn Tunables,
     ) -> Result<Arc<dyn Artifact>, CompileError>;
 
+    /// Compile a WebAssembly binary, failing with
+    /// [`CompileError::Cancelled`] once `cancellation` is cancelled.
+    ///
+    /// Engines which can't stop a compilation in progress only check
+    /// `cancellation` before starting it.
+    fn compile_with_cancellation(
+        &self,
+        binary: &[u8],
+        tunables: &dyn Tunables,
+        cancellation: &CancellationToken,
+    ) -> Result<Arc<dyn Artifact>, CompileError> {
+        cancellation.check()?;
+        self.compile(binary, tunables)
+    }
+
     /// Deserializes a WebAssembly module
     ///
     /// # Safety
@@ -61,6 +76,52 @@ pub trait Engine: MemoryUsage {
         self.deserialize(&mmap)
     }
 
+    /// Deserializes a WebAssembly module signed by one of the
+    /// `trusted_keys`.
+    ///
+    /// # Safety
+    ///
+    /// The trusted keys must only sign serialized WebAssembly modules
+    /// produced by this engine.
+    unsafe fn deserialize_signed(
+        &self,
+        bytes: &[u8],
+        trusted_keys: &TrustedKeys,
+    ) -> Result<Arc<dyn Artifact>, DeserializeError> {
+        let artifact = trusted_keys.verify(bytes)?;
+        self.deserialize(artifact)
+    }
+
+    /// Deserializes a WebAssembly module signed by one of the
+    /// `trusted_keys` from a path
+    ///
+    /// # Safety
+    ///
+    /// The trusted keys must only sign serialized WebAssembly modules
+    /// produced by this engine.
+    unsafe fn deserialize_from_file_signed(
+        &self,
+        file_ref: &Path,
+        trusted_keys: &TrustedKeys,
+    ) -> Result<Arc<dyn Artifact>, DeserializeError> {
+        // The file is read rather than mapped, so it can't change between
+        // the verification of the signature and the deserialization.
+        let bytes = std::fs::read(file_ref)?;
+        self.deserialize_signed(&bytes, trusted_keys)
+    }
+
+    /// Deserializes a WebAssembly module, validating the serialized
+    /// content first.
+    ///
+    /// Unlike [`Engine::deserialize`], this rejects content that was
+    /// produced by another engine or for another target, or that was
+    /// corrupted, with a [`DeserializeError`].
+    fn deserialize_checked(&self, _bytes: &[u8]) -> Result<Arc<dyn Artifact>, DeserializeError> {
+        Err(DeserializeError::Incompatible(
+            "this engine doesn't support checked deserialization".to_string(),
+        ))
+    }
+
     /// A unique identifier for this object.
     ///
     /// This exists to allow us to compare two Engines for equality. Otherwise,
@@ -33,6 +33,34 @@ pub enum DeserializeError {
     /// The provided binary is corrupted
     #[error("corrupted binary: {0}")]
     CorruptedBinary(String),
+    /// The binary was produced by another version of the engine
+    #[error("the binary was produced by {found}, but the engine is {expected}")]
+    EngineMismatch {
+        /// The engine deserializing the binary
+        expected: String,
+        /// The engine that produced the binary
+        found: String,
+    },
+    /// The binary was compiled for another target
+    #[error("the binary was compiled for {found}, but the engine targets {expected}")]
+    TargetMismatch {
+        /// The target of the engine deserializing the binary
+        expected: String,
+        /// The target the binary was compiled for
+        found: String,
+    },
+    /// The checksum of the binary doesn't match its contents
+    #[error("the checksum of the binary doesn't match its contents")]
+    ChecksumMismatch,
+    /// The binary isn't signed
+    #[error("the binary isn't signed")]
+    MissingSignature,
+    /// The binary is signed by a key that isn't trusted
+    #[error("the binary is signed by an untrusted key")]
+    UntrustedSignature,
+    /// The signature of the binary doesn't match its contents
+    #[error("the signature of the binary is invalid")]
+    InvalidSignature,
     /// The binary was valid, but we got an error when
     /// trying to allocate the required resources.
     #[error(transparent)]
@@ -25,6 +25,7 @@ mod engine;
 mod error;
 mod export;
 mod resolver;
+mod signature;
 mod trap;
 mod tunables;
 
@@ -38,6 +39,7 @@ pub use crate::resolver::{
     resolve_imports, ChainableNamedResolver, NamedResolver, NamedResolverChain, NullResolver,
     Resolver,
 };
+pub use crate::signature::{InvalidKeyError, SigningKey, TrustedKeys, SIGNATURE_MAGIC};
 pub use crate::trap::*;
 pub use crate::tunables::Tunables;
 
@@ -0,0 +1,137 @@
+//! Ed25519 signatures of serialized artifacts.
+//!
+//! A signed artifact is the serialized artifact followed by a trailer
+//! holding the public key of the signer, the signature of the serialized
+//! artifact, and [`SIGNATURE_MAGIC`]. The engines ignore trailing bytes, so
+//! signed artifacts can still be deserialized without checking the
+//! signature.
+
+use crate::error::DeserializeError;
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signature, Signer};
+use std::convert::TryFrom;
+use thiserror::Error;
+
+/// The bytes ending a signed artifact.
+pub const SIGNATURE_MAGIC: &[u8; 16] = b"\0wasmer-signed\0\0";
+
+const PUBLIC_KEY_LENGTH: usize = ed25519_dalek::PUBLIC_KEY_LENGTH;
+const SIGNATURE_LENGTH: usize = ed25519_dalek::SIGNATURE_LENGTH;
+const TRAILER_LENGTH: usize = PUBLIC_KEY_LENGTH + SIGNATURE_LENGTH + SIGNATURE_MAGIC.len();
+
+/// An invalid ed25519 key.
+#[derive(Error, Debug)]
+#[error("invalid ed25519 key: {0}")]
+pub struct InvalidKeyError(String);
+
+/// A key signing serialized artifacts.
+pub struct SigningKey {
+    keypair: Keypair,
+}
+
+impl SigningKey {
+    /// Creates a signing key from the 32 bytes of an ed25519 secret key.
+    pub fn from_secret_key(bytes: &[u8]) -> Result<Self, InvalidKeyError> {
+        let secret = SecretKey::from_bytes(bytes).map_err(|e| InvalidKeyError(e.to_string()))?;
+        let public = PublicKey::from(&secret);
+        Ok(Self {
+            keypair: Keypair { secret, public },
+        })
+    }
+
+    /// Returns the 32 bytes of the public key matching this signing key.
+    pub fn public_key(&self) -> [u8; PUBLIC_KEY_LENGTH] {
+        self.keypair.public.to_bytes()
+    }
+
+    /// Appends the signature of the serialized `artifact` to it.
+    pub fn sign(&self, mut artifact: Vec<u8>) -> Vec<u8> {
+        let signature = self.keypair.sign(&artifact);
+        artifact.reserve(TRAILER_LENGTH);
+        artifact.extend_from_slice(self.keypair.public.as_bytes());
+        artifact.extend_from_slice(&signature.to_bytes());
+        artifact.extend_from_slice(SIGNATURE_MAGIC);
+        artifact
+    }
+}
+
+/// The public keys whose signatures are trusted.
+#[derive(Debug, Clone, Default)]
+pub struct TrustedKeys {
+    keys: Vec<PublicKey>,
+}
+
+impl TrustedKeys {
+    /// Creates an empty set of trusted keys, trusting no signature.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trusts the signatures of the ed25519 public key given by its 32
+    /// bytes.
+    pub fn add(&mut self, public_key: &[u8]) -> Result<(), InvalidKeyError> {
+        let key = PublicKey::from_bytes(public_key).map_err(|e| InvalidKeyError(e.to_string()))?;
+        if !self.keys.contains(&key) {
+            self.keys.push(key);
+        }
+        Ok(())
+    }
+
+    /// Returns whether no key is trusted.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Checks that `bytes` is an artifact signed by one of the trusted
+    /// keys, and returns the serialized artifact without its signature.
+    pub fn verify<'a>(&self, bytes: &'a [u8]) -> Result<&'a [u8], DeserializeError> {
+        if bytes.len() < TRAILER_LENGTH || !bytes.ends_with(SIGNATURE_MAGIC) {
+            return Err(DeserializeError::MissingSignature);
+        }
+        let (artifact, trailer) = bytes.split_at(bytes.len() - TRAILER_LENGTH);
+        let (public_key, trailer) = trailer.split_at(PUBLIC_KEY_LENGTH);
+        let signature = &trailer[..SIGNATURE_LENGTH];
+
+        let key = self
+            .keys
+            .iter()
+            .find(|key| key.as_bytes()[..] == *public_key)
+            .ok_or(DeserializeError::UntrustedSignature)?;
+        let signature =
+            Signature::try_from(signature).map_err(|_| DeserializeError::InvalidSignature)?;
+        key.verify_strict(artifact, &signature)
+            .map_err(|_| DeserializeError::InvalidSignature)?;
+        Ok(artifact)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET_KEY: [u8; 32] = [7; 32];
+
+    #[test]
+    fn test_sign_and_verify() {
+        let signing_key = SigningKey::from_secret_key(&SECRET_KEY).unwrap();
+        let signed = signing_key.sign(b"artifact".to_vec());
+
+        let mut trusted_keys = TrustedKeys::new();
+        assert!(matches!(
+            trusted_keys.verify(&signed),
+            Err(DeserializeError::UntrustedSignature)
+        ));
+        trusted_keys.add(&signing_key.public_key()).unwrap();
+        assert_eq!(trusted_keys.verify(&signed).unwrap(), b"artifact");
+
+        let mut tampered = signed.clone();
+        tampered[0] ^= 1;
+        assert!(matches!(
+            trusted_keys.verify(&tampered),
+            Err(DeserializeError::InvalidSignature)
+        ));
+        assert!(matches!(
+            trusted_keys.verify(b"artifact"),
+            Err(DeserializeError::MissingSignature)
+        ));
+    }
+}
@@ -0,0 +1,437 @@
+//! Core dumps of trapped WebAssembly instances.
+//!
+//! A [`WasmCoreDump`] is written in the format described by the
+//! WebAssembly tool conventions
+//! (<https://github.com/WebAssembly/tool-conventions/blob/main/Coredump.md>):
+//! a regular Wasm module whose memory, global and data sections hold the
+//! state of the instance, with the trapped stack described by the `core`,
+//! `coremodules`, `coreinstances` and `corestack` custom sections.
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use wasmer_types::{GlobalType, Mutability, Pages, Type, WASM_PAGE_SIZE};
+
+/// A value of a local or of the operand stack in a [`CoreDumpFrame`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CoreDumpValue {
+    /// The value isn't available, e.g. because it was optimized out.
+    Missing,
+    /// A 32-bit integer.
+    I32(i32),
+    /// A 64-bit integer.
+    I64(i64),
+    /// A 32-bit float.
+    F32(f32),
+    /// A 64-bit float.
+    F64(f64),
+}
+
+/// A frame of the stack that led to the trap.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoreDumpFrame {
+    func_index: u32,
+    code_offset: u32,
+    locals: Vec<CoreDumpValue>,
+    stack: Vec<CoreDumpValue>,
+}
+
+impl CoreDumpFrame {
+    /// Creates a frame for the function `func_index`, stopped at
+    /// `code_offset` bytes from the start of its body.
+    pub fn new(func_index: u32, code_offset: u32) -> Self {
+        Self {
+            func_index,
+            code_offset,
+            locals: Vec::new(),
+            stack: Vec::new(),
+        }
+    }
+
+    /// Sets the values of the locals of the frame.
+    pub fn with_locals(mut self, locals: Vec<CoreDumpValue>) -> Self {
+        self.locals = locals;
+        self
+    }
+
+    /// Sets the values of the operand stack of the frame, bottom first.
+    pub fn with_stack(mut self, stack: Vec<CoreDumpValue>) -> Self {
+        self.stack = stack;
+        self
+    }
+
+    /// Returns the index of the function of the frame.
+    pub fn func_index(&self) -> u32 {
+        self.func_index
+    }
+
+    /// Returns the offset of the instruction of the frame, relative to the
+    /// start of the function body.
+    pub fn code_offset(&self) -> u32 {
+        self.code_offset
+    }
+
+    /// Returns the locals of the frame.
+    pub fn locals(&self) -> &[CoreDumpValue] {
+        &self.locals
+    }
+
+    /// Returns the operand stack of the frame.
+    pub fn stack(&self) -> &[CoreDumpValue] {
+        &self.stack
+    }
+}
+
+/// A linear memory of the dumped instance.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoreDumpMemory {
+    data: Vec<u8>,
+    maximum: Option<Pages>,
+}
+
+impl CoreDumpMemory {
+    /// Returns the contents of the memory.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the maximum size of the memory, if any.
+    pub fn maximum(&self) -> Option<Pages> {
+        self.maximum
+    }
+}
+
+/// A global of the dumped instance.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoreDumpGlobal {
+    ty: GlobalType,
+    bits: u128,
+}
+
+impl CoreDumpGlobal {
+    /// Returns the type of the global.
+    pub fn ty(&self) -> &GlobalType {
+        &self.ty
+    }
+
+    /// Returns the raw bits of the value of the global.
+    ///
+    /// References can't be restored from a core dump and are always
+    /// written as `ref.null`.
+    pub fn bits(&self) -> u128 {
+        self.bits
+    }
+}
+
+/// The state of a WebAssembly instance when it trapped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WasmCoreDump {
+    name: String,
+    module_name: String,
+    memories: Vec<CoreDumpMemory>,
+    globals: Vec<CoreDumpGlobal>,
+    frames: Vec<CoreDumpFrame>,
+}
+
+impl WasmCoreDump {
+    /// Creates an empty core dump of an instance of the module
+    /// `module_name`, run by the program `name`.
+    pub fn new(name: impl Into<String>, module_name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            module_name: module_name.into(),
+            memories: Vec::new(),
+            globals: Vec::new(),
+            frames: Vec::new(),
+        }
+    }
+
+    /// Adds the next memory of the instance.
+    pub fn add_memory(&mut self, data: Vec<u8>, maximum: Option<Pages>) {
+        self.memories.push(CoreDumpMemory { data, maximum });
+    }
+
+    /// Adds the next global of the instance, given the raw bits of its value.
+    pub fn add_global(&mut self, ty: GlobalType, bits: u128) {
+        self.globals.push(CoreDumpGlobal { ty, bits });
+    }
+
+    /// Adds the next frame of the stack, innermost first.
+    pub fn add_frame(&mut self, frame: CoreDumpFrame) {
+        self.frames.push(frame);
+    }
+
+    /// Returns the name of the program that ran the instance.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the name of the module of the instance.
+    pub fn module_name(&self) -> &str {
+        &self.module_name
+    }
+
+    /// Returns the memories of the instance.
+    pub fn memories(&self) -> &[CoreDumpMemory] {
+        &self.memories
+    }
+
+    /// Returns the globals of the instance.
+    pub fn globals(&self) -> &[CoreDumpGlobal] {
+        &self.globals
+    }
+
+    /// Returns the frames of the stack, innermost first.
+    pub fn frames(&self) -> &[CoreDumpFrame] {
+        &self.frames
+    }
+
+    /// Serializes the core dump into a Wasm binary.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"\0asm");
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+
+        let mut core = vec![0x00];
+        write_name(&mut core, &self.name);
+        write_custom_section(&mut bytes, "core", &core);
+
+        let mut modules = Vec::new();
+        write_u32(&mut modules, 1);
+        modules.push(0x00);
+        write_name(&mut modules, &self.module_name);
+        write_custom_section(&mut bytes, "coremodules", &modules);
+
+        let mut instances = Vec::new();
+        write_u32(&mut instances, 1);
+        instances.push(0x00);
+        write_u32(&mut instances, 0);
+        write_u32(&mut instances, self.memories.len() as u32);
+        for index in 0..self.memories.len() {
+            write_u32(&mut instances, index as u32);
+        }
+        write_u32(&mut instances, self.globals.len() as u32);
+        for index in 0..self.globals.len() {
+            write_u32(&mut instances, index as u32);
+        }
+        write_custom_section(&mut bytes, "coreinstances", &instances);
+
+        let mut stack = vec![0x00];
+        write_name(&mut stack, "main");
+        write_u32(&mut stack, self.frames.len() as u32);
+        for frame in &self.frames {
+            stack.push(0x00);
+            write_u32(&mut stack, 0);
+            write_u32(&mut stack, frame.func_index);
+            write_u32(&mut stack, frame.code_offset);
+            write_values(&mut stack, &frame.locals);
+            write_values(&mut stack, &frame.stack);
+        }
+        write_custom_section(&mut bytes, "corestack", &stack);
+
+        if !self.memories.is_empty() {
+            let mut section = Vec::new();
+            write_u32(&mut section, self.memories.len() as u32);
+            for memory in &self.memories {
+                let pages = (memory.data.len() / WASM_PAGE_SIZE) as u32;
+                match memory.maximum {
+                    Some(maximum) => {
+                        section.push(0x01);
+                        write_u32(&mut section, pages);
+                        write_u32(&mut section, maximum.0);
+                    }
+                    None => {
+                        section.push(0x00);
+                        write_u32(&mut section, pages);
+                    }
+                }
+            }
+            write_section(&mut bytes, 5, &section);
+        }
+
+        if !self.globals.is_empty() {
+            let mut section = Vec::new();
+            write_u32(&mut section, self.globals.len() as u32);
+            for global in &self.globals {
+                write_global(&mut section, global);
+            }
+            write_section(&mut bytes, 6, &section);
+        }
+
+        let segments = self
+            .memories
+            .iter()
+            .enumerate()
+            .flat_map(|(index, memory)| {
+                data_segments(&memory.data).map(move |(offset, data)| (index, offset, data))
+            })
+            .collect::<Vec<_>>();
+        if !segments.is_empty() {
+            let mut section = Vec::new();
+            write_u32(&mut section, segments.len() as u32);
+            for (index, offset, data) in segments {
+                if index == 0 {
+                    section.push(0x00);
+                } else {
+                    section.push(0x02);
+                    write_u32(&mut section, index as u32);
+                }
+                // i32.const offset; end
+                section.push(0x41);
+                write_i64(&mut section, offset as i32 as i64);
+                section.push(0x0b);
+                write_u32(&mut section, data.len() as u32);
+                section.extend_from_slice(data);
+            }
+            write_section(&mut bytes, 11, &section);
+        }
+
+        bytes
+    }
+
+    /// Writes the serialized core dump to the file at `path`.
+    pub fn serialize_to_file(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut file = File::create(path)?;
+        file.write_all(&self.serialize())
+    }
+}
+
+/// Zero runs shorter than this don't split data segments, as a new segment
+/// costs more than the bytes it skips.
+const MIN_ZERO_RUN: usize = 16;
+
+/// Returns the non-zero parts of `data`, with their offset.
+fn data_segments(data: &[u8]) -> impl Iterator<Item = (usize, &[u8])> {
+    let mut position = 0;
+    std::iter::from_fn(move || {
+        let start = position + data[position..].iter().position(|&byte| byte != 0)?;
+        let mut end = start;
+        let mut zeros = 0;
+        for (offset, &byte) in data[start..].iter().enumerate() {
+            if byte == 0 {
+                zeros += 1;
+                if zeros == MIN_ZERO_RUN {
+                    break;
+                }
+            } else {
+                zeros = 0;
+                end = start + offset + 1;
+            }
+        }
+        position = end;
+        Some((start, &data[start..end]))
+    })
+}
+
+fn write_global(bytes: &mut Vec<u8>, global: &CoreDumpGlobal) {
+    let GlobalType { ty, mutability } = global.ty;
+    let bits = global.bits;
+    bytes.push(match ty {
+        Type::I32 => 0x7f,
+        Type::I64 => 0x7e,
+        Type::F32 => 0x7d,
+        Type::F64 => 0x7c,
+        Type::V128 => 0x7b,
+        Type::FuncRef => 0x70,
+        Type::ExternRef => 0x6f,
+    });
+    bytes.push(match mutability {
+        Mutability::Const => 0x00,
+        Mutability::Var => 0x01,
+    });
+    match ty {
+        Type::I32 => {
+            bytes.push(0x41);
+            write_i64(bytes, bits as u32 as i32 as i64);
+        }
+        Type::I64 => {
+            bytes.push(0x42);
+            write_i64(bytes, bits as u64 as i64);
+        }
+        Type::F32 => {
+            bytes.push(0x43);
+            bytes.extend_from_slice(&(bits as u32).to_le_bytes());
+        }
+        Type::F64 => {
+            bytes.push(0x44);
+            bytes.extend_from_slice(&(bits as u64).to_le_bytes());
+        }
+        Type::V128 => {
+            bytes.extend_from_slice(&[0xfd, 0x0c]);
+            bytes.extend_from_slice(&bits.to_le_bytes());
+        }
+        Type::FuncRef => bytes.extend_from_slice(&[0xd0, 0x70]),
+        Type::ExternRef => bytes.extend_from_slice(&[0xd0, 0x6f]),
+    }
+    bytes.push(0x0b);
+}
+
+fn write_values(bytes: &mut Vec<u8>, values: &[CoreDumpValue]) {
+    write_u32(bytes, values.len() as u32);
+    for value in values {
+        match *value {
+            CoreDumpValue::Missing => bytes.push(0x01),
+            CoreDumpValue::I32(value) => {
+                bytes.push(0x7f);
+                write_i64(bytes, value as i64);
+            }
+            CoreDumpValue::I64(value) => {
+                bytes.push(0x7e);
+                write_i64(bytes, value);
+            }
+            CoreDumpValue::F32(value) => {
+                bytes.push(0x7d);
+                bytes.extend_from_slice(&value.to_bits().to_le_bytes());
+            }
+            CoreDumpValue::F64(value) => {
+                bytes.push(0x7c);
+                bytes.extend_from_slice(&value.to_bits().to_le_bytes());
+            }
+        }
+    }
+}
+
+fn write_section(bytes: &mut Vec<u8>, id: u8, contents: &[u8]) {
+    bytes.push(id);
+    write_u32(bytes, contents.len() as u32);
+    bytes.extend_from_slice(contents);
+}
+
+fn write_custom_section(bytes: &mut Vec<u8>, name: &str, contents: &[u8]) {
+    let mut section = Vec::with_capacity(name.len() + 1 + contents.len());
+    write_name(&mut section, name);
+    section.extend_from_slice(contents);
+    write_section(bytes, 0, &section);
+}
+
+fn write_name(bytes: &mut Vec<u8>, name: &str) {
+    write_u32(bytes, name.len() as u32);
+    bytes.extend_from_slice(name.as_bytes());
+}
+
+/// Writes `value` as an unsigned LEB128.
+fn write_u32(bytes: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+/// Writes `value` as a signed LEB128.
+fn write_i64(bytes: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        if done {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
@@ -1,3 +1,4 @@
+use super::coredump::WasmCoreDump;
 use super::frame_info::{FrameInfo, GlobalFrameInfo, FRAME_INFO};
 use backtrace::Backtrace;
 use std::error::Error;
@@ -10,6 +11,9 @@ use wasmer_vm::{raise_user_trap, Trap, TrapCode};
 #[derive(Clone)]
 pub struct RuntimeError {
     inner: Arc<RuntimeErrorInner>,
+    /// The state of the instance when it trapped, if the store captures
+    /// core dumps.
+    coredump: Option<Arc<WasmCoreDump>>,
 }
 
 /// The source of the `RuntimeError`.
@@ -153,6 +157,7 @@ impl RuntimeError {
                 wasm_trace,
                 native_trace,
             }),
+            coredump: None,
         }
     }
 
@@ -167,8 +172,21 @@ impl RuntimeError {
         &self.inner.wasm_trace
     }
 
+    /// Returns the core dump captured when the trap happened, if the
+    /// store was configured to capture one.
+    pub fn coredump(&self) -> Option<&WasmCoreDump> {
+        self.coredump.as_deref()
+    }
+
+    /// Attaches a core dump to this error.
+    pub fn with_coredump(mut self, coredump: WasmCoreDump) -> Self {
+        self.coredump = Some(Arc::new(coredump));
+        self
+    }
+
     /// Attempts to downcast the `RuntimeError` to a concrete type.
     pub fn downcast<T: Error + 'static>(self) -> Result<T, Self> {
+        let coredump = self.coredump;
         match Arc::try_unwrap(self.inner) {
             // We only try to downcast user errors
             Ok(RuntimeErrorInner {
@@ -177,8 +195,9 @@ impl RuntimeError {
             }) if err.is::<T>() => Ok(*err.downcast::<T>().unwrap()),
             Ok(inner) => Err(Self {
                 inner: Arc::new(inner),
+                coredump,
             }),
-            Err(inner) => Err(Self { inner }),
+            Err(inner) => Err(Self { inner, coredump }),
         }
     }
 
@@ -220,27 +239,47 @@ impl fmt::Display for RuntimeError {
         for frame in self.trace().iter() {
             let name = frame.module_name();
             let func_index = frame.func_index();
-            writeln!(f)?;
-            write!(f, "    at ")?;
-            match frame.function_name() {
-                Some(name) => match rustc_demangle::try_demangle(name) {
-                    Ok(name) => write!(f, "{}", name)?,
-                    Err(_) => write!(f, "{}", name)?,
-                },
-                None => write!(f, "<unnamed>")?,
+            let location = format!("{}[{}]:0x{:x}", name, func_index, frame.module_offset());
+            if frame.symbols().is_empty() {
+                writeln!(f)?;
+                write!(f, "    at ")?;
+                write_function_name(f, frame.function_name())?;
+                write!(f, " ({})", location)?;
+                continue;
+            }
+            // Like the backtraces of native panics, with a line for each
+            // inlined function
+            for symbol in frame.symbols() {
+                writeln!(f)?;
+                write!(f, "    at ")?;
+                write_function_name(f, symbol.name().or_else(|| frame.function_name()))?;
+                write!(f, " ({})", location)?;
+                if let Some(file) = symbol.file() {
+                    writeln!(f)?;
+                    write!(f, "        at {}", file)?;
+                    if let Some(line) = symbol.line() {
+                        write!(f, ":{}", line)?;
+                        if let Some(column) = symbol.column() {
+                            write!(f, ":{}", column)?;
+                        }
+                    }
+                }
             }
-            write!(
-                f,
-                " ({}[{}]:0x{:x})",
-                name,
-                func_index,
-                frame.module_offset()
-            )?;
         }
         Ok(())
     }
 }
 
+fn write_function_name(f: &mut fmt::Formatter<'_>, name: Option<&str>) -> fmt::Result {
+    match name {
+        Some(name) => match rustc_demangle::try_demangle(name) {
+            Ok(name) => write!(f, "{}", name),
+            Err(_) => write!(f, "{}", name),
+        },
+        None => write!(f, "<unnamed>"),
+    }
+}
+
 impl std::error::Error for RuntimeError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match &self.inner.source {
@@ -11,10 +11,12 @@
 //! let module: ModuleInfo = ...;
 //! FRAME_INFO.register(module, compiled_functions);
 //! ```
+use addr2line::gimli;
 use loupe::MemoryUsage;
 use std::cmp;
 use std::collections::BTreeMap;
-use std::sync::{Arc, RwLock};
+use std::fmt;
+use std::sync::{Arc, Mutex, RwLock};
 use wasmer_compiler::{CompiledFunctionFrameInfo, SourceLoc, TrapInformation};
 use wasmer_types::entity::{BoxedSlice, EntityRef, PrimaryMap};
 use wasmer_types::{LocalFunctionIndex, ModuleInfo};
@@ -66,6 +68,63 @@ struct ModuleInfoFrameInfo {
     functions: BTreeMap<usize, FunctionInfo>,
     module: Arc<ModuleInfo>,
     frame_infos: PrimaryMap<LocalFunctionIndex, CompiledFunctionFrameInfo>,
+    debug_context: Option<DebugContext>,
+}
+
+type DwarfReader = gimli::EndianReader<gimli::LittleEndian, Arc<[u8]>>;
+
+/// The DWARF debug info of a module, used to symbolicate its frames.
+struct DebugContext(Mutex<addr2line::Context<DwarfReader>>);
+
+impl DebugContext {
+    /// Loads the debug info embedded in the custom sections of `module`,
+    /// if any.
+    fn new(module: &ModuleInfo) -> Option<Self> {
+        if !module.custom_sections.contains_key(".debug_info") {
+            return None;
+        }
+        let dwarf = gimli::Dwarf::load(|id| {
+            let data = match module.custom_sections.get(id.name()) {
+                Some(index) => module.custom_sections_data[*index].clone(),
+                None => Arc::from(&[][..]),
+            };
+            Ok::<_, gimli::Error>(gimli::EndianReader::new(data, gimli::LittleEndian))
+        })
+        .ok()?;
+        let context = addr2line::Context::from_dwarf(dwarf).ok()?;
+        Some(Self(Mutex::new(context)))
+    }
+
+    /// The source locations of the code section address `address`, from
+    /// the innermost inlined function to the function containing it.
+    fn symbols(&self, address: u64) -> Vec<FrameSymbol> {
+        let context = self.0.lock().unwrap();
+        let mut frames = match context.find_frames(address) {
+            Ok(frames) => frames,
+            Err(_) => return vec![],
+        };
+        let mut symbols = vec![];
+        while let Ok(Some(frame)) = frames.next() {
+            let location = frame.location.as_ref();
+            symbols.push(FrameSymbol {
+                name: frame
+                    .function
+                    .as_ref()
+                    .and_then(|function| function.raw_name().ok())
+                    .map(|name| name.into_owned()),
+                file: location.and_then(|l| l.file).map(String::from),
+                line: location.and_then(|l| l.line),
+                column: location.and_then(|l| l.column),
+            });
+        }
+        symbols
+    }
+}
+
+impl fmt::Debug for DebugContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DebugContext").finish()
+    }
 }
 
 impl ModuleInfoFrameInfo {
@@ -139,12 +198,21 @@ impl GlobalFrameInfo {
             None => instr_map.start_srcloc,
         };
         let func_index = module.module.func_index(func.local_index);
+        // DWARF addresses are relative to the code section
+        let symbols = match (&module.debug_context, instr.is_default()) {
+            (Some(debug_context), false) => (instr.bits() as u64)
+                .checked_sub(module.module.code_section_offset as u64)
+                .map(|address| debug_context.symbols(address))
+                .unwrap_or_default(),
+            _ => vec![],
+        };
         Some(FrameInfo {
             module_name: module.module.name(),
             func_index: func_index.index() as u32,
             function_name: module.module.function_names.get(&func_index).cloned(),
             instr,
             func_start: instr_map.start_srcloc,
+            symbols,
         })
     }
 
@@ -226,6 +294,8 @@ pub fn register(
         return None;
     }
 
+    let debug_context = DebugContext::new(&module);
+
     let mut info = FRAME_INFO.write().unwrap();
     // First up assert that our chunk of jit functions doesn't collide with
     // any other known chunks of jit functions...
@@ -244,6 +314,7 @@ pub fn register(
             functions,
             module,
             frame_infos,
+            debug_context,
         },
     );
     assert!(prev.is_none());
@@ -265,6 +336,7 @@ pub struct FrameInfo {
     function_name: Option<String>,
     func_start: SourceLoc,
     instr: SourceLoc,
+    symbols: Vec<FrameSymbol>,
 }
 
 impl FrameInfo {
@@ -325,4 +397,52 @@ impl FrameInfo {
     pub fn func_offset(&self) -> usize {
         (self.instr.bits() - self.func_start.bits()) as usize
     }
+
+    /// Returns the source locations of this frame's program counter, from
+    /// the DWARF debug info of the module.
+    ///
+    /// When functions were inlined at this location, there is a symbol for
+    /// each of them, starting with the innermost one and ending with the
+    /// function containing the program counter. The list is empty if the
+    /// module has no debug info for this location.
+    pub fn symbols(&self) -> &[FrameSymbol] {
+        &self.symbols
+    }
+}
+
+/// A source location of a frame of a [`RuntimeError::trace`](crate::RuntimeError::trace),
+/// as described by the DWARF debug info of the module.
+///
+/// See [`FrameInfo::symbols`].
+#[derive(Debug, Clone)]
+pub struct FrameSymbol {
+    name: Option<String>,
+    file: Option<String>,
+    line: Option<u32>,
+    column: Option<u32>,
+}
+
+impl FrameSymbol {
+    /// Returns the name of the function, as found in the debug info.
+    ///
+    /// The name may be mangled: use a crate like `rustc-demangle` to make
+    /// it readable.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Returns the path of the source file.
+    pub fn file(&self) -> Option<&str> {
+        self.file.as_deref()
+    }
+
+    /// Returns the line in the source file, starting from 1.
+    pub fn line(&self) -> Option<u32> {
+        self.line
+    }
+
+    /// Returns the column in the source line, starting from 1.
+    pub fn column(&self) -> Option<u32> {
+        self.column
+    }
 }
@@ -1,7 +1,9 @@
+mod coredump;
 mod error;
 mod frame_info;
+pub use coredump::{CoreDumpFrame, CoreDumpGlobal, CoreDumpMemory, CoreDumpValue, WasmCoreDump};
 pub use error::RuntimeError;
 pub use frame_info::{
-    is_wasm_pc, register as register_frame_info, FrameInfo, FunctionExtent,
+    is_wasm_pc, register as register_frame_info, FrameInfo, FrameSymbol, FunctionExtent,
     GlobalFrameInfoRegistration, FRAME_INFO,
 };
@@ -2,6 +2,7 @@ use crate::error::LinkError;
 use loupe::MemoryUsage;
 use std::ptr::NonNull;
 use std::sync::Arc;
+use wasmer_compiler::ModuleLimits;
 use wasmer_types::entity::{EntityRef, PrimaryMap};
 use wasmer_types::{
     GlobalType, LocalGlobalIndex, LocalMemoryIndex, LocalTableIndex, MemoryIndex, MemoryType,
@@ -21,6 +22,13 @@ pub trait Tunables: MemoryUsage {
     /// Construct a `TableStyle` for the provided `TableType`
     fn table_style(&self, table: &TableType) -> TableStyle;
 
+    /// Returns the limits which the modules must satisfy to be compiled.
+    ///
+    /// The modules are unlimited by default.
+    fn module_limits(&self) -> ModuleLimits {
+        ModuleLimits::default()
+    }
+
     /// Create a memory owned by the host given a [`MemoryType`] and a [`MemoryStyle`].
     fn create_host_memory(
         &self,
@@ -0,0 +1,589 @@
+//! `coverage` is a middleware counting how many times each basic block
+//! of the functions of a module is executed, to report the code
+//! coverage of the module without recompiling it with instrumentation.
+//!
+//! The hit counters are stored in exported globals, and the location of
+//! each basic block is stored in a custom section of the module, so a
+//! coverage report can be produced from an instance of a module compiled
+//! ahead of time.
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::sync::Arc;
+//! use wasmer::{CompilerConfig, Instance, Module};
+//! use wasmer_middlewares::coverage::{Coverage, CoverageReport};
+//!
+//! fn add_coverage_middleware(compiler_config: &mut dyn CompilerConfig) {
+//!     compiler_config.push_middleware(Arc::new(Coverage::new()));
+//! }
+//!
+//! fn write_lcov_report(module: &Module, instance: &Instance) -> std::io::Result<()> {
+//!     let report = CoverageReport::new(module, instance);
+//!     std::fs::write("coverage.lcov", report.to_lcov())
+//! }
+//! ```
+
+use addr2line::gimli;
+use loupe::{MemoryUsage, MemoryUsageTracker};
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::fmt::{self, Write};
+use std::mem;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use wasmer::wasmparser::{BinaryReader, Operator};
+use wasmer::{
+    ExportIndex, FunctionBodyData, FunctionMiddleware, GlobalInit, GlobalType, Instance,
+    LocalFunctionIndex, MiddlewareError, MiddlewareReaderState, Module, ModuleMiddleware,
+    Mutability, Type,
+};
+use wasmer_types::entity::{EntityRef, PrimaryMap};
+use wasmer_types::{FunctionIndex, GlobalIndex, ModuleInfo};
+
+/// The name of the custom section holding the basic blocks of an
+/// instrumented module.
+const BLOCKS_SECTION: &str = "wasmer_coverage_blocks";
+
+/// The prefix of the names of the exported hit counters.
+const COUNTER_EXPORT_PREFIX: &str = "wasmer_coverage_counter_";
+
+/// A basic block of a function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, MemoryUsage)]
+struct Block {
+    /// The function containing the block.
+    function: LocalFunctionIndex,
+    /// The offset of the first operator of the block in the module.
+    offset: usize,
+}
+
+/// Tracks the basic blocks of a function as its operators are read.
+#[derive(Debug, Default)]
+struct BlockTracker {
+    depth: u32,
+}
+
+impl BlockTracker {
+    /// Returns whether a basic block starts after `operator`.
+    fn starts_block_after(&mut self, operator: &Operator) -> bool {
+        match operator {
+            Operator::Block { .. } => {
+                self.depth += 1;
+                false
+            }
+            Operator::Loop { .. } | Operator::If { .. } => {
+                self.depth += 1;
+                true
+            }
+            // The end of the function is not followed by any block.
+            Operator::End if self.depth == 0 => false,
+            Operator::End => {
+                self.depth -= 1;
+                true
+            }
+            Operator::Else
+            | Operator::Br { .. }
+            | Operator::BrIf { .. }
+            | Operator::BrTable { .. }
+            | Operator::Call { .. }
+            | Operator::CallIndirect { .. }
+            | Operator::Return
+            | Operator::Unreachable => true,
+            _ => false,
+        }
+    }
+}
+
+/// Finds the basic blocks of the body of `function`.
+fn find_blocks(function: LocalFunctionIndex, body: &FunctionBodyData) -> Vec<Block> {
+    let mut reader = BinaryReader::new_with_offset(body.data, body.module_offset);
+    let mut blocks = vec![];
+    let mut read_blocks = || -> Result<(), wasmer::wasmparser::BinaryReaderError> {
+        for _ in 0..reader.read_var_u32()? {
+            reader.read_var_u32()?;
+            reader.read_type()?;
+        }
+        let mut tracker = BlockTracker::default();
+        let mut block_starts = true;
+        while !reader.eof() {
+            let offset = reader.original_position();
+            let operator = reader.read_operator()?;
+            if block_starts {
+                blocks.push(Block { function, offset });
+            }
+            block_starts = tracker.starts_block_after(&operator);
+        }
+        Ok(())
+    };
+    // The module is validated before being compiled, so an invalid body
+    // only ends the list of blocks early.
+    let _ = read_blocks();
+    blocks
+}
+
+#[derive(Debug, Clone, MemoryUsage)]
+struct CoverageState {
+    /// The basic blocks of all the functions.
+    blocks: Vec<Block>,
+    /// The index in `blocks` of the first block of each function,
+    /// followed by the total number of blocks.
+    function_blocks: Vec<usize>,
+    /// The index of the counter of the first block.
+    first_counter: Option<GlobalIndex>,
+}
+
+/// The module-level coverage middleware.
+///
+/// It needs to see the basic blocks of the original code, so it must come
+/// before the middlewares changing the control flow of the functions.
+///
+/// # Panic
+///
+/// An instance of `Coverage` should _not_ be shared among different
+/// modules, since it tracks module-specific information like the
+/// basic blocks of the module. Attempts to use a `Coverage` instance
+/// from multiple modules will result in a panic.
+#[derive(Debug, Default)]
+pub struct Coverage {
+    state: Mutex<Option<CoverageState>>,
+}
+
+/// The function-level coverage middleware.
+#[derive(Debug)]
+pub struct FunctionCoverage {
+    /// The counters of the blocks of the function.
+    counters: Range<u32>,
+    /// The counter of the next block.
+    next_counter: u32,
+    /// Whether a block starts with the next operator.
+    block_starts: bool,
+    tracker: BlockTracker,
+}
+
+impl Coverage {
+    /// Creates a `Coverage` middleware.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ModuleMiddleware for Coverage {
+    /// Generates a `FunctionMiddleware` for a given function.
+    fn generate_function_middleware(
+        &self,
+        local_function_index: LocalFunctionIndex,
+    ) -> Box<dyn FunctionMiddleware> {
+        let state = self.state.lock().unwrap();
+        let state = state.as_ref().unwrap();
+        let first_counter = state.first_counter.unwrap().as_u32();
+        let function = local_function_index.index();
+        let counters = first_counter + state.function_blocks[function] as u32
+            ..first_counter + state.function_blocks[function + 1] as u32;
+        Box::new(FunctionCoverage {
+            next_counter: counters.start,
+            counters,
+            block_starts: true,
+            tracker: BlockTracker::default(),
+        })
+    }
+
+    /// Finds the basic blocks of the functions.
+    fn inspect_function_bodies(
+        &self,
+        function_bodies: &PrimaryMap<LocalFunctionIndex, FunctionBodyData<'_>>,
+    ) {
+        let mut state = self.state.lock().unwrap();
+
+        if state.is_some() {
+            panic!("Coverage::inspect_function_bodies: Attempting to use a `Coverage` middleware from multiple modules.");
+        }
+
+        let mut blocks = vec![];
+        let mut function_blocks = Vec::with_capacity(function_bodies.len() + 1);
+        for (function, body) in function_bodies.iter() {
+            function_blocks.push(blocks.len());
+            blocks.extend(find_blocks(function, body));
+        }
+        function_blocks.push(blocks.len());
+
+        *state = Some(CoverageState {
+            blocks,
+            function_blocks,
+            first_counter: None,
+        });
+    }
+
+    /// Adds and exports a counter for each basic block, and stores the
+    /// blocks in a custom section.
+    fn transform_module_info(&self, module_info: &mut ModuleInfo) {
+        let mut state = self.state.lock().unwrap();
+        let state = state
+            .as_mut()
+            .expect("Coverage::transform_module_info: The function bodies were not inspected.");
+
+        if state.first_counter.is_some() {
+            panic!("Coverage::transform_module_info: Attempting to use a `Coverage` middleware from multiple modules.");
+        }
+
+        let mut first_counter = None;
+        for block_index in 0..state.blocks.len() {
+            let counter = module_info
+                .globals
+                .push(GlobalType::new(Type::I64, Mutability::Var));
+            module_info
+                .global_initializers
+                .push(GlobalInit::I64Const(0));
+            module_info.exports.insert(
+                format!("{}{}", COUNTER_EXPORT_PREFIX, block_index),
+                ExportIndex::Global(counter),
+            );
+            first_counter.get_or_insert(counter);
+        }
+        state.first_counter = Some(first_counter.unwrap_or_else(|| module_info.globals.next_key()));
+
+        let mut section = Vec::with_capacity(state.blocks.len() * 8);
+        for block in &state.blocks {
+            section.extend_from_slice(&(block.function.as_u32()).to_le_bytes());
+            section.extend_from_slice(&(block.offset as u32).to_le_bytes());
+        }
+        let section_index = module_info.custom_sections_data.push(Arc::from(section));
+        module_info
+            .custom_sections
+            .insert(BLOCKS_SECTION.to_string(), section_index);
+    }
+}
+
+impl MemoryUsage for Coverage {
+    fn size_of_val(&self, tracker: &mut dyn MemoryUsageTracker) -> usize {
+        mem::size_of_val(self) + self.state.size_of_val(tracker) - mem::size_of_val(&self.state)
+    }
+}
+
+impl FunctionCoverage {
+    fn count_block(
+        &mut self,
+        state: &mut MiddlewareReaderState<'_>,
+    ) -> Result<(), MiddlewareError> {
+        if !self.counters.contains(&self.next_counter) {
+            return Err(MiddlewareError::new(
+                "coverage",
+                "the basic blocks of the function changed; the coverage middleware must come before the middlewares changing the control flow",
+            ));
+        }
+        let global_index = self.next_counter;
+        state.extend(&[
+            // globals[counter] += 1;
+            Operator::GlobalGet { global_index },
+            Operator::I64Const { value: 1 },
+            Operator::I64Add,
+            Operator::GlobalSet { global_index },
+        ]);
+        self.next_counter += 1;
+        Ok(())
+    }
+}
+
+impl FunctionMiddleware for FunctionCoverage {
+    fn feed<'a>(
+        &mut self,
+        operator: Operator<'a>,
+        state: &mut MiddlewareReaderState<'a>,
+    ) -> Result<(), MiddlewareError> {
+        if self.block_starts {
+            self.count_block(state)?;
+        }
+        self.block_starts = self.tracker.starts_block_after(&operator);
+        state.push_operator(operator);
+        Ok(())
+    }
+}
+
+/// The coverage of a basic block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockCoverage {
+    /// The index of the function containing the block.
+    pub function_index: FunctionIndex,
+    /// The name of the function containing the block, if known.
+    pub function_name: Option<String>,
+    /// The offset of the first operator of the block in the module.
+    pub offset: usize,
+    /// The number of times the block was executed.
+    pub hits: u64,
+    /// The source file of the block, from the DWARF debug info.
+    pub file: Option<String>,
+    /// The source line of the block, from the DWARF debug info.
+    pub line: Option<u32>,
+}
+
+/// The coverage of the basic blocks of an [`Instance`][wasmer::Instance].
+///
+/// Note: This can be used in a headless engine after an ahead-of-time
+/// compilation as all required state lives in the module and the
+/// instance.
+#[derive(Debug, Clone)]
+pub struct CoverageReport {
+    module_name: String,
+    blocks: Vec<BlockCoverage>,
+}
+
+type DwarfReader<'a> = gimli::EndianSlice<'a, gimli::LittleEndian>;
+
+/// Loads the DWARF debug info embedded in the custom sections of
+/// `module`, if any.
+fn debug_context(module: &ModuleInfo) -> Option<addr2line::Context<DwarfReader<'_>>> {
+    if !module.custom_sections.contains_key(".debug_info") {
+        return None;
+    }
+    let dwarf = gimli::Dwarf::load(|id| {
+        let data = match module.custom_sections.get(id.name()) {
+            Some(index) => &module.custom_sections_data[*index][..],
+            None => &[][..],
+        };
+        Ok::<_, gimli::Error>(gimli::EndianSlice::new(data, gimli::LittleEndian))
+    })
+    .ok()?;
+    addr2line::Context::from_dwarf(dwarf).ok()
+}
+
+impl CoverageReport {
+    /// Reads the hit counters of `instance`, an instance of `module`.
+    ///
+    /// # Panic
+    ///
+    /// The `module` must have been processed with the [`Coverage`]
+    /// middleware at compile time, otherwise this will panic.
+    pub fn new(module: &Module, instance: &Instance) -> Self {
+        let info = module.info();
+        let section = module
+            .custom_sections(BLOCKS_SECTION)
+            .next()
+            .expect("Can't get the coverage blocks from Module");
+        let debug_context = debug_context(info);
+
+        let blocks = section
+            .chunks_exact(8)
+            .enumerate()
+            .map(|(block_index, block)| {
+                let function = LocalFunctionIndex::from_u32(u32::from_le_bytes(
+                    block[..4].try_into().unwrap(),
+                ));
+                let offset = u32::from_le_bytes(block[4..].try_into().unwrap()) as usize;
+                let function_index = info.func_index(function);
+                let counter_name = format!("{}{}", COUNTER_EXPORT_PREFIX, block_index);
+                let hits: i64 = instance
+                    .exports
+                    .get_global(&counter_name)
+                    .expect("Can't get a coverage counter from Instance")
+                    .get()
+                    .try_into()
+                    .expect("A coverage counter from Instance has wrong type");
+                // DWARF addresses are relative to the code section
+                let location = debug_context.as_ref().and_then(|context| {
+                    let address = offset.checked_sub(info.code_section_offset)? as u64;
+                    context.find_location(address).ok().flatten()
+                });
+                BlockCoverage {
+                    function_index,
+                    function_name: info.function_names.get(&function_index).cloned(),
+                    offset,
+                    hits: hits as u64,
+                    file: location.as_ref().and_then(|l| l.file).map(String::from),
+                    line: location.as_ref().and_then(|l| l.line),
+                }
+            })
+            .collect();
+
+        Self {
+            module_name: info.name(),
+            blocks,
+        }
+    }
+
+    /// Returns the coverage of the basic blocks, in the order of the
+    /// module.
+    pub fn blocks(&self) -> &[BlockCoverage] {
+        &self.blocks
+    }
+
+    /// Formats the report in the lcov tracefile format.
+    ///
+    /// The blocks with a source location in the DWARF debug info are
+    /// reported against their source file and line. The other blocks are
+    /// reported against the module, with the bytecode offset of the block
+    /// as line number.
+    pub fn to_lcov(&self) -> String {
+        // file -> function name -> first line, and file -> line -> hits.
+        let mut functions = BTreeMap::<String, BTreeMap<String, (u64, u64)>>::new();
+        let mut lines = BTreeMap::<String, BTreeMap<u64, u64>>::new();
+        for block in &self.blocks {
+            let (file, line) = match (&block.file, block.line) {
+                (Some(file), Some(line)) => (file.clone(), line as u64),
+                _ => (self.module_name.clone(), block.offset as u64),
+            };
+            let function_name = block
+                .function_name
+                .clone()
+                .unwrap_or_else(|| format!("function_{}", block.function_index.index()));
+            let function = functions
+                .entry(file.clone())
+                .or_default()
+                .entry(function_name)
+                .or_insert((line, block.hits));
+            function.0 = function.0.min(line);
+            let line_hits = lines.entry(file).or_default().entry(line).or_insert(0);
+            *line_hits = (*line_hits).max(block.hits);
+        }
+
+        let mut lcov = String::new();
+        for (file, lines) in &lines {
+            writeln!(lcov, "SF:{}", file).unwrap();
+            let file_functions = &functions[file];
+            for (name, (line, _)) in file_functions {
+                writeln!(lcov, "FN:{},{}", line, name).unwrap();
+            }
+            for (name, (_, hits)) in file_functions {
+                writeln!(lcov, "FNDA:{},{}", hits, name).unwrap();
+            }
+            writeln!(lcov, "FNF:{}", file_functions.len()).unwrap();
+            let hit_functions = file_functions.values().filter(|(_, hits)| *hits > 0);
+            writeln!(lcov, "FNH:{}", hit_functions.count()).unwrap();
+            for (line, hits) in lines {
+                writeln!(lcov, "DA:{},{}", line, hits).unwrap();
+            }
+            writeln!(lcov, "LF:{}", lines.len()).unwrap();
+            writeln!(
+                lcov,
+                "LH:{}",
+                lines.values().filter(|hits| **hits > 0).count()
+            )
+            .unwrap();
+            writeln!(lcov, "end_of_record").unwrap();
+        }
+        lcov
+    }
+
+    /// Formats the report as JSON, an array with an object per basic
+    /// block.
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("[");
+        for (index, block) in self.blocks.iter().enumerate() {
+            if index > 0 {
+                json.push(',');
+            }
+            write!(
+                json,
+                "{{\"function_index\":{},\"function_name\":{},\"offset\":{},\"hits\":{},\"file\":{},\"line\":{}}}",
+                block.function_index.index(),
+                JsonOption(block.function_name.as_ref().map(JsonString)),
+                block.offset,
+                block.hits,
+                JsonOption(block.file.as_ref().map(JsonString)),
+                JsonOption(block.line),
+            )
+            .unwrap();
+        }
+        json.push(']');
+        json
+    }
+}
+
+/// Formats a string as a JSON string.
+struct JsonString<'a>(&'a String);
+
+impl fmt::Display for JsonString<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_char('"')?;
+        for c in self.0.chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+                c => f.write_char(c)?,
+            }
+        }
+        f.write_char('"')
+    }
+}
+
+/// Formats an optional value as JSON, `null` if it's absent.
+struct JsonOption<T>(Option<T>);
+
+impl<T: fmt::Display> fmt::Display for JsonOption<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            Some(value) => value.fmt(f),
+            None => f.write_str("null"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use wasmer::{imports, wat2wasm, CompilerConfig, Cranelift, Module, Store, Universal};
+
+    fn bytecode() -> Vec<u8> {
+        wat2wasm(
+            br#"
+            (module
+            (func $abs (export "abs") (param $value i32) (result i32)
+                local.get $value
+                i32.const 0
+                i32.lt_s
+                if (result i32)
+                    i32.const 0
+                    local.get $value
+                    i32.sub
+                else
+                    local.get $value
+                end))
+            "#,
+        )
+        .unwrap()
+        .into()
+    }
+
+    #[test]
+    fn coverage_report_works() {
+        let coverage = Arc::new(Coverage::new());
+        let mut compiler_config = Cranelift::default();
+        compiler_config.push_middleware(coverage);
+        let store = Store::new(&Universal::new(compiler_config).engine());
+        let module = Module::new(&store, bytecode()).unwrap();
+        let instance = Instance::new(&module, &imports! {}).unwrap();
+
+        // The blocks are the entry of the function, the `if` branch, the
+        // `else` branch and the code after the `if`.
+        let hits = |report: &CoverageReport| {
+            report
+                .blocks()
+                .iter()
+                .map(|block| block.hits)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(hits(&CoverageReport::new(&module, &instance)), [0, 0, 0, 0]);
+
+        let abs = instance
+            .exports
+            .get_function("abs")
+            .unwrap()
+            .native::<i32, i32>()
+            .unwrap();
+        assert_eq!(abs.call(-3).unwrap(), 3);
+        assert_eq!(abs.call(4).unwrap(), 4);
+        assert_eq!(abs.call(5).unwrap(), 5);
+
+        let report = CoverageReport::new(&module, &instance);
+        assert_eq!(hits(&report), [3, 1, 2, 3]);
+        assert!(report
+            .blocks()
+            .iter()
+            .all(|block| block.function_index.index() == 0));
+        assert!(report.to_lcov().contains("FNDA:3,abs\n"));
+        assert!(report
+            .to_json()
+            .starts_with("[{\"function_index\":0,\"function_name\":\"abs\",\"offset\":"));
+    }
+}
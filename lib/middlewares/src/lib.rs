@@ -1,6 +1,16 @@
+pub mod coverage;
+pub mod memory_tracing;
 pub mod metering;
+pub mod profiling;
+pub mod soft_float;
+pub mod stack_limit;
 
 // The most commonly used symbol are exported at top level of the
 // module. Others are available via modules,
 // e.g. `wasmer_middlewares::metering::get_remaining_points`
+pub use coverage::Coverage;
+pub use memory_tracing::MemoryTracing;
 pub use metering::Metering;
+pub use profiling::Profiling;
+pub use soft_float::SoftFloat;
+pub use stack_limit::StackLimit;
@@ -0,0 +1,648 @@
+//! `memory_tracing` is a middleware calling a host callback on every
+//! load and store, and on every `memory.grow`, to build debugging tools
+//! such as watchpoints without native debugger support.
+//!
+//! The instrumented modules import the function calling the callback,
+//! which is provided by a [`MemoryTracer`].
+//!
+//! The SIMD and atomic memory accesses are not traced.
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::sync::Arc;
+//! use wasmer::{CompilerConfig, Instance, Module};
+//! use wasmer_middlewares::memory_tracing::{MemoryAccessKind, MemoryTracer, MemoryTracing};
+//!
+//! fn add_memory_tracing_middleware(compiler_config: &mut dyn CompilerConfig) {
+//!     // Only trace the accesses to the first page.
+//!     let memory_tracing = MemoryTracing::new().address_range(0..0x1_0000);
+//!     compiler_config.push_middleware(Arc::new(memory_tracing));
+//! }
+//!
+//! fn instantiate(module: &Module) -> Result<Instance, Box<dyn std::error::Error>> {
+//!     let tracer = MemoryTracer::new(|access| {
+//!         if access.kind == MemoryAccessKind::Store {
+//!             eprintln!("{:#x} <- {:#x}", access.address, access.value);
+//!         }
+//!     });
+//!     Ok(Instance::new(module, &tracer.imports(module.store()))?)
+//! }
+//! ```
+
+use loupe::{MemoryUsage, MemoryUsageTracker};
+use std::collections::HashSet;
+use std::fmt;
+use std::mem;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use wasmer::wasmparser::{Operator, Type as WpType, TypeOrFuncType as WpTypeOrFuncType};
+use wasmer::{
+    Exports, Function, FunctionLocals, FunctionMiddleware, FunctionType, ImportObject,
+    ImportedFunctions, LocalFunctionIndex, MiddlewareError, MiddlewareReaderState,
+    ModuleMiddleware, Store, Type, WasmerEnv,
+};
+use wasmer_types::ModuleInfo;
+
+/// The module from which the instrumented modules import the function
+/// calling the callback of the [`MemoryTracer`].
+pub const IMPORT_MODULE: &str = "wasmer_memory_tracing";
+
+/// The kind of a memory access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum MemoryAccessKind {
+    /// A load from the memory.
+    Load = 0,
+    /// A store to the memory.
+    Store = 1,
+    /// A `memory.grow`.
+    Grow = 2,
+}
+
+/// A memory access traced by the [`MemoryTracing`] middleware.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAccess {
+    /// The kind of the access.
+    pub kind: MemoryAccessKind,
+    /// The address of the access, including the offset of the operator.
+    /// This is 0 for a `memory.grow`.
+    pub address: u64,
+    /// The number of bytes accessed, or the number of pages to grow the
+    /// memory by for a `memory.grow`.
+    pub size: u32,
+    /// The bytes loaded or stored, as a little-endian integer, or the
+    /// result of a `memory.grow`: the previous number of pages, or
+    /// `u32::MAX` if the memory couldn't grow.
+    pub value: u64,
+    /// The function accessing the memory.
+    pub function: LocalFunctionIndex,
+    /// The offset of the operator accessing the memory in the module.
+    pub offset: u32,
+}
+
+/// The type of a value loaded or stored.
+#[derive(Debug, Clone, Copy)]
+enum ValueType {
+    I32,
+    I64,
+    F32,
+    F64,
+}
+
+/// Returns the kind, size, value type and offset of the memory access
+/// of `operator`, if any.
+fn memory_access(operator: &Operator) -> Option<(MemoryAccessKind, u32, ValueType, u64)> {
+    use MemoryAccessKind::{Load, Store};
+    let (kind, size, value_type, memarg) = match operator {
+        Operator::I32Load { memarg } => (Load, 4, ValueType::I32, memarg),
+        Operator::I64Load { memarg } => (Load, 8, ValueType::I64, memarg),
+        Operator::F32Load { memarg } => (Load, 4, ValueType::F32, memarg),
+        Operator::F64Load { memarg } => (Load, 8, ValueType::F64, memarg),
+        Operator::I32Load8S { memarg } | Operator::I32Load8U { memarg } => {
+            (Load, 1, ValueType::I32, memarg)
+        }
+        Operator::I32Load16S { memarg } | Operator::I32Load16U { memarg } => {
+            (Load, 2, ValueType::I32, memarg)
+        }
+        Operator::I64Load8S { memarg } | Operator::I64Load8U { memarg } => {
+            (Load, 1, ValueType::I64, memarg)
+        }
+        Operator::I64Load16S { memarg } | Operator::I64Load16U { memarg } => {
+            (Load, 2, ValueType::I64, memarg)
+        }
+        Operator::I64Load32S { memarg } | Operator::I64Load32U { memarg } => {
+            (Load, 4, ValueType::I64, memarg)
+        }
+        Operator::I32Store { memarg } => (Store, 4, ValueType::I32, memarg),
+        Operator::I64Store { memarg } => (Store, 8, ValueType::I64, memarg),
+        Operator::F32Store { memarg } => (Store, 4, ValueType::F32, memarg),
+        Operator::F64Store { memarg } => (Store, 8, ValueType::F64, memarg),
+        Operator::I32Store8 { memarg } => (Store, 1, ValueType::I32, memarg),
+        Operator::I32Store16 { memarg } => (Store, 2, ValueType::I32, memarg),
+        Operator::I64Store8 { memarg } => (Store, 1, ValueType::I64, memarg),
+        Operator::I64Store16 { memarg } => (Store, 2, ValueType::I64, memarg),
+        Operator::I64Store32 { memarg } => (Store, 4, ValueType::I64, memarg),
+        _ => return None,
+    };
+    Some((kind, size, value_type, memarg.offset as u64))
+}
+
+/// The module-level memory tracing middleware.
+///
+/// # Panic
+///
+/// An instance of `MemoryTracing` should _not_ be shared among different
+/// modules, since it tracks module-specific information like the index
+/// of the imported function. Attempts to use a `MemoryTracing` instance
+/// from multiple modules will result in a panic.
+#[derive(Debug, Default)]
+pub struct MemoryTracing {
+    /// The functions to trace, or `None` to trace all of them.
+    functions: Option<HashSet<LocalFunctionIndex>>,
+    /// The addresses to trace, or `None` to trace all of them.
+    address_range: Option<Range<u64>>,
+    /// The function calling the callback.
+    imported_functions: Mutex<Option<ImportedFunctions>>,
+}
+
+/// The function-level memory tracing middleware.
+#[derive(Debug)]
+pub struct FunctionMemoryTracing {
+    local_function_index: LocalFunctionIndex,
+    address_range: Option<Range<u64>>,
+    /// The function calling the callback.
+    imported_functions: ImportedFunctions,
+    /// The locals holding the address and the value of an access.
+    address_local: u32,
+    effective_address_local: u32,
+    value_locals: [u32; 4],
+}
+
+impl MemoryTracing {
+    /// Creates a `MemoryTracing` middleware tracing all the memory
+    /// accesses.
+    ///
+    /// The instrumented modules import the function calling the callback
+    /// from [`IMPORT_MODULE`], so they must be instantiated with
+    /// [`MemoryTracer::imports`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only traces the memory accesses of the given local functions.
+    ///
+    /// The other functions aren't instrumented, so they run at full
+    /// speed.
+    pub fn functions(mut self, functions: impl IntoIterator<Item = LocalFunctionIndex>) -> Self {
+        self.functions = Some(functions.into_iter().collect());
+        self
+    }
+
+    /// Only traces the loads and stores accessing bytes in
+    /// `address_range`, and the `memory.grow`s.
+    pub fn address_range(mut self, address_range: Range<u64>) -> Self {
+        self.address_range = Some(address_range);
+        self
+    }
+}
+
+impl ModuleMiddleware for MemoryTracing {
+    /// Generates a `FunctionMiddleware` for a given function.
+    fn generate_function_middleware(
+        &self,
+        local_function_index: LocalFunctionIndex,
+    ) -> Box<dyn FunctionMiddleware> {
+        let imported_functions = self.imported_functions.lock().unwrap().clone().unwrap();
+        match &self.functions {
+            Some(functions) if !functions.contains(&local_function_index) => {
+                Box::new(UntracedFunction { imported_functions })
+            }
+            _ => Box::new(FunctionMemoryTracing {
+                local_function_index,
+                address_range: self.address_range.clone(),
+                imported_functions,
+                address_local: 0,
+                effective_address_local: 0,
+                value_locals: [0; 4],
+            }),
+        }
+    }
+
+    /// Imports the function calling the callback.
+    fn transform_module_info(&self, module_info: &mut ModuleInfo) {
+        let mut imported_functions = self.imported_functions.lock().unwrap();
+
+        if imported_functions.is_some() {
+            panic!("MemoryTracing::transform_module_info: Attempting to use a `MemoryTracing` middleware from multiple modules.");
+        }
+
+        let mut imports = ImportedFunctions::new();
+        imports.import(
+            module_info,
+            IMPORT_MODULE,
+            "trace",
+            FunctionType::new(
+                vec![
+                    Type::I32,
+                    Type::I64,
+                    Type::I32,
+                    Type::I64,
+                    Type::I32,
+                    Type::I32,
+                ],
+                vec![],
+            ),
+        );
+        *imported_functions = Some(imports);
+    }
+}
+
+impl MemoryUsage for MemoryTracing {
+    fn size_of_val(&self, tracker: &mut dyn MemoryUsageTracker) -> usize {
+        mem::size_of_val(self) + self.imported_functions.size_of_val(tracker)
+            - mem::size_of_val(&self.imported_functions)
+    }
+}
+
+/// A function which isn't traced, whose calls still need to be remapped.
+#[derive(Debug)]
+struct UntracedFunction {
+    imported_functions: ImportedFunctions,
+}
+
+impl FunctionMiddleware for UntracedFunction {
+    fn imported_functions(&self) -> Option<&ImportedFunctions> {
+        Some(&self.imported_functions)
+    }
+}
+
+impl FunctionMemoryTracing {
+    /// Calls the callback with the access of `kind` of `size` bytes at the
+    /// effective address, of the value of `value_type` held in its local.
+    fn trace(
+        &self,
+        kind: MemoryAccessKind,
+        size: u32,
+        value_type: ValueType,
+        state: &mut MiddlewareReaderState<'_>,
+    ) {
+        let value_local = self.value_locals[value_type as usize];
+        let offset = state.original_offset();
+        state.extend(&[
+            Operator::I32Const { value: kind as i32 },
+            Operator::LocalGet {
+                local_index: self.effective_address_local,
+            },
+            Operator::I32Const { value: size as i32 },
+            Operator::LocalGet {
+                local_index: value_local,
+            },
+        ]);
+        // The value, as a little-endian integer of `size` bytes.
+        match value_type {
+            ValueType::I32 => state.extend(&[Operator::I64ExtendI32U]),
+            ValueType::I64 => {}
+            ValueType::F32 => state.extend(&[Operator::I32ReinterpretF32, Operator::I64ExtendI32U]),
+            ValueType::F64 => state.extend(&[Operator::I64ReinterpretF64]),
+        }
+        if size < 8 {
+            state.extend(&[
+                Operator::I64Const {
+                    value: (1i64 << (size * 8)) - 1,
+                },
+                Operator::I64And,
+            ]);
+        }
+        state.extend(&[
+            Operator::I32Const {
+                value: self.local_function_index.as_u32() as i32,
+            },
+            Operator::I32Const {
+                value: offset as i32,
+            },
+            Operator::Call {
+                function_index: self.imported_functions.indices().next().unwrap().as_u32(),
+            },
+        ]);
+    }
+
+    /// Calls the callback if the access of `size` bytes at the effective
+    /// address is in the address range.
+    fn trace_in_range(
+        &self,
+        kind: MemoryAccessKind,
+        size: u32,
+        value_type: ValueType,
+        state: &mut MiddlewareReaderState<'_>,
+    ) {
+        let address_range = match &self.address_range {
+            Some(address_range) => address_range,
+            None => return self.trace(kind, size, value_type, state),
+        };
+        let effective_address = self.effective_address_local;
+        state.extend(&[
+            // if effective_address < end && effective_address + size > start { trace(); }
+            Operator::LocalGet {
+                local_index: effective_address,
+            },
+            Operator::I64Const {
+                value: address_range.end as i64,
+            },
+            Operator::I64LtU,
+            Operator::LocalGet {
+                local_index: effective_address,
+            },
+            Operator::I64Const { value: size as i64 },
+            Operator::I64Add,
+            Operator::I64Const {
+                value: address_range.start as i64,
+            },
+            Operator::I64GtU,
+            Operator::I32And,
+            Operator::If {
+                ty: WpTypeOrFuncType::Type(WpType::EmptyBlockType),
+            },
+        ]);
+        self.trace(kind, size, value_type, state);
+        state.extend(&[Operator::End]);
+    }
+
+    /// Stores the effective address of the access, from the address on
+    /// top of the stack.
+    fn save_effective_address(&self, offset: u64, state: &mut MiddlewareReaderState<'_>) {
+        state.extend(&[
+            Operator::LocalGet {
+                local_index: self.address_local,
+            },
+            Operator::I64ExtendI32U,
+            Operator::I64Const {
+                value: offset as i64,
+            },
+            Operator::I64Add,
+            Operator::LocalSet {
+                local_index: self.effective_address_local,
+            },
+        ]);
+    }
+}
+
+impl FunctionMiddleware for FunctionMemoryTracing {
+    fn declare_locals(&mut self, locals: &mut FunctionLocals<'_>) {
+        self.address_local = locals.declare(1, WpType::I32);
+        self.effective_address_local = locals.declare(1, WpType::I64);
+        self.value_locals = [
+            locals.declare(1, WpType::I32),
+            locals.declare(1, WpType::I64),
+            locals.declare(1, WpType::F32),
+            locals.declare(1, WpType::F64),
+        ];
+    }
+
+    fn imported_functions(&self) -> Option<&ImportedFunctions> {
+        Some(&self.imported_functions)
+    }
+
+    fn feed<'a>(
+        &mut self,
+        operator: Operator<'a>,
+        state: &mut MiddlewareReaderState<'a>,
+    ) -> Result<(), MiddlewareError> {
+        if let Operator::MemoryGrow { .. } = operator {
+            let pages = self.value_locals[ValueType::I32 as usize];
+            let offset = state.original_offset();
+            state.extend(&[Operator::LocalTee {
+                local_index: self.address_local,
+            }]);
+            state.push_operator(operator);
+            state.extend(&[
+                Operator::LocalSet { local_index: pages },
+                // trace(Grow, 0, delta, result, function, offset);
+                Operator::I32Const {
+                    value: MemoryAccessKind::Grow as i32,
+                },
+                Operator::I64Const { value: 0 },
+                Operator::LocalGet {
+                    local_index: self.address_local,
+                },
+                Operator::LocalGet { local_index: pages },
+                Operator::I64ExtendI32U,
+                Operator::I32Const {
+                    value: self.local_function_index.as_u32() as i32,
+                },
+                Operator::I32Const {
+                    value: offset as i32,
+                },
+                Operator::Call {
+                    function_index: self.imported_functions.indices().next().unwrap().as_u32(),
+                },
+                Operator::LocalGet { local_index: pages },
+            ]);
+            return Ok(());
+        }
+
+        let (kind, size, value_type, offset) = match memory_access(&operator) {
+            Some(access) => access,
+            None => {
+                state.push_operator(operator);
+                return Ok(());
+            }
+        };
+        let value_local = self.value_locals[value_type as usize];
+        match kind {
+            MemoryAccessKind::Load => {
+                state.extend(&[Operator::LocalTee {
+                    local_index: self.address_local,
+                }]);
+                state.push_operator(operator);
+                state.extend(&[Operator::LocalSet {
+                    local_index: value_local,
+                }]);
+                self.save_effective_address(offset, state);
+                self.trace_in_range(kind, size, value_type, state);
+                state.extend(&[Operator::LocalGet {
+                    local_index: value_local,
+                }]);
+            }
+            _ => {
+                state.extend(&[
+                    Operator::LocalSet {
+                        local_index: value_local,
+                    },
+                    Operator::LocalSet {
+                        local_index: self.address_local,
+                    },
+                ]);
+                self.save_effective_address(offset, state);
+                self.trace_in_range(kind, size, value_type, state);
+                state.extend(&[
+                    Operator::LocalGet {
+                        local_index: self.address_local,
+                    },
+                    Operator::LocalGet {
+                        local_index: value_local,
+                    },
+                ]);
+                state.push_operator(operator);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Calls a callback on the memory accesses of the instances of modules
+/// processed with the [`MemoryTracing`] middleware.
+#[derive(Clone)]
+pub struct MemoryTracer {
+    callback: Arc<dyn Fn(&MemoryAccess) + Send + Sync>,
+}
+
+impl fmt::Debug for MemoryTracer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryTracer")
+            .field("callback", &"<function>")
+            .finish()
+    }
+}
+
+impl WasmerEnv for MemoryTracer {}
+
+fn trace(
+    tracer: &MemoryTracer,
+    kind: i32,
+    address: i64,
+    size: i32,
+    value: i64,
+    function: i32,
+    offset: i32,
+) {
+    let kind = match kind {
+        0 => MemoryAccessKind::Load,
+        1 => MemoryAccessKind::Store,
+        _ => MemoryAccessKind::Grow,
+    };
+    (tracer.callback)(&MemoryAccess {
+        kind,
+        address: address as u64,
+        size: size as u32,
+        value: value as u64,
+        function: LocalFunctionIndex::from_u32(function as u32),
+        offset: offset as u32,
+    });
+}
+
+impl MemoryTracer {
+    /// Creates a tracer calling `callback` on every traced memory access.
+    pub fn new(callback: impl Fn(&MemoryAccess) + Send + Sync + 'static) -> Self {
+        Self {
+            callback: Arc::new(callback),
+        }
+    }
+
+    /// Returns the function of the tracer imported by the instrumented
+    /// modules.
+    pub fn exports(&self, store: &Store) -> Exports {
+        let mut exports = Exports::new();
+        exports.insert(
+            "trace",
+            Function::new_native_with_env(store, self.clone(), trace),
+        );
+        exports
+    }
+
+    /// Returns the imports of the instrumented modules, to be chained with
+    /// or extended by the other imports of the module.
+    pub fn imports(&self, store: &Store) -> ImportObject {
+        let mut import_object = ImportObject::new();
+        import_object.register(IMPORT_MODULE, self.exports(store));
+        import_object
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use wasmer::{wat2wasm, CompilerConfig, Cranelift, Instance, Module, Store, Universal};
+    use wasmer_types::entity::EntityRef;
+
+    fn bytecode() -> Vec<u8> {
+        wat2wasm(
+            br#"
+            (module
+            (memory 1)
+            (func $copy (export "copy") (param $from i32) (param $to i32)
+                local.get $to
+                local.get $from
+                i64.load offset=8
+                i64.store16)
+            (func $grow (export "grow") (result i32)
+                i32.const 2
+                memory.grow)
+            (func $init (export "init")
+                i32.const 16
+                f32.const 1.5
+                f32.store))
+            "#,
+        )
+        .unwrap()
+        .into()
+    }
+
+    fn trace(memory_tracing: MemoryTracing) -> Vec<MemoryAccess> {
+        let mut compiler_config = Cranelift::default();
+        compiler_config.push_middleware(Arc::new(memory_tracing));
+        let store = Store::new(&Universal::new(compiler_config).engine());
+        let module = Module::new(&store, bytecode()).unwrap();
+        let accesses = Arc::new(Mutex::new(vec![]));
+        let tracer = {
+            let accesses = accesses.clone();
+            MemoryTracer::new(move |access| accesses.lock().unwrap().push(*access))
+        };
+        let instance = Instance::new(&module, &tracer.imports(&store)).unwrap();
+
+        let init = instance
+            .exports
+            .get_native_function::<(), ()>("init")
+            .unwrap();
+        let copy = instance
+            .exports
+            .get_native_function::<(i32, i32), ()>("copy")
+            .unwrap();
+        let grow = instance
+            .exports
+            .get_native_function::<(), i32>("grow")
+            .unwrap();
+        init.call().unwrap();
+        copy.call(8, 32).unwrap();
+        assert_eq!(grow.call().unwrap(), 1);
+
+        let accesses = accesses.lock().unwrap();
+        accesses.clone()
+    }
+
+    #[test]
+    fn memory_tracing_works() {
+        let accesses = trace(MemoryTracing::new());
+        let summary = accesses
+            .iter()
+            .map(|access| {
+                (
+                    access.kind,
+                    access.address,
+                    access.size,
+                    access.value,
+                    access.function.as_u32(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                (MemoryAccessKind::Store, 16, 4, 1.5f32.to_bits() as u64, 2),
+                (MemoryAccessKind::Load, 16, 8, 1.5f32.to_bits() as u64, 0),
+                (MemoryAccessKind::Store, 32, 2, 0, 0),
+                (MemoryAccessKind::Grow, 0, 2, 1, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn memory_tracing_filters_work() {
+        let accesses = trace(
+            MemoryTracing::new()
+                .functions(vec![LocalFunctionIndex::new(0)])
+                .address_range(30..31),
+        );
+        assert_eq!(accesses.len(), 0);
+
+        let accesses = trace(
+            MemoryTracing::new()
+                .functions(vec![LocalFunctionIndex::new(0)])
+                .address_range(31..40),
+        );
+        assert_eq!(accesses.len(), 1);
+        assert_eq!(accesses[0].kind, MemoryAccessKind::Store);
+        assert_eq!(accesses[0].address, 32);
+    }
+}
@@ -3,6 +3,10 @@
 //! operators executed. The WebAssemblt instance execution is stopped
 //! when the limit is reached.
 //!
+//! The bulk memory and table operators, and `memory.grow` and
+//! `table.grow`, can also be charged in proportion to their size operand,
+//! see [`Metering::with_dynamic_cost_function`].
+//!
 //! # Example
 //!
 //! [See the `metering` detailed and complete
@@ -15,11 +19,15 @@ use std::mem;
 use std::sync::{Arc, Mutex};
 use wasmer::wasmparser::{Operator, Type as WpType, TypeOrFuncType as WpTypeOrFuncType};
 use wasmer::{
-    ExportIndex, FunctionMiddleware, GlobalInit, GlobalType, Instance, LocalFunctionIndex,
-    MiddlewareError, MiddlewareReaderState, ModuleMiddleware, Mutability, Type,
+    ExportIndex, FunctionLocals, FunctionMiddleware, GlobalInit, GlobalType, Instance,
+    LocalFunctionIndex, MiddlewareError, MiddlewareReaderState, ModuleMiddleware, Mutability, Type,
 };
 use wasmer_types::{GlobalIndex, ModuleInfo};
 
+/// A function mapping the operators with a size operand to a cost in
+/// "points" per unit of size.
+type DynamicCostFunction = dyn Fn(&Operator) -> u64 + Send + Sync;
+
 #[derive(Clone, MemoryUsage)]
 struct MeteringGlobalIndexes(GlobalIndex, GlobalIndex);
 
@@ -89,6 +97,10 @@ pub struct Metering<F: Fn(&Operator) -> u64 + Send + Sync> {
     /// Function that maps each operator to a cost in "points".
     cost_function: Arc<F>,
 
+    /// Function that maps the operators with a size operand to a cost in
+    /// "points" per unit of size.
+    dynamic_cost_function: Option<Arc<DynamicCostFunction>>,
+
     /// The global indexes for metering points.
     global_indexes: Mutex<Option<MeteringGlobalIndexes>>,
 }
@@ -98,11 +110,18 @@ pub struct FunctionMetering<F: Fn(&Operator) -> u64 + Send + Sync> {
     /// Function that maps each operator to a cost in "points".
     cost_function: Arc<F>,
 
+    /// Function that maps the operators with a size operand to a cost in
+    /// "points" per unit of size.
+    dynamic_cost_function: Option<Arc<DynamicCostFunction>>,
+
     /// The global indexes for metering points.
     global_indexes: MeteringGlobalIndexes,
 
     /// Accumulated cost of the current basic block.
     accumulated_cost: u64,
+
+    /// The local holding the size operand of the operator being charged.
+    size_local: u32,
 }
 
 /// Represents the type of the metering points, either `Remaining` or
@@ -130,9 +149,45 @@ impl<F: Fn(&Operator) -> u64 + Send + Sync> Metering<F> {
         Self {
             initial_limit,
             cost_function: Arc::new(cost_function),
+            dynamic_cost_function: None,
             global_indexes: Mutex::new(None),
         }
     }
+
+    /// Charges the operators with a size operand in proportion to it, on
+    /// top of the cost given by the cost function.
+    ///
+    /// `cost_per_unit` maps the operators to the cost of a unit of size:
+    /// a byte for `memory.copy`, `memory.fill` and `memory.init`, an
+    /// element for `table.copy`, `table.fill`, `table.init` and
+    /// `table.grow`, and a page for `memory.grow`. The cost is charged
+    /// before the operator executes, once the size is known, and the
+    /// execution is stopped if not enough points remain.
+    ///
+    /// The sizes are read as `i32`, so this doesn't support the 64-bit
+    /// memories of the memory64 proposal.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use wasmer::wasmparser::Operator;
+    /// use wasmer_middlewares::Metering;
+    ///
+    /// let metering = Metering::new(1_000_000, |_: &Operator| -> u64 { 1 })
+    ///     .with_dynamic_cost_function(|operator: &Operator| -> u64 {
+    ///         match operator {
+    ///             Operator::MemoryGrow { .. } => 10_000,
+    ///             _ => 1,
+    ///         }
+    ///     });
+    /// ```
+    pub fn with_dynamic_cost_function(
+        mut self,
+        cost_per_unit: impl Fn(&Operator) -> u64 + Send + Sync + 'static,
+    ) -> Self {
+        self.dynamic_cost_function = Some(Arc::new(cost_per_unit));
+        self
+    }
 }
 
 impl<F: Fn(&Operator) -> u64 + Send + Sync> fmt::Debug for Metering<F> {
@@ -140,6 +195,10 @@ impl<F: Fn(&Operator) -> u64 + Send + Sync> fmt::Debug for Metering<F> {
         f.debug_struct("Metering")
             .field("initial_limit", &self.initial_limit)
             .field("cost_function", &"<function>")
+            .field(
+                "dynamic_cost_function",
+                &self.dynamic_cost_function.as_ref().map(|_| "<function>"),
+            )
             .field("global_indexes", &self.global_indexes)
             .finish()
     }
@@ -150,8 +209,10 @@ impl<F: Fn(&Operator) -> u64 + Send + Sync + 'static> ModuleMiddleware for Meter
     fn generate_function_middleware(&self, _: LocalFunctionIndex) -> Box<dyn FunctionMiddleware> {
         Box::new(FunctionMetering {
             cost_function: self.cost_function.clone(),
+            dynamic_cost_function: self.dynamic_cost_function.clone(),
             global_indexes: self.global_indexes.lock().unwrap().clone().unwrap(),
             accumulated_cost: 0,
+            size_local: 0,
         })
     }
 
@@ -209,12 +270,130 @@ impl<F: Fn(&Operator) -> u64 + Send + Sync> fmt::Debug for FunctionMetering<F> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("FunctionMetering")
             .field("cost_function", &"<function>")
+            .field(
+                "dynamic_cost_function",
+                &self.dynamic_cost_function.as_ref().map(|_| "<function>"),
+            )
             .field("global_indexes", &self.global_indexes)
             .finish()
     }
 }
 
+/// Returns whether the last operand of `operator` is a size, for which
+/// it can be charged dynamically.
+fn has_size_operand(operator: &Operator) -> bool {
+    matches!(
+        operator,
+        Operator::MemoryCopy { .. }
+            | Operator::MemoryFill { .. }
+            | Operator::MemoryInit { .. }
+            | Operator::MemoryGrow { .. }
+            | Operator::TableCopy { .. }
+            | Operator::TableFill { .. }
+            | Operator::TableInit { .. }
+            | Operator::TableGrow { .. }
+    )
+}
+
+impl<F: Fn(&Operator) -> u64 + Send + Sync> FunctionMetering<F> {
+    /// Charges the accumulated cost of the current basic block.
+    fn charge_accumulated_cost(&mut self, state: &mut MiddlewareReaderState<'_>) {
+        if self.accumulated_cost > 0 {
+            state.extend(&[
+                // if unsigned(globals[remaining_points_index]) < unsigned(self.accumulated_cost) { throw(); }
+                Operator::GlobalGet {
+                    global_index: self.global_indexes.remaining_points().as_u32(),
+                },
+                Operator::I64Const {
+                    value: self.accumulated_cost as i64,
+                },
+                Operator::I64LtU,
+                Operator::If {
+                    ty: WpTypeOrFuncType::Type(WpType::EmptyBlockType),
+                },
+                Operator::I32Const { value: 1 },
+                Operator::GlobalSet {
+                    global_index: self.global_indexes.points_exhausted().as_u32(),
+                },
+                Operator::Unreachable,
+                Operator::End,
+                // globals[remaining_points_index] -= self.accumulated_cost;
+                Operator::GlobalGet {
+                    global_index: self.global_indexes.remaining_points().as_u32(),
+                },
+                Operator::I64Const {
+                    value: self.accumulated_cost as i64,
+                },
+                Operator::I64Sub,
+                Operator::GlobalSet {
+                    global_index: self.global_indexes.remaining_points().as_u32(),
+                },
+            ]);
+
+            self.accumulated_cost = 0;
+        }
+    }
+
+    /// Charges `unit_cost` per unit of the size operand on top of the
+    /// stack, leaving the operand in place.
+    fn charge_dynamic_cost(&self, unit_cost: u64, state: &mut MiddlewareReaderState<'_>) {
+        let size_local = self.size_local;
+        state.extend(&[
+            Operator::LocalSet {
+                local_index: size_local,
+            },
+            // if unsigned(globals[remaining_points_index]) / unit_cost < size { throw(); }
+            Operator::GlobalGet {
+                global_index: self.global_indexes.remaining_points().as_u32(),
+            },
+            Operator::I64Const {
+                value: unit_cost as i64,
+            },
+            Operator::I64DivU,
+            Operator::LocalGet {
+                local_index: size_local,
+            },
+            Operator::I64ExtendI32U,
+            Operator::I64LtU,
+            Operator::If {
+                ty: WpTypeOrFuncType::Type(WpType::EmptyBlockType),
+            },
+            Operator::I32Const { value: 1 },
+            Operator::GlobalSet {
+                global_index: self.global_indexes.points_exhausted().as_u32(),
+            },
+            Operator::Unreachable,
+            Operator::End,
+            // globals[remaining_points_index] -= size * unit_cost;
+            Operator::GlobalGet {
+                global_index: self.global_indexes.remaining_points().as_u32(),
+            },
+            Operator::LocalGet {
+                local_index: size_local,
+            },
+            Operator::I64ExtendI32U,
+            Operator::I64Const {
+                value: unit_cost as i64,
+            },
+            Operator::I64Mul,
+            Operator::I64Sub,
+            Operator::GlobalSet {
+                global_index: self.global_indexes.remaining_points().as_u32(),
+            },
+            Operator::LocalGet {
+                local_index: size_local,
+            },
+        ]);
+    }
+}
+
 impl<F: Fn(&Operator) -> u64 + Send + Sync> FunctionMiddleware for FunctionMetering<F> {
+    fn declare_locals(&mut self, locals: &mut FunctionLocals<'_>) {
+        if self.dynamic_cost_function.is_some() {
+            self.size_local = locals.declare(1, WpType::I32);
+        }
+    }
+
     fn feed<'a>(
         &mut self,
         operator: Operator<'a>,
@@ -225,6 +404,15 @@ impl<F: Fn(&Operator) -> u64 + Send + Sync> FunctionMiddleware for FunctionMeter
         // corner cases.
         self.accumulated_cost += (self.cost_function)(&operator);
 
+        // The cost per unit of size of the current operator, if charged
+        // dynamically.
+        let unit_cost = match &self.dynamic_cost_function {
+            Some(dynamic_cost_function) if has_size_operand(&operator) => {
+                dynamic_cost_function(&operator)
+            }
+            _ => 0,
+        };
+
         // Possible sources and targets of a branch. Finalize the cost of the previous basic block and perform necessary checks.
         match operator {
             Operator::Loop { .. } // loop headers are branch targets
@@ -237,30 +425,17 @@ impl<F: Fn(&Operator) -> u64 + Send + Sync> FunctionMiddleware for FunctionMeter
             | Operator::CallIndirect { .. } // function call - branch source
             | Operator::Return // end of function - branch source
             => {
-                if self.accumulated_cost > 0 {
-                    state.extend(&[
-                        // if unsigned(globals[remaining_points_index]) < unsigned(self.accumulated_cost) { throw(); }
-                        Operator::GlobalGet { global_index: self.global_indexes.remaining_points().as_u32() },
-                        Operator::I64Const { value: self.accumulated_cost as i64 },
-                        Operator::I64LtU,
-                        Operator::If { ty: WpTypeOrFuncType::Type(WpType::EmptyBlockType) },
-                        Operator::I32Const { value: 1 },
-                        Operator::GlobalSet { global_index: self.global_indexes.points_exhausted().as_u32() },
-                        Operator::Unreachable,
-                        Operator::End,
-
-                        // globals[remaining_points_index] -= self.accumulated_cost;
-                        Operator::GlobalGet { global_index: self.global_indexes.remaining_points().as_u32() },
-                        Operator::I64Const { value: self.accumulated_cost as i64 },
-                        Operator::I64Sub,
-                        Operator::GlobalSet { global_index: self.global_indexes.remaining_points().as_u32() },
-                    ]);
-
-                    self.accumulated_cost = 0;
-                }
+                self.charge_accumulated_cost(state);
+            }
+            // operator charged dynamically - charge the operators before it first
+            _ if unit_cost > 0 => {
+                self.charge_accumulated_cost(state);
             }
             _ => {}
         }
+        if unit_cost > 0 {
+            self.charge_dynamic_cost(unit_cost, state);
+        }
         state.push_operator(operator);
 
         Ok(())
@@ -485,4 +660,59 @@ mod tests {
             MeteringPoints::Remaining(4)
         );
     }
+
+    #[test]
+    fn dynamic_cost_function_works() {
+        let metering = Arc::new(
+            Metering::new(100, |_: &Operator| -> u64 { 0 }).with_dynamic_cost_function(
+                |operator: &Operator| -> u64 {
+                    match operator {
+                        Operator::MemoryFill { .. } => 2,
+                        _ => 0,
+                    }
+                },
+            ),
+        );
+        let mut compiler_config = Cranelift::default();
+        compiler_config.push_middleware(metering);
+        let store = Store::new(&Universal::new(compiler_config).engine());
+        let module = Module::new(
+            &store,
+            wat2wasm(
+                br#"
+                (module
+                (memory (export "memory") 1)
+                (func (export "fill") (param $length i32)
+                    i32.const 0
+                    i32.const 1
+                    local.get $length
+                    memory.fill))
+                "#,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let instance = Instance::new(&module, &imports! {}).unwrap();
+        let fill = instance
+            .exports
+            .get_function("fill")
+            .unwrap()
+            .native::<i32, ()>()
+            .unwrap();
+        let memory = instance.exports.get_memory("memory").unwrap();
+
+        // Filling 10 bytes costs 20 points.
+        fill.call(10).unwrap();
+        assert_eq!(
+            get_remaining_points(&instance),
+            MeteringPoints::Remaining(80)
+        );
+        assert_eq!(memory.view::<u8>()[9].get(), 1);
+
+        // Filling 50 bytes would cost 100 points, so it fails before
+        // writing to the memory.
+        assert!(fill.call(50).is_err());
+        assert_eq!(get_remaining_points(&instance), MeteringPoints::Exhausted);
+        assert_eq!(memory.view::<u8>()[10].get(), 0);
+    }
 }
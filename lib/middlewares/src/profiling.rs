@@ -0,0 +1,760 @@
+//! `profiling` is a middleware counting how many times each function of
+//! a module is called and, optionally, how much time is spent in each
+//! call stack, to find the hot functions of a module without an external
+//! profiler.
+//!
+//! The call counts are stored in exported globals. The timings are
+//! measured by a [`Profiler`], whose functions are imported by the
+//! instrumented module and called on entry and exit of every function.
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::sync::Arc;
+//! use wasmer::{CompilerConfig, Instance, Module};
+//! use wasmer_middlewares::profiling::{Profile, Profiler, Profiling};
+//!
+//! fn add_profiling_middleware(compiler_config: &mut dyn CompilerConfig) {
+//!     compiler_config.push_middleware(Arc::new(Profiling::with_timing()));
+//! }
+//!
+//! fn run_and_profile(module: &Module) -> Result<(), Box<dyn std::error::Error>> {
+//!     let profiler = Profiler::new();
+//!     let instance = Instance::new(module, &profiler.imports(module.store()))?;
+//!
+//!     instance.exports.get_function("run")?.call(&[])?;
+//!
+//!     let profile = Profile::with_timings(module, &instance, &profiler);
+//!     std::fs::write("profile.folded", profile.to_folded())?;
+//!     Ok(())
+//! }
+//! ```
+
+use loupe::{MemoryUsage, MemoryUsageTracker};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryInto;
+use std::fmt::Write;
+use std::mem;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use wasmer::wasmparser::{Operator, Type as WpType, TypeOrFuncType as WpTypeOrFuncType};
+use wasmer::{
+    ExportIndex, Exports, Function, FunctionLocals, FunctionMiddleware, FunctionType, GlobalInit,
+    GlobalType, ImportObject, ImportedFunctions, Instance, LocalFunctionIndex, MiddlewareError,
+    MiddlewareReaderState, Module, ModuleMiddleware, Mutability, Store, Type, WasmerEnv,
+};
+use wasmer_types::entity::EntityRef;
+use wasmer_types::{FunctionIndex, GlobalIndex, ModuleInfo};
+
+/// The module from which the instrumented modules import the functions
+/// of the [`Profiler`].
+pub const IMPORT_MODULE: &str = "wasmer_profiling";
+
+/// The prefix of the names of the exported call counters.
+const COUNTER_EXPORT_PREFIX: &str = "wasmer_profiling_calls_";
+
+#[derive(Debug, Clone, MemoryUsage)]
+struct ProfilingState {
+    /// The index of the call counter of the first local function.
+    first_counter: GlobalIndex,
+    /// The functions of the profiler, if timing.
+    imported_functions: ImportedFunctions,
+}
+
+/// The module-level profiling middleware.
+///
+/// # Panic
+///
+/// An instance of `Profiling` should _not_ be shared among different
+/// modules, since it tracks module-specific information like the
+/// global indexes of the call counters. Attempts to use a `Profiling`
+/// instance from multiple modules will result in a panic.
+#[derive(Debug)]
+pub struct Profiling {
+    /// Whether the module calls the profiler on entry and exit of the
+    /// functions.
+    timing: bool,
+    state: Mutex<Option<ProfilingState>>,
+}
+
+/// The function-level profiling middleware.
+#[derive(Debug)]
+pub struct FunctionProfiling {
+    local_function_index: LocalFunctionIndex,
+    /// The call counter of the function.
+    counter: GlobalIndex,
+    /// The functions of the profiler, if timing.
+    imported_functions: ImportedFunctions,
+    /// Whether the entry of the function was instrumented.
+    entered: bool,
+    /// The number of blocks the operators being fed are nested in.
+    depth: u32,
+    /// The local holding the condition of the conditional branches to the
+    /// label of the function, if timing.
+    condition_local: u32,
+}
+
+impl Profiling {
+    /// Creates a `Profiling` middleware counting the calls of the
+    /// functions.
+    pub fn new() -> Self {
+        Self {
+            timing: false,
+            state: Mutex::new(None),
+        }
+    }
+
+    /// Creates a `Profiling` middleware counting the calls of the
+    /// functions, and calling a [`Profiler`] on entry and exit of the
+    /// functions to time them.
+    ///
+    /// The instrumented modules import the functions of the profiler from
+    /// [`IMPORT_MODULE`], so they must be instantiated with
+    /// [`Profiler::imports`].
+    pub fn with_timing() -> Self {
+        Self {
+            timing: true,
+            state: Mutex::new(None),
+        }
+    }
+}
+
+impl Default for Profiling {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ModuleMiddleware for Profiling {
+    /// Generates a `FunctionMiddleware` for a given function.
+    fn generate_function_middleware(
+        &self,
+        local_function_index: LocalFunctionIndex,
+    ) -> Box<dyn FunctionMiddleware> {
+        let state = self.state.lock().unwrap();
+        let state = state.as_ref().unwrap();
+        Box::new(FunctionProfiling {
+            local_function_index,
+            counter: GlobalIndex::new(state.first_counter.index() + local_function_index.index()),
+            imported_functions: state.imported_functions.clone(),
+            entered: false,
+            depth: 0,
+            condition_local: 0,
+        })
+    }
+
+    /// Adds and exports a call counter for each local function, and
+    /// imports the functions of the profiler if timing.
+    fn transform_module_info(&self, module_info: &mut ModuleInfo) {
+        let mut state = self.state.lock().unwrap();
+
+        if state.is_some() {
+            panic!("Profiling::transform_module_info: Attempting to use a `Profiling` middleware from multiple modules.");
+        }
+
+        let first_counter = module_info.globals.next_key();
+        let num_local_functions = module_info.functions.len() - module_info.num_imported_functions;
+        for local_function_index in 0..num_local_functions {
+            let counter = module_info
+                .globals
+                .push(GlobalType::new(Type::I64, Mutability::Var));
+            module_info
+                .global_initializers
+                .push(GlobalInit::I64Const(0));
+            module_info.exports.insert(
+                format!("{}{}", COUNTER_EXPORT_PREFIX, local_function_index),
+                ExportIndex::Global(counter),
+            );
+        }
+
+        let mut imported_functions = ImportedFunctions::new();
+        if self.timing {
+            for field in &["enter", "exit"] {
+                imported_functions.import(
+                    module_info,
+                    IMPORT_MODULE,
+                    field,
+                    FunctionType::new(vec![Type::I32], vec![]),
+                );
+            }
+        }
+
+        *state = Some(ProfilingState {
+            first_counter,
+            imported_functions,
+        });
+    }
+}
+
+impl MemoryUsage for Profiling {
+    fn size_of_val(&self, tracker: &mut dyn MemoryUsageTracker) -> usize {
+        mem::size_of_val(self) + self.state.size_of_val(tracker) - mem::size_of_val(&self.state)
+    }
+}
+
+impl FunctionProfiling {
+    /// Returns whether the module calls the profiler.
+    fn timing(&self) -> bool {
+        self.imported_functions.indices().next().is_some()
+    }
+
+    /// Calls the profiler function `hook` (0 for `enter`, 1 for `exit`)
+    /// with the index of the function, if timing.
+    fn call_profiler(&self, hook: usize, state: &mut MiddlewareReaderState<'_>) {
+        if let Some(function_index) = self.imported_functions.indices().nth(hook) {
+            state.extend(&[
+                Operator::I32Const {
+                    value: self.local_function_index.as_u32() as i32,
+                },
+                Operator::Call {
+                    function_index: function_index.as_u32(),
+                },
+            ]);
+        }
+    }
+
+    /// Calls the profiler function `exit` if the branch condition on top of
+    /// the stack passes `test`, leaving the condition on the stack.
+    fn call_exit_if<'a>(&self, test: &[Operator<'a>], state: &mut MiddlewareReaderState<'a>) {
+        if !self.timing() {
+            return;
+        }
+        let local_index = self.condition_local;
+        state.push_operator(Operator::LocalTee { local_index });
+        state.extend(test);
+        state.push_operator(Operator::If {
+            ty: WpTypeOrFuncType::Type(WpType::EmptyBlockType),
+        });
+        self.call_profiler(1, state);
+        state.extend(&[Operator::End, Operator::LocalGet { local_index }]);
+    }
+}
+
+impl FunctionMiddleware for FunctionProfiling {
+    fn declare_locals(&mut self, locals: &mut FunctionLocals<'_>) {
+        if self.timing() {
+            self.condition_local = locals.declare(1, WpType::I32);
+        }
+    }
+
+    fn imported_functions(&self) -> Option<&ImportedFunctions> {
+        Some(&self.imported_functions)
+    }
+
+    fn feed<'a>(
+        &mut self,
+        operator: Operator<'a>,
+        state: &mut MiddlewareReaderState<'a>,
+    ) -> Result<(), MiddlewareError> {
+        if !self.entered {
+            self.entered = true;
+            let global_index = self.counter.as_u32();
+            state.extend(&[
+                // globals[counter] += 1;
+                Operator::GlobalGet { global_index },
+                Operator::I64Const { value: 1 },
+                Operator::I64Add,
+                Operator::GlobalSet { global_index },
+            ]);
+            self.call_profiler(0, state);
+        }
+
+        match operator {
+            Operator::Block { .. } | Operator::Loop { .. } | Operator::If { .. } => {
+                self.depth += 1;
+            }
+            Operator::End if self.depth > 0 => {
+                self.depth -= 1;
+            }
+            // The end of the function, and the branches to its label.
+            Operator::End
+            | Operator::Return
+            | Operator::ReturnCall { .. }
+            | Operator::ReturnCallIndirect { .. } => {
+                self.call_profiler(1, state);
+            }
+            Operator::Br { relative_depth } if relative_depth == self.depth => {
+                self.call_profiler(1, state);
+            }
+            Operator::BrIf { relative_depth } if relative_depth == self.depth => {
+                self.call_exit_if(&[], state);
+            }
+            Operator::BrTable { ref table } => {
+                let targets = table
+                    .targets()
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| MiddlewareError::new("profiling", e.to_string()))?;
+                let num_targets = targets.len() as i32 - 1;
+                for (index, (relative_depth, is_default)) in targets.into_iter().enumerate() {
+                    if relative_depth != self.depth {
+                        continue;
+                    }
+                    // the default target is taken for any index past the others
+                    let (value, test) = if is_default {
+                        (num_targets, Operator::I32GeU)
+                    } else {
+                        (index as i32, Operator::I32Eq)
+                    };
+                    self.call_exit_if(&[Operator::I32Const { value }, test], state);
+                }
+            }
+            _ => {}
+        }
+
+        state.push_operator(operator);
+        Ok(())
+    }
+}
+
+/// A function being executed.
+#[derive(Debug)]
+struct Frame {
+    function: LocalFunctionIndex,
+    start: Instant,
+    /// The time spent in the functions called by this one.
+    children: Duration,
+}
+
+#[derive(Debug, Default)]
+struct ProfilerState {
+    /// The functions being executed, the innermost last.
+    stack: Vec<Frame>,
+    /// The time spent in the innermost function of each call stack,
+    /// outermost function first.
+    stacks: HashMap<Vec<LocalFunctionIndex>, Duration>,
+}
+
+impl ProfilerState {
+    fn enter(&mut self, function: LocalFunctionIndex) {
+        self.stack.push(Frame {
+            function,
+            start: Instant::now(),
+            children: Duration::default(),
+        });
+    }
+
+    fn exit(&mut self, function: LocalFunctionIndex) {
+        // The frames unwound by a trap are never exited, so exit them
+        // along with the function.
+        if !self.stack.iter().any(|frame| frame.function == function) {
+            return;
+        }
+        let now = Instant::now();
+        while let Some(frame) = self.stack.last() {
+            let exited = frame.function == function;
+            let elapsed = now - frame.start;
+            let self_time = elapsed.checked_sub(frame.children).unwrap_or_default();
+            let stack = self.stack.iter().map(|frame| frame.function).collect();
+            *self.stacks.entry(stack).or_default() += self_time;
+            self.stack.pop();
+            if let Some(caller) = self.stack.last_mut() {
+                caller.children += elapsed;
+            }
+            if exited {
+                break;
+            }
+        }
+    }
+}
+
+/// Times the calls of the functions of the instances of modules
+/// processed with [`Profiling::with_timing`].
+///
+/// The profiler keeps track of the call stacks of the instances using
+/// its [`imports`](Profiler::imports). It can be shared by several
+/// instances, as long as they don't call each other.
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    state: Arc<Mutex<ProfilerState>>,
+}
+
+impl WasmerEnv for Profiler {}
+
+fn enter(profiler: &Profiler, function: i32) {
+    let function = LocalFunctionIndex::from_u32(function as u32);
+    profiler.state.lock().unwrap().enter(function);
+}
+
+fn exit(profiler: &Profiler, function: i32) {
+    let function = LocalFunctionIndex::from_u32(function as u32);
+    profiler.state.lock().unwrap().exit(function);
+}
+
+impl Profiler {
+    /// Creates a profiler with no timings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the functions of the profiler imported by the instrumented
+    /// modules.
+    pub fn exports(&self, store: &Store) -> Exports {
+        let mut exports = Exports::new();
+        exports.insert(
+            "enter",
+            Function::new_native_with_env(store, self.clone(), enter),
+        );
+        exports.insert(
+            "exit",
+            Function::new_native_with_env(store, self.clone(), exit),
+        );
+        exports
+    }
+
+    /// Returns the imports of the instrumented modules, to be chained with
+    /// or extended by the other imports of the module.
+    pub fn imports(&self, store: &Store) -> ImportObject {
+        let mut import_object = ImportObject::new();
+        import_object.register(IMPORT_MODULE, self.exports(store));
+        import_object
+    }
+
+    /// Discards the timings recorded so far.
+    ///
+    /// The functions unwound by a trap are only exited when one of their
+    /// callers returns, so this should be called after a trap escaping
+    /// the instance to forget about them.
+    pub fn reset(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.stack.clear();
+        state.stacks.clear();
+    }
+}
+
+/// Get the number of times each local function of an
+/// [`Instance`][wasmer::Instance] was called.
+///
+/// # Panic
+///
+/// The instance [`Module`][wasmer::Module] must have been processed with
+/// the [`Profiling`] middleware at compile time, otherwise this will
+/// panic.
+pub fn get_call_counts(instance: &Instance) -> Vec<u64> {
+    let info = instance.module().info();
+    let num_local_functions = info.functions.len() - info.num_imported_functions;
+    (0..num_local_functions)
+        .map(|local_function_index| {
+            let counter_name = format!("{}{}", COUNTER_EXPORT_PREFIX, local_function_index);
+            let calls: i64 = instance
+                .exports
+                .get_global(&counter_name)
+                .expect("Can't get a call counter from Instance")
+                .get()
+                .try_into()
+                .expect("A call counter from Instance has wrong type");
+            calls as u64
+        })
+        .collect()
+}
+
+/// The profile of a local function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionProfile {
+    /// The index of the function.
+    pub function_index: FunctionIndex,
+    /// The name of the function, or `function_{index}` if it has none.
+    pub name: String,
+    /// The number of times the function was called.
+    pub calls: u64,
+    /// The time spent in the function itself, if timed.
+    pub self_time: Option<Duration>,
+    /// The time spent in the function and the functions it called, if
+    /// timed.
+    pub total_time: Option<Duration>,
+}
+
+/// The profile of an [`Instance`][wasmer::Instance].
+#[derive(Debug, Clone)]
+pub struct Profile {
+    functions: Vec<FunctionProfile>,
+    /// The time spent in the innermost function of each call stack, if
+    /// timed.
+    stacks: Option<BTreeMap<Vec<LocalFunctionIndex>, Duration>>,
+}
+
+impl Profile {
+    /// Reads the call counts of `instance`, an instance of `module`.
+    ///
+    /// # Panic
+    ///
+    /// The `module` must have been processed with the [`Profiling`]
+    /// middleware at compile time, otherwise this will panic.
+    pub fn new(module: &Module, instance: &Instance) -> Self {
+        let info = module.info();
+        let functions = get_call_counts(instance)
+            .into_iter()
+            .enumerate()
+            .map(|(local_function_index, calls)| {
+                let function_index = info.func_index(LocalFunctionIndex::new(local_function_index));
+                FunctionProfile {
+                    function_index,
+                    name: info
+                        .function_names
+                        .get(&function_index)
+                        .cloned()
+                        .unwrap_or_else(|| format!("function_{}", function_index.index())),
+                    calls,
+                    self_time: None,
+                    total_time: None,
+                }
+            })
+            .collect();
+        Self {
+            functions,
+            stacks: None,
+        }
+    }
+
+    /// Reads the call counts of `instance`, an instance of `module`, and
+    /// the timings of its functions recorded by `profiler`.
+    ///
+    /// # Panic
+    ///
+    /// The `module` must have been processed with the [`Profiling`]
+    /// middleware at compile time, otherwise this will panic.
+    pub fn with_timings(module: &Module, instance: &Instance, profiler: &Profiler) -> Self {
+        let mut profile = Self::new(module, instance);
+        let stacks = profiler
+            .state
+            .lock()
+            .unwrap()
+            .stacks
+            .iter()
+            .filter(|(stack, _)| {
+                stack
+                    .iter()
+                    .all(|function| function.index() < profile.functions.len())
+            })
+            .map(|(stack, time)| (stack.clone(), *time))
+            .collect::<BTreeMap<_, _>>();
+
+        for function in profile.functions.iter_mut() {
+            function.self_time = Some(Duration::default());
+            function.total_time = Some(Duration::default());
+        }
+        for (stack, time) in &stacks {
+            let innermost = stack.last().unwrap();
+            *profile.functions[innermost.index()]
+                .self_time
+                .as_mut()
+                .unwrap() += *time;
+            // Recursive functions only count once per stack.
+            let mut functions = stack.clone();
+            functions.sort();
+            functions.dedup();
+            for function in functions {
+                *profile.functions[function.index()]
+                    .total_time
+                    .as_mut()
+                    .unwrap() += *time;
+            }
+        }
+        profile.stacks = Some(stacks);
+        profile
+    }
+
+    /// Returns the profiles of the local functions.
+    pub fn functions(&self) -> &[FunctionProfile] {
+        &self.functions
+    }
+
+    /// Formats the profile in the folded stacks format used by flame graph
+    /// tools such as `inferno` or `flamegraph.pl`.
+    ///
+    /// Each line is a call stack, outermost function first, followed by
+    /// the nanoseconds spent in its innermost function if timed, or by the
+    /// number of calls of a function otherwise.
+    pub fn to_folded(&self) -> String {
+        // The separators of the format can't appear in the names.
+        let names = self
+            .functions
+            .iter()
+            .map(|function| function.name.replace(';', ":").replace(' ', "_"))
+            .collect::<Vec<_>>();
+        let mut folded = String::new();
+        match &self.stacks {
+            Some(stacks) => {
+                for (stack, time) in stacks {
+                    let stack = stack
+                        .iter()
+                        .map(|function| names[function.index()].as_str())
+                        .collect::<Vec<_>>()
+                        .join(";");
+                    writeln!(folded, "{} {}", stack, time.as_nanos()).unwrap();
+                }
+            }
+            None => {
+                for (name, function) in names.iter().zip(&self.functions) {
+                    if function.calls > 0 {
+                        writeln!(folded, "{} {}", name, function.calls).unwrap();
+                    }
+                }
+            }
+        }
+        folded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use wasmer::{imports, wat2wasm, CompilerConfig, Cranelift, Module, Store, Universal};
+
+    fn bytecode() -> Vec<u8> {
+        wat2wasm(
+            br#"
+            (module
+            (func $fib (export "fib") (param $n i32) (result i32)
+                local.get $n
+                i32.const 2
+                i32.lt_u
+                if
+                    local.get $n
+                    return
+                end
+                local.get $n
+                i32.const 1
+                i32.sub
+                call $fib
+                local.get $n
+                i32.const 2
+                i32.sub
+                call $fib
+                i32.add)
+            (func $run (export "run") (result i32)
+                i32.const 3
+                call $fib))
+            "#,
+        )
+        .unwrap()
+        .into()
+    }
+
+    #[test]
+    fn call_counts_work() {
+        let mut compiler_config = Cranelift::default();
+        compiler_config.push_middleware(Arc::new(Profiling::new()));
+        let store = Store::new(&Universal::new(compiler_config).engine());
+        let module = Module::new(&store, bytecode()).unwrap();
+        assert_eq!(module.imports().count(), 0);
+        let instance = Instance::new(&module, &imports! {}).unwrap();
+
+        let run = instance
+            .exports
+            .get_function("run")
+            .unwrap()
+            .native::<(), i32>()
+            .unwrap();
+        assert_eq!(run.call().unwrap(), 2);
+
+        assert_eq!(get_call_counts(&instance), [5, 1]);
+        let profile = Profile::new(&module, &instance);
+        assert_eq!(profile.functions()[0].name, "fib");
+        assert_eq!(profile.functions()[0].self_time, None);
+        assert_eq!(profile.to_folded(), "fib 5\nrun 1\n");
+    }
+
+    #[test]
+    fn timings_work() {
+        let mut compiler_config = Cranelift::default();
+        compiler_config.push_middleware(Arc::new(Profiling::with_timing()));
+        let store = Store::new(&Universal::new(compiler_config).engine());
+        let module = Module::new(&store, bytecode()).unwrap();
+        let profiler = Profiler::new();
+        let instance = Instance::new(&module, &profiler.imports(&store)).unwrap();
+
+        let run = instance
+            .exports
+            .get_function("run")
+            .unwrap()
+            .native::<(), i32>()
+            .unwrap();
+        assert_eq!(run.call().unwrap(), 2);
+
+        let profile = Profile::with_timings(&module, &instance, &profiler);
+        assert_eq!(
+            profile
+                .functions()
+                .iter()
+                .map(|function| function.calls)
+                .collect::<Vec<_>>(),
+            [5, 1]
+        );
+        let run = &profile.functions()[1];
+        assert!(run.total_time.unwrap() >= profile.functions()[0].total_time.unwrap());
+        let stacks = profile
+            .to_folded()
+            .lines()
+            .map(|line| line.rsplitn(2, ' ').nth(1).unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(stacks, ["run", "run;fib", "run;fib;fib", "run;fib;fib;fib"]);
+    }
+
+    #[test]
+    fn timings_exit_on_branches_to_the_function_label() {
+        let bytecode = wat2wasm(
+            br#"
+            (module
+            (func $leave (param $how i32) (result i32)
+                block
+                    i32.const 1
+                    local.get $how
+                    i32.eqz
+                    br_if 1
+                    drop
+                end
+                block $other (result i32)
+                    i32.const 2
+                    local.get $how
+                    i32.const 1
+                    i32.sub
+                    br_table 1 $other 1
+                end
+                drop
+                i32.const 3
+                br 0)
+            (func $run (export "run") (result i32)
+                i32.const 0
+                call $leave
+                i32.const 1
+                call $leave
+                i32.add
+                i32.const 2
+                call $leave
+                i32.add
+                i32.const 3
+                call $leave
+                i32.add))
+            "#,
+        )
+        .unwrap();
+        let mut compiler_config = Cranelift::default();
+        compiler_config.push_middleware(Arc::new(Profiling::with_timing()));
+        let store = Store::new(&Universal::new(compiler_config).engine());
+        let module = Module::new(&store, bytecode).unwrap();
+        let profiler = Profiler::new();
+        let instance = Instance::new(&module, &profiler.imports(&store)).unwrap();
+
+        let run = instance
+            .exports
+            .get_function("run")
+            .unwrap()
+            .native::<(), i32>()
+            .unwrap();
+        // `$leave` returns with `br_if`, a `br_table` target, `br` and the
+        // `br_table` default.
+        assert_eq!(run.call().unwrap(), 1 + 2 + 3 + 2);
+
+        // Each call of `$leave` was exited before the next one.
+        assert!(profiler.state.lock().unwrap().stack.is_empty());
+        let profile = Profile::with_timings(&module, &instance, &profiler);
+        let stacks = profile
+            .to_folded()
+            .lines()
+            .map(|line| line.rsplitn(2, ' ').nth(1).unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(stacks, ["run", "run;leave"]);
+    }
+}
@@ -0,0 +1,636 @@
+//! `soft_float` is a middleware making the floating point arithmetic of
+//! a module deterministic across compilers and platforms, either by
+//! emulating it or by rejecting the modules using it.
+//!
+//! [`CompilerConfig::canonicalize_nans`][wasmer::CompilerConfig::canonicalize_nans]
+//! only covers the bit patterns of the NaNs produced by the compiled
+//! code, while the compilers may still differ in their lowering of the
+//! float operators. With [`SoftFloatMode::Emulate`], every float operator
+//! computing a new float (the arithmetic, rounding and conversion
+//! operators) is rewritten into a call to a host function, provided by
+//! [`imports`], which is the same for all the compilers and produces
+//! canonical NaNs. The other float operators (constants, loads, stores,
+//! comparisons, `abs`, `neg`, `copysign`, reinterpretations and
+//! truncations to integers) are exactly specified by WebAssembly and
+//! left untouched.
+//!
+//! With [`SoftFloatMode::Reject`], the compilation of a module using any
+//! float operator fails instead.
+//!
+//! The SIMD float operators are neither emulated nor rejected: the SIMD
+//! feature should be disabled for a deterministic execution.
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::sync::Arc;
+//! use wasmer::{CompilerConfig, Instance, Module};
+//! use wasmer_middlewares::soft_float::{imports, SoftFloat, SoftFloatMode};
+//!
+//! fn add_soft_float_middleware(compiler_config: &mut dyn CompilerConfig) {
+//!     compiler_config.push_middleware(Arc::new(SoftFloat::new(SoftFloatMode::Emulate)));
+//! }
+//!
+//! fn instantiate(module: &Module) -> Result<Instance, Box<dyn std::error::Error>> {
+//!     Ok(Instance::new(module, &imports(module.store()))?)
+//! }
+//! ```
+
+use loupe::{MemoryUsage, MemoryUsageTracker};
+use std::mem;
+use std::sync::Mutex;
+use wasmer::wasmparser::{Operator, Type as WpType};
+use wasmer::{
+    Exports, Function, FunctionMiddleware, FunctionType, ImportObject, ImportedFunctions,
+    LocalFunctionIndex, MiddlewareError, MiddlewareReaderState, ModuleMiddleware, Store, Type,
+};
+use wasmer_types::ModuleInfo;
+
+/// The module from which the modules processed in the
+/// [`SoftFloatMode::Emulate`] mode import the float operations.
+pub const IMPORT_MODULE: &str = "wasmer_soft_float";
+
+const F32_UNARY: (&[Type], &[Type]) = (&[Type::F32], &[Type::F32]);
+const F32_BINARY: (&[Type], &[Type]) = (&[Type::F32, Type::F32], &[Type::F32]);
+const F64_UNARY: (&[Type], &[Type]) = (&[Type::F64], &[Type::F64]);
+const F64_BINARY: (&[Type], &[Type]) = (&[Type::F64, Type::F64], &[Type::F64]);
+
+/// The emulated float operations, in the order in which they are
+/// imported, with their signatures.
+const OPERATIONS: &[(&str, (&[Type], &[Type]))] = &[
+    ("f32_add", F32_BINARY),
+    ("f32_sub", F32_BINARY),
+    ("f32_mul", F32_BINARY),
+    ("f32_div", F32_BINARY),
+    ("f32_min", F32_BINARY),
+    ("f32_max", F32_BINARY),
+    ("f32_sqrt", F32_UNARY),
+    ("f32_ceil", F32_UNARY),
+    ("f32_floor", F32_UNARY),
+    ("f32_trunc", F32_UNARY),
+    ("f32_nearest", F32_UNARY),
+    ("f32_convert_i32_s", (&[Type::I32], &[Type::F32])),
+    ("f32_convert_i32_u", (&[Type::I32], &[Type::F32])),
+    ("f32_convert_i64_s", (&[Type::I64], &[Type::F32])),
+    ("f32_convert_i64_u", (&[Type::I64], &[Type::F32])),
+    ("f32_demote_f64", (&[Type::F64], &[Type::F32])),
+    ("f64_add", F64_BINARY),
+    ("f64_sub", F64_BINARY),
+    ("f64_mul", F64_BINARY),
+    ("f64_div", F64_BINARY),
+    ("f64_min", F64_BINARY),
+    ("f64_max", F64_BINARY),
+    ("f64_sqrt", F64_UNARY),
+    ("f64_ceil", F64_UNARY),
+    ("f64_floor", F64_UNARY),
+    ("f64_trunc", F64_UNARY),
+    ("f64_nearest", F64_UNARY),
+    ("f64_convert_i32_s", (&[Type::I32], &[Type::F64])),
+    ("f64_convert_i32_u", (&[Type::I32], &[Type::F64])),
+    ("f64_convert_i64_s", (&[Type::I64], &[Type::F64])),
+    ("f64_convert_i64_u", (&[Type::I64], &[Type::F64])),
+    ("f64_promote_f32", (&[Type::F32], &[Type::F64])),
+];
+
+/// Returns the name of the emulated operation implementing `operator`,
+/// if any.
+fn emulated_operation(operator: &Operator) -> Option<&'static str> {
+    Some(match operator {
+        Operator::F32Add => "f32_add",
+        Operator::F32Sub => "f32_sub",
+        Operator::F32Mul => "f32_mul",
+        Operator::F32Div => "f32_div",
+        Operator::F32Min => "f32_min",
+        Operator::F32Max => "f32_max",
+        Operator::F32Sqrt => "f32_sqrt",
+        Operator::F32Ceil => "f32_ceil",
+        Operator::F32Floor => "f32_floor",
+        Operator::F32Trunc => "f32_trunc",
+        Operator::F32Nearest => "f32_nearest",
+        Operator::F32ConvertI32S => "f32_convert_i32_s",
+        Operator::F32ConvertI32U => "f32_convert_i32_u",
+        Operator::F32ConvertI64S => "f32_convert_i64_s",
+        Operator::F32ConvertI64U => "f32_convert_i64_u",
+        Operator::F32DemoteF64 => "f32_demote_f64",
+        Operator::F64Add => "f64_add",
+        Operator::F64Sub => "f64_sub",
+        Operator::F64Mul => "f64_mul",
+        Operator::F64Div => "f64_div",
+        Operator::F64Min => "f64_min",
+        Operator::F64Max => "f64_max",
+        Operator::F64Sqrt => "f64_sqrt",
+        Operator::F64Ceil => "f64_ceil",
+        Operator::F64Floor => "f64_floor",
+        Operator::F64Trunc => "f64_trunc",
+        Operator::F64Nearest => "f64_nearest",
+        Operator::F64ConvertI32S => "f64_convert_i32_s",
+        Operator::F64ConvertI32U => "f64_convert_i32_u",
+        Operator::F64ConvertI64S => "f64_convert_i64_s",
+        Operator::F64ConvertI64U => "f64_convert_i64_u",
+        Operator::F64PromoteF32 => "f64_promote_f32",
+        _ => return None,
+    })
+}
+
+/// Returns whether `operator` is a scalar float operator.
+fn is_float_operator(operator: &Operator) -> bool {
+    if emulated_operation(operator).is_some() {
+        return true;
+    }
+    match operator {
+        Operator::F32Const { .. }
+        | Operator::F64Const { .. }
+        | Operator::F32Load { .. }
+        | Operator::F64Load { .. }
+        | Operator::F32Store { .. }
+        | Operator::F64Store { .. }
+        | Operator::F32Eq
+        | Operator::F32Ne
+        | Operator::F32Lt
+        | Operator::F32Gt
+        | Operator::F32Le
+        | Operator::F32Ge
+        | Operator::F64Eq
+        | Operator::F64Ne
+        | Operator::F64Lt
+        | Operator::F64Gt
+        | Operator::F64Le
+        | Operator::F64Ge
+        | Operator::F32Abs
+        | Operator::F32Neg
+        | Operator::F32Copysign
+        | Operator::F64Abs
+        | Operator::F64Neg
+        | Operator::F64Copysign
+        | Operator::F32ReinterpretI32
+        | Operator::F64ReinterpretI64
+        | Operator::I32ReinterpretF32
+        | Operator::I64ReinterpretF64
+        | Operator::I32TruncF32S
+        | Operator::I32TruncF32U
+        | Operator::I32TruncF64S
+        | Operator::I32TruncF64U
+        | Operator::I64TruncF32S
+        | Operator::I64TruncF32U
+        | Operator::I64TruncF64S
+        | Operator::I64TruncF64U
+        | Operator::I32TruncSatF32S
+        | Operator::I32TruncSatF32U
+        | Operator::I32TruncSatF64S
+        | Operator::I32TruncSatF64U
+        | Operator::I64TruncSatF32S
+        | Operator::I64TruncSatF32U
+        | Operator::I64TruncSatF64S
+        | Operator::I64TruncSatF64U => true,
+        Operator::TypedSelect { ty } => *ty == WpType::F32 || *ty == WpType::F64,
+        _ => false,
+    }
+}
+
+/// How the [`SoftFloat`] middleware handles the float operators.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoftFloatMode {
+    /// The float operators computing a new float are rewritten into
+    /// calls to the host functions provided by [`imports`].
+    Emulate,
+    /// The compilation of the modules using float operators fails.
+    Reject,
+}
+
+/// The module-level soft float middleware.
+///
+/// # Panic
+///
+/// An instance of `SoftFloat` in the [`SoftFloatMode::Emulate`] mode
+/// should _not_ be shared among different modules, since it tracks
+/// module-specific information like the indices of the imported
+/// functions. Attempts to use such a `SoftFloat` instance from multiple
+/// modules will result in a panic.
+#[derive(Debug)]
+pub struct SoftFloat {
+    mode: SoftFloatMode,
+    /// The functions implementing the emulated operations.
+    imported_functions: Mutex<Option<ImportedFunctions>>,
+}
+
+/// The function-level soft float middleware.
+#[derive(Debug)]
+pub struct FunctionSoftFloat {
+    mode: SoftFloatMode,
+    /// The functions implementing the emulated operations, in the
+    /// [`SoftFloatMode::Emulate`] mode.
+    imported_functions: Option<ImportedFunctions>,
+}
+
+impl SoftFloat {
+    /// Creates a `SoftFloat` middleware handling the float operators
+    /// according to `mode`.
+    ///
+    /// In the [`SoftFloatMode::Emulate`] mode, the instrumented modules
+    /// import the float operations from [`IMPORT_MODULE`], so they must
+    /// be instantiated with [`imports`].
+    pub fn new(mode: SoftFloatMode) -> Self {
+        Self {
+            mode,
+            imported_functions: Mutex::new(None),
+        }
+    }
+}
+
+impl ModuleMiddleware for SoftFloat {
+    /// Generates a `FunctionMiddleware` for a given function.
+    fn generate_function_middleware(&self, _: LocalFunctionIndex) -> Box<dyn FunctionMiddleware> {
+        Box::new(FunctionSoftFloat {
+            mode: self.mode,
+            imported_functions: self.imported_functions.lock().unwrap().clone(),
+        })
+    }
+
+    /// Imports the emulated float operations.
+    fn transform_module_info(&self, module_info: &mut ModuleInfo) {
+        if self.mode != SoftFloatMode::Emulate {
+            return;
+        }
+
+        let mut imported_functions = self.imported_functions.lock().unwrap();
+
+        if imported_functions.is_some() {
+            panic!("SoftFloat::transform_module_info: Attempting to use a `SoftFloat` middleware from multiple modules.");
+        }
+
+        let mut imports = ImportedFunctions::new();
+        for (name, (params, results)) in OPERATIONS {
+            imports.import(
+                module_info,
+                IMPORT_MODULE,
+                name,
+                FunctionType::new(*params, *results),
+            );
+        }
+        *imported_functions = Some(imports);
+    }
+}
+
+impl MemoryUsage for SoftFloat {
+    fn size_of_val(&self, tracker: &mut dyn MemoryUsageTracker) -> usize {
+        mem::size_of_val(self) + self.imported_functions.size_of_val(tracker)
+            - mem::size_of_val(&self.imported_functions)
+    }
+}
+
+impl FunctionMiddleware for FunctionSoftFloat {
+    fn imported_functions(&self) -> Option<&ImportedFunctions> {
+        self.imported_functions.as_ref()
+    }
+
+    fn feed<'a>(
+        &mut self,
+        operator: Operator<'a>,
+        state: &mut MiddlewareReaderState<'a>,
+    ) -> Result<(), MiddlewareError> {
+        match self.mode {
+            SoftFloatMode::Reject if is_float_operator(&operator) => {
+                return Err(MiddlewareError::new(
+                    "soft_float",
+                    format!(
+                        "float operator `{:?}` at offset {} is not allowed",
+                        operator,
+                        state.original_offset()
+                    ),
+                ));
+            }
+            SoftFloatMode::Emulate => {
+                if let Some(name) = emulated_operation(&operator) {
+                    let position = OPERATIONS
+                        .iter()
+                        .position(|(operation, _)| *operation == name)
+                        .unwrap();
+                    let function_index = self
+                        .imported_functions
+                        .as_ref()
+                        .unwrap()
+                        .indices()
+                        .nth(position)
+                        .unwrap();
+                    state.push_operator(Operator::Call {
+                        function_index: function_index.as_u32(),
+                    });
+                    return Ok(());
+                }
+            }
+            _ => {}
+        }
+        state.push_operator(operator);
+        Ok(())
+    }
+}
+
+const CANONICAL_NAN_F32: u32 = 0x7fc0_0000;
+const CANONICAL_NAN_F64: u64 = 0x7ff8_0000_0000_0000;
+
+fn canonical_f32(value: f32) -> f32 {
+    if value.is_nan() {
+        f32::from_bits(CANONICAL_NAN_F32)
+    } else {
+        value
+    }
+}
+
+fn canonical_f64(value: f64) -> f64 {
+    if value.is_nan() {
+        f64::from_bits(CANONICAL_NAN_F64)
+    } else {
+        value
+    }
+}
+
+fn f32_add(a: f32, b: f32) -> f32 {
+    canonical_f32(a + b)
+}
+
+fn f32_sub(a: f32, b: f32) -> f32 {
+    canonical_f32(a - b)
+}
+
+fn f32_mul(a: f32, b: f32) -> f32 {
+    canonical_f32(a * b)
+}
+
+fn f32_div(a: f32, b: f32) -> f32 {
+    canonical_f32(a / b)
+}
+
+fn f32_min(a: f32, b: f32) -> f32 {
+    if a.is_nan() || b.is_nan() {
+        f32::from_bits(CANONICAL_NAN_F32)
+    } else if a == b {
+        // `-0.0` is less than `0.0`.
+        f32::from_bits(a.to_bits() | b.to_bits())
+    } else {
+        a.min(b)
+    }
+}
+
+fn f32_max(a: f32, b: f32) -> f32 {
+    if a.is_nan() || b.is_nan() {
+        f32::from_bits(CANONICAL_NAN_F32)
+    } else if a == b {
+        // `0.0` is greater than `-0.0`.
+        f32::from_bits(a.to_bits() & b.to_bits())
+    } else {
+        a.max(b)
+    }
+}
+
+fn f32_sqrt(a: f32) -> f32 {
+    canonical_f32(a.sqrt())
+}
+
+fn f32_ceil(a: f32) -> f32 {
+    canonical_f32(a.ceil())
+}
+
+fn f32_floor(a: f32) -> f32 {
+    canonical_f32(a.floor())
+}
+
+fn f32_trunc(a: f32) -> f32 {
+    canonical_f32(a.trunc())
+}
+
+fn f32_nearest(a: f32) -> f32 {
+    // Rounds half to even, while `round` rounds half away from zero.
+    if (a - a.trunc()).abs() == 0.5 {
+        canonical_f32(2.0 * (a / 2.0).round())
+    } else {
+        canonical_f32(a.round())
+    }
+}
+
+fn f32_convert_i32_s(a: i32) -> f32 {
+    a as f32
+}
+
+fn f32_convert_i32_u(a: i32) -> f32 {
+    a as u32 as f32
+}
+
+fn f32_convert_i64_s(a: i64) -> f32 {
+    a as f32
+}
+
+fn f32_convert_i64_u(a: i64) -> f32 {
+    a as u64 as f32
+}
+
+fn f32_demote_f64(a: f64) -> f32 {
+    canonical_f32(a as f32)
+}
+
+fn f64_add(a: f64, b: f64) -> f64 {
+    canonical_f64(a + b)
+}
+
+fn f64_sub(a: f64, b: f64) -> f64 {
+    canonical_f64(a - b)
+}
+
+fn f64_mul(a: f64, b: f64) -> f64 {
+    canonical_f64(a * b)
+}
+
+fn f64_div(a: f64, b: f64) -> f64 {
+    canonical_f64(a / b)
+}
+
+fn f64_min(a: f64, b: f64) -> f64 {
+    if a.is_nan() || b.is_nan() {
+        f64::from_bits(CANONICAL_NAN_F64)
+    } else if a == b {
+        // `-0.0` is less than `0.0`.
+        f64::from_bits(a.to_bits() | b.to_bits())
+    } else {
+        a.min(b)
+    }
+}
+
+fn f64_max(a: f64, b: f64) -> f64 {
+    if a.is_nan() || b.is_nan() {
+        f64::from_bits(CANONICAL_NAN_F64)
+    } else if a == b {
+        // `0.0` is greater than `-0.0`.
+        f64::from_bits(a.to_bits() & b.to_bits())
+    } else {
+        a.max(b)
+    }
+}
+
+fn f64_sqrt(a: f64) -> f64 {
+    canonical_f64(a.sqrt())
+}
+
+fn f64_ceil(a: f64) -> f64 {
+    canonical_f64(a.ceil())
+}
+
+fn f64_floor(a: f64) -> f64 {
+    canonical_f64(a.floor())
+}
+
+fn f64_trunc(a: f64) -> f64 {
+    canonical_f64(a.trunc())
+}
+
+fn f64_nearest(a: f64) -> f64 {
+    // Rounds half to even, while `round` rounds half away from zero.
+    if (a - a.trunc()).abs() == 0.5 {
+        canonical_f64(2.0 * (a / 2.0).round())
+    } else {
+        canonical_f64(a.round())
+    }
+}
+
+fn f64_convert_i32_s(a: i32) -> f64 {
+    a as f64
+}
+
+fn f64_convert_i32_u(a: i32) -> f64 {
+    a as u32 as f64
+}
+
+fn f64_convert_i64_s(a: i64) -> f64 {
+    a as f64
+}
+
+fn f64_convert_i64_u(a: i64) -> f64 {
+    a as u64 as f64
+}
+
+fn f64_promote_f32(a: f32) -> f64 {
+    canonical_f64(a as f64)
+}
+
+/// Returns the imports of the modules processed with the [`SoftFloat`]
+/// middleware in the [`SoftFloatMode::Emulate`] mode, to be chained with
+/// or extended by the other imports of the module.
+pub fn imports(store: &Store) -> ImportObject {
+    let mut exports = Exports::new();
+    macro_rules! insert {
+        ($($operation:ident),* $(,)?) => {
+            $(exports.insert(stringify!($operation), Function::new_native(store, $operation));)*
+        };
+    }
+    insert!(
+        f32_add,
+        f32_sub,
+        f32_mul,
+        f32_div,
+        f32_min,
+        f32_max,
+        f32_sqrt,
+        f32_ceil,
+        f32_floor,
+        f32_trunc,
+        f32_nearest,
+        f32_convert_i32_s,
+        f32_convert_i32_u,
+        f32_convert_i64_s,
+        f32_convert_i64_u,
+        f32_demote_f64,
+        f64_add,
+        f64_sub,
+        f64_mul,
+        f64_div,
+        f64_min,
+        f64_max,
+        f64_sqrt,
+        f64_ceil,
+        f64_floor,
+        f64_trunc,
+        f64_nearest,
+        f64_convert_i32_s,
+        f64_convert_i32_u,
+        f64_convert_i64_s,
+        f64_convert_i64_u,
+        f64_promote_f32,
+    );
+    let mut import_object = ImportObject::new();
+    import_object.register(IMPORT_MODULE, exports);
+    import_object
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use wasmer::{wat2wasm, CompilerConfig, Cranelift, Instance, Module, Store, Universal};
+
+    fn bytecode() -> Vec<u8> {
+        wat2wasm(
+            br#"
+            (module
+            (func (export "nearest") (param f32) (result f32)
+                local.get 0
+                f32.nearest)
+            (func (export "min") (param f64 f64) (result f64)
+                local.get 0
+                local.get 1
+                f64.min)
+            (func (export "div") (param f64 f64) (result i64)
+                local.get 0
+                local.get 1
+                f64.div
+                i64.reinterpret_f64))
+            "#,
+        )
+        .unwrap()
+        .into()
+    }
+
+    fn store(mode: SoftFloatMode) -> Store {
+        let mut compiler_config = Cranelift::default();
+        compiler_config.push_middleware(Arc::new(SoftFloat::new(mode)));
+        Store::new(&Universal::new(compiler_config).engine())
+    }
+
+    #[test]
+    fn emulation_works() {
+        let store = store(SoftFloatMode::Emulate);
+        let module = Module::new(&store, bytecode()).unwrap();
+        let instance = Instance::new(&module, &imports(&store)).unwrap();
+
+        let nearest = instance
+            .exports
+            .get_native_function::<f32, f32>("nearest")
+            .unwrap();
+        assert_eq!(nearest.call(2.5).unwrap(), 2.0);
+        assert_eq!(nearest.call(3.5).unwrap(), 4.0);
+        assert_eq!(nearest.call(-0.5).unwrap().to_bits(), (-0.0f32).to_bits());
+
+        let min = instance
+            .exports
+            .get_native_function::<(f64, f64), f64>("min")
+            .unwrap();
+        assert_eq!(min.call(0.0, -0.0).unwrap().to_bits(), (-0.0f64).to_bits());
+        assert_eq!(min.call(1.0, 2.0).unwrap(), 1.0);
+
+        let div = instance
+            .exports
+            .get_native_function::<(f64, f64), i64>("div")
+            .unwrap();
+        assert_eq!(div.call(0.0, 0.0).unwrap() as u64, CANONICAL_NAN_F64);
+        assert_eq!(div.call(1.0, 4.0).unwrap(), 0.25f64.to_bits() as i64);
+    }
+
+    #[test]
+    fn rejection_works() {
+        let store = store(SoftFloatMode::Reject);
+        assert!(Module::new(&store, bytecode()).is_err());
+
+        let bytecode = wat2wasm(br#"(module (func (export "f") (result i32) i32.const 1))"#)
+            .unwrap()
+            .into_owned();
+        assert!(Module::new(&store, bytecode).is_ok());
+    }
+}
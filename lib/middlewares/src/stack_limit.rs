@@ -0,0 +1,701 @@
+//! `stack_limit` is a middleware limiting the depth of the WebAssembly
+//! call stack deterministically. The WebAssembly instance execution
+//! traps with [`TrapCode::StackLimitExceeded`] when the limit is
+//! exceeded.
+//!
+//! Stack exhaustion is otherwise detected with a guard page on the native
+//! stack, so the depth at which an instance traps depends on the
+//! compiler, the platform and the host. Instead, this middleware gives
+//! each function a frame cost computed from its WebAssembly code only:
+//! one slot per parameter and local, plus the maximum height of its
+//! operand stack, plus one slot for the frame itself. The cost of the
+//! frames on the stack is tracked in a global, and checked against the
+//! limit when entering a function, identically with all the compilers.
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::sync::Arc;
+//! use wasmer::{CompilerConfig, Instance, Module};
+//! use wasmer_middlewares::stack_limit::{imports, StackLimit};
+//!
+//! fn add_stack_limit_middleware(compiler_config: &mut dyn CompilerConfig) {
+//!     compiler_config.push_middleware(Arc::new(StackLimit::new(64 * 1024)));
+//! }
+//!
+//! fn instantiate(module: &Module) -> Result<Instance, Box<dyn std::error::Error>> {
+//!     Ok(Instance::new(module, &imports(module.store()))?)
+//! }
+//! ```
+
+use loupe::{MemoryUsage, MemoryUsageTracker};
+use std::convert::TryInto;
+use std::mem;
+use std::sync::Mutex;
+use wasmer::wasmparser::{
+    BinaryReader, BinaryReaderError, Operator, Type as WpType, TypeOrFuncType as WpTypeOrFuncType,
+};
+use wasmer::{
+    ExportIndex, Exports, Function, FunctionBodyData, FunctionLocals, FunctionMiddleware,
+    FunctionType, GlobalInit, GlobalType, ImportObject, ImportedFunctions, Instance,
+    LocalFunctionIndex, MiddlewareError, MiddlewareReaderState, ModuleMiddleware, Mutability,
+    Store, Type,
+};
+use wasmer_types::entity::{EntityRef, PrimaryMap};
+use wasmer_types::{FunctionIndex, GlobalIndex, ModuleInfo, SignatureIndex};
+use wasmer_vm::{raise_lib_trap, Trap, TrapCode};
+
+/// The module from which the instrumented modules import the function
+/// raising [`TrapCode::StackLimitExceeded`].
+pub const IMPORT_MODULE: &str = "wasmer_stack_limit";
+
+/// The name of the exported global holding the cost of the frames on the
+/// stack.
+const USAGE_EXPORT: &str = "wasmer_stack_limit_usage";
+
+#[derive(Debug, Clone, MemoryUsage)]
+struct StackLimitState {
+    /// The bodies of the local functions, until their frame costs are
+    /// computed.
+    function_bodies: Vec<Vec<u8>>,
+    /// The frame cost of each local function.
+    frame_costs: Vec<u64>,
+    /// The global holding the cost of the frames on the stack.
+    usage: Option<GlobalIndex>,
+    /// The function raising the trap.
+    imported_functions: ImportedFunctions,
+}
+
+/// The module-level stack limit middleware.
+///
+/// The frame costs are computed from the function types of the module, so
+/// `StackLimit` must come before the middlewares importing functions.
+///
+/// # Panic
+///
+/// An instance of `StackLimit` should _not_ be shared among different
+/// modules, since it tracks module-specific information like the
+/// frame costs of the functions. Attempts to use a `StackLimit`
+/// instance from multiple modules will result in a panic.
+#[derive(Debug)]
+pub struct StackLimit {
+    /// The maximum cost of the frames on the stack.
+    limit: u64,
+    state: Mutex<Option<StackLimitState>>,
+}
+
+/// The function-level stack limit middleware.
+#[derive(Debug)]
+pub struct FunctionStackLimit {
+    limit: u64,
+    /// The frame cost of the function.
+    frame_cost: u64,
+    /// The global holding the cost of the frames on the stack.
+    usage: GlobalIndex,
+    /// The function raising the trap.
+    imported_functions: ImportedFunctions,
+    /// Whether the entry of the function was instrumented.
+    entered: bool,
+    /// The number of blocks the operators being fed are nested in.
+    depth: u32,
+    /// The local holding the condition of the conditional branches to the
+    /// label of the function.
+    condition_local: u32,
+}
+
+impl StackLimit {
+    /// Creates a `StackLimit` middleware allowing frames costing up to
+    /// `limit` slots in total on the stack.
+    ///
+    /// The instrumented modules import the function raising the trap from
+    /// [`IMPORT_MODULE`], so they must be instantiated with [`imports`].
+    pub fn new(limit: u64) -> Self {
+        Self {
+            limit,
+            state: Mutex::new(None),
+        }
+    }
+}
+
+impl ModuleMiddleware for StackLimit {
+    /// Generates a `FunctionMiddleware` for a given function.
+    fn generate_function_middleware(
+        &self,
+        local_function_index: LocalFunctionIndex,
+    ) -> Box<dyn FunctionMiddleware> {
+        let state = self.state.lock().unwrap();
+        let state = state.as_ref().unwrap();
+        Box::new(FunctionStackLimit {
+            limit: self.limit,
+            frame_cost: state.frame_costs[local_function_index.index()],
+            usage: state.usage.unwrap(),
+            imported_functions: state.imported_functions.clone(),
+            entered: false,
+            depth: 0,
+            condition_local: 0,
+        })
+    }
+
+    /// Keeps the bodies of the functions to compute their frame costs.
+    fn inspect_function_bodies(
+        &self,
+        function_bodies: &PrimaryMap<LocalFunctionIndex, FunctionBodyData<'_>>,
+    ) {
+        let mut state = self.state.lock().unwrap();
+
+        if state.is_some() {
+            panic!("StackLimit::inspect_function_bodies: Attempting to use a `StackLimit` middleware from multiple modules.");
+        }
+
+        *state = Some(StackLimitState {
+            function_bodies: function_bodies
+                .values()
+                .map(|body| body.data.to_vec())
+                .collect(),
+            frame_costs: vec![],
+            usage: None,
+            imported_functions: ImportedFunctions::new(),
+        });
+    }
+
+    /// Computes the frame costs of the functions, adds the global holding
+    /// the cost of the frames on the stack and imports the function
+    /// raising the trap.
+    fn transform_module_info(&self, module_info: &mut ModuleInfo) {
+        let mut state = self.state.lock().unwrap();
+        let state = state
+            .as_mut()
+            .expect("StackLimit::transform_module_info: The function bodies were not inspected.");
+
+        if state.usage.is_some() {
+            panic!("StackLimit::transform_module_info: Attempting to use a `StackLimit` middleware from multiple modules.");
+        }
+
+        // The module is validated before being compiled, so an invalid
+        // body can't be reached.
+        state.frame_costs = mem::take(&mut state.function_bodies)
+            .iter()
+            .enumerate()
+            .map(|(local_function_index, body)| {
+                let function_index =
+                    module_info.func_index(LocalFunctionIndex::new(local_function_index));
+                frame_cost(module_info, function_index, body).unwrap_or(u64::MAX)
+            })
+            .collect();
+
+        let usage = module_info
+            .globals
+            .push(GlobalType::new(Type::I64, Mutability::Var));
+        module_info
+            .global_initializers
+            .push(GlobalInit::I64Const(0));
+        module_info
+            .exports
+            .insert(USAGE_EXPORT.to_string(), ExportIndex::Global(usage));
+        state.usage = Some(usage);
+
+        state.imported_functions.import(
+            module_info,
+            IMPORT_MODULE,
+            "exceeded",
+            FunctionType::new(vec![], vec![]),
+        );
+    }
+}
+
+impl MemoryUsage for StackLimit {
+    fn size_of_val(&self, tracker: &mut dyn MemoryUsageTracker) -> usize {
+        mem::size_of_val(self) + self.state.size_of_val(tracker) - mem::size_of_val(&self.state)
+    }
+}
+
+/// Returns the number of parameters and results of `signature_index`.
+fn signature_arity(module_info: &ModuleInfo, signature_index: SignatureIndex) -> (u64, u64) {
+    let signature = &module_info.signatures[signature_index];
+    (
+        signature.params().len() as u64,
+        signature.results().len() as u64,
+    )
+}
+
+/// Returns the number of parameters and results of a block of type `ty`.
+fn block_arity(module_info: &ModuleInfo, ty: WpTypeOrFuncType) -> (u64, u64) {
+    match ty {
+        WpTypeOrFuncType::Type(WpType::EmptyBlockType) => (0, 0),
+        WpTypeOrFuncType::Type(_) => (0, 1),
+        WpTypeOrFuncType::FuncType(index) => {
+            signature_arity(module_info, SignatureIndex::from_u32(index))
+        }
+    }
+}
+
+/// Returns the number of operands popped and pushed by `operator`, for
+/// the operators which don't depend on the module or change the control
+/// flow.
+///
+/// The operators not listed explicitly pop and push one operand. This
+/// overestimates the height of the operand stack for the few of them
+/// popping more operands, so the frame costs stay deterministic.
+fn operand_arity(operator: &Operator) -> (u64, u64) {
+    match operator {
+        Operator::Nop | Operator::DataDrop { .. } | Operator::ElemDrop { .. } => (0, 0),
+
+        Operator::I32Const { .. }
+        | Operator::I64Const { .. }
+        | Operator::F32Const { .. }
+        | Operator::F64Const { .. }
+        | Operator::V128Const { .. }
+        | Operator::RefNull { .. }
+        | Operator::RefFunc { .. }
+        | Operator::LocalGet { .. }
+        | Operator::GlobalGet { .. }
+        | Operator::MemorySize { .. }
+        | Operator::TableSize { .. } => (0, 1),
+
+        Operator::Drop | Operator::LocalSet { .. } | Operator::GlobalSet { .. } => (1, 0),
+
+        Operator::Select | Operator::TypedSelect { .. } => (3, 1),
+
+        Operator::I32Store { .. }
+        | Operator::I64Store { .. }
+        | Operator::F32Store { .. }
+        | Operator::F64Store { .. }
+        | Operator::I32Store8 { .. }
+        | Operator::I32Store16 { .. }
+        | Operator::I64Store8 { .. }
+        | Operator::I64Store16 { .. }
+        | Operator::I64Store32 { .. }
+        | Operator::V128Store { .. }
+        | Operator::TableSet { .. } => (2, 0),
+
+        Operator::MemoryCopy { .. }
+        | Operator::MemoryFill { .. }
+        | Operator::MemoryInit { .. }
+        | Operator::TableCopy { .. }
+        | Operator::TableFill { .. }
+        | Operator::TableInit { .. } => (3, 0),
+
+        Operator::TableGrow { .. }
+        | Operator::I32Eq
+        | Operator::I32Ne
+        | Operator::I32LtS
+        | Operator::I32LtU
+        | Operator::I32GtS
+        | Operator::I32GtU
+        | Operator::I32LeS
+        | Operator::I32LeU
+        | Operator::I32GeS
+        | Operator::I32GeU
+        | Operator::I64Eq
+        | Operator::I64Ne
+        | Operator::I64LtS
+        | Operator::I64LtU
+        | Operator::I64GtS
+        | Operator::I64GtU
+        | Operator::I64LeS
+        | Operator::I64LeU
+        | Operator::I64GeS
+        | Operator::I64GeU
+        | Operator::F32Eq
+        | Operator::F32Ne
+        | Operator::F32Lt
+        | Operator::F32Gt
+        | Operator::F32Le
+        | Operator::F32Ge
+        | Operator::F64Eq
+        | Operator::F64Ne
+        | Operator::F64Lt
+        | Operator::F64Gt
+        | Operator::F64Le
+        | Operator::F64Ge
+        | Operator::I32Add
+        | Operator::I32Sub
+        | Operator::I32Mul
+        | Operator::I32DivS
+        | Operator::I32DivU
+        | Operator::I32RemS
+        | Operator::I32RemU
+        | Operator::I32And
+        | Operator::I32Or
+        | Operator::I32Xor
+        | Operator::I32Shl
+        | Operator::I32ShrS
+        | Operator::I32ShrU
+        | Operator::I32Rotl
+        | Operator::I32Rotr
+        | Operator::I64Add
+        | Operator::I64Sub
+        | Operator::I64Mul
+        | Operator::I64DivS
+        | Operator::I64DivU
+        | Operator::I64RemS
+        | Operator::I64RemU
+        | Operator::I64And
+        | Operator::I64Or
+        | Operator::I64Xor
+        | Operator::I64Shl
+        | Operator::I64ShrS
+        | Operator::I64ShrU
+        | Operator::I64Rotl
+        | Operator::I64Rotr
+        | Operator::F32Add
+        | Operator::F32Sub
+        | Operator::F32Mul
+        | Operator::F32Div
+        | Operator::F32Min
+        | Operator::F32Max
+        | Operator::F32Copysign
+        | Operator::F64Add
+        | Operator::F64Sub
+        | Operator::F64Mul
+        | Operator::F64Div
+        | Operator::F64Min
+        | Operator::F64Max
+        | Operator::F64Copysign => (2, 1),
+
+        _ => (1, 1),
+    }
+}
+
+/// A block of a function being analyzed.
+struct ControlFrame {
+    /// The height of the operand stack below the parameters of the block.
+    height: u64,
+    params: u64,
+    results: u64,
+}
+
+/// Computes the frame cost of `function_index`, whose body is `body`.
+fn frame_cost(
+    module_info: &ModuleInfo,
+    function_index: FunctionIndex,
+    body: &[u8],
+) -> Result<u64, BinaryReaderError> {
+    let (params, results) = signature_arity(module_info, module_info.functions[function_index]);
+    let mut reader = BinaryReader::new(body);
+
+    let mut locals = params;
+    for _ in 0..reader.read_var_u32()? {
+        locals += reader.read_var_u32()? as u64;
+        reader.read_type()?;
+    }
+
+    let mut control = vec![ControlFrame {
+        height: 0,
+        params: 0,
+        results,
+    }];
+    let mut height = 0u64;
+    let mut max_height = 0u64;
+    while !reader.eof() {
+        let frame_height = control.last().map_or(0, |frame| frame.height);
+        let (pops, pushes) = match reader.read_operator()? {
+            Operator::Block { ty } | Operator::Loop { ty } => {
+                let (params, results) = block_arity(module_info, ty);
+                control.push(ControlFrame {
+                    height: height.saturating_sub(params),
+                    params,
+                    results,
+                });
+                (0, 0)
+            }
+            Operator::If { ty } => {
+                let (params, results) = block_arity(module_info, ty);
+                height = height.saturating_sub(1);
+                control.push(ControlFrame {
+                    height: height.saturating_sub(params),
+                    params,
+                    results,
+                });
+                (0, 0)
+            }
+            Operator::Else => {
+                let frame = control.last().unwrap();
+                height = frame.height + frame.params;
+                (0, 0)
+            }
+            Operator::End => {
+                let frame = control.pop().unwrap();
+                height = frame.height + frame.results;
+                (0, 0)
+            }
+            // The operand stack below the block is unreachable after an
+            // unconditional branch.
+            Operator::Br { .. }
+            | Operator::BrTable { .. }
+            | Operator::Return
+            | Operator::ReturnCall { .. }
+            | Operator::ReturnCallIndirect { .. }
+            | Operator::Unreachable => {
+                height = frame_height;
+                (0, 0)
+            }
+            Operator::BrIf { .. } => (1, 0),
+            Operator::Call { function_index } => signature_arity(
+                module_info,
+                module_info.functions[FunctionIndex::from_u32(function_index)],
+            ),
+            Operator::CallIndirect { index, .. } => {
+                let (params, results) =
+                    signature_arity(module_info, SignatureIndex::from_u32(index));
+                (params + 1, results)
+            }
+            operator => operand_arity(&operator),
+        };
+        height = height.saturating_sub(pops) + pushes;
+        max_height = max_height.max(height);
+    }
+
+    Ok(locals + max_height + 1)
+}
+
+impl FunctionStackLimit {
+    /// Removes the frame of the function from the stack usage.
+    fn exit(&self, state: &mut MiddlewareReaderState<'_>) {
+        let global_index = self.usage.as_u32();
+        state.extend(&[
+            // globals[usage] -= frame_cost;
+            Operator::GlobalGet { global_index },
+            Operator::I64Const {
+                value: self.frame_cost as i64,
+            },
+            Operator::I64Sub,
+            Operator::GlobalSet { global_index },
+        ]);
+    }
+
+    /// Removes the frame of the function from the stack usage if the
+    /// branch condition on top of the stack passes `test`, leaving the
+    /// condition on the stack.
+    fn exit_if<'a>(&self, test: &[Operator<'a>], state: &mut MiddlewareReaderState<'a>) {
+        let local_index = self.condition_local;
+        state.push_operator(Operator::LocalTee { local_index });
+        state.extend(test);
+        state.push_operator(Operator::If {
+            ty: WpTypeOrFuncType::Type(WpType::EmptyBlockType),
+        });
+        self.exit(state);
+        state.extend(&[Operator::End, Operator::LocalGet { local_index }]);
+    }
+}
+
+impl FunctionMiddleware for FunctionStackLimit {
+    fn declare_locals(&mut self, locals: &mut FunctionLocals<'_>) {
+        self.condition_local = locals.declare(1, WpType::I32);
+    }
+
+    fn imported_functions(&self) -> Option<&ImportedFunctions> {
+        Some(&self.imported_functions)
+    }
+
+    fn feed<'a>(
+        &mut self,
+        operator: Operator<'a>,
+        state: &mut MiddlewareReaderState<'a>,
+    ) -> Result<(), MiddlewareError> {
+        if !self.entered {
+            self.entered = true;
+            let global_index = self.usage.as_u32();
+            let exceeded = self.imported_functions.indices().next().unwrap();
+            state.extend(&[
+                // globals[usage] += frame_cost;
+                Operator::GlobalGet { global_index },
+                Operator::I64Const {
+                    value: self.frame_cost as i64,
+                },
+                Operator::I64Add,
+                Operator::GlobalSet { global_index },
+                // if unsigned(globals[usage]) > unsigned(limit) { exceeded(); }
+                Operator::GlobalGet { global_index },
+                Operator::I64Const {
+                    value: self.limit as i64,
+                },
+                Operator::I64GtU,
+                Operator::If {
+                    ty: WpTypeOrFuncType::Type(WpType::EmptyBlockType),
+                },
+                Operator::Call {
+                    function_index: exceeded.as_u32(),
+                },
+                Operator::End,
+            ]);
+        }
+
+        match operator {
+            Operator::Block { .. } | Operator::Loop { .. } | Operator::If { .. } => {
+                self.depth += 1;
+            }
+            Operator::End if self.depth > 0 => {
+                self.depth -= 1;
+            }
+            // The end of the function, the tail calls replacing its frame,
+            // and the branches to its label.
+            Operator::End
+            | Operator::Return
+            | Operator::ReturnCall { .. }
+            | Operator::ReturnCallIndirect { .. } => {
+                self.exit(state);
+            }
+            Operator::Br { relative_depth } if relative_depth == self.depth => {
+                self.exit(state);
+            }
+            Operator::BrIf { relative_depth } if relative_depth == self.depth => {
+                self.exit_if(&[], state);
+            }
+            Operator::BrTable { ref table } => {
+                let targets = table
+                    .targets()
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| MiddlewareError::new("stack_limit", e.to_string()))?;
+                let num_targets = targets.len() as i32 - 1;
+                for (index, (relative_depth, is_default)) in targets.into_iter().enumerate() {
+                    if relative_depth != self.depth {
+                        continue;
+                    }
+                    // the default target is taken for any index past the others
+                    let (value, test) = if is_default {
+                        (num_targets, Operator::I32GeU)
+                    } else {
+                        (index as i32, Operator::I32Eq)
+                    };
+                    self.exit_if(&[Operator::I32Const { value }, test], state);
+                }
+            }
+            _ => {}
+        }
+
+        state.push_operator(operator);
+        Ok(())
+    }
+}
+
+fn stack_limit_exceeded() {
+    // Safety: this is only called by the WebAssembly functions
+    // instrumented by the middleware, and there is nothing to drop.
+    unsafe { raise_lib_trap(Trap::lib(TrapCode::StackLimitExceeded)) }
+}
+
+/// Returns the imports of the modules processed with the [`StackLimit`]
+/// middleware, to be chained with or extended by the other imports of
+/// the module.
+pub fn imports(store: &Store) -> ImportObject {
+    let mut exports = Exports::new();
+    exports.insert(
+        "exceeded",
+        Function::new_native(store, stack_limit_exceeded),
+    );
+    let mut import_object = ImportObject::new();
+    import_object.register(IMPORT_MODULE, exports);
+    import_object
+}
+
+/// Get the cost of the frames on the stack of an
+/// [`Instance`][wasmer::Instance].
+///
+/// This is 0 when no function of the instance is being executed, unless
+/// a trap unwound the stack.
+///
+/// # Panic
+///
+/// The [`Instance`][wasmer::Instance] must have been processed with
+/// the [`StackLimit`] middleware at compile time, otherwise this will
+/// panic.
+pub fn get_stack_usage(instance: &Instance) -> u64 {
+    let usage: i64 = instance
+        .exports
+        .get_global(USAGE_EXPORT)
+        .expect("Can't get `wasmer_stack_limit_usage` from Instance")
+        .get()
+        .try_into()
+        .expect("`wasmer_stack_limit_usage` from Instance has wrong type");
+    usage as u64
+}
+
+/// Reset the cost of the frames on the stack of an
+/// [`Instance`][wasmer::Instance].
+///
+/// The frames unwound by a trap are not removed from the stack usage, so
+/// this must be called before calling the instance again after a trap.
+///
+/// # Panic
+///
+/// The [`Instance`][wasmer::Instance] must have been processed with
+/// the [`StackLimit`] middleware at compile time, otherwise this will
+/// panic.
+pub fn reset_stack_usage(instance: &Instance) {
+    instance
+        .exports
+        .get_global(USAGE_EXPORT)
+        .expect("Can't get `wasmer_stack_limit_usage` from Instance")
+        .set(0i64.into())
+        .expect("Can't set `wasmer_stack_limit_usage` in Instance");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use wasmer::{wat2wasm, CompilerConfig, Cranelift, Module, Store, Universal};
+
+    fn bytecode() -> Vec<u8> {
+        wat2wasm(
+            br#"
+            (module
+            (global $depth (export "depth") (mut i32) (i32.const 0))
+            (func $recurse (export "recurse")
+                global.get $depth
+                i32.const 1
+                i32.add
+                global.set $depth
+                call $recurse)
+            (func $leaf (export "leaf") (param i32) (result i32)
+                local.get 0))
+            "#,
+        )
+        .unwrap()
+        .into()
+    }
+
+    #[test]
+    fn stack_limit_works() {
+        let mut compiler_config = Cranelift::default();
+        compiler_config.push_middleware(Arc::new(StackLimit::new(30)));
+        let store = Store::new(&Universal::new(compiler_config).engine());
+        let module = Module::new(&store, bytecode()).unwrap();
+        let instance = Instance::new(&module, &imports(&store)).unwrap();
+
+        let leaf = instance
+            .exports
+            .get_function("leaf")
+            .unwrap()
+            .native::<i32, i32>()
+            .unwrap();
+        assert_eq!(leaf.call(7).unwrap(), 7);
+        assert_eq!(get_stack_usage(&instance), 0);
+
+        // `$recurse` costs 3 slots: 2 for its operand stack and 1 for its
+        // frame, so its 11th call exceeds the limit.
+        let recurse = instance
+            .exports
+            .get_function("recurse")
+            .unwrap()
+            .native::<(), ()>()
+            .unwrap();
+        let error = recurse.call().unwrap_err();
+        assert_eq!(error.to_trap(), Some(TrapCode::StackLimitExceeded));
+        let depth: i32 = instance
+            .exports
+            .get_global("depth")
+            .unwrap()
+            .get()
+            .try_into()
+            .unwrap();
+        assert_eq!(depth, 10);
+        assert_eq!(get_stack_usage(&instance), 33);
+
+        reset_stack_usage(&instance);
+        assert_eq!(get_stack_usage(&instance), 0);
+        assert_eq!(leaf.call(7).unwrap(), 7);
+    }
+}
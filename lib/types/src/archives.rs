@@ -1,3 +1,4 @@
+use bytecheck::CheckBytes;
 #[cfg(feature = "core")]
 use core::hash::Hash;
 use indexmap::IndexMap;
@@ -6,6 +7,7 @@ use rkyv::{Archive, Deserialize, Serialize};
 use std::{collections::HashMap, hash::Hash};
 
 #[derive(Serialize, Deserialize, Archive)]
+#[archive(derive(CheckBytes))]
 /// Rkyv Archivable IndexMap
 pub struct ArchivableIndexMap<K: Hash + Eq + Archive, V: Archive> {
     indices: HashMap<K, u64>,
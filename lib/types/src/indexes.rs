@@ -1,5 +1,7 @@
 //! Helper functions and structures for the translation.
 use crate::entity::entity_impl;
+#[cfg(feature = "enable-rkyv")]
+use bytecheck::CheckBytes;
 use core::u32;
 use loupe::MemoryUsage;
 #[cfg(feature = "enable-rkyv")]
@@ -16,7 +18,7 @@ use serde::{Deserialize, Serialize};
 )]
 #[cfg_attr(
     feature = "enable-rkyv",
-    archive(derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug))
+    archive(derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, CheckBytes))
 )]
 pub struct LocalFunctionIndex(u32);
 entity_impl!(LocalFunctionIndex);
@@ -44,7 +46,7 @@ entity_impl!(LocalMemoryIndex);
 )]
 #[cfg_attr(
     feature = "enable-rkyv",
-    archive(derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug))
+    archive(derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, CheckBytes))
 )]
 pub struct LocalGlobalIndex(u32);
 entity_impl!(LocalGlobalIndex);
@@ -60,7 +62,7 @@ entity_impl!(ArchivedLocalGlobalIndex);
 )]
 #[cfg_attr(
     feature = "enable-rkyv",
-    archive(derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug))
+    archive(derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, CheckBytes))
 )]
 pub struct FunctionIndex(u32);
 entity_impl!(FunctionIndex);
@@ -76,7 +78,7 @@ entity_impl!(ArchivedFunctionIndex);
 )]
 #[cfg_attr(
     feature = "enable-rkyv",
-    archive(derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug))
+    archive(derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, CheckBytes))
 )]
 pub struct TableIndex(u32);
 entity_impl!(TableIndex);
@@ -92,7 +94,7 @@ entity_impl!(ArchivedTableIndex);
 )]
 #[cfg_attr(
     feature = "enable-rkyv",
-    archive(derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug))
+    archive(derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, CheckBytes))
 )]
 pub struct GlobalIndex(u32);
 entity_impl!(GlobalIndex);
@@ -108,7 +110,7 @@ entity_impl!(ArchivedGlobalIndex);
 )]
 #[cfg_attr(
     feature = "enable-rkyv",
-    archive(derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug))
+    archive(derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, CheckBytes))
 )]
 pub struct MemoryIndex(u32);
 entity_impl!(MemoryIndex);
@@ -124,7 +126,7 @@ entity_impl!(ArchivedMemoryIndex);
 )]
 #[cfg_attr(
     feature = "enable-rkyv",
-    archive(derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug))
+    archive(derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, CheckBytes))
 )]
 pub struct SignatureIndex(u32);
 entity_impl!(SignatureIndex);
@@ -140,7 +142,7 @@ entity_impl!(ArchivedSignatureIndex);
 )]
 #[cfg_attr(
     feature = "enable-rkyv",
-    archive(derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug))
+    archive(derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, CheckBytes))
 )]
 pub struct DataIndex(u32);
 entity_impl!(DataIndex);
@@ -156,7 +158,7 @@ entity_impl!(ArchivedDataIndex);
 )]
 #[cfg_attr(
     feature = "enable-rkyv",
-    archive(derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug))
+    archive(derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, CheckBytes))
 )]
 pub struct ElemIndex(u32);
 entity_impl!(ElemIndex);
@@ -172,7 +174,7 @@ entity_impl!(ArchivedElemIndex);
 )]
 #[cfg_attr(
     feature = "enable-rkyv",
-    archive(derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug))
+    archive(derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, CheckBytes))
 )]
 pub struct CustomSectionIndex(u32);
 entity_impl!(CustomSectionIndex);
@@ -188,7 +190,7 @@ entity_impl!(ArchivedCustomSectionIndex);
 )]
 #[cfg_attr(
     feature = "enable-rkyv",
-    archive(derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug))
+    archive(derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, CheckBytes))
 )]
 pub enum ExportIndex {
     /// Function export.
@@ -210,7 +212,7 @@ pub enum ExportIndex {
 )]
 #[cfg_attr(
     feature = "enable-rkyv",
-    archive(derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug))
+    archive(derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, CheckBytes))
 )]
 pub enum ImportIndex {
     /// Function import.
@@ -13,6 +13,8 @@ use crate::{
     LocalGlobalIndex, LocalMemoryIndex, LocalTableIndex, MemoryIndex, MemoryType, SignatureIndex,
     TableIndex, TableInitializer, TableType,
 };
+#[cfg(feature = "enable-rkyv")]
+use bytecheck::CheckBytes;
 use indexmap::IndexMap;
 use loupe::MemoryUsage;
 #[cfg(feature = "enable-rkyv")]
@@ -35,6 +37,7 @@ use std::sync::Arc;
     feature = "enable-rkyv",
     derive(RkyvSerialize, RkyvDeserialize, Archive)
 )]
+#[cfg_attr(feature = "enable-rkyv", archive(derive(CheckBytes)))]
 pub struct ModuleId {
     id: usize,
 }
@@ -120,6 +123,11 @@ pub struct ModuleInfo {
     /// The data for each CustomSection in the module.
     pub custom_sections_data: PrimaryMap<CustomSectionIndex, Arc<[u8]>>,
 
+    /// The offset of the code section's contents in the module file.
+    ///
+    /// Code addresses in the DWARF debug info of a module are relative to it.
+    pub code_section_offset: usize,
+
     /// Number of imported functions in the module.
     pub num_imported_functions: usize,
 
@@ -136,6 +144,7 @@ pub struct ModuleInfo {
 /// Mirror version of ModuleInfo that can derive rkyv traits
 #[cfg(feature = "enable-rkyv")]
 #[derive(RkyvSerialize, RkyvDeserialize, Archive)]
+#[archive(derive(CheckBytes))]
 pub struct ArchivableModuleInfo {
     name: Option<String>,
     imports: ArchivableIndexMap<(String, String, u32), ImportIndex>,
@@ -153,6 +162,7 @@ pub struct ArchivableModuleInfo {
     globals: PrimaryMap<GlobalIndex, GlobalType>,
     custom_sections: ArchivableIndexMap<String, CustomSectionIndex>,
     custom_sections_data: PrimaryMap<CustomSectionIndex, Arc<[u8]>>,
+    code_section_offset: usize,
     num_imported_functions: usize,
     num_imported_tables: usize,
     num_imported_memories: usize,
@@ -179,6 +189,7 @@ impl From<ModuleInfo> for ArchivableModuleInfo {
             globals: it.globals,
             custom_sections: ArchivableIndexMap::from(it.custom_sections),
             custom_sections_data: it.custom_sections_data,
+            code_section_offset: it.code_section_offset,
             num_imported_functions: it.num_imported_functions,
             num_imported_tables: it.num_imported_tables,
             num_imported_memories: it.num_imported_memories,
@@ -208,6 +219,7 @@ impl From<ArchivableModuleInfo> for ModuleInfo {
             globals: it.globals,
             custom_sections: it.custom_sections.into(),
             custom_sections_data: it.custom_sections_data,
+            code_section_offset: it.code_section_offset,
             num_imported_functions: it.num_imported_functions,
             num_imported_tables: it.num_imported_tables,
             num_imported_memories: it.num_imported_memories,
@@ -270,6 +282,7 @@ impl PartialEq for ModuleInfo {
             && self.globals == other.globals
             && self.custom_sections == other.custom_sections
             && self.custom_sections_data == other.custom_sections_data
+            && self.code_section_offset == other.code_section_offset
             && self.num_imported_functions == other.num_imported_functions
             && self.num_imported_tables == other.num_imported_tables
             && self.num_imported_memories == other.num_imported_memories
@@ -4,9 +4,10 @@
 //! This registry also helps ensure that the `VMFuncRef`s can stay valid for as
 //! long as we need them to.
 
-use crate::vmcontext::VMCallerCheckedAnyfunc;
+use crate::vmcontext::{VMCallerCheckedAnyfunc, VMFunctionBody};
 use loupe::MemoryUsage;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicPtr, Ordering};
 use std::sync::Mutex;
 
 /// The registry that holds the values that `VMFuncRef`s point to.
@@ -118,4 +119,45 @@ impl FuncDataRegistry {
             VMFuncRef(inner_ptr)
         }
     }
+
+    /// Points the function registered as `func_ref` to `func_ptr`, so the
+    /// calls made through `func_ref` from then on run `func_ptr`.
+    ///
+    /// The pointer is replaced with a single atomic, pointer-sized store, so
+    /// the calls made concurrently through `func_ref` run either the old or
+    /// the new function. Calls already in progress are not affected.
+    /// Returns `false` if `func_ref` was not registered in this registry.
+    ///
+    /// # Safety
+    ///
+    /// `func_ptr` must be a function with the same signature, expecting the
+    /// same `VMContext` layout, as the one it replaces.
+    pub unsafe fn set_func_ptr(
+        &self,
+        func_ref: VMFuncRef,
+        func_ptr: *const VMFunctionBody,
+    ) -> bool {
+        if func_ref.is_null() {
+            return false;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        let idx = match inner.anyfunc_to_index.remove(&*func_ref.0) {
+            Some(idx) if std::ptr::eq(&*inner.func_data[idx], func_ref.0) => idx,
+            Some(idx) => {
+                inner.anyfunc_to_index.insert(*func_ref.0, idx);
+                return false;
+            }
+            None => return false,
+        };
+        // The `VMCallerCheckedAnyfunc`s are never moved nor dropped while the
+        // registry is alive, and the callers only ever read them, so updating
+        // the pointer in place is seen by the next calls. `AtomicPtr` has the
+        // same in-memory representation as the pointer it replaces.
+        let slot = &mut inner.func_data[idx].func_ptr as *mut *const VMFunctionBody
+            as *const AtomicPtr<VMFunctionBody>;
+        (*slot).store(func_ptr as *mut VMFunctionBody, Ordering::Release);
+        let anyfunc = *inner.func_data[idx];
+        inner.anyfunc_to_index.insert(anyfunc, idx);
+        true
+    }
 }
@@ -322,6 +322,24 @@ impl Instance {
         }
     }
 
+    /// Get a locally defined or imported memory object.
+    pub(crate) fn memory_object(&self, index: MemoryIndex) -> &Arc<dyn Memory> {
+        if let Some(local_index) = self.module.local_memory_index(index) {
+            &self.memories[local_index]
+        } else {
+            &self.imported_memory(index).from
+        }
+    }
+
+    /// Get a locally defined or imported global object.
+    pub(crate) fn global_object(&self, index: GlobalIndex) -> &Arc<Global> {
+        if let Some(local_index) = self.module.local_global_index(index) {
+            &self.globals[local_index]
+        } else {
+            &self.imported_global(index).from
+        }
+    }
+
     /// Return the indexed `VMMemoryDefinition`.
     fn memory(&self, index: LocalMemoryIndex) -> VMMemoryDefinition {
         unsafe { *self.memory_ptr(index).as_ref() }
@@ -1030,6 +1048,20 @@ impl InstanceHandle {
         Ok(())
     }
 
+    /// Initializes the tables of the instance with the element segments of
+    /// its module, without initializing its memories nor invoking its start
+    /// function.
+    ///
+    /// This is for instances sharing their memories with an instance of the
+    /// same module which already initialized them.
+    ///
+    /// # Safety
+    ///
+    /// Only safe to call immediately after instantiation.
+    pub unsafe fn initialize_tables(&self) -> Result<(), Trap> {
+        initialize_tables(self.instance().as_ref())
+    }
+
     /// Return a reference to the vmctx used by compiled wasm code.
     pub fn vmctx(&self) -> &VMContext {
         self.instance().as_ref().vmctx()
@@ -1057,6 +1089,12 @@ impl InstanceHandle {
         self.instance().as_ref().module_ref()
     }
 
+    /// Return the `VMFuncRef` through which the tables and `ref.func`
+    /// call the given function.
+    pub fn func_ref(&self, function_index: FunctionIndex) -> Option<VMFuncRef> {
+        self.instance().as_ref().func_ref(function_index)
+    }
+
     /// Lookup an export with the given name.
     pub fn lookup(&self, field: &str) -> Option<VMExtern> {
         let export = self.module_ref().exports.get(field)?;
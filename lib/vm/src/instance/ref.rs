@@ -1,10 +1,13 @@
 use super::Instance;
+use crate::global::Global;
+use crate::memory::Memory;
 use loupe::{MemoryUsage, MemoryUsageTracker};
 use std::alloc::Layout;
 use std::convert::TryFrom;
 use std::mem;
 use std::ptr::{self, NonNull};
 use std::sync::{Arc, Weak};
+use wasmer_types::{GlobalIndex, MemoryIndex, ModuleInfo};
 
 /// Dynamic instance allocation.
 ///
@@ -137,6 +140,23 @@ impl InstanceRef {
         (&*self.0).as_ref()
     }
 
+    /// Return a reference-counting pointer to the module of the instance.
+    pub fn module(&self) -> &Arc<ModuleInfo> {
+        self.as_ref().module()
+    }
+
+    /// Return the locally defined or imported memory `index` of the
+    /// instance.
+    pub fn memory(&self, index: MemoryIndex) -> Arc<dyn Memory> {
+        self.as_ref().memory_object(index).clone()
+    }
+
+    /// Return the locally defined or imported global `index` of the
+    /// instance.
+    pub fn global(&self, index: GlobalIndex) -> Arc<Global> {
+        self.as_ref().global_object(index).clone()
+    }
+
     /// Only succeeds if ref count is 1.
     #[inline]
     pub(super) fn as_mut(&mut self) -> Option<&mut Instance> {
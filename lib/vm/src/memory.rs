@@ -7,6 +7,8 @@
 
 use crate::mmap::Mmap;
 use crate::vmcontext::VMMemoryDefinition;
+#[cfg(feature = "enable-rkyv")]
+use bytecheck::CheckBytes;
 use loupe::MemoryUsage;
 use more_asserts::assert_ge;
 #[cfg(feature = "enable-rkyv")]
@@ -69,6 +71,7 @@ pub enum MemoryError {
     feature = "enable-rkyv",
     derive(RkyvSerialize, RkyvDeserialize, Archive)
 )]
+#[cfg_attr(feature = "enable-rkyv", archive(derive(CheckBytes)))]
 pub enum MemoryStyle {
     /// The actual memory can be resized and moved.
     Dynamic {
@@ -3,6 +3,8 @@
 
 //! Trap codes describing the reason for a trap.
 
+#[cfg(feature = "enable-rkyv")]
+use bytecheck::CheckBytes;
 use core::fmt::{self, Display, Formatter};
 use core::str::FromStr;
 use loupe::MemoryUsage;
@@ -19,6 +21,7 @@ use thiserror::Error;
     feature = "enable-rkyv",
     derive(RkyvSerialize, RkyvDeserialize, Archive)
 )]
+#[cfg_attr(feature = "enable-rkyv", archive(derive(CheckBytes)))]
 #[repr(u32)]
 pub enum TrapCode {
     /// The current stack space was exhausted.
@@ -63,6 +66,13 @@ pub enum TrapCode {
 
     /// An atomic memory access was attempted with an unaligned pointer.
     UnalignedAtomic = 11,
+
+    /// The stack limit enforced by the stack limit middleware was exceeded.
+    ///
+    /// Unlike [`TrapCode::StackOverflow`], the depth at which this is raised
+    /// only depends on the WebAssembly code, not on the compiler or the
+    /// platform.
+    StackLimitExceeded = 12,
 }
 
 impl TrapCode {
@@ -81,6 +91,7 @@ impl TrapCode {
             Self::BadConversionToInteger => "invalid conversion to integer",
             Self::UnreachableCodeReached => "unreachable",
             Self::UnalignedAtomic => "unaligned atomic access",
+            Self::StackLimitExceeded => "stack limit exceeded",
         }
     }
 }
@@ -100,6 +111,7 @@ impl Display for TrapCode {
             Self::BadConversionToInteger => "bad_toint",
             Self::UnreachableCodeReached => "unreachable",
             Self::UnalignedAtomic => "unalign_atom",
+            Self::StackLimitExceeded => "stk_limit",
         };
         f.write_str(identifier)
     }
@@ -122,6 +134,7 @@ impl FromStr for TrapCode {
             "bad_toint" => Ok(TrapCode::BadConversionToInteger),
             "unreachable" => Ok(TrapCode::UnreachableCodeReached),
             "unalign_atom" => Ok(TrapCode::UnalignedAtomic),
+            "stk_limit" => Ok(TrapCode::StackLimitExceeded),
             _ => Err(()),
         }
     }
@@ -132,7 +145,7 @@ mod tests {
     use super::*;
 
     // Everything but user-defined codes.
-    const CODES: [TrapCode; 12] = [
+    const CODES: [TrapCode; 13] = [
         TrapCode::StackOverflow,
         TrapCode::HeapAccessOutOfBounds,
         TrapCode::HeapMisaligned,
@@ -145,6 +158,7 @@ mod tests {
         TrapCode::BadConversionToInteger,
         TrapCode::UnreachableCodeReached,
         TrapCode::UnalignedAtomic,
+        TrapCode::StackLimitExceeded,
     ];
 
     #[test]
@@ -76,3 +76,88 @@ pub const __WASI_ETIMEDOUT: u16 = 73;
 pub const __WASI_ETXTBSY: u16 = 74;
 pub const __WASI_EXDEV: u16 = 75;
 pub const __WASI_ENOTCAPABLE: u16 = 76;
+
+/// Returns the symbolic name of `errno`, like `"ENOENT"`, or `None` if it
+/// isn't a valid WASI error code.
+pub fn errno_to_string(errno: __wasi_errno_t) -> Option<&'static str> {
+    Some(match errno {
+        __WASI_ESUCCESS => "ESUCCESS",
+        __WASI_E2BIG => "E2BIG",
+        __WASI_EACCES => "EACCES",
+        __WASI_EADDRINUSE => "EADDRINUSE",
+        __WASI_EADDRNOTAVAIL => "EADDRNOTAVAIL",
+        __WASI_EAFNOSUPPORT => "EAFNOSUPPORT",
+        __WASI_EAGAIN => "EAGAIN",
+        __WASI_EALREADY => "EALREADY",
+        __WASI_EBADF => "EBADF",
+        __WASI_EBADMSG => "EBADMSG",
+        __WASI_EBUSY => "EBUSY",
+        __WASI_ECANCELED => "ECANCELED",
+        __WASI_ECHILD => "ECHILD",
+        __WASI_ECONNABORTED => "ECONNABORTED",
+        __WASI_ECONNREFUSED => "ECONNREFUSED",
+        __WASI_ECONNRESET => "ECONNRESET",
+        __WASI_EDEADLK => "EDEADLK",
+        __WASI_EDESTADDRREQ => "EDESTADDRREQ",
+        __WASI_EDOM => "EDOM",
+        __WASI_EDQUOT => "EDQUOT",
+        __WASI_EEXIST => "EEXIST",
+        __WASI_EFAULT => "EFAULT",
+        __WASI_EFBIG => "EFBIG",
+        __WASI_EHOSTUNREACH => "EHOSTUNREACH",
+        __WASI_EIDRM => "EIDRM",
+        __WASI_EILSEQ => "EILSEQ",
+        __WASI_EINPROGRESS => "EINPROGRESS",
+        __WASI_EINTR => "EINTR",
+        __WASI_EINVAL => "EINVAL",
+        __WASI_EIO => "EIO",
+        __WASI_EISCONN => "EISCONN",
+        __WASI_EISDIR => "EISDIR",
+        __WASI_ELOOP => "ELOOP",
+        __WASI_EMFILE => "EMFILE",
+        __WASI_EMLINK => "EMLINK",
+        __WASI_EMSGSIZE => "EMSGSIZE",
+        __WASI_EMULTIHOP => "EMULTIHOP",
+        __WASI_ENAMETOOLONG => "ENAMETOOLONG",
+        __WASI_ENETDOWN => "ENETDOWN",
+        __WASI_ENETRESET => "ENETRESET",
+        __WASI_ENETUNREACH => "ENETUNREACH",
+        __WASI_ENFILE => "ENFILE",
+        __WASI_ENOBUFS => "ENOBUFS",
+        __WASI_ENODEV => "ENODEV",
+        __WASI_ENOENT => "ENOENT",
+        __WASI_ENOEXEC => "ENOEXEC",
+        __WASI_ENOLCK => "ENOLCK",
+        __WASI_ENOLINK => "ENOLINK",
+        __WASI_ENOMEM => "ENOMEM",
+        __WASI_ENOMSG => "ENOMSG",
+        __WASI_ENOPROTOOPT => "ENOPROTOOPT",
+        __WASI_ENOSPC => "ENOSPC",
+        __WASI_ENOSYS => "ENOSYS",
+        __WASI_ENOTCONN => "ENOTCONN",
+        __WASI_ENOTDIR => "ENOTDIR",
+        __WASI_ENOTEMPTY => "ENOTEMPTY",
+        __WASI_ENOTRECOVERABLE => "ENOTRECOVERABLE",
+        __WASI_ENOTSOCK => "ENOTSOCK",
+        __WASI_ENOTSUP => "ENOTSUP",
+        __WASI_ENOTTY => "ENOTTY",
+        __WASI_ENXIO => "ENXIO",
+        __WASI_EOVERFLOW => "EOVERFLOW",
+        __WASI_EOWNERDEAD => "EOWNERDEAD",
+        __WASI_EPERM => "EPERM",
+        __WASI_EPIPE => "EPIPE",
+        __WASI_EPROTO => "EPROTO",
+        __WASI_EPROTONOSUPPORT => "EPROTONOSUPPORT",
+        __WASI_EPROTOTYPE => "EPROTOTYPE",
+        __WASI_ERANGE => "ERANGE",
+        __WASI_EROFS => "EROFS",
+        __WASI_ESPIPE => "ESPIPE",
+        __WASI_ESRCH => "ESRCH",
+        __WASI_ESTALE => "ESTALE",
+        __WASI_ETIMEDOUT => "ETIMEDOUT",
+        __WASI_ETXTBSY => "ETXTBSY",
+        __WASI_EXDEV => "EXDEV",
+        __WASI_ENOTCAPABLE => "ENOTCAPABLE",
+        _ => return None,
+    })
+}
@@ -17,6 +17,7 @@ mod macros;
 mod ptr;
 mod state;
 mod syscalls;
+mod trace;
 mod utils;
 
 use crate::syscalls::*;
@@ -26,6 +27,7 @@ pub use crate::state::{
     WasiStateCreationError, ALL_RIGHTS, VIRTUAL_ROOT_FD,
 };
 pub use crate::syscalls::types;
+pub use crate::trace::{SyscallArg, SyscallEvent, TraceFormat, TraceWriter, WasiTracer};
 pub use crate::utils::{get_wasi_version, get_wasi_versions, is_wasi_module, WasiVersion};
 
 use thiserror::Error;
@@ -58,6 +60,8 @@ pub struct WasiEnv {
     pub state: Arc<Mutex<WasiState>>,
     #[wasmer(export)]
     memory: LazyInit<Memory>,
+    /// Reported every syscall, if set
+    tracer: Option<Arc<dyn WasiTracer>>,
 }
 
 impl WasiEnv {
@@ -65,9 +69,19 @@ impl WasiEnv {
         Self {
             state: Arc::new(Mutex::new(state)),
             memory: LazyInit::new(),
+            tracer: None,
         }
     }
 
+    /// Report every syscall made by the module to `tracer`.
+    ///
+    /// This must be called before creating the import object, as the
+    /// imports get their own copy of the `WasiEnv`.
+    pub fn set_tracer(&mut self, tracer: impl WasiTracer) -> &mut Self {
+        self.tracer = Some(Arc::new(tracer));
+        self
+    }
+
     /// Get an `ImportObject` for a specific version of WASI detected in the module.
     pub fn import_object(&mut self, module: &Module) -> Result<ImportObject, WasiError> {
         let wasi_version = get_wasi_version(module, false).ok_or(WasiError::UnknownWasiVersion)?;
@@ -141,51 +155,51 @@ pub fn generate_import_object_from_env(
 fn generate_import_object_snapshot0(store: &Store, env: WasiEnv) -> ImportObject {
     imports! {
         "wasi_unstable" => {
-            "args_get" => Function::new_native_with_env(store, env.clone(), args_get),
-            "args_sizes_get" => Function::new_native_with_env(store, env.clone(), args_sizes_get),
-            "clock_res_get" => Function::new_native_with_env(store, env.clone(), clock_res_get),
-            "clock_time_get" => Function::new_native_with_env(store, env.clone(), clock_time_get),
-            "environ_get" => Function::new_native_with_env(store, env.clone(), environ_get),
-            "environ_sizes_get" => Function::new_native_with_env(store, env.clone(), environ_sizes_get),
-            "fd_advise" => Function::new_native_with_env(store, env.clone(), fd_advise),
-            "fd_allocate" => Function::new_native_with_env(store, env.clone(), fd_allocate),
-            "fd_close" => Function::new_native_with_env(store, env.clone(), fd_close),
-            "fd_datasync" => Function::new_native_with_env(store, env.clone(), fd_datasync),
-            "fd_fdstat_get" => Function::new_native_with_env(store, env.clone(), fd_fdstat_get),
-            "fd_fdstat_set_flags" => Function::new_native_with_env(store, env.clone(), fd_fdstat_set_flags),
-            "fd_fdstat_set_rights" => Function::new_native_with_env(store, env.clone(), fd_fdstat_set_rights),
-            "fd_filestat_get" => Function::new_native_with_env(store, env.clone(), legacy::snapshot0::fd_filestat_get),
-            "fd_filestat_set_size" => Function::new_native_with_env(store, env.clone(), fd_filestat_set_size),
-            "fd_filestat_set_times" => Function::new_native_with_env(store, env.clone(), fd_filestat_set_times),
-            "fd_pread" => Function::new_native_with_env(store, env.clone(), fd_pread),
-            "fd_prestat_get" => Function::new_native_with_env(store, env.clone(), fd_prestat_get),
-            "fd_prestat_dir_name" => Function::new_native_with_env(store, env.clone(), fd_prestat_dir_name),
-            "fd_pwrite" => Function::new_native_with_env(store, env.clone(), fd_pwrite),
-            "fd_read" => Function::new_native_with_env(store, env.clone(), fd_read),
-            "fd_readdir" => Function::new_native_with_env(store, env.clone(), fd_readdir),
-            "fd_renumber" => Function::new_native_with_env(store, env.clone(), fd_renumber),
-            "fd_seek" => Function::new_native_with_env(store, env.clone(), legacy::snapshot0::fd_seek),
-            "fd_sync" => Function::new_native_with_env(store, env.clone(), fd_sync),
-            "fd_tell" => Function::new_native_with_env(store, env.clone(), fd_tell),
-            "fd_write" => Function::new_native_with_env(store, env.clone(), fd_write),
-            "path_create_directory" => Function::new_native_with_env(store, env.clone(), path_create_directory),
-            "path_filestat_get" => Function::new_native_with_env(store, env.clone(), legacy::snapshot0::path_filestat_get),
-            "path_filestat_set_times" => Function::new_native_with_env(store, env.clone(), path_filestat_set_times),
-            "path_link" => Function::new_native_with_env(store, env.clone(), path_link),
-            "path_open" => Function::new_native_with_env(store, env.clone(), path_open),
-            "path_readlink" => Function::new_native_with_env(store, env.clone(), path_readlink),
-            "path_remove_directory" => Function::new_native_with_env(store, env.clone(), path_remove_directory),
-            "path_rename" => Function::new_native_with_env(store, env.clone(), path_rename),
-            "path_symlink" => Function::new_native_with_env(store, env.clone(), path_symlink),
-            "path_unlink_file" => Function::new_native_with_env(store, env.clone(), path_unlink_file),
-            "poll_oneoff" => Function::new_native_with_env(store, env.clone(), legacy::snapshot0::poll_oneoff),
-            "proc_exit" => Function::new_native_with_env(store, env.clone(), proc_exit),
-            "proc_raise" => Function::new_native_with_env(store, env.clone(), proc_raise),
-            "random_get" => Function::new_native_with_env(store, env.clone(), random_get),
-            "sched_yield" => Function::new_native_with_env(store, env.clone(), sched_yield),
-            "sock_recv" => Function::new_native_with_env(store, env.clone(), sock_recv),
-            "sock_send" => Function::new_native_with_env(store, env.clone(), sock_send),
-            "sock_shutdown" => Function::new_native_with_env(store, env.clone(), sock_shutdown),
+            "args_get" => Function::new_native_with_env(store, env.clone(), traced::args_get),
+            "args_sizes_get" => Function::new_native_with_env(store, env.clone(), traced::args_sizes_get),
+            "clock_res_get" => Function::new_native_with_env(store, env.clone(), traced::clock_res_get),
+            "clock_time_get" => Function::new_native_with_env(store, env.clone(), traced::clock_time_get),
+            "environ_get" => Function::new_native_with_env(store, env.clone(), traced::environ_get),
+            "environ_sizes_get" => Function::new_native_with_env(store, env.clone(), traced::environ_sizes_get),
+            "fd_advise" => Function::new_native_with_env(store, env.clone(), traced::fd_advise),
+            "fd_allocate" => Function::new_native_with_env(store, env.clone(), traced::fd_allocate),
+            "fd_close" => Function::new_native_with_env(store, env.clone(), traced::fd_close),
+            "fd_datasync" => Function::new_native_with_env(store, env.clone(), traced::fd_datasync),
+            "fd_fdstat_get" => Function::new_native_with_env(store, env.clone(), traced::fd_fdstat_get),
+            "fd_fdstat_set_flags" => Function::new_native_with_env(store, env.clone(), traced::fd_fdstat_set_flags),
+            "fd_fdstat_set_rights" => Function::new_native_with_env(store, env.clone(), traced::fd_fdstat_set_rights),
+            "fd_filestat_get" => Function::new_native_with_env(store, env.clone(), traced::snapshot0::fd_filestat_get),
+            "fd_filestat_set_size" => Function::new_native_with_env(store, env.clone(), traced::fd_filestat_set_size),
+            "fd_filestat_set_times" => Function::new_native_with_env(store, env.clone(), traced::fd_filestat_set_times),
+            "fd_pread" => Function::new_native_with_env(store, env.clone(), traced::fd_pread),
+            "fd_prestat_get" => Function::new_native_with_env(store, env.clone(), traced::fd_prestat_get),
+            "fd_prestat_dir_name" => Function::new_native_with_env(store, env.clone(), traced::fd_prestat_dir_name),
+            "fd_pwrite" => Function::new_native_with_env(store, env.clone(), traced::fd_pwrite),
+            "fd_read" => Function::new_native_with_env(store, env.clone(), traced::fd_read),
+            "fd_readdir" => Function::new_native_with_env(store, env.clone(), traced::fd_readdir),
+            "fd_renumber" => Function::new_native_with_env(store, env.clone(), traced::fd_renumber),
+            "fd_seek" => Function::new_native_with_env(store, env.clone(), traced::snapshot0::fd_seek),
+            "fd_sync" => Function::new_native_with_env(store, env.clone(), traced::fd_sync),
+            "fd_tell" => Function::new_native_with_env(store, env.clone(), traced::fd_tell),
+            "fd_write" => Function::new_native_with_env(store, env.clone(), traced::fd_write),
+            "path_create_directory" => Function::new_native_with_env(store, env.clone(), traced::path_create_directory),
+            "path_filestat_get" => Function::new_native_with_env(store, env.clone(), traced::snapshot0::path_filestat_get),
+            "path_filestat_set_times" => Function::new_native_with_env(store, env.clone(), traced::path_filestat_set_times),
+            "path_link" => Function::new_native_with_env(store, env.clone(), traced::path_link),
+            "path_open" => Function::new_native_with_env(store, env.clone(), traced::path_open),
+            "path_readlink" => Function::new_native_with_env(store, env.clone(), traced::path_readlink),
+            "path_remove_directory" => Function::new_native_with_env(store, env.clone(), traced::path_remove_directory),
+            "path_rename" => Function::new_native_with_env(store, env.clone(), traced::path_rename),
+            "path_symlink" => Function::new_native_with_env(store, env.clone(), traced::path_symlink),
+            "path_unlink_file" => Function::new_native_with_env(store, env.clone(), traced::path_unlink_file),
+            "poll_oneoff" => Function::new_native_with_env(store, env.clone(), traced::snapshot0::poll_oneoff),
+            "proc_exit" => Function::new_native_with_env(store, env.clone(), traced::proc_exit),
+            "proc_raise" => Function::new_native_with_env(store, env.clone(), traced::proc_raise),
+            "random_get" => Function::new_native_with_env(store, env.clone(), traced::random_get),
+            "sched_yield" => Function::new_native_with_env(store, env.clone(), traced::sched_yield),
+            "sock_recv" => Function::new_native_with_env(store, env.clone(), traced::sock_recv),
+            "sock_send" => Function::new_native_with_env(store, env.clone(), traced::sock_send),
+            "sock_shutdown" => Function::new_native_with_env(store, env.clone(), traced::sock_shutdown),
         },
     }
 }
@@ -194,51 +208,51 @@ fn generate_import_object_snapshot0(store: &Store, env: WasiEnv) -> ImportObject
 fn generate_import_object_snapshot1(store: &Store, env: WasiEnv) -> ImportObject {
     imports! {
         "wasi_snapshot_preview1" => {
-            "args_get" => Function::new_native_with_env(store, env.clone(), args_get),
-            "args_sizes_get" => Function::new_native_with_env(store, env.clone(), args_sizes_get),
-            "clock_res_get" => Function::new_native_with_env(store, env.clone(), clock_res_get),
-            "clock_time_get" => Function::new_native_with_env(store, env.clone(), clock_time_get),
-            "environ_get" => Function::new_native_with_env(store, env.clone(), environ_get),
-            "environ_sizes_get" => Function::new_native_with_env(store, env.clone(), environ_sizes_get),
-            "fd_advise" => Function::new_native_with_env(store, env.clone(), fd_advise),
-            "fd_allocate" => Function::new_native_with_env(store, env.clone(), fd_allocate),
-            "fd_close" => Function::new_native_with_env(store, env.clone(), fd_close),
-            "fd_datasync" => Function::new_native_with_env(store, env.clone(), fd_datasync),
-            "fd_fdstat_get" => Function::new_native_with_env(store, env.clone(), fd_fdstat_get),
-            "fd_fdstat_set_flags" => Function::new_native_with_env(store, env.clone(), fd_fdstat_set_flags),
-            "fd_fdstat_set_rights" => Function::new_native_with_env(store, env.clone(), fd_fdstat_set_rights),
-            "fd_filestat_get" => Function::new_native_with_env(store, env.clone(), fd_filestat_get),
-            "fd_filestat_set_size" => Function::new_native_with_env(store, env.clone(), fd_filestat_set_size),
-            "fd_filestat_set_times" => Function::new_native_with_env(store, env.clone(), fd_filestat_set_times),
-            "fd_pread" => Function::new_native_with_env(store, env.clone(), fd_pread),
-            "fd_prestat_get" => Function::new_native_with_env(store, env.clone(), fd_prestat_get),
-            "fd_prestat_dir_name" => Function::new_native_with_env(store, env.clone(), fd_prestat_dir_name),
-            "fd_pwrite" => Function::new_native_with_env(store, env.clone(), fd_pwrite),
-            "fd_read" => Function::new_native_with_env(store, env.clone(), fd_read),
-            "fd_readdir" => Function::new_native_with_env(store, env.clone(), fd_readdir),
-            "fd_renumber" => Function::new_native_with_env(store, env.clone(), fd_renumber),
-            "fd_seek" => Function::new_native_with_env(store, env.clone(), fd_seek),
-            "fd_sync" => Function::new_native_with_env(store, env.clone(), fd_sync),
-            "fd_tell" => Function::new_native_with_env(store, env.clone(), fd_tell),
-            "fd_write" => Function::new_native_with_env(store, env.clone(), fd_write),
-            "path_create_directory" => Function::new_native_with_env(store, env.clone(), path_create_directory),
-            "path_filestat_get" => Function::new_native_with_env(store, env.clone(), path_filestat_get),
-            "path_filestat_set_times" => Function::new_native_with_env(store, env.clone(), path_filestat_set_times),
-            "path_link" => Function::new_native_with_env(store, env.clone(), path_link),
-            "path_open" => Function::new_native_with_env(store, env.clone(), path_open),
-            "path_readlink" => Function::new_native_with_env(store, env.clone(), path_readlink),
-            "path_remove_directory" => Function::new_native_with_env(store, env.clone(), path_remove_directory),
-            "path_rename" => Function::new_native_with_env(store, env.clone(), path_rename),
-            "path_symlink" => Function::new_native_with_env(store, env.clone(), path_symlink),
-            "path_unlink_file" => Function::new_native_with_env(store, env.clone(), path_unlink_file),
-            "poll_oneoff" => Function::new_native_with_env(store, env.clone(), poll_oneoff),
-            "proc_exit" => Function::new_native_with_env(store, env.clone(), proc_exit),
-            "proc_raise" => Function::new_native_with_env(store, env.clone(), proc_raise),
-            "random_get" => Function::new_native_with_env(store, env.clone(), random_get),
-            "sched_yield" => Function::new_native_with_env(store, env.clone(), sched_yield),
-            "sock_recv" => Function::new_native_with_env(store, env.clone(), sock_recv),
-            "sock_send" => Function::new_native_with_env(store, env.clone(), sock_send),
-            "sock_shutdown" => Function::new_native_with_env(store, env.clone(), sock_shutdown),
+            "args_get" => Function::new_native_with_env(store, env.clone(), traced::args_get),
+            "args_sizes_get" => Function::new_native_with_env(store, env.clone(), traced::args_sizes_get),
+            "clock_res_get" => Function::new_native_with_env(store, env.clone(), traced::clock_res_get),
+            "clock_time_get" => Function::new_native_with_env(store, env.clone(), traced::clock_time_get),
+            "environ_get" => Function::new_native_with_env(store, env.clone(), traced::environ_get),
+            "environ_sizes_get" => Function::new_native_with_env(store, env.clone(), traced::environ_sizes_get),
+            "fd_advise" => Function::new_native_with_env(store, env.clone(), traced::fd_advise),
+            "fd_allocate" => Function::new_native_with_env(store, env.clone(), traced::fd_allocate),
+            "fd_close" => Function::new_native_with_env(store, env.clone(), traced::fd_close),
+            "fd_datasync" => Function::new_native_with_env(store, env.clone(), traced::fd_datasync),
+            "fd_fdstat_get" => Function::new_native_with_env(store, env.clone(), traced::fd_fdstat_get),
+            "fd_fdstat_set_flags" => Function::new_native_with_env(store, env.clone(), traced::fd_fdstat_set_flags),
+            "fd_fdstat_set_rights" => Function::new_native_with_env(store, env.clone(), traced::fd_fdstat_set_rights),
+            "fd_filestat_get" => Function::new_native_with_env(store, env.clone(), traced::fd_filestat_get),
+            "fd_filestat_set_size" => Function::new_native_with_env(store, env.clone(), traced::fd_filestat_set_size),
+            "fd_filestat_set_times" => Function::new_native_with_env(store, env.clone(), traced::fd_filestat_set_times),
+            "fd_pread" => Function::new_native_with_env(store, env.clone(), traced::fd_pread),
+            "fd_prestat_get" => Function::new_native_with_env(store, env.clone(), traced::fd_prestat_get),
+            "fd_prestat_dir_name" => Function::new_native_with_env(store, env.clone(), traced::fd_prestat_dir_name),
+            "fd_pwrite" => Function::new_native_with_env(store, env.clone(), traced::fd_pwrite),
+            "fd_read" => Function::new_native_with_env(store, env.clone(), traced::fd_read),
+            "fd_readdir" => Function::new_native_with_env(store, env.clone(), traced::fd_readdir),
+            "fd_renumber" => Function::new_native_with_env(store, env.clone(), traced::fd_renumber),
+            "fd_seek" => Function::new_native_with_env(store, env.clone(), traced::fd_seek),
+            "fd_sync" => Function::new_native_with_env(store, env.clone(), traced::fd_sync),
+            "fd_tell" => Function::new_native_with_env(store, env.clone(), traced::fd_tell),
+            "fd_write" => Function::new_native_with_env(store, env.clone(), traced::fd_write),
+            "path_create_directory" => Function::new_native_with_env(store, env.clone(), traced::path_create_directory),
+            "path_filestat_get" => Function::new_native_with_env(store, env.clone(), traced::path_filestat_get),
+            "path_filestat_set_times" => Function::new_native_with_env(store, env.clone(), traced::path_filestat_set_times),
+            "path_link" => Function::new_native_with_env(store, env.clone(), traced::path_link),
+            "path_open" => Function::new_native_with_env(store, env.clone(), traced::path_open),
+            "path_readlink" => Function::new_native_with_env(store, env.clone(), traced::path_readlink),
+            "path_remove_directory" => Function::new_native_with_env(store, env.clone(), traced::path_remove_directory),
+            "path_rename" => Function::new_native_with_env(store, env.clone(), traced::path_rename),
+            "path_symlink" => Function::new_native_with_env(store, env.clone(), traced::path_symlink),
+            "path_unlink_file" => Function::new_native_with_env(store, env.clone(), traced::path_unlink_file),
+            "poll_oneoff" => Function::new_native_with_env(store, env.clone(), traced::poll_oneoff),
+            "proc_exit" => Function::new_native_with_env(store, env.clone(), traced::proc_exit),
+            "proc_raise" => Function::new_native_with_env(store, env.clone(), traced::proc_raise),
+            "random_get" => Function::new_native_with_env(store, env.clone(), traced::random_get),
+            "sched_yield" => Function::new_native_with_env(store, env.clone(), traced::sched_yield),
+            "sock_recv" => Function::new_native_with_env(store, env.clone(), traced::sock_recv),
+            "sock_send" => Function::new_native_with_env(store, env.clone(), traced::sock_send),
+            "sock_shutdown" => Function::new_native_with_env(store, env.clone(), traced::sock_shutdown),
         }
     }
 }
@@ -0,0 +1,613 @@
+//! Read-only directories backed by tar and zip archives.
+//!
+//! Archives are indexed once when they're mounted; lookups, directory
+//! listings and file metadata are then served from that index, and file
+//! contents are read straight out of the archive without extracting it.
+
+use crate::state::WasiFile;
+use crate::state::WasiFsError;
+use crate::syscalls::types::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+/// How much is allocated up front for a decompressed entry, whatever size
+/// the archive declares for it.
+const MAX_PREALLOCATED_SIZE: u64 = 1 << 20;
+
+/// The kind of archive mounted by an [`ArchiveDir`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ArchiveFormat {
+    /// An uncompressed tarball
+    Tar,
+    /// A zip file, possibly with deflated entries
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// Detects the format of the archive at `path` from its magic bytes.
+    pub fn detect(path: &Path) -> Result<Self, WasiFsError> {
+        let mut header = [0u8; 262];
+        let mut file = fs::File::open(path)?;
+        let mut read = 0;
+        while read < header.len() {
+            match file.read(&mut header[read..])? {
+                0 => break,
+                n => read += n,
+            }
+        }
+        if read >= 4 && (&header[..4] == b"PK\x03\x04" || &header[..4] == b"PK\x05\x06") {
+            Ok(Self::Zip)
+        } else if read >= 262 && &header[257..262] == b"ustar" {
+            Ok(Self::Tar)
+        } else if path.extension().map(|ext| ext == "tar").unwrap_or(false) {
+            // pre-POSIX tarballs have no magic
+            Ok(Self::Tar)
+        } else {
+            Err(WasiFsError::InvalidData)
+        }
+    }
+}
+
+/// Where the contents of an archive entry are found.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+enum EntryData {
+    /// The entry is a directory and has no contents
+    None,
+    /// The contents are stored uncompressed at `offset` in the archive
+    Stored { offset: u64 },
+    /// The contents are the compressed zip entry number `index`
+    Compressed { index: usize },
+}
+
+/// An entry of an archive index.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ArchiveEntry {
+    size: u64,
+    /// last modification time in nanoseconds as a UNIX timestamp
+    mtime: u64,
+    data: EntryData,
+}
+
+impl ArchiveEntry {
+    fn dir(mtime: u64) -> Self {
+        Self {
+            size: 0,
+            mtime,
+            data: EntryData::None,
+        }
+    }
+
+    fn is_dir(&self) -> bool {
+        matches!(self.data, EntryData::None)
+    }
+}
+
+/// A tar or zip archive mounted as a read-only directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveDir {
+    /// The archive on the host
+    pub path: PathBuf,
+    /// The format of the archive
+    pub format: ArchiveFormat,
+    /// Every entry of the archive by its path relative to the archive root,
+    /// the root itself being the empty path
+    entries: BTreeMap<PathBuf, ArchiveEntry>,
+}
+
+impl ArchiveDir {
+    /// Indexes the archive at `path`.
+    pub fn open(path: &Path) -> Result<Self, WasiFsError> {
+        let format = ArchiveFormat::detect(path)?;
+        let mtime = path
+            .metadata()?
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map(|time| time.as_nanos() as u64)
+            .unwrap_or(0);
+        let mut archive = Self {
+            path: path.to_path_buf(),
+            format,
+            entries: BTreeMap::new(),
+        };
+        archive
+            .entries
+            .insert(PathBuf::new(), ArchiveEntry::dir(mtime));
+        match format {
+            ArchiveFormat::Tar => archive.index_tar()?,
+            ArchiveFormat::Zip => archive.index_zip()?,
+        }
+        Ok(archive)
+    }
+
+    fn index_tar(&mut self) -> Result<(), WasiFsError> {
+        let mut tar = tar::Archive::new(fs::File::open(&self.path)?);
+        for entry in tar.entries()? {
+            let entry = entry?;
+            let rel_path = match sanitize_path(&entry.path()?) {
+                Some(rel_path) => rel_path,
+                None => continue,
+            };
+            let header = entry.header();
+            let mtime = header.mtime().unwrap_or(0).saturating_mul(1_000_000_000);
+            let archive_entry = match header.entry_type() {
+                tar::EntryType::Directory => ArchiveEntry::dir(mtime),
+                tar::EntryType::Regular | tar::EntryType::Continuous => ArchiveEntry {
+                    size: entry.size(),
+                    mtime,
+                    data: EntryData::Stored {
+                        offset: entry.raw_file_position(),
+                    },
+                },
+                // links, devices and sparse files are not supported
+                _ => continue,
+            };
+            self.insert(rel_path, archive_entry);
+        }
+        Ok(())
+    }
+
+    fn index_zip(&mut self) -> Result<(), WasiFsError> {
+        let mut zip = zip::ZipArchive::new(fs::File::open(&self.path)?).map_err(zip_err)?;
+        for index in 0..zip.len() {
+            let file = zip.by_index(index).map_err(zip_err)?;
+            let rel_path = match file.enclosed_name().and_then(sanitize_path) {
+                Some(rel_path) => rel_path,
+                None => continue,
+            };
+            let mtime = zip_datetime_to_nanos(file.last_modified());
+            let archive_entry = if file.is_dir() {
+                ArchiveEntry::dir(mtime)
+            } else {
+                ArchiveEntry {
+                    size: file.size(),
+                    mtime,
+                    data: match file.compression() {
+                        zip::CompressionMethod::Stored => EntryData::Stored {
+                            offset: file.data_start(),
+                        },
+                        _ => EntryData::Compressed { index },
+                    },
+                }
+            };
+            self.insert(rel_path, archive_entry);
+        }
+        Ok(())
+    }
+
+    /// Inserts an entry along with the parent directories that the archive
+    /// doesn't list explicitly.
+    fn insert(&mut self, rel_path: PathBuf, entry: ArchiveEntry) {
+        let mut parent = rel_path.parent();
+        while let Some(dir) = parent {
+            if self.entries.contains_key(dir) {
+                break;
+            }
+            self.entries
+                .insert(dir.to_path_buf(), ArchiveEntry::dir(entry.mtime));
+            parent = dir.parent();
+        }
+        self.entries.insert(rel_path, entry);
+    }
+
+    fn entry(&self, host_path: &Path) -> Option<&ArchiveEntry> {
+        let rel_path = host_path.strip_prefix(&self.path).ok()?;
+        self.entries.get(rel_path)
+    }
+
+    /// Returns whether `host_path` is inside of this archive.
+    pub(crate) fn contains(&self, host_path: &Path) -> bool {
+        host_path.starts_with(&self.path)
+    }
+
+    /// Returns whether `host_path` exists in the archive, and whether it's a
+    /// directory if it does.
+    pub(crate) fn is_dir(&self, host_path: &Path) -> Option<bool> {
+        self.entry(host_path).map(ArchiveEntry::is_dir)
+    }
+
+    /// The metadata of the entry at `host_path`.
+    pub(crate) fn filestat(&self, host_path: &Path) -> Option<__wasi_filestat_t> {
+        self.entry(host_path).map(|entry| __wasi_filestat_t {
+            st_filetype: if entry.is_dir() {
+                __WASI_FILETYPE_DIRECTORY
+            } else {
+                __WASI_FILETYPE_REGULAR_FILE
+            },
+            st_size: entry.size,
+            st_atim: entry.mtime,
+            st_mtim: entry.mtime,
+            st_ctim: entry.mtime,
+            ..__wasi_filestat_t::default()
+        })
+    }
+
+    /// Lists the directory at `host_path`, returning the name of every entry
+    /// and whether it's a directory.
+    pub(crate) fn read_dir(&self, host_path: &Path) -> Result<Vec<(String, bool)>, WasiFsError> {
+        let rel_path = host_path
+            .strip_prefix(&self.path)
+            .map_err(|_| WasiFsError::EntityNotFound)?;
+        match self.entries.get(rel_path) {
+            Some(entry) if entry.is_dir() => (),
+            Some(_) => return Err(WasiFsError::BaseNotDirectory),
+            None => return Err(WasiFsError::EntityNotFound),
+        }
+        Ok(self
+            .entries
+            .iter()
+            .filter(|(path, _)| !path.as_os_str().is_empty() && path.parent() == Some(rel_path))
+            .filter_map(|(path, entry)| {
+                path.file_name()
+                    .map(|name| (name.to_string_lossy().into_owned(), entry.is_dir()))
+            })
+            .collect())
+    }
+
+    /// Opens the file at `host_path` for reading.
+    pub(crate) fn open_file(&self, host_path: &Path) -> Result<ArchiveFile, WasiFsError> {
+        let entry = self.entry(host_path).ok_or(WasiFsError::EntityNotFound)?;
+        if entry.is_dir() {
+            return Err(WasiFsError::NotAFile);
+        }
+        Ok(ArchiveFile {
+            archive_path: self.path.clone(),
+            data: entry.data,
+            size: entry.size,
+            mtime: entry.mtime,
+            pos: 0,
+            reader: None,
+        })
+    }
+}
+
+/// Turns an archive member path into a relative path that can't escape the
+/// archive root.
+fn sanitize_path(path: &Path) -> Option<PathBuf> {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => out.push(part),
+            Component::CurDir => (),
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    if out.as_os_str().is_empty() {
+        None
+    } else {
+        Some(out)
+    }
+}
+
+fn zip_err(err: zip::result::ZipError) -> WasiFsError {
+    match err {
+        zip::result::ZipError::Io(err) => err.into(),
+        zip::result::ZipError::FileNotFound => WasiFsError::EntityNotFound,
+        _ => WasiFsError::InvalidData,
+    }
+}
+
+/// Converts the MS-DOS timestamp of a zip entry to nanoseconds since the
+/// UNIX epoch.
+fn zip_datetime_to_nanos(datetime: zip::DateTime) -> u64 {
+    // days from civil, see http://howardhinnant.github.io/date_algorithms.html
+    let (month, day) = (datetime.month() as i64, datetime.day() as i64);
+    let year = datetime.year() as i64 - if month <= 2 { 1 } else { 0 };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+    let seconds = days * 86_400
+        + datetime.hour() as i64 * 3_600
+        + datetime.minute() as i64 * 60
+        + datetime.second() as i64;
+    seconds.max(0) as u64 * 1_000_000_000
+}
+
+/// The open contents of an [`ArchiveFile`].
+#[derive(Debug)]
+enum ArchiveReader {
+    /// The archive itself, reading from the entry's offset
+    Stored(fs::File),
+    /// The decompressed contents of the entry
+    Decompressed(Vec<u8>),
+}
+
+/// A read-only file inside of a mounted archive.
+///
+/// The archive is (re)opened lazily on the first read, so that this file
+/// survives serialization of the [`WasiState`](crate::WasiState).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchiveFile {
+    archive_path: PathBuf,
+    data: EntryData,
+    size: u64,
+    mtime: u64,
+    pos: u64,
+    #[serde(skip)]
+    reader: Option<ArchiveReader>,
+}
+
+impl ArchiveFile {
+    fn reader(&mut self) -> io::Result<&mut ArchiveReader> {
+        if self.reader.is_none() {
+            let reader = match self.data {
+                EntryData::None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Other,
+                        "archive directories can't be read",
+                    ))
+                }
+                EntryData::Stored { .. } => {
+                    ArchiveReader::Stored(fs::File::open(&self.archive_path)?)
+                }
+                EntryData::Compressed { index } => {
+                    let mut zip = zip::ZipArchive::new(fs::File::open(&self.archive_path)?)
+                        .map_err(io::Error::from)?;
+                    let mut file = zip.by_index(index).map_err(io::Error::from)?;
+                    // the declared size isn't trusted for allocating, and a
+                    // single byte past it is read to reject longer entries
+                    let capacity = std::cmp::min(self.size, MAX_PREALLOCATED_SIZE) as usize;
+                    let mut contents = Vec::with_capacity(capacity);
+                    (&mut file)
+                        .take(self.size.saturating_add(1))
+                        .read_to_end(&mut contents)?;
+                    if contents.len() as u64 > self.size {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "archive entry is larger than its declared size",
+                        ));
+                    }
+                    ArchiveReader::Decompressed(contents)
+                }
+            };
+            self.reader = Some(reader);
+        }
+        Ok(self.reader.as_mut().unwrap())
+    }
+}
+
+impl Read for ArchiveFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.size.saturating_sub(self.pos);
+        let amt = std::cmp::min(buf.len() as u64, remaining) as usize;
+        if amt == 0 {
+            return Ok(0);
+        }
+        let (pos, data) = (self.pos, self.data);
+        let read = match self.reader()? {
+            ArchiveReader::Stored(file) => {
+                let offset = match data {
+                    EntryData::Stored { offset } => offset,
+                    _ => unreachable!("stored reader for an entry that isn't stored"),
+                };
+                file.seek(SeekFrom::Start(offset.saturating_add(pos)))?;
+                file.read(&mut buf[..amt])?
+            }
+            ArchiveReader::Decompressed(contents) => {
+                // the archive may be shorter than the size it declares
+                let start = std::cmp::min(pos, contents.len() as u64) as usize;
+                let end = std::cmp::min(start + amt, contents.len());
+                buf[..end - start].copy_from_slice(&contents[start..end]);
+                end - start
+            }
+        };
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for ArchiveFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::End(offset) => self.size as i64 + offset,
+            SeekFrom::Current(offset) => self.pos as i64 + offset,
+        };
+        if new_pos < 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative position",
+            ));
+        }
+        self.pos = new_pos as u64;
+        Ok(self.pos)
+    }
+}
+
+impl Write for ArchiveFile {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "can not write to a file in an archive",
+        ))
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[typetag::serde]
+impl WasiFile for ArchiveFile {
+    fn last_accessed(&self) -> __wasi_timestamp_t {
+        self.mtime
+    }
+    fn last_modified(&self) -> __wasi_timestamp_t {
+        self.mtime
+    }
+    fn created_time(&self) -> __wasi_timestamp_t {
+        self.mtime
+    }
+    fn size(&self) -> u64 {
+        self.size
+    }
+    fn set_len(&mut self, _new_size: __wasi_filesize_t) -> Result<(), WasiFsError> {
+        Err(WasiFsError::PermissionDenied)
+    }
+    fn unlink(&mut self) -> Result<(), WasiFsError> {
+        Err(WasiFsError::PermissionDenied)
+    }
+    fn rename_file(&self, _new_name: &Path) -> Result<(), WasiFsError> {
+        Err(WasiFsError::PermissionDenied)
+    }
+    fn bytes_available(&self) -> Result<usize, WasiFsError> {
+        Ok(self.size.saturating_sub(self.pos) as usize)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn scratch_file(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("wasmer-wasi-archive-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(name)
+    }
+
+    fn read_file(archive: &ArchiveDir, rel_path: &str) -> Vec<u8> {
+        let mut file = archive.open_file(&archive.path.join(rel_path)).unwrap();
+        let mut contents = vec![];
+        file.read_to_end(&mut contents).unwrap();
+        contents
+    }
+
+    fn check_archive(archive: &ArchiveDir) {
+        let root = archive.path.clone();
+        let mut listing = archive.read_dir(&root).unwrap();
+        listing.sort();
+        assert_eq!(
+            listing,
+            vec![
+                ("assets".to_string(), true),
+                ("hello.txt".to_string(), false)
+            ]
+        );
+        assert_eq!(archive.is_dir(&root.join("assets")), Some(true));
+        assert_eq!(archive.is_dir(&root.join("missing")), None);
+        assert_eq!(read_file(archive, "hello.txt"), b"hello world");
+        assert_eq!(read_file(archive, "assets/data.bin"), vec![7u8; 1000]);
+        assert_eq!(
+            archive
+                .filestat(&root.join("assets/data.bin"))
+                .unwrap()
+                .st_size,
+            1000
+        );
+
+        let mut file = archive.open_file(&root.join("hello.txt")).unwrap();
+        file.seek(SeekFrom::End(-5)).unwrap();
+        let mut contents = String::new();
+        file.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "world");
+    }
+
+    #[test]
+    fn tar_archive() {
+        let path = scratch_file("test.tar");
+        {
+            let mut builder = tar::Builder::new(fs::File::create(&path).unwrap());
+            for (name, contents) in &[
+                ("hello.txt", b"hello world".to_vec()),
+                ("assets/data.bin", vec![7u8; 1000]),
+            ] {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(contents.len() as u64);
+                header.set_mode(0o644);
+                header.set_cksum();
+                builder
+                    .append_data(&mut header, name, &contents[..])
+                    .unwrap();
+            }
+            builder.finish().unwrap();
+        }
+        let archive = ArchiveDir::open(&path).unwrap();
+        assert_eq!(archive.format, ArchiveFormat::Tar);
+        check_archive(&archive);
+    }
+
+    #[test]
+    fn truncated_entries_read_short() {
+        let mut file = ArchiveFile {
+            archive_path: PathBuf::new(),
+            data: EntryData::Compressed { index: 0 },
+            size: 10,
+            mtime: 0,
+            pos: 0,
+            reader: Some(ArchiveReader::Decompressed(vec![1, 2, 3])),
+        };
+        let mut contents = vec![];
+        file.read_to_end(&mut contents).unwrap();
+        assert_eq!(contents, vec![1, 2, 3]);
+
+        file.seek(SeekFrom::Start(5)).unwrap();
+        assert_eq!(file.read(&mut [0; 4]).unwrap(), 0);
+    }
+
+    #[test]
+    fn compressed_entries_are_bounded_by_their_declared_size() {
+        let path = scratch_file("sizes.zip");
+        {
+            let mut writer = zip::ZipWriter::new(fs::File::create(&path).unwrap());
+            writer
+                .start_file(
+                    "data.bin",
+                    zip::write::FileOptions::default()
+                        .compression_method(zip::CompressionMethod::Deflated),
+                )
+                .unwrap();
+            writer.write_all(&[7u8; 1000]).unwrap();
+            writer.finish().unwrap();
+        }
+        let entry = |size| ArchiveFile {
+            archive_path: path.clone(),
+            data: EntryData::Compressed { index: 0 },
+            size,
+            mtime: 0,
+            pos: 0,
+            reader: None,
+        };
+
+        // a huge declared size isn't allocated up front
+        let mut contents = vec![];
+        entry(u64::MAX).read_to_end(&mut contents).unwrap();
+        assert_eq!(contents, vec![7u8; 1000]);
+
+        // and an entry decompressing past its declared size is an error
+        let error = entry(10).read(&mut [0; 4]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn zip_archive() {
+        let path = scratch_file("test.zip");
+        {
+            let mut writer = zip::ZipWriter::new(fs::File::create(&path).unwrap());
+            writer
+                .start_file(
+                    "hello.txt",
+                    zip::write::FileOptions::default()
+                        .compression_method(zip::CompressionMethod::Stored),
+                )
+                .unwrap();
+            writer.write_all(b"hello world").unwrap();
+            writer
+                .start_file(
+                    "assets/data.bin",
+                    zip::write::FileOptions::default()
+                        .compression_method(zip::CompressionMethod::Deflated),
+                )
+                .unwrap();
+            writer.write_all(&[7u8; 1000]).unwrap();
+            writer.finish().unwrap();
+        }
+        let archive = ArchiveDir::open(&path).unwrap();
+        assert_eq!(archive.format, ArchiveFormat::Zip);
+        check_archive(&archive);
+    }
+}
@@ -242,6 +242,44 @@ impl WasiStateBuilder {
         Ok(self)
     }
 
+    /// Mount the tar or zip archive `archive` as a read-only directory
+    /// exposed to the WASI as `alias`.
+    ///
+    /// The archive is indexed when the [`WasiState`] is built, and files are
+    /// read straight out of it without extracting it.
+    pub fn map_dir_archive<FilePath>(
+        &mut self,
+        alias: &str,
+        archive: FilePath,
+    ) -> Result<&mut Self, WasiStateCreationError>
+    where
+        FilePath: AsRef<Path>,
+    {
+        let mut pdb = PreopenDirBuilder::new();
+        pdb.archive(archive).alias(alias).read(true);
+        let preopen = pdb.build()?;
+
+        self.preopens.push(preopen);
+
+        Ok(self)
+    }
+
+    /// Mount archives as read-only directories with names exposed to the WASI.
+    pub fn map_dir_archives<I, FilePath>(
+        &mut self,
+        mapped_archives: I,
+    ) -> Result<&mut Self, WasiStateCreationError>
+    where
+        I: IntoIterator<Item = (String, FilePath)>,
+        FilePath: AsRef<Path>,
+    {
+        for (alias, archive) in mapped_archives {
+            self.map_dir_archive(&alias, archive)?;
+        }
+
+        Ok(self)
+    }
+
     /// Preopen directorys with a different names exposed to the WASI.
     pub fn map_dirs<I, FilePath>(
         &mut self,
@@ -414,6 +452,8 @@ impl WasiStateBuilder {
 pub struct PreopenDirBuilder {
     path: Option<PathBuf>,
     alias: Option<String>,
+    overlay: Option<PathBuf>,
+    archive: bool,
     read: bool,
     write: bool,
     create: bool,
@@ -424,6 +464,8 @@ pub struct PreopenDirBuilder {
 pub(crate) struct PreopenedDir {
     pub(crate) path: PathBuf,
     pub(crate) alias: Option<String>,
+    pub(crate) overlay: Option<PathBuf>,
+    pub(crate) archive: bool,
     pub(crate) read: bool,
     pub(crate) write: bool,
     pub(crate) create: bool,
@@ -442,6 +484,21 @@ impl PreopenDirBuilder {
     {
         let path = po_dir.as_ref();
         self.path = Some(path.to_path_buf());
+        self.archive = false;
+
+        self
+    }
+
+    /// Point the preopened directory to the root of the tar or zip archive
+    /// `archive`.
+    ///
+    /// Archives are always read-only.
+    pub fn archive<FilePath>(&mut self, archive: FilePath) -> &mut Self
+    where
+        FilePath: AsRef<Path>,
+    {
+        self.path = Some(archive.as_ref().to_path_buf());
+        self.archive = true;
 
         self
     }
@@ -456,6 +513,40 @@ impl PreopenDirBuilder {
         self
     }
 
+    /// Layer the scratch directory `scratch_dir` over the preopened
+    /// directory, which is then never modified.
+    ///
+    /// Reads fall through to the preopened directory, files are copied up
+    /// into `scratch_dir` before being written to, and deleted entries are
+    /// hidden by whiteout files in `scratch_dir`.
+    ///
+    /// Usage:
+    ///
+    /// ```no_run
+    /// # use wasmer_wasi::{WasiState, WasiStateCreationError};
+    /// # fn main() -> Result<(), WasiStateCreationError> {
+    /// WasiState::new("program_name")
+    ///    .preopen(|p| {
+    ///        p.directory("/opt/dataset")
+    ///            .overlay("/tmp/dataset-scratch")
+    ///            .alias("dataset")
+    ///            .read(true)
+    ///            .write(true)
+    ///            .create(true)
+    ///    })?
+    ///    .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn overlay<FilePath>(&mut self, scratch_dir: FilePath) -> &mut Self
+    where
+        FilePath: AsRef<Path>,
+    {
+        self.overlay = Some(scratch_dir.as_ref().to_path_buf());
+
+        self
+    }
+
     /// Set read permissions affecting files in the directory
     pub fn read(&mut self, toggle: bool) -> &mut Self {
         self.read = toggle;
@@ -501,10 +592,39 @@ impl PreopenDirBuilder {
         if let Some(alias) = &self.alias {
             validate_mapped_dir_alias(alias)?;
         }
+        if self.archive {
+            if !path.is_file() {
+                return Err(WasiStateCreationError::PreopenedDirectoryError(format!(
+                    "Archive {:?} is not a file",
+                    path
+                )));
+            }
+            if self.write || self.create || self.overlay.is_some() {
+                return Err(WasiStateCreationError::PreopenedDirectoryError(format!(
+                    "Archive {:?} can only be mounted read-only",
+                    path
+                )));
+            }
+        }
+        if let Some(scratch_dir) = &self.overlay {
+            if !scratch_dir.is_dir() {
+                return Err(WasiStateCreationError::PreopenedDirectoryNotFound(
+                    scratch_dir.clone(),
+                ));
+            }
+            if scratch_dir.starts_with(&path) || path.starts_with(scratch_dir) {
+                return Err(WasiStateCreationError::PreopenedDirectoryError(format!(
+                    "Overlay scratch directory {:?} and preopened directory {:?} must not contain each other",
+                    scratch_dir, path
+                )));
+            }
+        }
 
         Ok(PreopenedDir {
             path,
             alias: self.alias.clone(),
+            overlay: self.overlay.clone(),
+            archive: self.archive,
             read: self.read,
             write: self.write,
             create: self.create,
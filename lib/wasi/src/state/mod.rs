@@ -15,10 +15,14 @@
 
 #![allow(clippy::cognitive_complexity, clippy::too_many_arguments)]
 
+mod archive;
 mod builder;
+mod overlay;
 mod types;
 
+pub use self::archive::{ArchiveDir, ArchiveFile, ArchiveFormat};
 pub use self::builder::*;
+pub use self::overlay::OverlayDir;
 pub use self::types::*;
 use crate::syscalls::types::*;
 use generational_arena::Arena;
@@ -163,6 +167,10 @@ pub struct WasiFs {
     inode_counter: Cell<u64>,
     /// for fds still open after the file has been deleted
     pub orphan_fds: HashMap<Inode, InodeVal>,
+    /// copy-on-write layers of the preopened directories that have one
+    pub overlays: Vec<OverlayDir>,
+    /// archives mounted as read-only preopened directories
+    pub archives: Vec<ArchiveDir>,
 }
 
 impl WasiFs {
@@ -173,6 +181,8 @@ impl WasiFs {
         for PreopenedDir {
             path,
             alias,
+            overlay,
+            archive,
             read,
             write,
             create,
@@ -191,12 +201,33 @@ impl WasiFs {
                 )
             })?;
 
-            let kind = if cur_dir_metadata.is_dir() {
+            let kind = if *archive {
+                // archives are indexed up front and looked up with their own
+                // path as the root directory
+                let archive = ArchiveDir::open(path)
+                    .map_err(|e| format!("Could not open archive {:?}: {}", path, e))?;
+                wasi_fs.archives.push(archive);
                 Kind::Dir {
                     parent: Some(root_inode),
                     path: path.clone(),
                     entries: Default::default(),
                 }
+            } else if cur_dir_metadata.is_dir() {
+                // overlaid directories live in their scratch directory, which
+                // falls through to `path` for anything it doesn't have
+                let host_path = if let Some(scratch_dir) = overlay {
+                    wasi_fs
+                        .overlays
+                        .push(OverlayDir::new(scratch_dir.clone(), path.clone()));
+                    scratch_dir.clone()
+                } else {
+                    path.clone()
+                };
+                Kind::Dir {
+                    parent: Some(root_inode),
+                    path: host_path,
+                    entries: Default::default(),
+                }
             } else {
                 return Err(format!(
                     "WASI only supports pre-opened directories right now; found \"{}\"",
@@ -305,6 +336,8 @@ impl WasiFs {
             next_fd: Cell::new(3),
             inode_counter: Cell::new(1024),
             orphan_fds: HashMap::new(),
+            overlays: vec![],
+            archives: vec![],
         };
         wasi_fs.create_stdin();
         wasi_fs.create_stdout();
@@ -657,7 +690,58 @@ impl WasiFs {
                                 cd.push(component);
                                 cd
                             };
-                            let metadata = file.symlink_metadata().ok().ok_or(__WASI_EINVAL)?;
+                            if let Some(archive) = self.archive_for(&file) {
+                                // archive entries come from the index built when
+                                // the archive was mounted
+                                let kind = match archive.is_dir(&file) {
+                                    Some(true) => Kind::Dir {
+                                        parent: Some(cur_inode),
+                                        path: file.clone(),
+                                        entries: Default::default(),
+                                    },
+                                    Some(false) => Kind::File {
+                                        handle: None,
+                                        path: file.clone(),
+                                        fd: None,
+                                    },
+                                    None => return Err(__WASI_EINVAL),
+                                };
+                                let new_inode = self.create_inode(
+                                    kind,
+                                    false,
+                                    file.to_string_lossy().to_string(),
+                                )?;
+                                if let Kind::Dir {
+                                    ref mut entries, ..
+                                } = &mut self.inodes[cur_inode].kind
+                                {
+                                    entries.insert(
+                                        component.as_os_str().to_string_lossy().to_string(),
+                                        new_inode,
+                                    );
+                                }
+                                cur_inode = new_inode;
+                                continue 'path_iter;
+                            }
+                            // inside of an overlay `file` is the path in the scratch
+                            // directory, and `source` is where the entry actually lives
+                            let source = match self.overlay_for(&file) {
+                                Some((overlay, _)) => {
+                                    let source = overlay.resolve(&file).ok_or(__WASI_EINVAL)?;
+                                    if source != file && source.is_dir() {
+                                        // directories are copied up (empty) when first
+                                        // traversed so that `Kind::Dir::path` always exists
+                                        overlay
+                                            .copy_up(&file)
+                                            .map_err(WasiFsError::into_wasi_err)?;
+                                        file.clone()
+                                    } else {
+                                        source
+                                    }
+                                }
+                                None => file.clone(),
+                            };
+                            let metadata = source.symlink_metadata().ok().ok_or(__WASI_EINVAL)?;
                             let file_type = metadata.file_type();
                             // we want to insert newly opened dirs and files, but not transient symlinks
                             // TODO: explain why (think about this deeply when well rested)
@@ -676,12 +760,12 @@ impl WasiFs {
                                 // load file
                                 Kind::File {
                                     handle: None,
-                                    path: file.clone(),
+                                    path: source.clone(),
                                     fd: None,
                                 }
                             } else if file_type.is_symlink() {
                                 should_insert = false;
-                                let link_value = file.read_link().ok().ok_or(__WASI_EIO)?;
+                                let link_value = source.read_link().ok().ok_or(__WASI_EIO)?;
                                 debug!("attempting to decompose path {:?}", link_value);
 
                                 let (pre_open_dir_fd, relative_path) = if link_value.is_relative() {
@@ -896,6 +980,87 @@ impl WasiFs {
         }
     }
 
+    /// Finds the overlay that the host path `path` belongs to, either through
+    /// its scratch directory or its lower directory, along with the path
+    /// that `path` has in the scratch directory.
+    pub(crate) fn overlay_for(&self, path: &Path) -> Option<(&OverlayDir, PathBuf)> {
+        self.overlays
+            .iter()
+            .find_map(|overlay| overlay.upper_path(path).map(|upper| (overlay, upper)))
+    }
+
+    /// Finds the mounted archive that the host path `path` is inside of.
+    pub(crate) fn archive_for(&self, path: &Path) -> Option<&ArchiveDir> {
+        self.archives.iter().find(|archive| archive.contains(path))
+    }
+
+    /// Gives `inode` a handle reading from its archive if it's a file inside
+    /// of a mounted archive, returning whether it is one.
+    pub(crate) fn open_archive_file(&mut self, inode: Inode) -> Result<bool, __wasi_errno_t> {
+        let archive_file = match &self.inodes[inode].kind {
+            Kind::File {
+                handle: None, path, ..
+            } => match self.archive_for(path) {
+                Some(archive) => archive
+                    .open_file(path)
+                    .map_err(WasiFsError::into_wasi_err)?,
+                None => return Ok(false),
+            },
+            Kind::File {
+                handle: Some(handle),
+                ..
+            } => return Ok(handle.downcast_ref::<ArchiveFile>().is_some()),
+            _ => return Ok(false),
+        };
+        if let Kind::File { handle, .. } = &mut self.inodes[inode].kind {
+            *handle = Some(Box::new(archive_file));
+        }
+        Ok(true)
+    }
+
+    /// Returns whether `inode` is a file that is still only present in the
+    /// read-only lower directory of an overlay.
+    pub(crate) fn is_overlay_lower(&self, inode: Inode) -> bool {
+        match &self.inodes[inode].kind {
+            Kind::File { path, .. } => self
+                .overlay_for(path)
+                .map(|(overlay, _)| overlay.is_lower_path(path))
+                .unwrap_or(false),
+            _ => false,
+        }
+    }
+
+    /// Copies the file `inode` up into the scratch directory of its overlay
+    /// so that it can be modified, if it isn't there already.
+    pub(crate) fn overlay_copy_up(&mut self, inode: Inode) -> Result<(), __wasi_errno_t> {
+        if !self.is_overlay_lower(inode) {
+            return Ok(());
+        }
+        let upper_path = match &self.inodes[inode].kind {
+            Kind::File { path, .. } => {
+                let (overlay, upper_path) = self.overlay_for(path).ok_or(__WASI_EIO)?;
+                overlay
+                    .copy_up(&upper_path)
+                    .map_err(WasiFsError::into_wasi_err)?;
+                upper_path
+            }
+            _ => unreachable!("only files can live in the lower directory of an overlay"),
+        };
+        if let Kind::File { handle, path, .. } = &mut self.inodes[inode].kind {
+            // an open handle would keep reading and modifying the lower file
+            if let Some(handle) = handle {
+                if let Some(host_file) = handle.downcast_ref::<HostFile>() {
+                    let reopened = host_file
+                        .reopen(upper_path.clone())
+                        .map_err(|e| WasiFsError::from(e).into_wasi_err())?;
+                    *handle = Box::new(reopened);
+                }
+            }
+            *path = upper_path;
+        }
+        Ok(())
+    }
+
     /// finds the number of directories between the fd and the inode if they're connected
     /// expects inode to point to a directory
     pub(crate) fn path_depth_from_fd(
@@ -1271,9 +1436,15 @@ impl WasiFs {
                         ..__wasi_filestat_t::default()
                     })
                 }
+                None => match self.archive_for(path) {
+                    Some(archive) => return archive.filestat(path),
+                    None => path.metadata().ok()?,
+                },
+            },
+            Kind::Dir { path, .. } => match self.archive_for(path) {
+                Some(archive) => return archive.filestat(path),
                 None => path.metadata().ok()?,
             },
-            Kind::Dir { path, .. } => path.metadata().ok()?,
             Kind::Symlink {
                 base_po_dir,
                 path_to_symlink,
@@ -0,0 +1,352 @@
+//! Copy-on-write overlays for preopened directories.
+//!
+//! An overlay layers a writable scratch directory (the "upper" layer) over a
+//! read-only host directory (the "lower" layer), in the spirit of Linux's
+//! overlayfs:
+//!
+//! - reads fall through to the lower layer when the upper one doesn't have
+//!   the entry;
+//! - files are copied up into the upper layer before they're written to;
+//! - deleting an entry that exists in the lower layer leaves a whiteout file
+//!   (`.wh.<name>`) in the upper layer that hides it;
+//! - a directory that is re-created over a whiteout is marked opaque
+//!   (`.wh..wh..opq`) so that the old lower contents stay hidden.
+//!
+//! The lower directory is never modified.
+
+use crate::state::WasiFsError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Prefix of the files that hide lower entries.
+const WHITEOUT_PREFIX: &str = ".wh.";
+/// Marker file hiding all lower entries of the directory it's in.
+const OPAQUE_MARKER: &str = ".wh..wh..opq";
+
+/// A scratch directory layered over a read-only host directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OverlayDir {
+    /// The scratch directory receiving all modifications.
+    pub upper: PathBuf,
+    /// The read-only directory reads fall through to.
+    pub lower: PathBuf,
+}
+
+impl OverlayDir {
+    pub(crate) fn new(upper: PathBuf, lower: PathBuf) -> Self {
+        Self { upper, lower }
+    }
+
+    /// Returns whether `name` is one of the bookkeeping files of the
+    /// overlay and must never be shown to the guest.
+    pub(crate) fn is_hidden_name(name: &str) -> bool {
+        name.starts_with(WHITEOUT_PREFIX)
+    }
+
+    /// Maps a host path in either layer to the path it has (or would have)
+    /// in the upper layer.
+    pub(crate) fn upper_path(&self, path: &Path) -> Option<PathBuf> {
+        if let Ok(rel) = path.strip_prefix(&self.upper) {
+            Some(self.upper.join(rel))
+        } else if let Ok(rel) = path.strip_prefix(&self.lower) {
+            Some(self.upper.join(rel))
+        } else {
+            None
+        }
+    }
+
+    /// Returns whether `path` points inside the lower layer.
+    pub(crate) fn is_lower_path(&self, path: &Path) -> bool {
+        path.starts_with(&self.lower)
+    }
+
+    /// Returns the lower counterpart of `upper_path` if it isn't hidden by
+    /// a whiteout or an opaque directory in the upper layer.
+    fn visible_lower_path(&self, upper_path: &Path) -> Option<PathBuf> {
+        let rel = upper_path.strip_prefix(&self.upper).ok()?;
+        let mut cur_upper = self.upper.clone();
+        let mut cur_lower = self.lower.clone();
+        for component in rel.components() {
+            if cur_upper.join(OPAQUE_MARKER).exists() {
+                return None;
+            }
+            cur_upper.push(component);
+            cur_lower.push(component);
+            if Self::whiteout_path(&cur_upper)?.exists() {
+                return None;
+            }
+        }
+        Some(cur_lower)
+    }
+
+    fn whiteout_path(upper_path: &Path) -> Option<PathBuf> {
+        let name = upper_path.file_name()?.to_string_lossy();
+        Some(
+            upper_path
+                .parent()?
+                .join(format!("{}{}", WHITEOUT_PREFIX, name)),
+        )
+    }
+
+    /// Finds the layer that backs `upper_path`, returning the host path the
+    /// entry currently lives at, or `None` if it doesn't exist in either.
+    pub(crate) fn resolve(&self, upper_path: &Path) -> Option<PathBuf> {
+        let name = upper_path.file_name()?.to_string_lossy();
+        if Self::is_hidden_name(&name) {
+            return None;
+        }
+        if upper_path.symlink_metadata().is_ok() {
+            return Some(upper_path.to_path_buf());
+        }
+        self.visible_lower_path(upper_path)
+            .filter(|lower_path| lower_path.symlink_metadata().is_ok())
+    }
+
+    /// Makes sure the entry at `upper_path` exists in the upper layer,
+    /// copying it up from the lower layer if needed.
+    ///
+    /// Directories are copied up empty; their lower contents keep showing
+    /// through. Symbolic links are recreated rather than followed, so that
+    /// nothing outside of the lower directory is copied up.
+    pub(crate) fn copy_up(&self, upper_path: &Path) -> Result<(), WasiFsError> {
+        if upper_path.symlink_metadata().is_ok() {
+            return Ok(());
+        }
+        let lower_path = self
+            .visible_lower_path(upper_path)
+            .ok_or(WasiFsError::EntityNotFound)?;
+        let metadata = lower_path.symlink_metadata()?;
+        if let Some(parent) = upper_path.parent() {
+            self.copy_up(parent)?;
+        }
+        if metadata.is_dir() {
+            fs::create_dir(upper_path)?;
+        } else if metadata.file_type().is_symlink() {
+            Self::copy_symlink(&lower_path, upper_path)?;
+        } else {
+            fs::copy(&lower_path, upper_path)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    fn copy_symlink(lower_path: &Path, upper_path: &Path) -> Result<(), WasiFsError> {
+        std::os::unix::fs::symlink(fs::read_link(lower_path)?, upper_path)?;
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn copy_symlink(_lower_path: &Path, _upper_path: &Path) -> Result<(), WasiFsError> {
+        Err(WasiFsError::PermissionDenied)
+    }
+
+    /// Creates a directory at `upper_path`.
+    ///
+    /// An existing directory in the lower layer is copied up, and a directory
+    /// created over a whiteout is marked opaque.
+    pub(crate) fn create_dir(&self, upper_path: &Path) -> Result<(), WasiFsError> {
+        if let Some(source) = self.resolve(upper_path) {
+            return if source.is_dir() {
+                self.copy_up(upper_path)
+            } else {
+                Err(WasiFsError::BaseNotDirectory)
+            };
+        }
+        let whited_out = self.clear_whiteout(upper_path)?;
+        fs::create_dir(upper_path)?;
+        if whited_out {
+            fs::File::create(upper_path.join(OPAQUE_MARKER))?;
+        }
+        Ok(())
+    }
+
+    /// Removes a whiteout hiding `upper_path`, returning whether there was
+    /// one. Must be called before creating a new entry at `upper_path`.
+    pub(crate) fn clear_whiteout(&self, upper_path: &Path) -> Result<bool, WasiFsError> {
+        let whiteout = Self::whiteout_path(upper_path).ok_or(WasiFsError::InvalidInput)?;
+        if whiteout.exists() {
+            fs::remove_file(&whiteout)?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Deletes the entry at `upper_path`, leaving a whiteout behind if the
+    /// lower layer has an entry with the same name.
+    ///
+    /// Directories are only removed if they are empty once both layers are
+    /// merged.
+    pub(crate) fn remove(&self, upper_path: &Path) -> Result<(), WasiFsError> {
+        let source = self
+            .resolve(upper_path)
+            .ok_or(WasiFsError::EntityNotFound)?;
+        if source.symlink_metadata()?.is_dir() && !self.read_dir(upper_path)?.is_empty() {
+            return Err(WasiFsError::DirectoryNotEmpty);
+        }
+        let lower_exists = self
+            .visible_lower_path(upper_path)
+            .map(|lower_path| lower_path.symlink_metadata().is_ok())
+            .unwrap_or(false);
+        match upper_path.symlink_metadata() {
+            Ok(metadata) if metadata.is_dir() => {
+                // the merged directory is empty, so only the overlay's own
+                // bookkeeping files may be left in the upper one
+                for entry in fs::read_dir(upper_path)? {
+                    fs::remove_file(entry?.path())?;
+                }
+                fs::remove_dir(upper_path)?;
+            }
+            Ok(_) => fs::remove_file(upper_path)?,
+            Err(_) => (),
+        }
+        if lower_exists {
+            // the whiteout lives next to the entry, so its parent must exist
+            // in the upper layer too
+            if let Some(parent) = upper_path.parent() {
+                self.copy_up(parent)?;
+            }
+            let whiteout = Self::whiteout_path(upper_path).ok_or(WasiFsError::InvalidInput)?;
+            fs::File::create(whiteout)?;
+        }
+        Ok(())
+    }
+
+    /// Lists the merged contents of the directory at `upper_path`.
+    ///
+    /// Entries of the upper layer shadow lower entries of the same name, and
+    /// whited out lower entries are skipped.
+    pub(crate) fn read_dir(
+        &self,
+        upper_path: &Path,
+    ) -> Result<Vec<(String, fs::FileType)>, WasiFsError> {
+        let mut merged: HashMap<String, fs::FileType> = HashMap::new();
+        let mut whiteouts = vec![];
+        let mut opaque = false;
+        if upper_path.is_dir() {
+            for entry in fs::read_dir(upper_path)? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if name == OPAQUE_MARKER {
+                    opaque = true;
+                } else if let Some(hidden) = name.strip_prefix(WHITEOUT_PREFIX) {
+                    whiteouts.push(hidden.to_string());
+                } else {
+                    merged.insert(name, entry.file_type()?);
+                }
+            }
+        }
+        if !opaque {
+            if let Some(lower_path) = self.visible_lower_path(upper_path) {
+                if lower_path.is_dir() {
+                    for entry in fs::read_dir(lower_path)? {
+                        let entry = entry?;
+                        let name = entry.file_name().to_string_lossy().into_owned();
+                        if whiteouts.contains(&name) || merged.contains_key(&name) {
+                            continue;
+                        }
+                        merged.insert(name, entry.file_type()?);
+                    }
+                }
+            }
+        }
+        Ok(merged.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn scratch(name: &str) -> (PathBuf, PathBuf) {
+        let root = std::env::temp_dir().join(format!(
+            "wasmer-wasi-overlay-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&root);
+        let upper = root.join("upper");
+        let lower = root.join("lower");
+        fs::create_dir_all(&upper).unwrap();
+        fs::create_dir_all(lower.join("dir")).unwrap();
+        fs::write(lower.join("file.txt"), b"lower").unwrap();
+        fs::write(lower.join("dir").join("nested.txt"), b"nested").unwrap();
+        (upper, lower)
+    }
+
+    fn names(overlay: &OverlayDir, path: &Path) -> Vec<String> {
+        let mut names: Vec<String> = overlay
+            .read_dir(path)
+            .unwrap()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn reads_fall_through_and_copy_up() {
+        let (upper, lower) = scratch("copy-up");
+        let overlay = OverlayDir::new(upper.clone(), lower.clone());
+
+        let file = upper.join("file.txt");
+        assert_eq!(overlay.resolve(&file), Some(lower.join("file.txt")));
+        overlay.copy_up(&file).unwrap();
+        assert_eq!(overlay.resolve(&file), Some(file.clone()));
+        fs::write(&file, b"upper").unwrap();
+        assert_eq!(fs::read(lower.join("file.txt")).unwrap(), b"lower");
+
+        let nested = upper.join("dir").join("nested.txt");
+        overlay.copy_up(&nested).unwrap();
+        assert!(upper.join("dir").is_dir());
+        assert_eq!(fs::read(&nested).unwrap(), b"nested");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_are_copied_up_as_links() {
+        let (upper, lower) = scratch("symlink");
+        let overlay = OverlayDir::new(upper.clone(), lower.clone());
+        let outside = lower.parent().unwrap().join("outside.txt");
+        fs::write(&outside, b"outside").unwrap();
+        std::os::unix::fs::symlink(&outside, lower.join("link")).unwrap();
+
+        let link = upper.join("link");
+        overlay.copy_up(&link).unwrap();
+        assert!(link.symlink_metadata().unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_link(&link).unwrap(), outside);
+    }
+
+    #[test]
+    fn whiteouts_hide_lower_entries() {
+        let (upper, lower) = scratch("whiteout");
+        let overlay = OverlayDir::new(upper.clone(), lower.clone());
+
+        fs::write(upper.join("new.txt"), b"new").unwrap();
+        assert_eq!(names(&overlay, &upper), vec!["dir", "file.txt", "new.txt"]);
+
+        overlay.remove(&upper.join("file.txt")).unwrap();
+        assert!(lower.join("file.txt").exists());
+        assert_eq!(overlay.resolve(&upper.join("file.txt")), None);
+        assert_eq!(names(&overlay, &upper), vec!["dir", "new.txt"]);
+
+        assert!(matches!(
+            overlay.remove(&upper.join("dir")),
+            Err(WasiFsError::DirectoryNotEmpty)
+        ));
+        assert_eq!(names(&overlay, &upper.join("dir")), vec!["nested.txt"]);
+
+        overlay
+            .remove(&upper.join("dir").join("nested.txt"))
+            .unwrap();
+        assert!(names(&overlay, &upper.join("dir")).is_empty());
+        overlay.remove(&upper.join("dir")).unwrap();
+        assert_eq!(overlay.resolve(&upper.join("dir")), None);
+        overlay.create_dir(&upper.join("dir")).unwrap();
+        assert!(names(&overlay, &upper.join("dir")).is_empty());
+        assert!(lower.join("dir").join("nested.txt").exists());
+    }
+}
@@ -87,6 +87,9 @@ pub enum WasiFsError {
     /// A call to write returned 0
     #[error("write returned 0")]
     WriteZero,
+    /// The directory still contains entries
+    #[error("directory not empty")]
+    DirectoryNotEmpty,
     /// A WASI error without an external name.  If you encounter this it means
     /// that there's probably a bug on our side (maybe as simple as forgetting to wrap
     /// this error, but perhaps something broke)
@@ -116,6 +119,7 @@ impl WasiFsError {
             __WASI_EPROTO => WasiFsError::UnexpectedEof,
             __WASI_EAGAIN => WasiFsError::WouldBlock,
             __WASI_ENOSPC => WasiFsError::WriteZero,
+            __WASI_ENOTEMPTY => WasiFsError::DirectoryNotEmpty,
             _ => WasiFsError::UnknownError(err),
         }
     }
@@ -144,6 +148,7 @@ impl WasiFsError {
             WasiFsError::UnexpectedEof => __WASI_EPROTO,
             WasiFsError::WouldBlock => __WASI_EAGAIN,
             WasiFsError::WriteZero => __WASI_ENOSPC,
+            WasiFsError::DirectoryNotEmpty => __WASI_ENOTEMPTY,
             WasiFsError::UnknownError(ec) => ec,
         }
     }
@@ -528,6 +533,23 @@ impl HostFile {
     pub fn metadata(&self) -> fs::Metadata {
         self.inner.metadata().unwrap()
     }
+
+    /// Opens the file at `host_path` the same way as this one, at the same
+    /// position, for when the file is moved to `host_path`.
+    pub(crate) fn reopen(&self, host_path: PathBuf) -> io::Result<Self> {
+        let mut inner = fs::OpenOptions::new()
+            .read(self.flags & Self::READ != 0)
+            .write(self.flags & Self::WRITE != 0)
+            .append(self.flags & Self::APPEND != 0)
+            .open(&host_path)?;
+        let position = (&self.inner).seek(io::SeekFrom::Current(0))?;
+        inner.seek(io::SeekFrom::Start(position))?;
+        Ok(Self {
+            inner,
+            host_path,
+            flags: self.flags,
+        })
+    }
 }
 
 impl Read for HostFile {
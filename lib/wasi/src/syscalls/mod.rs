@@ -15,6 +15,7 @@ pub mod unix;
 pub mod windows;
 
 pub mod legacy;
+pub mod traced;
 
 use self::types::*;
 use crate::{
@@ -606,6 +607,8 @@ pub fn fd_filestat_set_times(
     }
 
     let inode_idx = fd_entry.inode;
+    // files of an overlay are copied up before their times are modified
+    wasi_try!(state.fs.overlay_copy_up(inode_idx));
     let inode = &mut state.fs.inodes[inode_idx];
 
     if fst_flags & __WASI_FILESTAT_SET_ATIM != 0 || fst_flags & __WASI_FILESTAT_SET_ATIM_NOW != 0 {
@@ -1000,17 +1003,39 @@ pub fn fd_readdir(
             // we need to support multiple calls,
             // simple and obviously correct implementation for now:
             // maintain consistent order via lexacographic sorting
-            let fs_info = wasi_try!(wasi_try!(std::fs::read_dir(path).map_err(|_| __WASI_EIO))
-                .collect::<Result<Vec<_>, _>>()
-                .map_err(|_| __WASI_EIO));
-            let mut entry_vec = wasi_try!(fs_info
-                .into_iter()
-                .map(|entry| Ok((
-                    entry.file_name().to_string_lossy().to_string(),
-                    host_file_type_to_wasi_file_type(entry.file_type().map_err(|_| __WASI_EIO)?),
-                    0, // TODO: inode
-                )))
-                .collect::<Result<Vec<(String, u8, u64)>, _>>());
+            let mut entry_vec = if let Some(archive) = state.fs.archive_for(path) {
+                wasi_try!(archive.read_dir(path).map_err(WasiFsError::into_wasi_err))
+                    .into_iter()
+                    .map(|(name, is_dir)| {
+                        let file_type = if is_dir {
+                            __WASI_FILETYPE_DIRECTORY
+                        } else {
+                            __WASI_FILETYPE_REGULAR_FILE
+                        };
+                        (name, file_type, 0)
+                    })
+                    .collect::<Vec<(String, u8, u64)>>()
+            } else if let Some((overlay, _)) = state.fs.overlay_for(path) {
+                // merge the scratch directory with the directory it's layered over
+                wasi_try!(overlay.read_dir(path).map_err(WasiFsError::into_wasi_err))
+                    .into_iter()
+                    .map(|(name, file_type)| (name, host_file_type_to_wasi_file_type(file_type), 0))
+                    .collect::<Vec<(String, u8, u64)>>()
+            } else {
+                let fs_info = wasi_try!(wasi_try!(std::fs::read_dir(path).map_err(|_| __WASI_EIO))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| __WASI_EIO));
+                wasi_try!(fs_info
+                    .into_iter()
+                    .map(|entry| Ok((
+                        entry.file_name().to_string_lossy().to_string(),
+                        host_file_type_to_wasi_file_type(
+                            entry.file_type().map_err(|_| __WASI_EIO)?
+                        ),
+                        0, // TODO: inode
+                    )))
+                    .collect::<Result<Vec<(String, u8, u64)>, _>>())
+            };
             entry_vec.extend(
                 entries
                     .iter()
@@ -1412,7 +1437,13 @@ pub fn path_create_directory(
                     let mut adjusted_path = path.clone();
                     // TODO: double check this doesn't risk breaking the sandbox
                     adjusted_path.push(comp);
-                    if adjusted_path.exists() && !adjusted_path.is_dir() {
+                    if state.fs.archive_for(&adjusted_path).is_some() {
+                        return __WASI_EROFS;
+                    } else if let Some((overlay, _)) = state.fs.overlay_for(&adjusted_path) {
+                        wasi_try!(overlay
+                            .create_dir(&adjusted_path)
+                            .map_err(WasiFsError::into_wasi_err));
+                    } else if adjusted_path.exists() && !adjusted_path.is_dir() {
                         return __WASI_ENOTDIR;
                     } else if !adjusted_path.exists() {
                         wasi_try!(std::fs::create_dir(&adjusted_path).ok(), __WASI_EIO);
@@ -1549,6 +1580,8 @@ pub fn path_filestat_set_times(
         .get_stat_for_kind(&state.fs.inodes[file_inode].kind)
         .ok_or(__WASI_EIO));
 
+    // files of an overlay are copied up before their times are modified
+    wasi_try!(state.fs.overlay_copy_up(file_inode));
     let inode = &mut state.fs.inodes[fd_inode];
 
     if fst_flags & __WASI_FILESTAT_SET_ATIM != 0 || fst_flags & __WASI_FILESTAT_SET_ATIM_NOW != 0 {
@@ -1735,7 +1768,27 @@ pub fn path_open(
     // TODO: traverse rights of dirs properly
     // COMMENTED OUT: WASI isn't giving appropriate rights here when opening
     //              TODO: look into this; file a bug report if this is a bug
-    let adjusted_rights = /*fs_rights_base &*/ working_dir_rights_inheriting;
+    let mut adjusted_rights = /*fs_rights_base &*/ working_dir_rights_inheriting;
+    let mut is_archive_file = false;
+    if let Ok(inode) = maybe_inode {
+        // files of an overlay are copied up before anything can modify them,
+        // and stay read-only in the lower directory otherwise
+        let wants_write = fs_rights_base & __WASI_RIGHT_FD_WRITE != 0
+            || fs_flags & __WASI_FDFLAG_APPEND != 0
+            || o_flags & __WASI_O_TRUNC != 0;
+        if wants_write && adjusted_rights & __WASI_RIGHT_FD_WRITE != 0 {
+            wasi_try!(state.fs.overlay_copy_up(inode));
+        } else if state.fs.is_overlay_lower(inode) {
+            adjusted_rights &= !(__WASI_RIGHT_FD_WRITE
+                | __WASI_RIGHT_FD_ALLOCATE
+                | __WASI_RIGHT_FD_FILESTAT_SET_SIZE);
+        }
+        // files of mounted archives are read straight out of the archive
+        is_archive_file = wasi_try!(state.fs.open_archive_file(inode));
+        if is_archive_file && wants_write {
+            return __WASI_EROFS;
+        }
+    }
     let inode = if let Ok(inode) = maybe_inode {
         // Happy path, we found the file we're trying to open
         match &mut state.fs.inodes[inode].kind {
@@ -1753,46 +1806,54 @@ pub fn path_open(
                 if o_flags & __WASI_O_DIRECTORY != 0 {
                     return __WASI_ENOTDIR;
                 }
-                if o_flags & __WASI_O_EXCL != 0 && path.exists() {
-                    return __WASI_EEXIST;
-                }
-                let mut open_options = std::fs::OpenOptions::new();
-                let write_permission = adjusted_rights & __WASI_RIGHT_FD_WRITE != 0;
-                // append, truncate, and create all require the permission to write
-                let (append_permission, truncate_permission, create_permission) =
-                    if write_permission {
-                        (
-                            fs_flags & __WASI_FDFLAG_APPEND != 0,
-                            o_flags & __WASI_O_TRUNC != 0,
-                            o_flags & __WASI_O_CREAT != 0,
-                        )
-                    } else {
-                        (false, false, false)
-                    };
-                let open_options = open_options
-                    .read(true)
-                    // TODO: ensure these rights are actually valid given parent, etc.
-                    .write(write_permission)
-                    .create(create_permission)
-                    .append(append_permission)
-                    .truncate(truncate_permission);
-                open_flags |= Fd::READ;
-                if adjusted_rights & __WASI_RIGHT_FD_WRITE != 0 {
-                    open_flags |= Fd::WRITE;
-                }
-                if o_flags & __WASI_O_CREAT != 0 {
-                    open_flags |= Fd::CREATE;
-                }
-                if o_flags & __WASI_O_TRUNC != 0 {
-                    open_flags |= Fd::TRUNCATE;
+                if is_archive_file {
+                    // the handle reading from the archive is already in place
+                    if o_flags & __WASI_O_EXCL != 0 {
+                        return __WASI_EEXIST;
+                    }
+                    open_flags |= Fd::READ;
+                } else {
+                    if o_flags & __WASI_O_EXCL != 0 && path.exists() {
+                        return __WASI_EEXIST;
+                    }
+                    let mut open_options = std::fs::OpenOptions::new();
+                    let write_permission = adjusted_rights & __WASI_RIGHT_FD_WRITE != 0;
+                    // append, truncate, and create all require the permission to write
+                    let (append_permission, truncate_permission, create_permission) =
+                        if write_permission {
+                            (
+                                fs_flags & __WASI_FDFLAG_APPEND != 0,
+                                o_flags & __WASI_O_TRUNC != 0,
+                                o_flags & __WASI_O_CREAT != 0,
+                            )
+                        } else {
+                            (false, false, false)
+                        };
+                    let open_options = open_options
+                        .read(true)
+                        // TODO: ensure these rights are actually valid given parent, etc.
+                        .write(write_permission)
+                        .create(create_permission)
+                        .append(append_permission)
+                        .truncate(truncate_permission);
+                    open_flags |= Fd::READ;
+                    if adjusted_rights & __WASI_RIGHT_FD_WRITE != 0 {
+                        open_flags |= Fd::WRITE;
+                    }
+                    if o_flags & __WASI_O_CREAT != 0 {
+                        open_flags |= Fd::CREATE;
+                    }
+                    if o_flags & __WASI_O_TRUNC != 0 {
+                        open_flags |= Fd::TRUNCATE;
+                    }
+                    *handle = Some(Box::new(HostFile::new(
+                        wasi_try!(open_options.open(&path).map_err(|_| __WASI_EIO)),
+                        path.to_path_buf(),
+                        true,
+                        adjusted_rights & __WASI_RIGHT_FD_WRITE != 0,
+                        false,
+                    )));
                 }
-                *handle = Some(Box::new(HostFile::new(
-                    wasi_try!(open_options.open(&path).map_err(|_| __WASI_EIO)),
-                    path.to_path_buf(),
-                    true,
-                    adjusted_rights & __WASI_RIGHT_FD_WRITE != 0,
-                    false,
-                )));
             }
             Kind::Buffer { .. } => unimplemented!("wasi::path_open for Buffer type files"),
             Kind::Dir { .. } | Kind::Root { .. } => {
@@ -1836,6 +1897,14 @@ pub fn path_open(
                 Kind::Root { .. } => return __WASI_EACCES,
                 _ => return __WASI_EINVAL,
             };
+            if state.fs.archive_for(&new_file_host_path).is_some() {
+                return __WASI_EROFS;
+            }
+            if let Some((overlay, _)) = state.fs.overlay_for(&new_file_host_path) {
+                wasi_try!(overlay
+                    .clear_whiteout(&new_file_host_path)
+                    .map_err(WasiFsError::into_wasi_err));
+            }
             // once we got the data we need from the parent, we lookup the host file
             // todo: extra check that opening with write access is okay
             let handle = {
@@ -1986,9 +2055,18 @@ pub fn path_remove_directory(
 
     let host_path_to_remove = match &state.fs.inodes[inode].kind {
         Kind::Dir { entries, path, .. } => {
-            if !entries.is_empty()
-                || wasi_try!(std::fs::read_dir(path).ok(), __WASI_EIO).count() != 0
-            {
+            if state.fs.archive_for(path).is_some() {
+                return __WASI_EROFS;
+            }
+            let is_empty = if let Some((overlay, upper_path)) = state.fs.overlay_for(path) {
+                wasi_try!(overlay
+                    .read_dir(&upper_path)
+                    .map_err(WasiFsError::into_wasi_err))
+                .is_empty()
+            } else {
+                wasi_try!(std::fs::read_dir(path).ok(), __WASI_EIO).count() == 0
+            };
+            if !entries.is_empty() || !is_empty {
                 return __WASI_ENOTEMPTY;
             }
             path.clone()
@@ -2011,7 +2089,15 @@ pub fn path_remove_directory(
         ),
     }
 
-    if std::fs::remove_dir(path_str).is_err() {
+    let remove_result =
+        if let Some((overlay, upper_path)) = state.fs.overlay_for(&host_path_to_remove) {
+            overlay
+                .remove(&upper_path)
+                .map_err(WasiFsError::into_wasi_err)
+        } else {
+            std::fs::remove_dir(path_str).map_err(|_| __WASI_EIO)
+        };
+    if remove_result.is_err() {
         // reinsert to prevent FS from being in bad state
         if let Kind::Dir {
             ref mut entries, ..
@@ -2091,6 +2177,14 @@ pub fn path_rename(
             unreachable!("Fatal internal logic error: parent of inode is not a directory")
         }
     };
+    // nothing can be moved in or out of a mounted archive
+    let source_in_archive = match &state.fs.inodes[source_parent_inode].kind {
+        Kind::Dir { path, .. } => state.fs.archive_for(path).is_some(),
+        _ => false,
+    };
+    if source_in_archive || state.fs.archive_for(&host_adjusted_target_path).is_some() {
+        return __WASI_EROFS;
+    }
     let source_entry = match &mut state.fs.inodes[source_parent_inode].kind {
         Kind::Dir { entries, .. } => wasi_try!(entries.remove(&source_entry_name), __WASI_EINVAL),
         Kind::Root { .. } => return __WASI_ENOTCAPABLE,
@@ -2099,33 +2193,67 @@ pub fn path_rename(
         }
     };
 
-    match &mut state.fs.inodes[source_entry].kind {
-        Kind::File {
-            handle,
-            ref mut path,
-            ..
-        } => {
-            let result = if let Some(h) = handle {
-                h.rename_file(&host_adjusted_target_path)
-                    .map_err(|e| e.into_wasi_err())
-            } else {
-                let out =
-                    std::fs::rename(&path, &host_adjusted_target_path).map_err(|_| __WASI_EIO);
-                *path = host_adjusted_target_path;
-                out
-            };
-            // if the above operation failed we have to revert the previous change and then fail
-            if let Err(e) = result {
-                if let Kind::Dir { entries, .. } = &mut state.fs.inodes[source_parent_inode].kind {
-                    entries.insert(source_entry_name, source_entry);
-                    return e;
+    let overlay_paths = match &state.fs.inodes[source_entry].kind {
+        Kind::File { path, .. } => state.fs.overlay_for(path).map(|(_, upper_path)| upper_path),
+        _ => None,
+    };
+    if let Some(source_upper_path) = overlay_paths {
+        // renaming within an overlay moves the copied up file and hides the
+        // original, which stays in place in the lower directory
+        let result = state.fs.overlay_copy_up(source_entry).and_then(|()| {
+            let (overlay, _) = state.fs.overlay_for(&source_upper_path).unwrap();
+            overlay
+                .clear_whiteout(&host_adjusted_target_path)
+                .map_err(WasiFsError::into_wasi_err)?;
+            std::fs::rename(&source_upper_path, &host_adjusted_target_path)
+                .map_err(|_| __WASI_EIO)?;
+            if overlay.resolve(&source_upper_path).is_some() {
+                overlay
+                    .remove(&source_upper_path)
+                    .map_err(WasiFsError::into_wasi_err)?;
+            }
+            Ok(())
+        });
+        if let Err(e) = result {
+            if let Kind::Dir { entries, .. } = &mut state.fs.inodes[source_parent_inode].kind {
+                entries.insert(source_entry_name, source_entry);
+            }
+            return e;
+        }
+        if let Kind::File { path, .. } = &mut state.fs.inodes[source_entry].kind {
+            *path = host_adjusted_target_path;
+        }
+    } else {
+        match &mut state.fs.inodes[source_entry].kind {
+            Kind::File {
+                handle,
+                ref mut path,
+                ..
+            } => {
+                let result = if let Some(h) = handle {
+                    h.rename_file(&host_adjusted_target_path)
+                        .map_err(|e| e.into_wasi_err())
+                } else {
+                    let out =
+                        std::fs::rename(&path, &host_adjusted_target_path).map_err(|_| __WASI_EIO);
+                    *path = host_adjusted_target_path;
+                    out
+                };
+                // if the above operation failed we have to revert the previous change and then fail
+                if let Err(e) = result {
+                    if let Kind::Dir { entries, .. } =
+                        &mut state.fs.inodes[source_parent_inode].kind
+                    {
+                        entries.insert(source_entry_name, source_entry);
+                        return e;
+                    }
                 }
             }
+            Kind::Dir { path, .. } => unimplemented!("wasi::path_rename on Directories"),
+            Kind::Buffer { .. } => {}
+            Kind::Symlink { .. } => {}
+            Kind::Root { .. } => unreachable!("The root can not be moved"),
         }
-        Kind::Dir { path, .. } => unimplemented!("wasi::path_rename on Directories"),
-        Kind::Buffer { .. } => {}
-        Kind::Symlink { .. } => {}
-        Kind::Root { .. } => unreachable!("The root can not be moved"),
     }
 
     if let Kind::Dir { entries, .. } = &mut state.fs.inodes[target_parent_inode].kind {
@@ -2253,6 +2381,15 @@ pub fn path_unlink_file(
             .fs
             .get_parent_inode_at_path(fd, std::path::Path::new(path_str), false));
 
+    // check before touching the inodes, so nothing changes when failing
+    let parent_in_archive = match &state.fs.inodes[parent_inode].kind {
+        Kind::Dir { path, .. } => state.fs.archive_for(path).is_some(),
+        _ => false,
+    };
+    if parent_in_archive {
+        return __WASI_EROFS;
+    }
+
     let removed_inode = match &mut state.fs.inodes[parent_inode].kind {
         Kind::Dir {
             ref mut entries, ..
@@ -2271,22 +2408,35 @@ pub fn path_unlink_file(
 
     state.fs.inodes[removed_inode].stat.st_nlink -= 1;
     if state.fs.inodes[removed_inode].stat.st_nlink == 0 {
-        match &mut state.fs.inodes[removed_inode].kind {
-            Kind::File { handle, path, .. } => {
-                if let Some(h) = handle {
-                    wasi_try!(h.unlink().map_err(WasiFsError::into_wasi_err));
-                } else {
-                    // File is closed
-                    // problem with the abstraction, we can't call unlink because there's no handle
-                    // TODO: replace this code
-                    wasi_try!(std::fs::remove_file(path).map_err(|_| __WASI_EIO));
+        let overlay_result = match &state.fs.inodes[removed_inode].kind {
+            Kind::File { path, .. } => state.fs.overlay_for(path).map(|(overlay, upper_path)| {
+                // never touch the lower directory, hide its file with a whiteout instead
+                overlay
+                    .remove(&upper_path)
+                    .map_err(WasiFsError::into_wasi_err)
+            }),
+            _ => None,
+        };
+        if let Some(result) = overlay_result {
+            wasi_try!(result);
+        } else {
+            match &mut state.fs.inodes[removed_inode].kind {
+                Kind::File { handle, path, .. } => {
+                    if let Some(h) = handle {
+                        wasi_try!(h.unlink().map_err(WasiFsError::into_wasi_err));
+                    } else {
+                        // File is closed
+                        // problem with the abstraction, we can't call unlink because there's no handle
+                        // TODO: replace this code
+                        wasi_try!(std::fs::remove_file(path).map_err(|_| __WASI_EIO));
+                    }
                 }
+                Kind::Dir { .. } | Kind::Root { .. } => return __WASI_EISDIR,
+                Kind::Symlink { .. } => {
+                    // TODO: actually delete real symlinks and do nothing for virtual symlinks
+                }
+                _ => unimplemented!("wasi::path_unlink_file for Buffer"),
             }
-            Kind::Dir { .. } | Kind::Root { .. } => return __WASI_EISDIR,
-            Kind::Symlink { .. } => {
-                // TODO: actually delete real symlinks and do nothing for virtual symlinks
-            }
-            _ => unimplemented!("wasi::path_unlink_file for Buffer"),
         }
         // TODO: test this on Windows and actually make it portable
         // make the file an orphan fd if the fd is still open
@@ -2584,3 +2734,129 @@ pub fn sock_shutdown(env: &WasiEnv, sock: __wasi_fd_t, how: __wasi_sdflags_t) ->
     debug!("wasi::sock_shutdown");
     unimplemented!("wasi::sock_shutdown")
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::{ALL_RIGHTS, VIRTUAL_ROOT_FD};
+    use wasmer::{MemoryType, Store, Universal};
+
+    /// Where the output parameters are written in the memory.
+    const OUT_OFFSET: u32 = 1024;
+
+    fn archive_env(name: &str) -> WasiEnv {
+        let archive_path = std::env::temp_dir().join(format!(
+            "wasmer-wasi-syscalls-{}-{}.tar",
+            name,
+            std::process::id()
+        ));
+        {
+            let mut builder = tar::Builder::new(std::fs::File::create(&archive_path).unwrap());
+            for (name, contents) in &[("hello.txt", &b"hello"[..]), ("dir/nested.txt", b"nested")] {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(contents.len() as u64);
+                header.set_mode(0o644);
+                header.set_cksum();
+                builder.append_data(&mut header, name, *contents).unwrap();
+            }
+            builder.finish().unwrap();
+        }
+        let state = WasiState::new("test")
+            .map_dir_archive("archive", &archive_path)
+            .unwrap()
+            .build()
+            .unwrap();
+        let mut env = WasiEnv::new(state);
+        let store = Store::new(&Universal::headless().engine());
+        let memory = Memory::new(&store, MemoryType::new(1, None, false)).unwrap();
+        env.memory.initialize(memory);
+        env
+    }
+
+    /// Writes `path` at the start of the memory.
+    fn path(env: &WasiEnv, path: &str) -> (WasmPtr<u8, Array>, u32) {
+        let view = env.memory().view::<u8>();
+        for (cell, byte) in view[..path.len()].iter().zip(path.bytes()) {
+            cell.set(byte);
+        }
+        (WasmPtr::new(0), path.len() as u32)
+    }
+
+    fn nlink(env: &WasiEnv, path: &str) -> __wasi_linkcount_t {
+        let mut state = env.state();
+        let inode = state
+            .fs
+            .get_inode_at_path(VIRTUAL_ROOT_FD, path, false)
+            .unwrap();
+        state.fs.inodes[inode].stat.st_nlink
+    }
+
+    #[test]
+    fn archives_are_read_only() {
+        let env = archive_env("read-only");
+
+        let before = nlink(&env, "archive/hello.txt");
+        let (ptr, len) = path(&env, "archive/hello.txt");
+        assert_eq!(
+            path_unlink_file(&env, VIRTUAL_ROOT_FD, ptr, len),
+            __WASI_EROFS
+        );
+        assert_eq!(nlink(&env, "archive/hello.txt"), before);
+
+        let (ptr, len) = path(&env, "archive/dir");
+        assert_eq!(
+            path_remove_directory(&env, VIRTUAL_ROOT_FD, ptr, len),
+            __WASI_EROFS
+        );
+        let (ptr, len) = path(&env, "archive/new");
+        assert_eq!(
+            path_create_directory(&env, VIRTUAL_ROOT_FD, ptr, len),
+            __WASI_EROFS
+        );
+        let (ptr, len) = path(&env, "archive/new.txt");
+        assert_eq!(
+            path_open(
+                &env,
+                VIRTUAL_ROOT_FD,
+                0,
+                ptr,
+                len,
+                __WASI_O_CREAT,
+                ALL_RIGHTS,
+                ALL_RIGHTS,
+                0,
+                WasmPtr::new(OUT_OFFSET),
+            ),
+            __WASI_EROFS
+        );
+    }
+
+    #[test]
+    fn archive_files_can_be_opened() {
+        let env = archive_env("open");
+
+        let (ptr, len) = path(&env, "archive/dir/nested.txt");
+        assert_eq!(
+            path_open(
+                &env,
+                VIRTUAL_ROOT_FD,
+                0,
+                ptr,
+                len,
+                0,
+                __WASI_RIGHT_FD_READ,
+                0,
+                0,
+                WasmPtr::new(OUT_OFFSET),
+            ),
+            __WASI_ESUCCESS
+        );
+        let fd = WasmPtr::<__wasi_fd_t>::new(OUT_OFFSET)
+            .deref(env.memory())
+            .unwrap()
+            .get();
+        let mut state = env.state();
+        let inode = state.fs.get_fd(fd).unwrap().inode;
+        assert_eq!(state.fs.inodes[inode].stat.st_size, 6);
+    }
+}
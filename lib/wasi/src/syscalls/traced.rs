@@ -0,0 +1,1060 @@
+//! Wrappers around the syscalls reporting them to the [`WasiTracer`] of the
+//! [`WasiEnv`], if it has one.
+//!
+//! These are what the import objects are made of; without a tracer they
+//! call straight through to the syscall.
+//!
+//! [`WasiTracer`]: crate::WasiTracer
+
+use super::types::*;
+use crate::ptr::{Array, WasmPtr};
+use crate::syscalls::{self, legacy};
+use crate::trace::{self, SyscallArg, SyscallEvent};
+use crate::WasiEnv;
+use std::time::{Duration, Instant};
+use wasmer::Memory;
+
+type Args = Vec<(&'static str, SyscallArg)>;
+
+/// Runs `syscall`, reporting it along with the arguments decoded by `args`
+/// and, if it succeeded, the results decoded by `results`.
+fn traced(
+    env: &WasiEnv,
+    name: &'static str,
+    args: impl FnOnce(&Memory) -> Args,
+    syscall: impl FnOnce() -> __wasi_errno_t,
+    results: impl FnOnce(&Memory) -> Args,
+) -> __wasi_errno_t {
+    let tracer = match &env.tracer {
+        Some(tracer) => tracer,
+        None => return syscall(),
+    };
+    let memory = env.memory();
+    // arguments are decoded up front, the syscall may overwrite them
+    let args = args(memory);
+    let start = Instant::now();
+    let errno = syscall();
+    let duration = start.elapsed();
+    let results = if errno == __WASI_ESUCCESS {
+        results(memory)
+    } else {
+        vec![]
+    };
+    tracer.on_syscall(&SyscallEvent {
+        name,
+        args,
+        results,
+        errno: Some(errno),
+        duration,
+    });
+    errno
+}
+
+fn no_results(_memory: &Memory) -> Args {
+    vec![]
+}
+
+fn path(memory: &Memory, path: WasmPtr<u8, Array>, path_len: u32) -> SyscallArg {
+    // the string is copied out before anything else can touch the memory
+    match unsafe { path.get_utf8_str(memory, path_len) } {
+        Some(path) => SyscallArg::Path(path.to_string()),
+        None => SyscallArg::Ptr(path.offset()),
+    }
+}
+
+/// Decodes `iovs_len` iovecs at `iovs`, `__wasi_ciovec_t` sharing the layout
+/// of `__wasi_iovec_t`.
+fn iovecs(memory: &Memory, iovs: u32, iovs_len: u32) -> SyscallArg {
+    match WasmPtr::<__wasi_iovec_t, Array>::new(iovs).deref(memory, 0, iovs_len) {
+        Ok(cells) => SyscallArg::IoVecs {
+            count: iovs_len,
+            bytes: cells.iter().map(|iov| iov.get().buf_len as u64).sum(),
+        },
+        Err(_) => SyscallArg::Ptr(iovs),
+    }
+}
+
+fn read_u32(memory: &Memory, ptr: WasmPtr<u32>) -> SyscallArg {
+    match ptr.deref(memory) {
+        Ok(cell) => SyscallArg::Int(cell.get() as u64),
+        Err(_) => SyscallArg::Ptr(ptr.offset()),
+    }
+}
+
+fn read_u64(memory: &Memory, ptr: WasmPtr<u64>) -> SyscallArg {
+    match ptr.deref(memory) {
+        Ok(cell) => SyscallArg::Int(cell.get()),
+        Err(_) => SyscallArg::Ptr(ptr.offset()),
+    }
+}
+
+fn read_fd(memory: &Memory, ptr: WasmPtr<__wasi_fd_t>) -> SyscallArg {
+    match ptr.deref(memory) {
+        Ok(cell) => SyscallArg::Fd(cell.get()),
+        Err(_) => SyscallArg::Ptr(ptr.offset()),
+    }
+}
+
+fn fd(fd: __wasi_fd_t) -> SyscallArg {
+    SyscallArg::Fd(fd)
+}
+
+fn int(value: impl Into<u64>) -> SyscallArg {
+    SyscallArg::Int(value.into())
+}
+
+fn ptr<T: Copy, Ty>(ptr: WasmPtr<T, Ty>) -> SyscallArg {
+    SyscallArg::Ptr(ptr.offset())
+}
+
+pub fn args_get(
+    env: &WasiEnv,
+    argv: WasmPtr<WasmPtr<u8, Array>, Array>,
+    argv_buf: WasmPtr<u8, Array>,
+) -> __wasi_errno_t {
+    traced(
+        env,
+        "args_get",
+        |_| vec![("argv", ptr(argv)), ("argv_buf", ptr(argv_buf))],
+        || syscalls::args_get(env, argv, argv_buf),
+        no_results,
+    )
+}
+
+pub fn args_sizes_get(
+    env: &WasiEnv,
+    argc: WasmPtr<u32>,
+    argv_buf_size: WasmPtr<u32>,
+) -> __wasi_errno_t {
+    traced(
+        env,
+        "args_sizes_get",
+        |_| vec![],
+        || syscalls::args_sizes_get(env, argc, argv_buf_size),
+        |memory| {
+            vec![
+                ("argc", read_u32(memory, argc)),
+                ("argv_buf_size", read_u32(memory, argv_buf_size)),
+            ]
+        },
+    )
+}
+
+pub fn clock_res_get(
+    env: &WasiEnv,
+    clock_id: __wasi_clockid_t,
+    resolution: WasmPtr<__wasi_timestamp_t>,
+) -> __wasi_errno_t {
+    traced(
+        env,
+        "clock_res_get",
+        |_| vec![("clock_id", trace::clockid(clock_id))],
+        || syscalls::clock_res_get(env, clock_id, resolution),
+        |memory| vec![("resolution", read_u64(memory, resolution))],
+    )
+}
+
+pub fn clock_time_get(
+    env: &WasiEnv,
+    clock_id: __wasi_clockid_t,
+    precision: __wasi_timestamp_t,
+    time: WasmPtr<__wasi_timestamp_t>,
+) -> __wasi_errno_t {
+    traced(
+        env,
+        "clock_time_get",
+        |_| {
+            vec![
+                ("clock_id", trace::clockid(clock_id)),
+                ("precision", int(precision)),
+            ]
+        },
+        || syscalls::clock_time_get(env, clock_id, precision, time),
+        |memory| vec![("time", read_u64(memory, time))],
+    )
+}
+
+pub fn environ_get(
+    env: &WasiEnv,
+    environ: WasmPtr<WasmPtr<u8, Array>, Array>,
+    environ_buf: WasmPtr<u8, Array>,
+) -> __wasi_errno_t {
+    traced(
+        env,
+        "environ_get",
+        |_| vec![("environ", ptr(environ)), ("environ_buf", ptr(environ_buf))],
+        || syscalls::environ_get(env, environ, environ_buf),
+        no_results,
+    )
+}
+
+pub fn environ_sizes_get(
+    env: &WasiEnv,
+    environ_count: WasmPtr<u32>,
+    environ_buf_size: WasmPtr<u32>,
+) -> __wasi_errno_t {
+    traced(
+        env,
+        "environ_sizes_get",
+        |_| vec![],
+        || syscalls::environ_sizes_get(env, environ_count, environ_buf_size),
+        |memory| {
+            vec![
+                ("environ_count", read_u32(memory, environ_count)),
+                ("environ_buf_size", read_u32(memory, environ_buf_size)),
+            ]
+        },
+    )
+}
+
+pub fn fd_advise(
+    env: &WasiEnv,
+    fd_: __wasi_fd_t,
+    offset: __wasi_filesize_t,
+    len: __wasi_filesize_t,
+    advice: __wasi_advice_t,
+) -> __wasi_errno_t {
+    traced(
+        env,
+        "fd_advise",
+        |_| {
+            vec![
+                ("fd", fd(fd_)),
+                ("offset", int(offset)),
+                ("len", int(len)),
+                ("advice", int(advice)),
+            ]
+        },
+        || syscalls::fd_advise(env, fd_, offset, len, advice),
+        no_results,
+    )
+}
+
+pub fn fd_allocate(
+    env: &WasiEnv,
+    fd_: __wasi_fd_t,
+    offset: __wasi_filesize_t,
+    len: __wasi_filesize_t,
+) -> __wasi_errno_t {
+    traced(
+        env,
+        "fd_allocate",
+        |_| vec![("fd", fd(fd_)), ("offset", int(offset)), ("len", int(len))],
+        || syscalls::fd_allocate(env, fd_, offset, len),
+        no_results,
+    )
+}
+
+pub fn fd_close(env: &WasiEnv, fd_: __wasi_fd_t) -> __wasi_errno_t {
+    traced(
+        env,
+        "fd_close",
+        |_| vec![("fd", fd(fd_))],
+        || syscalls::fd_close(env, fd_),
+        no_results,
+    )
+}
+
+pub fn fd_datasync(env: &WasiEnv, fd_: __wasi_fd_t) -> __wasi_errno_t {
+    traced(
+        env,
+        "fd_datasync",
+        |_| vec![("fd", fd(fd_))],
+        || syscalls::fd_datasync(env, fd_),
+        no_results,
+    )
+}
+
+pub fn fd_fdstat_get(
+    env: &WasiEnv,
+    fd_: __wasi_fd_t,
+    buf_ptr: WasmPtr<__wasi_fdstat_t>,
+) -> __wasi_errno_t {
+    traced(
+        env,
+        "fd_fdstat_get",
+        |_| vec![("fd", fd(fd_))],
+        || syscalls::fd_fdstat_get(env, fd_, buf_ptr),
+        |memory| match buf_ptr.deref(memory) {
+            Ok(cell) => {
+                let stat = cell.get();
+                vec![
+                    ("fs_filetype", int(stat.fs_filetype)),
+                    ("fs_flags", trace::fdflags(stat.fs_flags)),
+                    ("fs_rights_base", trace::rights(stat.fs_rights_base)),
+                ]
+            }
+            Err(_) => vec![],
+        },
+    )
+}
+
+pub fn fd_fdstat_set_flags(
+    env: &WasiEnv,
+    fd_: __wasi_fd_t,
+    flags: __wasi_fdflags_t,
+) -> __wasi_errno_t {
+    traced(
+        env,
+        "fd_fdstat_set_flags",
+        |_| vec![("fd", fd(fd_)), ("flags", trace::fdflags(flags))],
+        || syscalls::fd_fdstat_set_flags(env, fd_, flags),
+        no_results,
+    )
+}
+
+pub fn fd_fdstat_set_rights(
+    env: &WasiEnv,
+    fd_: __wasi_fd_t,
+    fs_rights_base: __wasi_rights_t,
+    fs_rights_inheriting: __wasi_rights_t,
+) -> __wasi_errno_t {
+    traced(
+        env,
+        "fd_fdstat_set_rights",
+        |_| {
+            vec![
+                ("fd", fd(fd_)),
+                ("fs_rights_base", trace::rights(fs_rights_base)),
+                ("fs_rights_inheriting", trace::rights(fs_rights_inheriting)),
+            ]
+        },
+        || syscalls::fd_fdstat_set_rights(env, fd_, fs_rights_base, fs_rights_inheriting),
+        no_results,
+    )
+}
+
+pub fn fd_filestat_get(
+    env: &WasiEnv,
+    fd_: __wasi_fd_t,
+    buf: WasmPtr<__wasi_filestat_t>,
+) -> __wasi_errno_t {
+    traced(
+        env,
+        "fd_filestat_get",
+        |_| vec![("fd", fd(fd_))],
+        || syscalls::fd_filestat_get(env, fd_, buf),
+        |memory| filestat_results(memory, buf),
+    )
+}
+
+fn filestat_results(memory: &Memory, buf: WasmPtr<__wasi_filestat_t>) -> Args {
+    match buf.deref(memory) {
+        Ok(cell) => {
+            let stat = cell.get();
+            vec![
+                ("st_filetype", int(stat.st_filetype)),
+                ("st_size", int(stat.st_size)),
+            ]
+        }
+        Err(_) => vec![],
+    }
+}
+
+pub fn fd_filestat_set_size(
+    env: &WasiEnv,
+    fd_: __wasi_fd_t,
+    st_size: __wasi_filesize_t,
+) -> __wasi_errno_t {
+    traced(
+        env,
+        "fd_filestat_set_size",
+        |_| vec![("fd", fd(fd_)), ("st_size", int(st_size))],
+        || syscalls::fd_filestat_set_size(env, fd_, st_size),
+        no_results,
+    )
+}
+
+pub fn fd_filestat_set_times(
+    env: &WasiEnv,
+    fd_: __wasi_fd_t,
+    st_atim: __wasi_timestamp_t,
+    st_mtim: __wasi_timestamp_t,
+    fst_flags: __wasi_fstflags_t,
+) -> __wasi_errno_t {
+    traced(
+        env,
+        "fd_filestat_set_times",
+        |_| {
+            vec![
+                ("fd", fd(fd_)),
+                ("st_atim", int(st_atim)),
+                ("st_mtim", int(st_mtim)),
+                ("fst_flags", trace::fstflags(fst_flags)),
+            ]
+        },
+        || syscalls::fd_filestat_set_times(env, fd_, st_atim, st_mtim, fst_flags),
+        no_results,
+    )
+}
+
+pub fn fd_pread(
+    env: &WasiEnv,
+    fd_: __wasi_fd_t,
+    iovs: WasmPtr<__wasi_iovec_t, Array>,
+    iovs_len: u32,
+    offset: __wasi_filesize_t,
+    nread: WasmPtr<u32>,
+) -> __wasi_errno_t {
+    traced(
+        env,
+        "fd_pread",
+        |memory| {
+            vec![
+                ("fd", fd(fd_)),
+                ("iovs", iovecs(memory, iovs.offset(), iovs_len)),
+                ("offset", int(offset)),
+            ]
+        },
+        || syscalls::fd_pread(env, fd_, iovs, iovs_len, offset, nread),
+        |memory| vec![("nread", read_u32(memory, nread))],
+    )
+}
+
+pub fn fd_prestat_get(
+    env: &WasiEnv,
+    fd_: __wasi_fd_t,
+    buf: WasmPtr<__wasi_prestat_t>,
+) -> __wasi_errno_t {
+    traced(
+        env,
+        "fd_prestat_get",
+        |_| vec![("fd", fd(fd_))],
+        || syscalls::fd_prestat_get(env, fd_, buf),
+        no_results,
+    )
+}
+
+pub fn fd_prestat_dir_name(
+    env: &WasiEnv,
+    fd_: __wasi_fd_t,
+    path_: WasmPtr<u8, Array>,
+    path_len: u32,
+) -> __wasi_errno_t {
+    traced(
+        env,
+        "fd_prestat_dir_name",
+        |_| vec![("fd", fd(fd_)), ("path_len", int(path_len))],
+        || syscalls::fd_prestat_dir_name(env, fd_, path_, path_len),
+        |memory| vec![("path", path(memory, path_, path_len))],
+    )
+}
+
+pub fn fd_pwrite(
+    env: &WasiEnv,
+    fd_: __wasi_fd_t,
+    iovs: WasmPtr<__wasi_ciovec_t, Array>,
+    iovs_len: u32,
+    offset: __wasi_filesize_t,
+    nwritten: WasmPtr<u32>,
+) -> __wasi_errno_t {
+    traced(
+        env,
+        "fd_pwrite",
+        |memory| {
+            vec![
+                ("fd", fd(fd_)),
+                ("iovs", iovecs(memory, iovs.offset(), iovs_len)),
+                ("offset", int(offset)),
+            ]
+        },
+        || syscalls::fd_pwrite(env, fd_, iovs, iovs_len, offset, nwritten),
+        |memory| vec![("nwritten", read_u32(memory, nwritten))],
+    )
+}
+
+pub fn fd_read(
+    env: &WasiEnv,
+    fd_: __wasi_fd_t,
+    iovs: WasmPtr<__wasi_iovec_t, Array>,
+    iovs_len: u32,
+    nread: WasmPtr<u32>,
+) -> __wasi_errno_t {
+    traced(
+        env,
+        "fd_read",
+        |memory| {
+            vec![
+                ("fd", fd(fd_)),
+                ("iovs", iovecs(memory, iovs.offset(), iovs_len)),
+            ]
+        },
+        || syscalls::fd_read(env, fd_, iovs, iovs_len, nread),
+        |memory| vec![("nread", read_u32(memory, nread))],
+    )
+}
+
+pub fn fd_readdir(
+    env: &WasiEnv,
+    fd_: __wasi_fd_t,
+    buf: WasmPtr<u8, Array>,
+    buf_len: u32,
+    cookie: __wasi_dircookie_t,
+    bufused: WasmPtr<u32>,
+) -> __wasi_errno_t {
+    traced(
+        env,
+        "fd_readdir",
+        |_| {
+            vec![
+                ("fd", fd(fd_)),
+                ("buf_len", int(buf_len)),
+                ("cookie", int(cookie)),
+            ]
+        },
+        || syscalls::fd_readdir(env, fd_, buf, buf_len, cookie, bufused),
+        |memory| vec![("bufused", read_u32(memory, bufused))],
+    )
+}
+
+pub fn fd_renumber(env: &WasiEnv, from: __wasi_fd_t, to: __wasi_fd_t) -> __wasi_errno_t {
+    traced(
+        env,
+        "fd_renumber",
+        |_| vec![("from", fd(from)), ("to", fd(to))],
+        || syscalls::fd_renumber(env, from, to),
+        no_results,
+    )
+}
+
+pub fn fd_seek(
+    env: &WasiEnv,
+    fd_: __wasi_fd_t,
+    offset: __wasi_filedelta_t,
+    whence: __wasi_whence_t,
+    newoffset: WasmPtr<__wasi_filesize_t>,
+) -> __wasi_errno_t {
+    traced(
+        env,
+        "fd_seek",
+        |_| {
+            vec![
+                ("fd", fd(fd_)),
+                ("offset", SyscallArg::SignedInt(offset)),
+                ("whence", trace::whence(whence)),
+            ]
+        },
+        || syscalls::fd_seek(env, fd_, offset, whence, newoffset),
+        |memory| vec![("newoffset", read_u64(memory, newoffset))],
+    )
+}
+
+pub fn fd_sync(env: &WasiEnv, fd_: __wasi_fd_t) -> __wasi_errno_t {
+    traced(
+        env,
+        "fd_sync",
+        |_| vec![("fd", fd(fd_))],
+        || syscalls::fd_sync(env, fd_),
+        no_results,
+    )
+}
+
+pub fn fd_tell(
+    env: &WasiEnv,
+    fd_: __wasi_fd_t,
+    offset: WasmPtr<__wasi_filesize_t>,
+) -> __wasi_errno_t {
+    traced(
+        env,
+        "fd_tell",
+        |_| vec![("fd", fd(fd_))],
+        || syscalls::fd_tell(env, fd_, offset),
+        |memory| vec![("offset", read_u64(memory, offset))],
+    )
+}
+
+pub fn fd_write(
+    env: &WasiEnv,
+    fd_: __wasi_fd_t,
+    iovs: WasmPtr<__wasi_ciovec_t, Array>,
+    iovs_len: u32,
+    nwritten: WasmPtr<u32>,
+) -> __wasi_errno_t {
+    traced(
+        env,
+        "fd_write",
+        |memory| {
+            vec![
+                ("fd", fd(fd_)),
+                ("iovs", iovecs(memory, iovs.offset(), iovs_len)),
+            ]
+        },
+        || syscalls::fd_write(env, fd_, iovs, iovs_len, nwritten),
+        |memory| vec![("nwritten", read_u32(memory, nwritten))],
+    )
+}
+
+pub fn path_create_directory(
+    env: &WasiEnv,
+    fd_: __wasi_fd_t,
+    path_: WasmPtr<u8, Array>,
+    path_len: u32,
+) -> __wasi_errno_t {
+    traced(
+        env,
+        "path_create_directory",
+        |memory| vec![("fd", fd(fd_)), ("path", path(memory, path_, path_len))],
+        || syscalls::path_create_directory(env, fd_, path_, path_len),
+        no_results,
+    )
+}
+
+pub fn path_filestat_get(
+    env: &WasiEnv,
+    fd_: __wasi_fd_t,
+    flags: __wasi_lookupflags_t,
+    path_: WasmPtr<u8, Array>,
+    path_len: u32,
+    buf: WasmPtr<__wasi_filestat_t>,
+) -> __wasi_errno_t {
+    traced(
+        env,
+        "path_filestat_get",
+        |memory| {
+            vec![
+                ("fd", fd(fd_)),
+                ("flags", trace::lookupflags(flags)),
+                ("path", path(memory, path_, path_len)),
+            ]
+        },
+        || syscalls::path_filestat_get(env, fd_, flags, path_, path_len, buf),
+        |memory| filestat_results(memory, buf),
+    )
+}
+
+pub fn path_filestat_set_times(
+    env: &WasiEnv,
+    fd_: __wasi_fd_t,
+    flags: __wasi_lookupflags_t,
+    path_: WasmPtr<u8, Array>,
+    path_len: u32,
+    st_atim: __wasi_timestamp_t,
+    st_mtim: __wasi_timestamp_t,
+    fst_flags: __wasi_fstflags_t,
+) -> __wasi_errno_t {
+    traced(
+        env,
+        "path_filestat_set_times",
+        |memory| {
+            vec![
+                ("fd", fd(fd_)),
+                ("flags", trace::lookupflags(flags)),
+                ("path", path(memory, path_, path_len)),
+                ("st_atim", int(st_atim)),
+                ("st_mtim", int(st_mtim)),
+                ("fst_flags", trace::fstflags(fst_flags)),
+            ]
+        },
+        || {
+            syscalls::path_filestat_set_times(
+                env, fd_, flags, path_, path_len, st_atim, st_mtim, fst_flags,
+            )
+        },
+        no_results,
+    )
+}
+
+pub fn path_link(
+    env: &WasiEnv,
+    old_fd: __wasi_fd_t,
+    old_flags: __wasi_lookupflags_t,
+    old_path: WasmPtr<u8, Array>,
+    old_path_len: u32,
+    new_fd: __wasi_fd_t,
+    new_path: WasmPtr<u8, Array>,
+    new_path_len: u32,
+) -> __wasi_errno_t {
+    traced(
+        env,
+        "path_link",
+        |memory| {
+            vec![
+                ("old_fd", fd(old_fd)),
+                ("old_flags", trace::lookupflags(old_flags)),
+                ("old_path", path(memory, old_path, old_path_len)),
+                ("new_fd", fd(new_fd)),
+                ("new_path", path(memory, new_path, new_path_len)),
+            ]
+        },
+        || {
+            syscalls::path_link(
+                env,
+                old_fd,
+                old_flags,
+                old_path,
+                old_path_len,
+                new_fd,
+                new_path,
+                new_path_len,
+            )
+        },
+        no_results,
+    )
+}
+
+pub fn path_open(
+    env: &WasiEnv,
+    dirfd: __wasi_fd_t,
+    dirflags: __wasi_lookupflags_t,
+    path_: WasmPtr<u8, Array>,
+    path_len: u32,
+    o_flags: __wasi_oflags_t,
+    fs_rights_base: __wasi_rights_t,
+    fs_rights_inheriting: __wasi_rights_t,
+    fs_flags: __wasi_fdflags_t,
+    fd_: WasmPtr<__wasi_fd_t>,
+) -> __wasi_errno_t {
+    traced(
+        env,
+        "path_open",
+        |memory| {
+            vec![
+                ("dirfd", fd(dirfd)),
+                ("dirflags", trace::lookupflags(dirflags)),
+                ("path", path(memory, path_, path_len)),
+                ("o_flags", trace::oflags(o_flags)),
+                ("fs_rights_base", trace::rights(fs_rights_base)),
+                ("fs_rights_inheriting", trace::rights(fs_rights_inheriting)),
+                ("fs_flags", trace::fdflags(fs_flags)),
+            ]
+        },
+        || {
+            syscalls::path_open(
+                env,
+                dirfd,
+                dirflags,
+                path_,
+                path_len,
+                o_flags,
+                fs_rights_base,
+                fs_rights_inheriting,
+                fs_flags,
+                fd_,
+            )
+        },
+        |memory| vec![("fd", read_fd(memory, fd_))],
+    )
+}
+
+pub fn path_readlink(
+    env: &WasiEnv,
+    dir_fd: __wasi_fd_t,
+    path_: WasmPtr<u8, Array>,
+    path_len: u32,
+    buf: WasmPtr<u8, Array>,
+    buf_len: u32,
+    buf_used: WasmPtr<u32>,
+) -> __wasi_errno_t {
+    traced(
+        env,
+        "path_readlink",
+        |memory| {
+            vec![
+                ("dir_fd", fd(dir_fd)),
+                ("path", path(memory, path_, path_len)),
+                ("buf_len", int(buf_len)),
+            ]
+        },
+        || syscalls::path_readlink(env, dir_fd, path_, path_len, buf, buf_len, buf_used),
+        |memory| match buf_used.deref(memory) {
+            Ok(cell) => vec![("link", path(memory, buf, cell.get()))],
+            Err(_) => vec![],
+        },
+    )
+}
+
+pub fn path_remove_directory(
+    env: &WasiEnv,
+    fd_: __wasi_fd_t,
+    path_: WasmPtr<u8, Array>,
+    path_len: u32,
+) -> __wasi_errno_t {
+    traced(
+        env,
+        "path_remove_directory",
+        |memory| vec![("fd", fd(fd_)), ("path", path(memory, path_, path_len))],
+        || syscalls::path_remove_directory(env, fd_, path_, path_len),
+        no_results,
+    )
+}
+
+pub fn path_rename(
+    env: &WasiEnv,
+    old_fd: __wasi_fd_t,
+    old_path: WasmPtr<u8, Array>,
+    old_path_len: u32,
+    new_fd: __wasi_fd_t,
+    new_path: WasmPtr<u8, Array>,
+    new_path_len: u32,
+) -> __wasi_errno_t {
+    traced(
+        env,
+        "path_rename",
+        |memory| {
+            vec![
+                ("old_fd", fd(old_fd)),
+                ("old_path", path(memory, old_path, old_path_len)),
+                ("new_fd", fd(new_fd)),
+                ("new_path", path(memory, new_path, new_path_len)),
+            ]
+        },
+        || {
+            syscalls::path_rename(
+                env,
+                old_fd,
+                old_path,
+                old_path_len,
+                new_fd,
+                new_path,
+                new_path_len,
+            )
+        },
+        no_results,
+    )
+}
+
+pub fn path_symlink(
+    env: &WasiEnv,
+    old_path: WasmPtr<u8, Array>,
+    old_path_len: u32,
+    fd_: __wasi_fd_t,
+    new_path: WasmPtr<u8, Array>,
+    new_path_len: u32,
+) -> __wasi_errno_t {
+    traced(
+        env,
+        "path_symlink",
+        |memory| {
+            vec![
+                ("old_path", path(memory, old_path, old_path_len)),
+                ("fd", fd(fd_)),
+                ("new_path", path(memory, new_path, new_path_len)),
+            ]
+        },
+        || syscalls::path_symlink(env, old_path, old_path_len, fd_, new_path, new_path_len),
+        no_results,
+    )
+}
+
+pub fn path_unlink_file(
+    env: &WasiEnv,
+    fd_: __wasi_fd_t,
+    path_: WasmPtr<u8, Array>,
+    path_len: u32,
+) -> __wasi_errno_t {
+    traced(
+        env,
+        "path_unlink_file",
+        |memory| vec![("fd", fd(fd_)), ("path", path(memory, path_, path_len))],
+        || syscalls::path_unlink_file(env, fd_, path_, path_len),
+        no_results,
+    )
+}
+
+pub fn poll_oneoff(
+    env: &WasiEnv,
+    in_: WasmPtr<__wasi_subscription_t, Array>,
+    out_: WasmPtr<__wasi_event_t, Array>,
+    nsubscriptions: u32,
+    nevents: WasmPtr<u32>,
+) -> __wasi_errno_t {
+    traced(
+        env,
+        "poll_oneoff",
+        |_| vec![("nsubscriptions", int(nsubscriptions))],
+        || syscalls::poll_oneoff(env, in_, out_, nsubscriptions, nevents),
+        |memory| vec![("nevents", read_u32(memory, nevents))],
+    )
+}
+
+pub fn proc_exit(env: &WasiEnv, code: __wasi_exitcode_t) {
+    // `proc_exit` unwinds instead of returning, so it's reported up front
+    if let Some(tracer) = &env.tracer {
+        tracer.on_syscall(&SyscallEvent {
+            name: "proc_exit",
+            args: vec![("code", int(code))],
+            results: vec![],
+            errno: None,
+            duration: Duration::default(),
+        });
+    }
+    syscalls::proc_exit(env, code)
+}
+
+pub fn proc_raise(env: &WasiEnv, sig: __wasi_signal_t) -> __wasi_errno_t {
+    traced(
+        env,
+        "proc_raise",
+        |_| vec![("sig", int(sig))],
+        || syscalls::proc_raise(env, sig),
+        no_results,
+    )
+}
+
+pub fn random_get(env: &WasiEnv, buf: u32, buf_len: u32) -> __wasi_errno_t {
+    traced(
+        env,
+        "random_get",
+        |_| vec![("buf", SyscallArg::Ptr(buf)), ("buf_len", int(buf_len))],
+        || syscalls::random_get(env, buf, buf_len),
+        no_results,
+    )
+}
+
+pub fn sched_yield(env: &WasiEnv) -> __wasi_errno_t {
+    traced(
+        env,
+        "sched_yield",
+        |_| vec![],
+        || syscalls::sched_yield(env),
+        no_results,
+    )
+}
+
+pub fn sock_recv(
+    env: &WasiEnv,
+    sock: __wasi_fd_t,
+    ri_data: WasmPtr<__wasi_iovec_t, Array>,
+    ri_data_len: u32,
+    ri_flags: __wasi_riflags_t,
+    ro_datalen: WasmPtr<u32>,
+    ro_flags: WasmPtr<__wasi_roflags_t>,
+) -> __wasi_errno_t {
+    traced(
+        env,
+        "sock_recv",
+        |memory| {
+            vec![
+                ("sock", fd(sock)),
+                ("ri_data", iovecs(memory, ri_data.offset(), ri_data_len)),
+                ("ri_flags", int(ri_flags)),
+            ]
+        },
+        || {
+            syscalls::sock_recv(
+                env,
+                sock,
+                ri_data,
+                ri_data_len,
+                ri_flags,
+                ro_datalen,
+                ro_flags,
+            )
+        },
+        |memory| vec![("ro_datalen", read_u32(memory, ro_datalen))],
+    )
+}
+
+pub fn sock_send(
+    env: &WasiEnv,
+    sock: __wasi_fd_t,
+    si_data: WasmPtr<__wasi_ciovec_t, Array>,
+    si_data_len: u32,
+    si_flags: __wasi_siflags_t,
+    so_datalen: WasmPtr<u32>,
+) -> __wasi_errno_t {
+    traced(
+        env,
+        "sock_send",
+        |memory| {
+            vec![
+                ("sock", fd(sock)),
+                ("si_data", iovecs(memory, si_data.offset(), si_data_len)),
+                ("si_flags", int(si_flags)),
+            ]
+        },
+        || syscalls::sock_send(env, sock, si_data, si_data_len, si_flags, so_datalen),
+        |memory| vec![("so_datalen", read_u32(memory, so_datalen))],
+    )
+}
+
+pub fn sock_shutdown(env: &WasiEnv, sock: __wasi_fd_t, how: __wasi_sdflags_t) -> __wasi_errno_t {
+    traced(
+        env,
+        "sock_shutdown",
+        |_| vec![("sock", fd(sock)), ("how", int(how))],
+        || syscalls::sock_shutdown(env, sock, how),
+        no_results,
+    )
+}
+
+/// Traced versions of the syscalls that differ in legacy WASI.
+pub mod snapshot0 {
+    use super::*;
+    use crate::syscalls::types::snapshot0;
+
+    pub fn fd_filestat_get(
+        env: &WasiEnv,
+        fd_: __wasi_fd_t,
+        buf: WasmPtr<snapshot0::__wasi_filestat_t>,
+    ) -> __wasi_errno_t {
+        traced(
+            env,
+            "fd_filestat_get",
+            |_| vec![("fd", fd(fd_))],
+            || legacy::snapshot0::fd_filestat_get(env, fd_, buf),
+            no_results,
+        )
+    }
+
+    pub fn path_filestat_get(
+        env: &WasiEnv,
+        fd_: __wasi_fd_t,
+        flags: __wasi_lookupflags_t,
+        path_: WasmPtr<u8, Array>,
+        path_len: u32,
+        buf: WasmPtr<snapshot0::__wasi_filestat_t>,
+    ) -> __wasi_errno_t {
+        traced(
+            env,
+            "path_filestat_get",
+            |memory| {
+                vec![
+                    ("fd", fd(fd_)),
+                    ("flags", trace::lookupflags(flags)),
+                    ("path", path(memory, path_, path_len)),
+                ]
+            },
+            || legacy::snapshot0::path_filestat_get(env, fd_, flags, path_, path_len, buf),
+            no_results,
+        )
+    }
+
+    pub fn fd_seek(
+        env: &WasiEnv,
+        fd_: __wasi_fd_t,
+        offset: __wasi_filedelta_t,
+        whence: snapshot0::__wasi_whence_t,
+        newoffset: WasmPtr<__wasi_filesize_t>,
+    ) -> __wasi_errno_t {
+        traced(
+            env,
+            "fd_seek",
+            |_| {
+                vec![
+                    ("fd", fd(fd_)),
+                    ("offset", SyscallArg::SignedInt(offset)),
+                    ("whence", int(whence)),
+                ]
+            },
+            || legacy::snapshot0::fd_seek(env, fd_, offset, whence, newoffset),
+            |memory| vec![("newoffset", read_u64(memory, newoffset))],
+        )
+    }
+
+    pub fn poll_oneoff(
+        env: &WasiEnv,
+        in_: WasmPtr<snapshot0::__wasi_subscription_t, Array>,
+        out_: WasmPtr<__wasi_event_t, Array>,
+        nsubscriptions: u32,
+        nevents: WasmPtr<u32>,
+    ) -> __wasi_errno_t {
+        traced(
+            env,
+            "poll_oneoff",
+            |_| vec![("nsubscriptions", int(nsubscriptions))],
+            || legacy::snapshot0::poll_oneoff(env, in_, out_, nsubscriptions, nevents),
+            |memory| vec![("nevents", read_u32(memory, nevents))],
+        )
+    }
+}
@@ -0,0 +1,357 @@
+//! Tracing of the WASI syscalls made by a module, in the spirit of `strace`.
+//!
+//! Install a [`WasiTracer`] with [`WasiEnv::set_tracer`] before creating the
+//! import object and it will be told about every syscall, along with its
+//! decoded arguments, its results, the returned errno and how long it took.
+//! [`TraceWriter`] prints those events in a strace-like format or as JSON
+//! lines.
+//!
+//! [`WasiEnv::set_tracer`]: crate::WasiEnv::set_tracer
+
+use crate::state::ALL_RIGHTS;
+use crate::syscalls::types::*;
+use std::fmt;
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Receives the syscalls made by a WASI module.
+pub trait WasiTracer: fmt::Debug + Send + Sync + 'static {
+    /// Called when a syscall returns; `proc_exit` is reported before it
+    /// unwinds instead.
+    fn on_syscall(&self, event: &SyscallEvent);
+}
+
+/// A decoded syscall argument or result.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyscallArg {
+    /// A file descriptor
+    Fd(__wasi_fd_t),
+    /// An unsigned integer, like a size or an offset
+    Int(u64),
+    /// A signed integer, like a relative offset
+    SignedInt(i64),
+    /// A bitmask or enum shown by the names of its values
+    Flags(String),
+    /// A path or string read from the guest memory
+    Path(String),
+    /// A list of buffers
+    IoVecs {
+        /// the number of buffers
+        count: u32,
+        /// the total length of the buffers
+        bytes: u64,
+    },
+    /// A pointer into the guest memory that wasn't decoded
+    Ptr(u32),
+}
+
+impl fmt::Display for SyscallArg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Fd(fd) => write!(f, "{}", fd),
+            Self::Int(value) => write!(f, "{}", value),
+            Self::SignedInt(value) => write!(f, "{}", value),
+            Self::Flags(flags) => write!(f, "{}", flags),
+            Self::Path(path) => write!(f, "{:?}", path),
+            Self::IoVecs { count, bytes } => write!(f, "[{} iovs, {} bytes]", count, bytes),
+            Self::Ptr(ptr) => write!(f, "{:#x}", ptr),
+        }
+    }
+}
+
+/// A syscall made by a WASI module.
+#[derive(Debug, Clone)]
+pub struct SyscallEvent {
+    /// The name of the syscall, like `path_open`
+    pub name: &'static str,
+    /// The arguments of the syscall by name
+    pub args: Vec<(&'static str, SyscallArg)>,
+    /// The values written back by a successful syscall by name, like the fd
+    /// opened by `path_open`
+    pub results: Vec<(&'static str, SyscallArg)>,
+    /// The returned errno, `None` for syscalls that don't return
+    pub errno: Option<__wasi_errno_t>,
+    /// The time spent in the syscall
+    pub duration: Duration,
+}
+
+impl SyscallEvent {
+    /// Formats the event as a single line in the style of `strace`, like
+    /// `fd_read(fd=3, iovs=[1 iovs, 1024 bytes]) = 0 ESUCCESS (nread=12) <0.000012>`.
+    pub fn to_strace_line(&self) -> String {
+        let args = self
+            .args
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut line = format!("{}({})", self.name, args);
+        match self.errno {
+            Some(errno) => {
+                line.push_str(&format!(
+                    " = {} {}",
+                    errno,
+                    errno_to_string(errno).unwrap_or("EUNKNOWN")
+                ));
+            }
+            None => line.push_str(" = ?"),
+        }
+        if !self.results.is_empty() {
+            let results = self
+                .results
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect::<Vec<_>>()
+                .join(", ");
+            line.push_str(&format!(" ({})", results));
+        }
+        line.push_str(&format!(" <{:.6}>", self.duration.as_secs_f64()));
+        line
+    }
+
+    /// Formats the event as a JSON object on a single line.
+    pub fn to_json_line(&self) -> String {
+        fn to_json(values: &[(&'static str, SyscallArg)]) -> serde_json::Value {
+            values
+                .iter()
+                .map(|(name, value)| {
+                    let value = match value {
+                        SyscallArg::Fd(fd) => serde_json::json!(fd),
+                        SyscallArg::Int(value) => serde_json::json!(value),
+                        SyscallArg::SignedInt(value) => serde_json::json!(value),
+                        SyscallArg::Flags(flags) => serde_json::json!(flags),
+                        SyscallArg::Path(path) => serde_json::json!(path),
+                        SyscallArg::IoVecs { count, bytes } => {
+                            serde_json::json!({ "count": count, "bytes": bytes })
+                        }
+                        SyscallArg::Ptr(ptr) => serde_json::json!(ptr),
+                    };
+                    (name.to_string(), value)
+                })
+                .collect::<serde_json::Map<_, _>>()
+                .into()
+        }
+
+        serde_json::json!({
+            "syscall": self.name,
+            "args": to_json(&self.args),
+            "results": to_json(&self.results),
+            "errno": self.errno,
+            "errno_name": self.errno.and_then(errno_to_string),
+            "duration_ns": self.duration.as_nanos() as u64,
+        })
+        .to_string()
+    }
+}
+
+/// The output format of a [`TraceWriter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// One line per syscall in the style of `strace`
+    Strace,
+    /// One JSON object per line
+    Json,
+}
+
+impl std::str::FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "strace" => Ok(Self::Strace),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("The trace format `{}` does not exist.", s)),
+        }
+    }
+}
+
+/// A [`WasiTracer`] writing every syscall as a line to `W`.
+pub struct TraceWriter<W: Write + Send + 'static> {
+    out: Mutex<W>,
+    format: TraceFormat,
+}
+
+impl<W: Write + Send + 'static> TraceWriter<W> {
+    /// Creates a tracer writing to `out` in the given format.
+    pub fn new(out: W, format: TraceFormat) -> Self {
+        Self {
+            out: Mutex::new(out),
+            format,
+        }
+    }
+}
+
+impl<W: Write + Send + 'static> fmt::Debug for TraceWriter<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TraceWriter")
+            .field("format", &self.format)
+            .finish()
+    }
+}
+
+impl<W: Write + Send + 'static> WasiTracer for TraceWriter<W> {
+    fn on_syscall(&self, event: &SyscallEvent) {
+        let line = match self.format {
+            TraceFormat::Strace => event.to_strace_line(),
+            TraceFormat::Json => event.to_json_line(),
+        };
+        let mut out = self.out.lock().unwrap();
+        // tracing must never make the traced program fail
+        let _ = writeln!(out, "{}", line);
+        let _ = out.flush();
+    }
+}
+
+/// Joins the names of the bits set in `bits`, or shows `0` if none are.
+fn flag_names(bits: u64, names: &[(u64, &'static str)]) -> SyscallArg {
+    let mut set = names
+        .iter()
+        .filter(|(bit, _)| bits & bit != 0)
+        .map(|(_, name)| name.to_string())
+        .collect::<Vec<_>>();
+    let unknown = names.iter().fold(bits, |bits, (bit, _)| bits & !bit);
+    if unknown != 0 {
+        set.push(format!("{:#x}", unknown));
+    }
+    if set.is_empty() {
+        SyscallArg::Flags("0".to_string())
+    } else {
+        SyscallArg::Flags(set.join("|"))
+    }
+}
+
+pub(crate) fn oflags(o_flags: __wasi_oflags_t) -> SyscallArg {
+    flag_names(
+        o_flags as u64,
+        &[
+            (__WASI_O_CREAT as u64, "O_CREAT"),
+            (__WASI_O_DIRECTORY as u64, "O_DIRECTORY"),
+            (__WASI_O_EXCL as u64, "O_EXCL"),
+            (__WASI_O_TRUNC as u64, "O_TRUNC"),
+        ],
+    )
+}
+
+pub(crate) fn fdflags(fd_flags: __wasi_fdflags_t) -> SyscallArg {
+    flag_names(
+        fd_flags as u64,
+        &[
+            (__WASI_FDFLAG_APPEND as u64, "FDFLAG_APPEND"),
+            (__WASI_FDFLAG_DSYNC as u64, "FDFLAG_DSYNC"),
+            (__WASI_FDFLAG_NONBLOCK as u64, "FDFLAG_NONBLOCK"),
+            (__WASI_FDFLAG_RSYNC as u64, "FDFLAG_RSYNC"),
+            (__WASI_FDFLAG_SYNC as u64, "FDFLAG_SYNC"),
+        ],
+    )
+}
+
+pub(crate) fn lookupflags(lookup_flags: __wasi_lookupflags_t) -> SyscallArg {
+    flag_names(
+        lookup_flags as u64,
+        &[(__WASI_LOOKUP_SYMLINK_FOLLOW as u64, "LOOKUP_SYMLINK_FOLLOW")],
+    )
+}
+
+pub(crate) fn fstflags(fst_flags: __wasi_fstflags_t) -> SyscallArg {
+    flag_names(
+        fst_flags as u64,
+        &[
+            (__WASI_FILESTAT_SET_ATIM as u64, "FILESTAT_SET_ATIM"),
+            (__WASI_FILESTAT_SET_ATIM_NOW as u64, "FILESTAT_SET_ATIM_NOW"),
+            (__WASI_FILESTAT_SET_MTIM as u64, "FILESTAT_SET_MTIM"),
+            (__WASI_FILESTAT_SET_MTIM_NOW as u64, "FILESTAT_SET_MTIM_NOW"),
+        ],
+    )
+}
+
+pub(crate) fn rights(rights: __wasi_rights_t) -> SyscallArg {
+    if rights == ALL_RIGHTS {
+        return SyscallArg::Flags("ALL_RIGHTS".to_string());
+    }
+    let names = (0..29)
+        .map(|i| 1 << i)
+        .filter_map(|right| {
+            right_to_string(right).map(|name| (right, name.trim_start_matches("__WASI_")))
+        })
+        .collect::<Vec<_>>();
+    flag_names(rights, &names)
+}
+
+pub(crate) fn whence(whence: __wasi_whence_t) -> SyscallArg {
+    SyscallArg::Flags(
+        match whence {
+            __WASI_WHENCE_SET => "WHENCE_SET",
+            __WASI_WHENCE_CUR => "WHENCE_CUR",
+            __WASI_WHENCE_END => "WHENCE_END",
+            _ => return SyscallArg::Int(whence as u64),
+        }
+        .to_string(),
+    )
+}
+
+pub(crate) fn clockid(clock_id: __wasi_clockid_t) -> SyscallArg {
+    SyscallArg::Flags(
+        match clock_id {
+            __WASI_CLOCK_REALTIME => "CLOCK_REALTIME",
+            __WASI_CLOCK_MONOTONIC => "CLOCK_MONOTONIC",
+            __WASI_CLOCK_PROCESS_CPUTIME_ID => "CLOCK_PROCESS_CPUTIME_ID",
+            __WASI_CLOCK_THREAD_CPUTIME_ID => "CLOCK_THREAD_CPUTIME_ID",
+            _ => return SyscallArg::Int(clock_id as u64),
+        }
+        .to_string(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn event() -> SyscallEvent {
+        SyscallEvent {
+            name: "path_open",
+            args: vec![
+                ("dirfd", SyscallArg::Fd(3)),
+                ("path", SyscallArg::Path("data/in.txt".to_string())),
+                ("o_flags", oflags(__WASI_O_CREAT | __WASI_O_TRUNC)),
+            ],
+            results: vec![("fd", SyscallArg::Fd(4))],
+            errno: Some(__WASI_ESUCCESS),
+            duration: Duration::from_micros(12),
+        }
+    }
+
+    #[test]
+    fn strace_format() {
+        assert_eq!(
+            event().to_strace_line(),
+            r#"path_open(dirfd=3, path="data/in.txt", o_flags=O_CREAT|O_TRUNC) = 0 ESUCCESS (fd=4) <0.000012>"#
+        );
+        let failed = SyscallEvent {
+            results: vec![],
+            errno: Some(__WASI_ENOENT),
+            ..event()
+        };
+        assert!(failed.to_strace_line().contains(") = 44 ENOENT <"));
+    }
+
+    #[test]
+    fn json_format() {
+        let json: serde_json::Value = serde_json::from_str(&event().to_json_line()).unwrap();
+        assert_eq!(json["syscall"], "path_open");
+        assert_eq!(json["args"]["path"], "data/in.txt");
+        assert_eq!(json["args"]["o_flags"], "O_CREAT|O_TRUNC");
+        assert_eq!(json["results"]["fd"], 4);
+        assert_eq!(json["errno_name"], "ESUCCESS");
+        assert_eq!(json["duration_ns"], 12_000);
+    }
+
+    #[test]
+    fn unknown_flags_are_kept() {
+        assert_eq!(
+            fdflags(__WASI_FDFLAG_APPEND | 1 << 8),
+            SyscallArg::Flags("FDFLAG_APPEND|0x100".to_string())
+        );
+        assert_eq!(oflags(0), SyscallArg::Flags("0".to_string()));
+    }
+}
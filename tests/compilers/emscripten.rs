@@ -0,0 +1,161 @@
+//! Runs modules shaped like the ones Emscripten emits with `-pthread`,
+//! written by hand so that they only import what they test.
+
+use anyhow::{anyhow, Result};
+use wasmer::*;
+use wasmer_emscripten::{
+    generate_emscripten_env, run_emscripten_instance, EmEnv, EmSandbox, EmscriptenGlobals,
+};
+
+/// The imports, the runtime exports and the thread-specific data destructor
+/// of the modules, around the `$thread` start routine and the `_main`
+/// function of a test.
+///
+/// The tests use the memory from 0x800000: it starts with the top of the
+/// heap the stacks of the threads are allocated from, followed by a word only
+/// initialized by the data segment.
+fn pthread_module(thread_and_main: &str) -> String {
+    format!(
+        r#"(module
+  (type $ii (func (param i32) (result i32)))
+  (type $vi (func (param i32)))
+  (import "env" "memory" (memory 256 256))
+  (import "env" "table" (table 3 3 funcref))
+  (import "env" "_emscripten_memcpy_big" (func $memcpy_big (param i32 i32 i32) (result i32)))
+  (import "env" "_pthread_create" (func $pthread_create (param i32 i32 i32 i32) (result i32)))
+  (import "env" "_pthread_join" (func $pthread_join (param i32 i32) (result i32)))
+  (import "env" "_pthread_mutexattr_init" (func $pthread_mutexattr_init (param i32) (result i32)))
+  (import "env" "_pthread_mutexattr_settype" (func $pthread_mutexattr_settype (param i32 i32) (result i32)))
+  (import "env" "_pthread_mutex_init" (func $pthread_mutex_init (param i32 i32) (result i32)))
+  (import "env" "_pthread_mutex_lock" (func $pthread_mutex_lock (param i32) (result i32)))
+  (import "env" "_pthread_mutex_unlock" (func $pthread_mutex_unlock (param i32) (result i32)))
+  (import "env" "_pthread_cond_wait" (func $pthread_cond_wait (param i32 i32) (result i32)))
+  (import "env" "_pthread_cond_signal" (func $pthread_cond_signal (param i32) (result i32)))
+  (import "env" "_pthread_key_create" (func $pthread_key_create (param i32 i32) (result i32)))
+  (import "env" "_pthread_getspecific" (func $pthread_getspecific (param i32) (result i32)))
+  (import "env" "_pthread_setspecific" (func $pthread_setspecific (param i32 i32) (result i32)))
+  (elem (i32.const 1) $thread $dtor)
+  (data (i32.const 0x800000) "\00\00\90\00\01\00\00\00")
+  (func (export "_memalign") (param $align i32) (param $size i32) (result i32)
+    (local $ptr i32)
+    (local.set $ptr
+      (i32.and
+        (i32.add (i32.load (i32.const 0x800000)) (i32.sub (local.get $align) (i32.const 1)))
+        (i32.sub (i32.const 0) (local.get $align))))
+    (i32.store (i32.const 0x800000) (i32.add (local.get $ptr) (local.get $size)))
+    (local.get $ptr))
+  (func (export "_free") (param i32))
+  (func (export "dynCall_ii") (param i32 i32) (result i32)
+    (call_indirect (type $ii) (local.get 1) (local.get 0)))
+  (func (export "dynCall_vi") (param i32 i32)
+    (call_indirect (type $vi) (local.get 1) (local.get 0)))
+  (func $dtor (param i32)
+    (i32.store (i32.const 0x800060) (local.get 0)))
+  {}
+)"#,
+        thread_and_main
+    )
+}
+
+/// Runs the `_main` function of the module, returning its memory once all
+/// of its threads are joined.
+fn run_pthread_module(config: crate::Config, thread_and_main: &str) -> Result<Memory> {
+    let store = config.store();
+    let module = Module::new(&store, pthread_module(thread_and_main))?;
+    let mut globals = EmscriptenGlobals::new(&store, &module).map_err(|e| anyhow!("{}", e))?;
+    let mut env = EmEnv::new(&globals.data, EmSandbox::new());
+    let import_object = generate_emscripten_env(&store, &mut globals, &env);
+    let mut instance = Instance::new(&module, &import_object)?;
+    run_emscripten_instance(&mut instance, &mut env, &mut globals, "", vec![], None)?;
+    Ok(globals.memory)
+}
+
+fn read_u32(memory: &Memory, addr: u32) -> u32 {
+    memory.view::<u32>()[addr as usize / 4].get()
+}
+
+#[compiler_test(emscripten)]
+fn pthread_create_join(config: crate::Config) -> Result<()> {
+    let memory = run_pthread_module(
+        config,
+        r#"
+  (func $thread (param $arg i32) (result i32)
+    (i32.add (i32.load (i32.const 0x800004)) (local.get $arg)))
+  (func (export "_main")
+    (i32.store (i32.const 0x800004) (i32.const 7))
+    (i32.store (i32.const 0x800080)
+      (call $pthread_create (i32.const 0x800008) (i32.const 0) (i32.const 1) (i32.const 5)))
+    (i32.store (i32.const 0x800084)
+      (call $pthread_join (i32.load (i32.const 0x800008)) (i32.const 0x800010))))
+"#,
+    )?;
+    assert_eq!(read_u32(&memory, 0x800080), 0);
+    assert_eq!(read_u32(&memory, 0x800084), 0);
+    // the thread saw the memory as the main thread left it, not as the data
+    // segment initializes it
+    assert_eq!(read_u32(&memory, 0x800010), 7 + 5);
+    Ok(())
+}
+
+#[compiler_test(emscripten)]
+fn pthread_recursive_mutex_cond(config: crate::Config) -> Result<()> {
+    let memory = run_pthread_module(
+        config,
+        r#"
+  (func $thread (param i32) (result i32)
+    (drop (call $pthread_mutex_lock (i32.const 0x800030)))
+    (i32.store (i32.const 0x800040) (i32.const 1))
+    (drop (call $pthread_cond_signal (i32.const 0x800050)))
+    (call $pthread_mutex_unlock (i32.const 0x800030)))
+  (func (export "_main")
+    ;; a recursive mutex, locked twice while waiting on the condition
+    (drop (call $pthread_mutexattr_init (i32.const 0x800020)))
+    (drop (call $pthread_mutexattr_settype (i32.const 0x800020) (i32.const 1)))
+    (drop (call $pthread_mutex_init (i32.const 0x800030) (i32.const 0x800020)))
+    (drop (call $pthread_mutex_lock (i32.const 0x800030)))
+    (drop (call $pthread_mutex_lock (i32.const 0x800030)))
+    (drop (call $pthread_create (i32.const 0x800008) (i32.const 0) (i32.const 1) (i32.const 0)))
+    (block $ready
+      (loop $wait
+        (br_if $ready (i32.load (i32.const 0x800040)))
+        (drop (call $pthread_cond_wait (i32.const 0x800050) (i32.const 0x800030)))
+        (br $wait)))
+    (i32.store (i32.const 0x800080) (call $pthread_mutex_unlock (i32.const 0x800030)))
+    (i32.store (i32.const 0x800084) (call $pthread_mutex_unlock (i32.const 0x800030)))
+    (i32.store (i32.const 0x800088) (call $pthread_mutex_unlock (i32.const 0x800030)))
+    (i32.store (i32.const 0x80008c)
+      (call $pthread_join (i32.load (i32.const 0x800008)) (i32.const 0x800010))))
+"#,
+    )?;
+    // the mutex is locked twice again after the wait, and only twice
+    assert_eq!(read_u32(&memory, 0x800080), 0);
+    assert_eq!(read_u32(&memory, 0x800084), 0);
+    assert_eq!(read_u32(&memory, 0x800088), 1 /* EPERM */);
+    assert_eq!(read_u32(&memory, 0x80008c), 0);
+    assert_eq!(read_u32(&memory, 0x800010), 0);
+    Ok(())
+}
+
+#[compiler_test(emscripten)]
+fn pthread_thread_specific_data(config: crate::Config) -> Result<()> {
+    let memory = run_pthread_module(
+        config,
+        r#"
+  (func $thread (param i32) (result i32)
+    (drop (call $pthread_setspecific (i32.load (i32.const 0x800070)) (i32.const 99)))
+    (i32.store (i32.const 0x800064) (call $pthread_getspecific (i32.load (i32.const 0x800070))))
+    (i32.const 0))
+  (func (export "_main")
+    (drop (call $pthread_key_create (i32.const 0x800070) (i32.const 2)))
+    (drop (call $pthread_setspecific (i32.load (i32.const 0x800070)) (i32.const 11)))
+    (drop (call $pthread_create (i32.const 0x800008) (i32.const 0) (i32.const 1) (i32.const 0)))
+    (drop (call $pthread_join (i32.load (i32.const 0x800008)) (i32.const 0)))
+    (i32.store (i32.const 0x800068) (call $pthread_getspecific (i32.load (i32.const 0x800070)))))
+"#,
+    )?;
+    assert_eq!(read_u32(&memory, 0x800064), 99);
+    // the destructor of the key ran when the thread exited, with its value
+    assert_eq!(read_u32(&memory, 0x800060), 99);
+    assert_eq!(read_u32(&memory, 0x800068), 11);
+    Ok(())
+}
@@ -6,6 +6,8 @@
 extern crate compiler_test_derive;
 
 mod config;
+#[cfg(feature = "emscripten")]
+mod emscripten;
 mod imports;
 mod issues;
 mod metering;
@@ -13,6 +15,9 @@ mod middlewares;
 // mod multi_value_imports;
 mod native_functions;
 mod serialize;
+mod stack_limit;
+#[cfg(all(feature = "universal", feature = "singlepass", feature = "cranelift"))]
+mod tiered;
 mod traps;
 mod wasi;
 mod wast;
@@ -1,9 +1,11 @@
 use anyhow::Result;
 
-use loupe::MemoryUsage;
-use std::sync::Arc;
-use wasmer::wasmparser::Operator;
+use loupe::{MemoryUsage, MemoryUsageTracker};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Arc, Mutex};
+use wasmer::wasmparser::{Operator, Type as WpType};
 use wasmer::*;
+use wasmer_types::{FunctionIndex, ModuleInfo};
 
 #[derive(Debug, MemoryUsage)]
 struct Add2MulGen {
@@ -205,3 +207,111 @@ fn middleware_chain_order_2(mut config: crate::Config) -> Result<()> {
     assert_eq!(result, 48);
     Ok(())
 }
+
+/// Calls an imported hook with the right operand of every `i32.add`.
+#[derive(Debug, Default)]
+struct AddHookGen {
+    imported_functions: Mutex<ImportedFunctions>,
+}
+
+#[derive(Debug)]
+struct AddHook {
+    imported_functions: ImportedFunctions,
+    hook: FunctionIndex,
+    scratch_local: u32,
+}
+
+impl MemoryUsage for AddHookGen {
+    fn size_of_val(&self, _: &mut dyn MemoryUsageTracker) -> usize {
+        std::mem::size_of_val(self)
+    }
+}
+
+impl ModuleMiddleware for AddHookGen {
+    fn generate_function_middleware(&self, _: LocalFunctionIndex) -> Box<dyn FunctionMiddleware> {
+        let imported_functions = self.imported_functions.lock().unwrap().clone();
+        let hook = imported_functions.indices().next().unwrap();
+        Box::new(AddHook {
+            imported_functions,
+            hook,
+            scratch_local: 0,
+        })
+    }
+
+    fn transform_module_info(&self, module_info: &mut ModuleInfo) {
+        self.imported_functions.lock().unwrap().import(
+            module_info,
+            "hooks",
+            "on_add",
+            FunctionType::new(vec![Type::I32], vec![]),
+        );
+    }
+}
+
+impl FunctionMiddleware for AddHook {
+    fn declare_locals(&mut self, locals: &mut FunctionLocals<'_>) {
+        self.scratch_local = locals.declare(1, WpType::I32);
+    }
+
+    fn imported_functions(&self) -> Option<&ImportedFunctions> {
+        Some(&self.imported_functions)
+    }
+
+    fn feed<'a>(
+        &mut self,
+        operator: Operator<'a>,
+        state: &mut MiddlewareReaderState<'a>,
+    ) -> Result<(), MiddlewareError> {
+        if let Operator::I32Add = operator {
+            let local_index = self.scratch_local;
+            state.extend(&[
+                Operator::LocalSet { local_index },
+                Operator::LocalGet { local_index },
+                Operator::Call {
+                    function_index: self.hook.as_u32(),
+                },
+                Operator::LocalGet { local_index },
+            ]);
+        }
+        state.push_operator(operator);
+        Ok(())
+    }
+}
+
+static LAST_ADDED_VALUE: AtomicI32 = AtomicI32::new(0);
+
+fn on_add(value: i32) {
+    LAST_ADDED_VALUE.store(value, Ordering::SeqCst);
+}
+
+#[compiler_test(middlewares)]
+fn middleware_imports_and_locals(mut config: crate::Config) -> Result<()> {
+    config.set_middlewares(vec![
+        Arc::new(AddHookGen::default()) as Arc<dyn ModuleMiddleware>
+    ]);
+    let store = config.store();
+    let wat = r#"(module
+        (func $add (param i32 i32) (result i32)
+           (i32.add (local.get 0)
+                    (local.get 1)))
+        (func (export "run") (param i32) (result i32)
+           (call $add (local.get 0) (i32.const 10)))
+)"#;
+    let module = Module::new(&store, wat).unwrap();
+    let import = module.imports().functions().next().unwrap();
+    assert_eq!((import.module(), import.name()), ("hooks", "on_add"));
+
+    let import_object = imports! {
+        "hooks" => {
+            "on_add" => Function::new_native(&store, on_add),
+        },
+    };
+
+    let instance = Instance::new(&module, &import_object)?;
+
+    let f: NativeFunc<i32, i32> = instance.exports.get_native_function("run")?;
+    let result = f.call(5)?;
+    assert_eq!(result, 15);
+    assert_eq!(LAST_ADDED_VALUE.load(Ordering::SeqCst), 10);
+    Ok(())
+}
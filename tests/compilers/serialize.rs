@@ -1,4 +1,5 @@
 use anyhow::Result;
+use std::convert::TryInto;
 use wasmer::*;
 
 #[compiler_test(serialize)]
@@ -74,3 +75,105 @@ fn test_deserialize(config: crate::Config) -> Result<()> {
     assert_eq!(result.to_vec(), vec![Value::I64(1500)]);
     Ok(())
 }
+
+#[compiler_test(serialize)]
+fn test_deserialize_checked(config: crate::Config) -> Result<()> {
+    if config.engine != crate::Engine::Universal {
+        return Ok(());
+    }
+    let store = config.store();
+    let wat = r#"
+        (module $name
+            (func (export "answer") (result i32)
+                i32.const 42
+            )
+        )
+    "#;
+
+    let module = Module::new(&store, wat)?;
+    let serialized_bytes = module.serialize()?;
+
+    let headless_store = config.headless_store();
+    let deserialized_module = Module::deserialize_checked(&headless_store, &serialized_bytes)?;
+    assert_eq!(deserialized_module.name(), Some("name"));
+    let instance = Instance::new(&deserialized_module, &imports! {})?;
+    let answer = instance.exports.get_function("answer")?;
+    assert_eq!(answer.call(&[])?.to_vec(), vec![Value::I32(42)]);
+
+    // Flipping a byte of the serialized module is caught by the checksum.
+    let mut corrupted_bytes = serialized_bytes.clone();
+    let last = corrupted_bytes.len() - 9;
+    corrupted_bytes[last] ^= 0xff;
+    assert!(matches!(
+        Module::deserialize_checked(&headless_store, &corrupted_bytes),
+        Err(DeserializeError::ChecksumMismatch)
+    ));
+
+    // And so is changing its header, such as the name of the compiler that
+    // follows the version of the engine.
+    let mut corrupted_bytes = serialized_bytes.clone();
+    let engine_version = b"wasmer-engine-universal ";
+    let engine_version_start = corrupted_bytes
+        .windows(engine_version.len())
+        .position(|window| window == engine_version)
+        .expect("the header records the engine version");
+    let engine_version_length = u32::from_le_bytes(
+        corrupted_bytes[engine_version_start - 4..engine_version_start]
+            .try_into()
+            .unwrap(),
+    ) as usize;
+    let compiler_start = engine_version_start + engine_version_length + 4;
+    corrupted_bytes[compiler_start] ^= 0x20;
+    assert!(matches!(
+        Module::deserialize_checked(&headless_store, &corrupted_bytes),
+        Err(DeserializeError::ChecksumMismatch)
+    ));
+
+    // So is truncating it.
+    let truncated_bytes = &serialized_bytes[..serialized_bytes.len() / 2];
+    assert!(Module::deserialize_checked(&headless_store, truncated_bytes).is_err());
+
+    Ok(())
+}
+
+#[compiler_test(serialize)]
+fn test_deserialize_compressed(config: crate::Config) -> Result<()> {
+    let store = config.store();
+    let wat = r#"
+        (module $name
+            (func (export "answer") (result i32)
+                i32.const 42
+            )
+        )
+    "#;
+
+    let module = Module::new(&store, wat)?;
+    let compressed_bytes = module.serialize_compressed()?;
+    if config.engine == crate::Engine::Universal {
+        assert!(compressed_bytes.len() < module.serialize()?.len());
+    }
+
+    let headless_store = config.headless_store();
+    let deserialized_module = unsafe { Module::deserialize(&headless_store, &compressed_bytes)? };
+    assert_eq!(deserialized_module.name(), Some("name"));
+    let instance = Instance::new(&deserialized_module, &imports! {})?;
+    let answer = instance.exports.get_function("answer")?;
+    assert_eq!(answer.call(&[])?.to_vec(), vec![Value::I32(42)]);
+
+    // An uncompressed length far larger than the compressed module allows is
+    // rejected before anything is allocated for it, even without the checksum.
+    if config.engine == crate::Engine::Universal {
+        let mut corrupted_bytes = compressed_bytes.clone();
+        // the header follows the 22 magic bytes, and ends with the
+        // uncompressed length and the checksum
+        let header_length = u32::from_le_bytes(corrupted_bytes[26..30].try_into().unwrap());
+        let uncompressed_length_start = 30 + header_length as usize - 12;
+        corrupted_bytes[uncompressed_length_start..uncompressed_length_start + 8]
+            .copy_from_slice(&(1u64 << 40).to_le_bytes());
+        assert!(matches!(
+            unsafe { Module::deserialize(&headless_store, &corrupted_bytes) },
+            Err(DeserializeError::CorruptedBinary(_))
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use anyhow::Result;
+use wasmer_middlewares::stack_limit::{get_stack_usage, imports, StackLimit};
+
+use std::sync::Arc;
+use wasmer::*;
+
+#[compiler_test(stack_limit)]
+fn stack_limit_exit_on_branches_to_the_function_label(mut config: crate::Config) -> Result<()> {
+    config.middlewares.push(Arc::new(StackLimit::new(100)));
+    let store = config.store();
+    // `$leave` returns with `br_if`, a `br_table` target, `br` and the
+    // `br_table` default, so calling it in a loop exceeds the limit unless
+    // its frame is removed on each of them.
+    let wat = r#"(module
+        (func $leave (param $how i32) (result i32)
+            block
+                i32.const 1
+                local.get $how
+                i32.eqz
+                br_if 1
+                drop
+            end
+            block $other (result i32)
+                i32.const 2
+                local.get $how
+                i32.const 1
+                i32.sub
+                br_table 1 $other 1
+            end
+            drop
+            i32.const 3
+            br 0)
+        (func (export "run") (param $iterations i32) (result i32)
+            (local $sum i32)
+            loop $loop
+                local.get $sum
+                local.get $iterations
+                i32.const 4
+                i32.rem_u
+                call $leave
+                i32.add
+                local.set $sum
+                local.get $iterations
+                i32.const 1
+                i32.sub
+                local.tee $iterations
+                br_if $loop
+            end
+            local.get $sum)
+)"#;
+    let module = Module::new(&store, wat)?;
+    let instance = Instance::new(&module, &imports(&store))?;
+
+    let run: NativeFunc<i32, i32> = instance.exports.get_native_function("run")?;
+    assert_eq!(run.call(1000)?, 250 * (1 + 2 + 3 + 2));
+    assert_eq!(get_stack_usage(&instance), 0);
+    Ok(())
+}
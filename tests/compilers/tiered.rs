@@ -0,0 +1,54 @@
+use anyhow::Result;
+use wasmer::*;
+use wasmer_engine::Artifact;
+
+#[test]
+fn tiered_compilation() -> Result<()> {
+    let engine = Universal::new(Singlepass::new())
+        .tier_up(Cranelift::new())
+        .engine();
+    let store = Store::new(&engine);
+    let wat = r#"
+        (module
+            (type $t (func (param i32) (result i32)))
+            (table (export "table") 1 funcref)
+            (elem (i32.const 0) $add_one)
+            (func $add_one (type $t)
+                local.get 0
+                i32.const 1
+                i32.add)
+            (func (export "call_add_one") (param i32) (result i32)
+                local.get 0
+                i32.const 0
+                call_indirect (type $t)))
+    "#;
+    let module = Module::new(&store, wat)?;
+    let baseline_instance = Instance::new(&module, &imports! {})?;
+
+    let artifact = module
+        .artifact()
+        .downcast_ref::<TieredArtifact>()
+        .expect("the module should be tiered");
+    assert!(artifact.wait_optimized());
+
+    // The table of the baseline instance now refers to the optimized code.
+    let add_one = match baseline_instance.exports.get_table("table")?.get(0) {
+        Some(Value::FuncRef(Some(add_one))) => add_one,
+        _ => panic!("the table should hold `$add_one`"),
+    };
+    let optimized_add_one = artifact.finished_functions()[LocalFunctionIndex::from_u32(0)].0;
+    assert_eq!(
+        unsafe { add_one.get_vm_function().address },
+        optimized_add_one
+    );
+
+    let optimized_instance = Instance::new(&module, &imports! {})?;
+    for instance in &[baseline_instance, optimized_instance] {
+        let call_add_one = instance
+            .exports
+            .get_native_function::<i32, i32>("call_add_one")?;
+        assert_eq!(call_add_one.call(41)?, 42);
+    }
+
+    Ok(())
+}
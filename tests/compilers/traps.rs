@@ -73,6 +73,50 @@ fn test_trap_trace(config: crate::Config) -> Result<()> {
     Ok(())
 }
 
+#[cfg_attr(target_env = "musl", ignore)]
+#[compiler_test(traps)]
+fn test_trap_coredump(config: crate::Config) -> Result<()> {
+    let store = config.store();
+    let wat = r#"
+        (module $hello_mod
+            (memory 1 2)
+            (global (mut i32) (i32.const 42))
+            (data (i32.const 16) "hello")
+            (func (export "run") (call $hello))
+            (func $hello (unreachable))
+        )
+    "#;
+
+    let module = Module::new(&store, wat)?;
+    let instance = Instance::new(&module, &imports! {})?;
+    let run_func = instance
+        .exports
+        .get_function("run")
+        .expect("expected function export");
+
+    let e = run_func.call(&[]).err().expect("error calling function");
+    assert!(e.coredump().is_none());
+
+    store.set_coredump_on_trap(true);
+    let e = run_func.call(&[]).err().expect("error calling function");
+    let coredump = e.coredump().expect("expected a core dump");
+    assert_eq!(coredump.module_name(), "hello_mod");
+    assert_eq!(coredump.frames().len(), 2);
+    assert_eq!(coredump.frames()[0].func_index(), 1);
+    assert_eq!(coredump.frames()[1].func_index(), 0);
+    assert_eq!(coredump.memories().len(), 1);
+    assert_eq!(&coredump.memories()[0].data()[16..21], b"hello");
+    assert_eq!(coredump.memories()[0].maximum(), Some(Pages(2)));
+    assert_eq!(coredump.globals().len(), 1);
+    assert_eq!(coredump.globals()[0].bits() as u32, 42);
+
+    let bytes = coredump.serialize();
+    assert_eq!(&bytes[..8], b"\0asm\x01\0\0\0");
+    wasmparser::validate(&bytes)?;
+
+    Ok(())
+}
+
 #[compiler_test(traps)]
 fn test_trap_trace_cb(config: crate::Config) -> Result<()> {
     let store = config.store();
@@ -471,3 +515,179 @@ fn present_after_module_drop(config: crate::Config) -> Result<()> {
         // assert_eq!(t.trace()[0].func_index(), 0);
     }
 }
+
+fn write_uleb128(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// The size of the payload of the code section of `wasm`.
+fn code_section_len(wasm: &[u8]) -> u32 {
+    let mut position = 8;
+    loop {
+        let id = wasm[position];
+        position += 1;
+        let (mut size, mut shift) = (0, 0);
+        loop {
+            let byte = wasm[position];
+            position += 1;
+            size |= u32::from(byte & 0x7f) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        if id == 10 {
+            return size;
+        }
+        position += size as usize;
+    }
+}
+
+fn append_custom_section(wasm: &mut Vec<u8>, name: &str, data: &[u8]) {
+    let mut payload = vec![];
+    write_uleb128(&mut payload, name.len() as u64);
+    payload.extend_from_slice(name.as_bytes());
+    payload.extend_from_slice(data);
+    wasm.push(0);
+    write_uleb128(wasm, payload.len() as u64);
+    wasm.extend(payload);
+}
+
+/// Appends DWARF debug info to `wasm`, mapping all of its code to line 7
+/// of `die.c`, in a function named `die`.
+fn append_dwarf(wasm: &mut Vec<u8>) {
+    // the code starts after the count of the functions
+    let (low_pc, length) = (1u32, code_section_len(wasm) - 1);
+
+    let debug_abbrev = [
+        1, 0x11, 1, // DW_TAG_compile_unit, with children
+        0x03, 0x08, // DW_AT_name, DW_FORM_string
+        0x10, 0x17, // DW_AT_stmt_list, DW_FORM_sec_offset
+        0x11, 0x01, // DW_AT_low_pc, DW_FORM_addr
+        0x12, 0x06, // DW_AT_high_pc, DW_FORM_data4
+        0, 0, //
+        2, 0x2e, 0, // DW_TAG_subprogram, without children
+        0x03, 0x08, // DW_AT_name, DW_FORM_string
+        0x11, 0x01, // DW_AT_low_pc, DW_FORM_addr
+        0x12, 0x06, // DW_AT_high_pc, DW_FORM_data4
+        0, 0, //
+        0,
+    ];
+
+    let mut unit = vec![];
+    unit.extend_from_slice(&4u16.to_le_bytes());
+    unit.extend_from_slice(&0u32.to_le_bytes());
+    unit.push(4);
+    unit.push(1);
+    unit.extend_from_slice(b"die.c\0");
+    unit.extend_from_slice(&0u32.to_le_bytes());
+    unit.extend_from_slice(&low_pc.to_le_bytes());
+    unit.extend_from_slice(&length.to_le_bytes());
+    unit.push(2);
+    unit.extend_from_slice(b"die\0");
+    unit.extend_from_slice(&low_pc.to_le_bytes());
+    unit.extend_from_slice(&length.to_le_bytes());
+    unit.push(0);
+    let mut debug_info = (unit.len() as u32).to_le_bytes().to_vec();
+    debug_info.extend(unit);
+
+    // minimum_instruction_length, maximum_operations_per_instruction,
+    // default_is_stmt, line_base, line_range, opcode_base
+    let mut header = vec![1, 1, 1, -5i8 as u8, 14, 13];
+    header.extend_from_slice(&[0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1]);
+    // no include directories, then `die.c` in the compilation directory
+    header.push(0);
+    header.extend_from_slice(b"die.c\0");
+    header.extend_from_slice(&[0, 0, 0]);
+    header.push(0);
+    // DW_LNE_set_address, DW_LNS_advance_line, DW_LNS_copy,
+    // DW_LNS_advance_pc and DW_LNE_end_sequence
+    let mut program = vec![0, 5, 2];
+    program.extend_from_slice(&low_pc.to_le_bytes());
+    program.extend_from_slice(&[3, 6, 1]);
+    program.push(2);
+    write_uleb128(&mut program, u64::from(length));
+    program.extend_from_slice(&[0, 1, 1]);
+    let mut unit = vec![];
+    unit.extend_from_slice(&4u16.to_le_bytes());
+    unit.extend_from_slice(&(header.len() as u32).to_le_bytes());
+    unit.extend(header);
+    unit.extend(program);
+    let mut debug_line = (unit.len() as u32).to_le_bytes().to_vec();
+    debug_line.extend(unit);
+
+    append_custom_section(wasm, ".debug_abbrev", &debug_abbrev);
+    append_custom_section(wasm, ".debug_info", &debug_info);
+    append_custom_section(wasm, ".debug_line", &debug_line);
+}
+
+fn call_die(config: crate::Config, with_dwarf: bool) -> Result<RuntimeError> {
+    let store = config.store();
+    let mut wasm = wat2wasm(
+        br#"
+        (module $m
+            (func $die (export "die") nop unreachable)
+        )
+    "#,
+    )?
+    .into_owned();
+    if with_dwarf {
+        append_dwarf(&mut wasm);
+    }
+
+    let module = Module::new(&store, wasm)?;
+    let instance = Instance::new(&module, &imports! {})?;
+    let die = instance.exports.get_function("die")?;
+    Ok(die.call(&[]).err().expect("error calling function"))
+}
+
+#[cfg_attr(target_env = "musl", ignore)]
+#[compiler_test(traps)]
+fn trap_display_with_dwarf(config: crate::Config) -> Result<()> {
+    let e = call_die(config, true)?;
+    let frame = &e.trace()[0];
+    let symbols = frame.symbols();
+    assert_eq!(symbols.len(), 1);
+    assert_eq!(symbols[0].name(), Some("die"));
+    let file = symbols[0].file().expect("the frame has a source file");
+    assert!(file.ends_with("die.c"));
+    assert_eq!(symbols[0].line(), Some(7));
+    assert_eq!(
+        e.to_string(),
+        format!(
+            "\
+RuntimeError: unreachable
+    at die (m[0]:0x{:x})
+        at {}:7",
+            frame.module_offset(),
+            file
+        )
+    );
+    Ok(())
+}
+
+#[cfg_attr(target_env = "musl", ignore)]
+#[compiler_test(traps)]
+fn trap_display_without_dwarf(config: crate::Config) -> Result<()> {
+    let e = call_die(config, false)?;
+    let frame = &e.trace()[0];
+    assert!(frame.symbols().is_empty());
+    assert_eq!(
+        e.to_string(),
+        format!(
+            "\
+RuntimeError: unreachable
+    at die (m[0]:0x{:x})",
+            frame.module_offset()
+        )
+    );
+    Ok(())
+}